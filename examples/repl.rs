@@ -104,7 +104,7 @@ fn repl() {
         let expr_ref = match parser.parse(&line, &mut slab.ps) {
             Ok(expr_i) => slab.ps.get_expr(expr_i),
             Err(err) => {
-                eprintln!("parse error: {}", err);
+                eprintln!("parse error: {}", err.render(&line));
                 continue;
             }
         };
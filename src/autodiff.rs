@@ -0,0 +1,337 @@
+//! This module evaluates compiled `Instruction`s with forward-mode automatic differentiation.
+//!
+//! `eval_grad()` evaluates an `Instruction` on dual numbers: every
+//! intermediate value carries its partial derivatives with respect to a chosen
+//! list of variables.  The result is the value of the expression together with
+//! its gradient, computed in a single pass and without building any new
+//! expressions.  (If you need the derivative as an expression, use
+//! [`derive()`](../derive/index.html) instead.)
+//!
+//! Variables are matched by name, so Normal Variables, slots, Safe Variables
+//! and Unsafe Variables can all be differentiated.
+//!
+//! # Non-Differentiable Points
+//!
+//! Some functions are not differentiable everywhere.  `eval_grad()` handles
+//! them like this:
+//!
+//! * `int()`, `ceil()`, `floor()`, `round()`, `sign()`, comparisons, `!` and
+//!   the bitwise operators are treated as piecewise constant: their gradient
+//!   is always `0`, even at their jumps.
+//! * `abs(x)` has a gradient of `0` at `x == 0`.
+//! * `min()` and `max()` use the gradient of the argument that they select.
+//!   If the arguments are equal, the left one is used.  If either argument is
+//!   `NaN`, the gradient is `NaN`.
+//! * `&&`, `||` and `? :` use the gradient of the branch that they select.
+//! * `x % y` uses `x' - y'*int(x/y)`.
+//! * `x^y` uses `y*x^(y-1)*x' + x^y*ln(x)*y'`.  The second term is skipped
+//!   when `y` doesn't depend on the variables, so negative bases work as long
+//!   as the power is constant.
+//! * `log(b, x)` is `NaN` (or infinite) for non-positive `x` and `b`, just
+//!   like its value.
+//! * Custom functions and `print()` can only be used if their arguments
+//!   don't depend on the variables.  Otherwise, an
+//!   [`Error::NotDifferentiable`](../error/enum.Error.html#variant.NotDifferentiable)
+//!   is returned.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut map = fasteval::StringToF64Namespace::new();
+//!     map.insert("x".to_string(), 3.0);
+//!     map.insert("y".to_string(), 2.0);
+//!
+//!     let instr = fasteval::Parser::new().parse("x^2 * y + y", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     let dual = fasteval::eval_grad(&instr, &slab, &["x", "y"], &mut map)?;
+//!
+//!     assert_eq!(dual.val, 20.0);
+//!     assert_eq!(dual.grad, vec![12.0, 10.0]);  // [2*x*y, x^2+1]
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evaler::Evaler;
+use crate::evalns::{EvalNamespace, LocalNamespace};
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, bitnot, store_local, IC, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+
+/// A value, together with its partial derivatives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dual {
+    /// The value.
+    pub val :f64,
+    /// The partial derivatives of the value with respect to each variable,
+    /// in the same order as the `vars` that were passed to `eval_grad()`.
+    pub grad:Vec<f64>,
+}
+
+impl Dual {
+    #[inline]
+    fn constant(val:f64, n:usize) -> Self {
+        Dual{ val, grad:vec![0.0; n] }
+    }
+    #[inline]
+    fn is_constant(&self) -> bool {
+        self.grad.iter().all(|&g| g==0.0)
+    }
+
+    // Chain rule for f(x):  f'(x) * x'
+    // Zero partials stay zero, so that an infinite `dfdx` doesn't turn an
+    // unrelated partial into `NaN`.
+    #[inline]
+    fn chain(mut self, val:f64, dfdx:f64) -> Self {
+        self.val = val;
+        for g in self.grad.iter_mut() {
+            if *g!=0.0 { *g *= dfdx; }
+        }
+        self
+    }
+
+    // Chain rule for f(x,y):  df/dx * x' + df/dy * y'
+    #[inline]
+    fn chain2(mut self, other:&Dual, val:f64, dfdx:f64, dfdy:f64) -> Self {
+        self.val = val;
+        for (g,o) in self.grad.iter_mut().zip(other.grad.iter()) {
+            let x = if *g!=0.0 { *g*dfdx } else { 0.0 };
+            let y = if *o!=0.0 { *o*dfdy } else { 0.0 };
+            *g = x+y;
+        }
+        self
+    }
+}
+
+/// Evaluates `instr`, and calculates its gradient with respect to `vars`.
+///
+/// [See the `autodiff` module documentation.](index.html)
+pub fn eval_grad(instr:&Instruction, slab:&Slab, vars:&[&str], ns:&mut impl EvalNamespace) -> Result<Dual,Error> {
+    AutoDiff{ slab, vars, ns, keybuf:String::new(), names:Vec::new(), locals:Vec::new() }.instr(instr)
+}
+
+struct AutoDiff<'a,NS> {
+    slab  :&'a Slab,
+    vars  :&'a [&'a str],
+    ns    :&'a mut NS,
+    keybuf:String,
+    names :Vec<String>,  // The names of the local variables, for print().
+    locals:Vec<Dual>,  // The arguments of the user-defined function whose body is being evaluated, or the variables of the program.
+}
+
+impl<NS> AutoDiff<'_,NS> where NS:EvalNamespace {
+    fn var(&self, name:&str, val:f64) -> Dual {
+        let mut dual = Dual::constant(val, self.vars.len());
+        for (g,var) in dual.grad.iter_mut().zip(self.vars.iter()) {
+            if *var==name { *g = 1.0; }
+        }
+        dual
+    }
+
+    #[inline]
+    fn ic(&mut self, ic:&IC) -> Result<Dual,Error> {
+        match ic {
+            IC::C(c) => Ok(Dual::constant(*c, self.vars.len())),
+            IC::I(i) => self.instr(get_instr!(self.slab.cs,i)),
+        }
+    }
+
+    fn instr(&mut self, instr:&Instruction) -> Result<Dual,Error> {
+        macro_rules! unary {
+            ($i:ident, |$x:ident| $val:expr, $dfdx:expr) => {{
+                let x = self.instr(get_instr!(self.slab.cs,$i))?;
+                let $x = x.val;
+                let (val, dfdx) = ($val, $dfdx);
+                Ok(x.chain(val, dfdx))
+            }};
+        }
+        macro_rules! flat {
+            ($i:ident, |$x:ident| $val:expr) => {{
+                let x = self.instr(get_instr!(self.slab.cs,$i))?;
+                let $x = x.val;
+                Ok(Dual::constant($val, self.vars.len()))
+            }};
+        }
+        macro_rules! bitwise {
+            ($lic:ident, $ric:ident, $f:expr) => {{
+                let l = self.ic($lic)?.val;
+                let r = self.ic($ric)?.val;
+                Ok(Dual::constant($f(l,r)?, self.vars.len()))
+            }};
+        }
+        macro_rules! compare {
+            ($lic:ident, $ric:ident, |$l:ident, $r:ident| $val:expr) => {{
+                let $l = self.ic($lic)?.val;
+                let $r = self.ic($ric)?.val;
+                Ok(Dual::constant(bool_to_f64!($val), self.vars.len()))
+            }};
+        }
+
+        match instr {
+            IConst(c) => Ok(Dual::constant(*c, self.vars.len())),
+            IVar(name) => {
+                match self.ns.lookup(name, Vec::new(), &mut self.keybuf) {
+                    Some(val) => Ok(self.var(name, val)),
+                    None => Err(Error::Undefined(name.to_string())),
+                }
+            }
+            ISlot{name, slot} => {
+                match self.ns.slot(*slot).or_else(|| self.ns.lookup(name, Vec::new(), &mut self.keybuf)) {
+                    Some(val) => Ok(self.var(name, val)),
+                    None => Err(Error::Undefined(name.to_string())),
+                }
+            }
+            ISafeVar{name, var} => Ok(self.var(name, var.get())),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{name, ptr} => Ok(self.var(name, unsafe { **ptr })),
+            IFunc{name, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    let arg = self.ic(ic)?;
+                    if !arg.is_constant() { return Err(Error::NotDifferentiable(format!("custom function `{}`", name))); }
+                    args.push(arg.val);
+                }
+                match self.ns.lookup(name, args, &mut self.keybuf) {
+                    Some(val) => Ok(Dual::constant(val, self.vars.len())),
+                    None => Err(Error::Undefined(name.to_string())),
+                }
+            }
+            IPrintFunc(pf) => {
+                let var_names = pf.var_names(self.slab);
+                let names = &self.names[..self.locals.len()];
+                // print() can't depend on the variables, either directly or through a local variable:
+                for (name,local) in names.iter().zip(self.locals.iter()) {
+                    if !local.is_constant() && var_names.contains(name) { return Err(Error::NotDifferentiable("print()".to_string())); }
+                }
+                if self.vars.iter().any(|var| var_names.contains(*var) && !names.iter().any(|n| n==var)) { return Err(Error::NotDifferentiable("print()".to_string())); }
+                let vals : Vec<f64> = self.locals.iter().map(|local| local.val).collect();
+                Ok(Dual::constant(pf.eval(self.slab, &mut LocalNamespace::new(names, &vals, self.ns))?, self.vars.len()))
+            }
+            ILocal(i) => self.locals.get(*i).cloned().ok_or(Error::Unreachable),
+            ICall{names, args:ics, body, ..} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics { args.push(self.ic(ic)?); }
+                let locals = std::mem::replace(&mut self.locals, args);
+                let names = std::mem::replace(&mut self.names, names.clone());
+                let res = self.instr(get_instr!(self.slab.cs,body));
+                self.locals = locals;
+                self.names = names;
+                res
+            }
+            IProgram{names, stmts} => {
+                self.names = names.clone();
+                let mut val = Dual::constant(0.0, self.vars.len());
+                for (slot,ic) in stmts {
+                    val = self.ic(ic)?;
+                    if slot.is_some() { store_local(&mut self.locals, *slot, val.clone()); }
+                }
+                Ok(val)
+            }
+
+            INeg(i) => unary!(i, |x| -x, -1.0),
+            IInv(i) => unary!(i, |x| 1.0/x, -1.0/(x*x)),
+            INot(i) => flat!(i, |x| bool_to_f64!(f64_eq!(x,0.0))),
+            IBitNot(i) => flat!(i, |x| bitnot(x)?),
+
+            IAdd(li, ric) => {
+                let l = self.instr(get_instr!(self.slab.cs,li))?;
+                let r = self.ic(ric)?;
+                let val = l.val+r.val;
+                Ok(l.chain2(&r, val, 1.0, 1.0))
+            }
+            IMul(li, ric) => {
+                let l = self.instr(get_instr!(self.slab.cs,li))?;
+                let r = self.ic(ric)?;
+                let (lv, rv) = (l.val, r.val);
+                Ok(l.chain2(&r, lv*rv, rv, lv))
+            }
+            IMod{dividend, divisor} => {
+                let l = self.ic(dividend)?;
+                let r = self.ic(divisor)?;
+                let (lv, rv) = (l.val, r.val);
+                Ok(l.chain2(&r, lv%rv, 1.0, -(lv/rv).trunc()))
+            }
+            IExp{base, power} => {
+                let b = self.ic(base)?;
+                let p = self.ic(power)?;
+                let (bv, pv) = (b.val, p.val);
+                let val = bv.powf(pv);
+                if p.is_constant() {
+                    Ok(b.chain(val, pv*bv.powf(pv-1.0)))
+                } else {
+                    Ok(b.chain2(&p, val, pv*bv.powf(pv-1.0), val*bv.ln()))
+                }
+            }
+
+            ILT(l, r) => compare!(l, r, |l,r| l<r),
+            ILTE(l, r) => compare!(l, r, |l,r| l<=r),
+            IEQ(l, r) => compare!(l, r, |l,r| f64_eq!(l,r)),
+            INE(l, r) => compare!(l, r, |l,r| f64_ne!(l,r)),
+            IGTE(l, r) => compare!(l, r, |l,r| l>=r),
+            IGT(l, r) => compare!(l, r, |l,r| l>r),
+            IBitOr(l, r) => bitwise!(l, r, bitor),
+            IBitXor(l, r) => bitwise!(l, r, bitxor),
+            IBitAnd(l, r) => bitwise!(l, r, bitand),
+            IShl(l, r) => bitwise!(l, r, shl),
+            IShr(l, r) => bitwise!(l, r, shr),
+
+            IAND(li, ric) => {
+                let l = self.instr(get_instr!(self.slab.cs,li))?;
+                if f64_eq!(l.val,0.0) { Ok(l) } else { self.ic(ric) }
+            }
+            IOR(li, ric) => {
+                let l = self.instr(get_instr!(self.slab.cs,li))?;
+                if f64_ne!(l.val,0.0) { Ok(l) } else { self.ic(ric) }
+            }
+            IFuncIf{cond, then, otherwise} => {
+                let c = self.instr(get_instr!(self.slab.cs,cond))?;
+                if f64_ne!(c.val,0.0) { self.ic(then) } else { self.ic(otherwise) }
+            }
+
+            IFuncInt(i) => flat!(i, |x| x.trunc()),
+            IFuncCeil(i) => flat!(i, |x| x.ceil()),
+            IFuncFloor(i) => flat!(i, |x| x.floor()),
+            IFuncSign(i) => flat!(i, |x| x.signum()),
+            IFuncRound{modulus, of} => {
+                let modulus = self.ic(modulus)?.val;
+                let of = self.ic(of)?.val;
+                Ok(Dual::constant((of/modulus).round() * modulus, self.vars.len()))
+            }
+            IFuncAbs(i) => unary!(i, |x| x.abs(), if x==0.0 { 0.0 } else { x.signum() }),
+            IFuncLog{base, of} => {
+                // log_b(x) = ln(x)/ln(b)
+                let b = self.ic(base)?;
+                let x = self.ic(of)?;
+                let (bv, xv) = (b.val, x.val);
+                let lnb = bv.ln();
+                Ok(x.chain2(&b, log(bv,xv), 1.0/(xv*lnb), -xv.ln()/(bv*lnb*lnb)))
+            }
+            IFuncMin(li, ric) | IFuncMax(li, ric) => {
+                let l = self.instr(get_instr!(self.slab.cs,li))?;
+                let r = self.ic(ric)?;
+                if l.val.is_nan() || r.val.is_nan() {
+                    return Ok(Dual{ val:std::f64::NAN, grad:vec![std::f64::NAN; self.vars.len()] });
+                }
+                let take_right = if let IFuncMin(..) = instr { r.val<l.val } else { r.val>l.val };
+                Ok(if take_right { r } else { l })
+            }
+
+            IFuncSin(i) => unary!(i, |x| x.sin(), x.cos()),
+            IFuncCos(i) => unary!(i, |x| x.cos(), -x.sin()),
+            IFuncTan(i) => unary!(i, |x| x.tan(), 1.0/(x.cos()*x.cos())),
+            IFuncASin(i) => unary!(i, |x| x.asin(), 1.0/(1.0-x*x).sqrt()),
+            IFuncACos(i) => unary!(i, |x| x.acos(), -1.0/(1.0-x*x).sqrt()),
+            IFuncATan(i) => unary!(i, |x| x.atan(), 1.0/(1.0+x*x)),
+            IFuncSinH(i) => unary!(i, |x| x.sinh(), x.cosh()),
+            IFuncCosH(i) => unary!(i, |x| x.cosh(), x.sinh()),
+            IFuncTanH(i) => unary!(i, |x| x.tanh(), 1.0/(x.cosh()*x.cosh())),
+            IFuncASinH(i) => unary!(i, |x| x.asinh(), 1.0/(x*x+1.0).sqrt()),
+            IFuncACosH(i) => unary!(i, |x| x.acosh(), 1.0/(x*x-1.0).sqrt()),
+            IFuncATanH(i) => unary!(i, |x| x.atanh(), 1.0/(1.0-x*x)),
+        }
+    }
+}
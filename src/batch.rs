@@ -0,0 +1,343 @@
+//! This module evaluates a compiled `Instruction` over whole columns of input data.
+//!
+//! Calling `eval()` once per row repeats the instruction traversal and the
+//! variable lookups for every row.  `eval_batch()` instead processes each
+//! instruction node across a chunk of rows at a time, so the traversal cost is
+//! shared by the whole chunk and the inner loops are simple enough for the
+//! compiler to vectorize.
+//!
+//! The input columns are bound to variable slots: column `i` holds the values
+//! of slot `i` (see [`CompileSlab::add_slot()`](../slab/struct.CompileSlab.html#method.add_slot)).
+//! Slots without a column, other variables, and custom functions are resolved
+//! with a normal `EvalNamespace`.  Variables are looked up once per chunk, so their values
+//! should not change during the call.  Custom functions are called once per row.
+//!
+//! Short-circuit operators (`&&`, `||`, and `? :`) only evaluate their right
+//! side (or taken branch) for the rows that need it, just like `eval()`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     slab.cs.add_slot("price".to_string());
+//!     slab.cs.add_slot("qty".to_string());
+//!
+//!     let instr = fasteval::Parser::new().parse("price * qty * (1 + tax)", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!
+//!     let price = [1.0, 2.0, 3.0];
+//!     let qty = [10.0, 20.0, 30.0];
+//!     let mut out = [0.0; 3];
+//!     let mut ns = |name:&str, _:Vec<f64>| if name=="tax" { Some(0.5) } else { None };
+//!     fasteval::eval_batch(&instr, &slab, &[&price, &qty], &mut out, &mut ns)?;
+//!
+//!     assert_eq!(out, [15.0, 60.0, 135.0]);
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evaler::Evaler;
+use crate::evalns::EvalNamespace;
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, bitnot, store_local, IC, InstructionI, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+
+// Big enough to amortize the traversal, small enough that the temporary
+// buffers stay in the L1 cache:
+const CHUNK:usize = 256;
+
+/// Evaluates `instr` for every row of `columns`, and writes the results to `out`.
+///
+/// `columns[i]` provides the values of variable slot `i`.  Each column must
+/// have at least `out.len()` rows.
+///
+/// [See the `batch` module documentation.](index.html)
+pub fn eval_batch(instr:&Instruction, slab:&Slab, columns:&[&[f64]], out:&mut [f64], ns:&mut impl EvalNamespace) -> Result<(),Error> {
+    for (i,col) in columns.iter().enumerate() {
+        if col.len()<out.len() {
+            return Err(Error::WrongArgs(format!("column {} has {} rows, but {} are needed", i, col.len(), out.len())));
+        }
+    }
+
+    let mut batch = Batch{ slab, columns, row:0, ns, bufs:Vec::new(), keybuf:String::new(), base:0, locals:Vec::new(), names:Vec::new() };
+    if let IProgram{names, ..} = instr { batch.names = names.clone(); }
+    for (i,chunk) in out.chunks_mut(CHUNK).enumerate() {
+        batch.row = i*CHUNK;
+        batch.instr(instr, chunk)?;
+    }
+    Ok(())
+}
+
+struct Batch<'a,NS> {
+    slab   :&'a Slab,
+    columns:&'a [&'a [f64]],
+    row    :usize,  // The first row of the rows that are currently being evaluated.
+    ns     :&'a mut NS,
+    bufs   :Vec<Vec<f64>>,  // Re-use temporary buffers to avoid allocations.
+    keybuf :String,
+    base   :usize,  // The first row of the rows that 'locals' holds.
+    locals :Vec<Vec<f64>>,  // The arguments of the user-defined function whose body is being evaluated, or the variables of the program.
+    names  :Vec<String>,  // The names of the local variables, for print().
+}
+
+macro_rules! unary {
+    ($batch:ident, $i:ident, $dst:ident, $f:expr) => {{
+        $batch.instr(get_instr!($batch.slab.cs,$i), $dst)?;
+        for x in $dst.iter_mut() { *x = $f(*x); }
+    }};
+}
+macro_rules! binary {
+    ($batch:ident, $lic:expr, $ric:expr, $dst:ident, $f:expr) => {{
+        $batch.ic($lic, $dst)?;
+        let mut right = $batch.buf($dst.len());
+        $batch.ic($ric, &mut right)?;
+        for (l,r) in $dst.iter_mut().zip(right.iter()) { *l = $f(*l,*r); }
+        $batch.bufs.push(right);
+    }};
+}
+// Like unary!() and binary!(), for operations that can fail:
+macro_rules! try_unary {
+    ($batch:ident, $i:ident, $dst:ident, $f:expr) => {{
+        $batch.instr(get_instr!($batch.slab.cs,$i), $dst)?;
+        for x in $dst.iter_mut() { *x = $f(*x)?; }
+    }};
+}
+macro_rules! try_binary {
+    ($batch:ident, $lic:expr, $ric:expr, $dst:ident, $f:expr) => {{
+        $batch.ic($lic, $dst)?;
+        let mut right = $batch.buf($dst.len());
+        $batch.ic($ric, &mut right)?;
+        for (l,r) in $dst.iter_mut().zip(right.iter()) { *l = $f(*l,*r)?; }
+        $batch.bufs.push(right);
+    }};
+}
+
+impl<NS> Batch<'_,NS> where NS:EvalNamespace {
+    fn buf(&mut self, len:usize) -> Vec<f64> {
+        let mut buf = self.bufs.pop().unwrap_or_default();
+        buf.clear();
+        buf.resize(len, 0.0);
+        buf
+    }
+
+    #[inline]
+    fn ic(&mut self, ic:&IC, dst:&mut [f64]) -> Result<(),Error> {
+        match ic {
+            IC::C(c) => { for x in dst.iter_mut() { *x = *c; } Ok(()) }
+            IC::I(i) => self.instr(get_instr!(self.slab.cs,i), dst),
+        }
+    }
+
+    // Evaluates `ic` only for the rows where `pick(flags[row])` is true.
+    // Consecutive rows are evaluated together.
+    fn branch(&mut self, ic:&IC, flags:&[f64], pick:impl Fn(f64)->bool, dst:&mut [f64]) -> Result<(),Error> {
+        let first = self.row;
+        let mut start = 0;
+        while start<flags.len() {
+            if !pick(flags[start]) { start+=1; continue; }
+            let mut end = start+1;
+            while end<flags.len() && pick(flags[end]) { end+=1; }
+
+            self.row = first+start;
+            let res = self.ic(ic, &mut dst[start..end]);
+            self.row = first;
+            res?;
+
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr:&Instruction, dst:&mut [f64]) -> Result<(),Error> {
+        match instr {
+            IConst(c) => for x in dst.iter_mut() { *x = *c; },
+            ISlot{name, slot} => {
+                match self.columns.get(*slot) {
+                    Some(col) => dst.copy_from_slice(&col[self.row..self.row+dst.len()]),
+                    // There is no column for this slot, so look it up by name:
+                    None => match self.ns.lookup(name, Vec::new(), &mut self.keybuf) {
+                        Some(val) => for x in dst.iter_mut() { *x = val; },
+                        None => return Err(Error::Undefined(name.to_string())),
+                    }
+                }
+            }
+            ILocal(i) => {
+                match self.locals.get(*i) {
+                    Some(col) => dst.copy_from_slice(&col[self.row-self.base..self.row-self.base+dst.len()]),
+                    None => return Err(Error::Unreachable),
+                }
+            }
+            ISafeVar{var, ..} => { let val=var.get(); for x in dst.iter_mut() { *x = val; } }
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => { let val=unsafe { **ptr }; for x in dst.iter_mut() { *x = val; } }
+            IVar(name) => {
+                match self.ns.lookup(name, Vec::new(), &mut self.keybuf) {
+                    Some(val) => for x in dst.iter_mut() { *x = val; },
+                    None => return Err(Error::Undefined(name.to_string())),
+                }
+            }
+            IFunc{name, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    let mut arg = self.buf(dst.len());
+                    self.ic(ic, &mut arg)?;
+                    args.push(arg);
+                }
+                for (r,x) in dst.iter_mut().enumerate() {
+                    match self.ns.lookup(name, args.iter().map(|arg| arg[r]).collect(), &mut self.keybuf) {
+                        Some(val) => *x = val,
+                        None => return Err(Error::Undefined(name.to_string())),
+                    }
+                }
+                self.bufs.append(&mut args);
+            }
+            IPrintFunc(pf) => {
+                for (r,x) in dst.iter_mut().enumerate() {
+                    let mut ns = RowNamespace{ slab:self.slab, columns:self.columns, row:self.row+r, ns:&mut *self.ns,
+                                               names:&self.names[..self.locals.len()], locals:&self.locals, local_row:self.row-self.base+r };
+                    *x = pf.eval(self.slab, &mut ns)?;
+                }
+            }
+
+            INeg(i) => unary!(self, i, dst, |x:f64| -x),
+            INot(i) => unary!(self, i, dst, |x:f64| bool_to_f64!(f64_eq!(x,0.0))),
+            IInv(i) => unary!(self, i, dst, |x:f64| 1.0/x),
+            IBitNot(i) => try_unary!(self, i, dst, bitnot),
+
+            IAdd(li, ric) => binary!(self, &IC::I(*li), ric, dst, |l,r| l+r),
+            IMul(li, ric) => binary!(self, &IC::I(*li), ric, dst, |l,r| l*r),
+            IMod{dividend, divisor} => binary!(self, dividend, divisor, dst, |l,r| l%r),
+            IExp{base, power} => binary!(self, base, power, dst, |l:f64,r| l.powf(r)),
+            ILT(l, r) => binary!(self, l, r, dst, |l,r| bool_to_f64!(l<r)),
+            ILTE(l, r) => binary!(self, l, r, dst, |l,r| bool_to_f64!(l<=r)),
+            IEQ(l, r) => binary!(self, l, r, dst, |l:f64,r:f64| bool_to_f64!(f64_eq!(l,r))),
+            INE(l, r) => binary!(self, l, r, dst, |l:f64,r:f64| bool_to_f64!(f64_ne!(l,r))),
+            IGTE(l, r) => binary!(self, l, r, dst, |l,r| bool_to_f64!(l>=r)),
+            IGT(l, r) => binary!(self, l, r, dst, |l,r| bool_to_f64!(l>r)),
+            IBitOr(l, r) => try_binary!(self, l, r, dst, bitor),
+            IBitXor(l, r) => try_binary!(self, l, r, dst, bitxor),
+            IBitAnd(l, r) => try_binary!(self, l, r, dst, bitand),
+            IShl(l, r) => try_binary!(self, l, r, dst, shl),
+            IShr(l, r) => try_binary!(self, l, r, dst, shr),
+
+            IFuncInt(i) => unary!(self, i, dst, f64::trunc),
+            IFuncCeil(i) => unary!(self, i, dst, f64::ceil),
+            IFuncFloor(i) => unary!(self, i, dst, f64::floor),
+            IFuncAbs(i) => unary!(self, i, dst, f64::abs),
+            IFuncSign(i) => unary!(self, i, dst, f64::signum),
+            IFuncLog{base, of} => binary!(self, base, of, dst, log),
+            IFuncRound{modulus, of} => binary!(self, modulus, of, dst, |modulus:f64,of:f64| (of/modulus).round() * modulus),
+            IFuncMin(li, ric) => binary!(self, &IC::I(*li), ric, dst, |l:f64,r:f64| if l.is_nan() || r.is_nan() { std::f64::NAN } else if l<r { l } else { r }),
+            IFuncMax(li, ric) => binary!(self, &IC::I(*li), ric, dst, |l:f64,r:f64| if l.is_nan() || r.is_nan() { std::f64::NAN } else if l>r { l } else { r }),
+            IFuncSin(i) => unary!(self, i, dst, f64::sin),
+            IFuncCos(i) => unary!(self, i, dst, f64::cos),
+            IFuncTan(i) => unary!(self, i, dst, f64::tan),
+            IFuncASin(i) => unary!(self, i, dst, f64::asin),
+            IFuncACos(i) => unary!(self, i, dst, f64::acos),
+            IFuncATan(i) => unary!(self, i, dst, f64::atan),
+            IFuncSinH(i) => unary!(self, i, dst, f64::sinh),
+            IFuncCosH(i) => unary!(self, i, dst, f64::cosh),
+            IFuncTanH(i) => unary!(self, i, dst, f64::tanh),
+            IFuncASinH(i) => unary!(self, i, dst, f64::asinh),
+            IFuncACosH(i) => unary!(self, i, dst, f64::acosh),
+            IFuncATanH(i) => unary!(self, i, dst, f64::atanh),
+
+            IAND(li, ric) => {
+                let left = self.left(*li, dst)?;
+                self.branch(ric, &left, |x| f64_ne!(x,0.0), dst)?;
+                self.bufs.push(left);
+            }
+            IOR(li, ric) => {
+                let left = self.left(*li, dst)?;
+                self.branch(ric, &left, |x| f64_eq!(x,0.0), dst)?;
+                self.bufs.push(left);
+            }
+            IFuncIf{cond, then, otherwise} => {
+                let mut conds = self.buf(dst.len());
+                self.instr(get_instr!(self.slab.cs,cond), &mut conds)?;
+                self.branch(then, &conds, |x| f64_ne!(x,0.0), dst)?;
+                self.branch(otherwise, &conds, |x| f64_eq!(x,0.0), dst)?;
+                self.bufs.push(conds);
+            }
+            ICall{names, args:ics, body, ..} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics {
+                    let mut arg = self.buf(dst.len());
+                    self.ic(ic, &mut arg)?;
+                    args.push(arg);
+                }
+                // The body gets a new frame of locals, one column per argument:
+                let base = std::mem::replace(&mut self.base, self.row);
+                let mut locals = std::mem::replace(&mut self.locals, args);
+                let names = std::mem::replace(&mut self.names, names.clone());
+                let res = self.instr(get_instr!(self.slab.cs,body), dst);
+                self.base = base;
+                std::mem::swap(&mut self.locals, &mut locals);
+                self.names = names;
+                self.bufs.append(&mut locals);
+                res?;
+            }
+            IProgram{stmts, ..} => {
+                // The last statement leaves its value in 'dst':
+                self.base = self.row;
+                for (slot,ic) in stmts {
+                    self.ic(ic, dst)?;
+                    if slot.is_some() {
+                        let mut col = self.buf(dst.len());
+                        col.copy_from_slice(dst);
+                        store_local(&mut self.locals, *slot, col);
+                    }
+                }
+                let locals = std::mem::take(&mut self.locals);
+                self.bufs.extend(locals);
+            }
+        }
+        Ok(())
+    }
+
+    // Evaluates the left side of a short-circuit operator into `dst`, and also returns a copy of it.
+    fn left(&mut self, li:InstructionI, dst:&mut [f64]) -> Result<Vec<f64>,Error> {
+        self.instr(get_instr!(self.slab.cs,li), dst)?;
+        let mut left = self.buf(dst.len());
+        left.copy_from_slice(dst);
+        Ok(left)
+    }
+}
+
+// Used to evaluate `print()` for a single row.  The parsed expressions inside
+// `print()` refer to variables by name, so local and slot names are resolved here.
+struct RowNamespace<'a,NS> {
+    slab     :&'a Slab,
+    columns  :&'a [&'a [f64]],
+    row      :usize,
+    ns       :&'a mut NS,
+    names    :&'a [String],
+    locals   :&'a [Vec<f64>],
+    local_row:usize,
+}
+
+impl<NS> EvalNamespace for RowNamespace<'_,NS> where NS:EvalNamespace {
+    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        if args.is_empty() {
+            if let Some(i) = self.names.iter().position(|n| n==name) {
+                return Some(self.locals[i][self.local_row]);
+            }
+            if let Some(val) = self.slab.cs.slot_index(name).and_then(|i| self.slot(i)) {
+                return Some(val);
+            }
+        }
+        self.ns.lookup(name, args, keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<f64> {
+        self.columns.get(i).map(|col| col[self.row])
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.ns.print(line)
+    }
+}
@@ -0,0 +1,258 @@
+//! This module limits the work that an evaluation can do.
+//!
+//! `Parser`'s `expr_len_limit` and `expr_depth_limit` protect the parser from
+//! malicious inputs.  A `Budget` protects evaluation in the same way, which is
+//! useful when a server evaluates expressions for many untrusted users, or
+//! when custom functions are expensive:
+//!
+//! * `eval_step_limit` limits the number of evaluation steps.  Every
+//!   `Expression`, operator, function and `print()` of an interpreted
+//!   expression is one step, and so is every `Instruction` of a compiled
+//!   expression, except for the constants and Safe Variables that are inlined
+//!   into the `Instruction` that uses them.
+//! * `ns_call_limit` limits the number of Namespace lookups (of variables and
+//!   custom functions).  Reads of slots are not counted.
+//! * `cancel` is a flag that can be set by another thread (for example, by a
+//!   timer) to stop the evaluation.  It is checked before the evaluation
+//!   starts, before every Namespace lookup, and every 1024 steps.
+//!
+//! `eval_budgeted()` returns `Error::BudgetExceeded` if a limit is exceeded,
+//! and `Error::Cancelled` if the flag is set.  `eval_traced_budgeted()` also
+//! records how the result was computed, like `eval_traced()`.
+//!
+//! Only parsed `Expression`s (and their parts) and compiled `Instruction`s
+//! can be limited.  The other evaluators (`Bytecode`, `eval_batch()`,
+//! `CompiledExpr`, `eval_complex()`, `eval_interval()` and `eval_grad()`)
+//! can't be stopped, so they are not accepted.  A plain `eval()` is never
+//! limited, and it doesn't pay for the checks.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::{Budget, Error, eval_budgeted};
+//! use std::sync::Arc;
+//! use std::sync::atomic::{AtomicBool, Ordering};
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut ns = |name:&str, _args:Vec<f64>| -> Option<f64> {
+//!         match name {
+//!             "x" => Some(2.0),
+//!             _ => None,
+//!         }
+//!     };
+//!     let expr = fasteval::Parser::new().parse("x * (x + 1)", &mut slab.ps)?.from(&slab.ps);
+//!
+//!     let mut budget = Budget::new();
+//!     budget.ns_call_limit = 2;
+//!     assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Ok(6.0));
+//!     budget.ns_call_limit = 1;
+//!     assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Err(Error::BudgetExceeded));
+//!
+//!     // The flag would usually be set by another thread:
+//!     let cancel = Arc::new(AtomicBool::new(false));
+//!     let budget = Budget{ cancel:Some(Arc::clone(&cancel)), ..Budget::new() };
+//!     assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Ok(6.0));
+//!     cancel.store(true, Ordering::Relaxed);
+//!     assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Err(Error::Cancelled));
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evaler::Evaler;
+use crate::evalns::EvalNamespace;
+use crate::hooks::{Hooks, HookedEvaler};
+use crate::trace::{Trace, Tracer};
+
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The cancellation flag is checked this often, so that it doesn't slow down cheap steps.
+// It must be a power of 2:
+const CANCEL_CHECK_INTERVAL : usize = 1024;
+
+/// The limits of an evaluation by [`eval_budgeted()`](fn.eval_budgeted.html).
+///
+/// `Budget::new()` has no limits.  The same `Budget` can be used for any
+/// number of evaluations; each one starts with the full budget.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    /// The maximum number of evaluation steps.
+    pub eval_step_limit:usize,
+    /// The maximum number of Namespace lookups.
+    pub ns_call_limit  :usize,
+    /// If this flag becomes `true`, the evaluation is cancelled.
+    pub cancel         :Option<Arc<AtomicBool>>,
+}
+
+impl Budget {
+    #[inline]
+    pub const fn new() -> Self { Self{eval_step_limit:usize::MAX,
+                                      ns_call_limit:usize::MAX,
+                                      cancel:None} }
+}
+
+impl Default for Budget {
+    #[inline]
+    fn default() -> Self { Self::new() }
+}
+
+/// Evaluates an `Expression` or an `Instruction`, and stops if it exceeds the
+/// `Budget`.
+///
+/// Returns `Error::BudgetExceeded` if the evaluation takes too many steps or
+/// Namespace lookups, and `Error::Cancelled` if the cancellation flag is set.
+/// Otherwise, the result is the same as the result of `eval()`.
+pub fn eval_budgeted(evaler:&(impl Evaler + HookedEvaler), slab:&Slab, ns:&mut impl EvalNamespace, budget:&Budget) -> Result<f64,Error> {
+    let meter = Meter::new(budget);
+    if meter.cancelled() { return Err(Error::Cancelled); }
+    let val = evaler.eval_hooked(slab, &mut BudgetNamespace{ ns, meter:&meter }, &mut &meter);
+    meter.result(val)
+}
+
+/// Evaluates an `Expression` or an `Instruction` like
+/// [`eval_budgeted()`](fn.eval_budgeted.html), and records how the result was
+/// computed like [`eval_traced()`](../trace/fn.eval_traced.html).
+///
+/// If the evaluation is stopped, the value of the returned `Trace` is the
+/// error, and the steps that were completed before it was stopped are still
+/// recorded.
+pub fn eval_traced_budgeted(evaler:&(impl Evaler + HookedEvaler), slab:&Slab, ns:&mut impl EvalNamespace, budget:&Budget) -> Trace {
+    let meter = Meter::new(budget);
+    let mut hooks = TracedMeter{ tracer:Tracer::new(), meter:&meter };
+    let val = if meter.cancelled() {
+        Err(Error::Cancelled)
+    } else {
+        evaler.eval_hooked(slab, &mut BudgetNamespace{ ns, meter:&meter }, &mut hooks)
+    };
+    hooks.tracer.finish(meter.result(val))
+}
+
+// Counts the steps and the lookups of an evaluation.  The steps are counted
+// by the evaluators through the Hooks, and the lookups by a BudgetNamespace,
+// so they share the counters:
+struct Meter<'a> {
+    budget :&'a Budget,
+    steps  :Cell<usize>,
+    calls  :Cell<usize>,
+    // A lookup can't return an error, so it returns None (which the evaluator
+    // reports as an undefined variable), and the real error is kept here.
+    // step() keeps its error here too, so that the evaluators only have to
+    // check a bool, which keeps their stack frames small:
+    stopped:Cell<Option<Error>>,
+}
+
+impl<'a> Meter<'a> {
+    #[inline]
+    fn new(budget:&'a Budget) -> Self {
+        Meter{ budget, steps:Cell::new(0), calls:Cell::new(0), stopped:Cell::new(None) }
+    }
+
+    #[inline]
+    fn cancelled(&self) -> bool {
+        match &self.budget.cancel {
+            Some(flag) => flag.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    #[inline]
+    fn is_stopped(&self) -> bool {
+        self.stopped_by().is_some()
+    }
+
+    fn stopped_by(&self) -> Option<Error> {
+        let stopped = self.stopped.take();
+        self.stopped.set(stopped.clone());
+        stopped
+    }
+
+    #[inline]
+    fn stop(&self, err:Error) -> bool {
+        self.stopped.set(Some(err));
+        false
+    }
+
+    // Returns false if the lookup must not be performed:
+    #[inline]
+    fn call(&self) -> bool {
+        if self.is_stopped() { return false; }
+        self.calls.set(self.calls.get()+1);
+        if self.calls.get()>self.budget.ns_call_limit { return self.stop(Error::BudgetExceeded); }
+        if self.cancelled() { return self.stop(Error::Cancelled); }
+        true
+    }
+
+    // The reason for stopping replaces the error that the evaluator returned:
+    #[inline]
+    fn result(&self, val:Result<f64,Error>) -> Result<f64,Error> {
+        match self.stopped.take() {
+            Some(err) => Err(err),
+            None => val,
+        }
+    }
+}
+
+impl Hooks for &Meter<'_> {
+    #[inline]
+    fn step(&mut self) -> bool {
+        if self.is_stopped() { return false; }
+        self.steps.set(self.steps.get()+1);
+        if self.steps.get()>self.budget.eval_step_limit { return self.stop(Error::BudgetExceeded); }
+        if self.steps.get()&(CANCEL_CHECK_INTERVAL-1)==0 && self.cancelled() { return self.stop(Error::Cancelled); }
+        true
+    }
+}
+
+// Records the steps like eval_traced(), but a step that was stopped is
+// recorded with the reason, instead of the error that the evaluator returned
+// (like an undefined variable for a lookup that was not performed):
+struct TracedMeter<'a> {
+    tracer:Tracer,
+    meter :&'a Meter<'a>,
+}
+
+impl Hooks for TracedMeter<'_> {
+    #[inline]
+    fn step(&mut self) -> bool { (&mut self.meter).step() }
+    fn tracing(&self) -> bool { true }
+    fn trace_enter(&mut self) { self.tracer.trace_enter() }
+    fn trace_arg(&mut self, val:f64) { self.tracer.trace_arg(val) }
+    fn trace_exit(&mut self, label:&dyn Fn(&[Trace],&[f64])->String, val:&Result<f64,Error>) {
+        match self.meter.stopped_by() {
+            Some(err) => self.tracer.trace_exit(label, &Err(err)),
+            None => self.tracer.trace_exit(label, val),
+        }
+    }
+}
+
+// Counts the lookups, and forwards everything to the Namespace that the user
+// provided:
+struct BudgetNamespace<'a,NS> {
+    ns   :&'a mut NS,
+    meter:&'a Meter<'a>,
+}
+
+impl<NS> EvalNamespace for BudgetNamespace<'_,NS> where NS:EvalNamespace {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        if !self.meter.call() { return None; }
+        self.ns.lookup(name,args,keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<f64> {
+        self.ns.slot(i)
+    }
+    #[inline]
+    fn lookup_global(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        if !self.meter.call() { return None; }
+        self.ns.lookup_global(name,args,keybuf)
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.ns.print(line)
+    }
+}
@@ -0,0 +1,457 @@
+//! This module lowers compiled `Instruction` trees into flat, stack-based `Bytecode`.
+//!
+//! The tree evaluator follows an `InstructionI` index into the `CompileSlab`
+//! for every node, which costs a function call and a bounds check per node.
+//! `Bytecode` stores the same program as a linear list of `Op`s that is
+//! executed by a single dispatch loop with a small value stack.  This helps
+//! most for large expressions with many variables, where the tree evaluator
+//! spends most of its time on traversal.
+//!
+//! `Bytecode` implements the `Evaler` trait, so it can be used anywhere that
+//! an `Instruction` can.  It produces exactly the same results as the tree
+//! evaluator, including short-circuit behavior.  (One small difference:
+//! `var_names()` also reports the variables used in the arguments of custom
+//! functions, because they appear in the flat op list.)
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut map = fasteval::StringToF64Namespace::new();
+//!     map.insert("x".to_string(), 2.0);
+//!
+//!     let instr = fasteval::Parser::new().parse("x^2 + 3*x + 1", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     let bytecode = fasteval::Bytecode::new(&instr, &slab);
+//!
+//!     assert_eq!(bytecode.eval(&slab, &mut map)?, 11.0);
+//!     assert_eq!(bytecode.eval(&slab, &mut map)?, instr.eval(&slab, &mut map)?);
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evaler::Evaler;
+use crate::evalns::{EvalNamespace, LocalNamespace, SafeVar};
+use crate::parser::PrintFunc;
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, bitnot, store_local, IC, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+
+use std::collections::BTreeSet;
+
+/// A single `Bytecode` operation.
+///
+/// Operations pop their operands from the value stack and push their result.
+/// Jump targets are indexes into `Bytecode.ops`.
+#[derive(Debug, PartialEq)]
+pub enum Op {
+    //---- Values:
+    Const(f64),
+    Var(String),
+    Slot{name:String, slot:usize},
+    Local(usize),
+    SafeVar{name:String, var:SafeVar},
+    #[cfg(feature="unsafe-vars")]
+    UnsafeVar{name:String, ptr:*const f64},
+    Func{name:String, nargs:usize},
+    Print(PrintFunc),
+
+    //---- Unary Ops:
+    Neg,
+    Not,
+    Inv,
+    BitNot,
+
+    //---- Binary Ops:
+    Add,
+    Mul,
+    Mod,
+    Exp,
+    LT,
+    LTE,
+    EQ,
+    NE,
+    GTE,
+    GT,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+
+    //---- Built-in Functions:
+    Int,
+    Ceil,
+    Floor,
+    Abs,
+    Sign,
+    Log,
+    Round,
+    Min,
+    Max,
+    Sin,
+    Cos,
+    Tan,
+    ASin,
+    ACos,
+    ATan,
+    SinH,
+    CosH,
+    TanH,
+    ASinH,
+    ACosH,
+    ATanH,
+
+    //---- Control Flow:
+    /// Jump if the top of the stack is true (and keep it), otherwise pop it.  Used by `||`.
+    JumpIfTrueOrPop(usize),
+    /// Jump if the top of the stack is false (and keep it), otherwise pop it.  Used by `&&`.
+    JumpIfFalseOrPop(usize),
+    /// Pop the top of the stack, and jump if it is false.  Used by `? :`.
+    JumpIfFalse(usize),
+    Jump(usize),
+
+    //---- User-defined Functions:
+    /// Pop one argument for each parameter name into a new frame of `Local`s.
+    Enter(Vec<String>),
+    /// Discard the current frame of `Local`s.
+    Leave,
+
+    //---- Programs:
+    /// Pop the top of the stack into a local variable.
+    Store(usize),
+    /// Discard the top of the stack.
+    Pop,
+}
+use Op::*;
+
+/// A compiled `Instruction`, lowered into a flat list of `Op`s.
+///
+/// [See the `bytecode` module documentation.](index.html)
+#[derive(Debug, PartialEq)]
+pub struct Bytecode {
+    ops      :Vec<Op>,
+    max_depth:usize,
+    names    :Vec<String>,  // The names of the local variables, for print().
+}
+
+// Most expressions need only a few stack entries, so we keep the stack on the
+// Rust stack to avoid a memory allocation for every evaluation:
+const INLINE_STACK:usize = 32;
+
+struct Lowerer {
+    ops  :Vec<Op>,
+    depth:usize,
+    max  :usize,
+    names:Vec<String>,
+}
+
+impl Lowerer {
+    #[inline]
+    fn emit(&mut self, op:Op, pops:usize, pushes:usize) {
+        self.depth = self.depth - pops + pushes;
+        if self.depth>self.max { self.max=self.depth; }
+        self.ops.push(op);
+    }
+    #[inline]
+    fn patch(&mut self, at:usize) {
+        let target = self.ops.len();
+        match &mut self.ops[at] {
+            JumpIfTrueOrPop(t) | JumpIfFalseOrPop(t) | JumpIfFalse(t) | Jump(t) => *t = target,
+            _ => (),
+        }
+    }
+
+    fn ic(&mut self, ic:&IC, slab:&Slab) {
+        match ic {
+            IC::C(c) => self.emit(Const(*c), 0, 1),
+            IC::I(i) => self.instr(slab.cs.get_instr(*i), slab),
+        }
+    }
+    fn unary(&mut self, i:crate::compiler::InstructionI, op:Op, slab:&Slab) {
+        self.instr(slab.cs.get_instr(i), slab);
+        self.emit(op, 1, 1);
+    }
+    fn binary(&mut self, l:&IC, r:&IC, op:Op, slab:&Slab) {
+        self.ic(l, slab);
+        self.ic(r, slab);
+        self.emit(op, 2, 1);
+    }
+
+    fn instr(&mut self, instr:&Instruction, slab:&Slab) {
+        match instr {
+            IConst(c) => self.emit(Const(*c), 0, 1),
+            IVar(name) => self.emit(Var(name.clone()), 0, 1),
+            ISlot{name, slot} => self.emit(Slot{name:name.clone(), slot:*slot}, 0, 1),
+            ILocal(i) => self.emit(Local(*i), 0, 1),
+            ISafeVar{name, var} => self.emit(Op::SafeVar{name:name.clone(), var:var.clone()}, 0, 1),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{name, ptr} => self.emit(UnsafeVar{name:name.clone(), ptr:*ptr}, 0, 1),
+            IFunc{name, args} => {
+                for ic in args { self.ic(ic, slab); }
+                self.emit(Func{name:name.clone(), nargs:args.len()}, args.len(), 1);
+            }
+            IPrintFunc(pf) => self.emit(Print(pf.clone()), 0, 1),
+
+            INeg(i) => self.unary(*i, Neg, slab),
+            INot(i) => self.unary(*i, Not, slab),
+            IInv(i) => self.unary(*i, Inv, slab),
+            IBitNot(i) => self.unary(*i, BitNot, slab),
+
+            IAdd(li, ric) => self.binary(&IC::I(*li), ric, Add, slab),
+            IMul(li, ric) => self.binary(&IC::I(*li), ric, Mul, slab),
+            IMod{dividend, divisor} => self.binary(dividend, divisor, Mod, slab),
+            IExp{base, power} => self.binary(base, power, Exp, slab),
+            ILT(l, r) => self.binary(l, r, LT, slab),
+            ILTE(l, r) => self.binary(l, r, LTE, slab),
+            IEQ(l, r) => self.binary(l, r, EQ, slab),
+            INE(l, r) => self.binary(l, r, NE, slab),
+            IGTE(l, r) => self.binary(l, r, GTE, slab),
+            IGT(l, r) => self.binary(l, r, GT, slab),
+            IBitOr(l, r) => self.binary(l, r, BitOr, slab),
+            IBitXor(l, r) => self.binary(l, r, BitXor, slab),
+            IBitAnd(l, r) => self.binary(l, r, BitAnd, slab),
+            IShl(l, r) => self.binary(l, r, Shl, slab),
+            IShr(l, r) => self.binary(l, r, Shr, slab),
+
+            IFuncInt(i) => self.unary(*i, Int, slab),
+            IFuncCeil(i) => self.unary(*i, Ceil, slab),
+            IFuncFloor(i) => self.unary(*i, Floor, slab),
+            IFuncAbs(i) => self.unary(*i, Abs, slab),
+            IFuncSign(i) => self.unary(*i, Sign, slab),
+            IFuncLog{base, of} => self.binary(base, of, Log, slab),
+            IFuncRound{modulus, of} => self.binary(modulus, of, Round, slab),
+            IFuncMin(li, ric) => self.binary(&IC::I(*li), ric, Min, slab),
+            IFuncMax(li, ric) => self.binary(&IC::I(*li), ric, Max, slab),
+            IFuncSin(i) => self.unary(*i, Sin, slab),
+            IFuncCos(i) => self.unary(*i, Cos, slab),
+            IFuncTan(i) => self.unary(*i, Tan, slab),
+            IFuncASin(i) => self.unary(*i, ASin, slab),
+            IFuncACos(i) => self.unary(*i, ACos, slab),
+            IFuncATan(i) => self.unary(*i, ATan, slab),
+            IFuncSinH(i) => self.unary(*i, SinH, slab),
+            IFuncCosH(i) => self.unary(*i, CosH, slab),
+            IFuncTanH(i) => self.unary(*i, TanH, slab),
+            IFuncASinH(i) => self.unary(*i, ASinH, slab),
+            IFuncACosH(i) => self.unary(*i, ACosH, slab),
+            IFuncATanH(i) => self.unary(*i, ATanH, slab),
+
+            IOR(li, ric) | IAND(li, ric) => {
+                self.instr(slab.cs.get_instr(*li), slab);
+                let jump = self.ops.len();
+                if let IOR(..) = instr {
+                    self.emit(JumpIfTrueOrPop(0), 1, 0);
+                } else {
+                    self.emit(JumpIfFalseOrPop(0), 1, 0);
+                }
+                self.ic(ric, slab);
+                self.patch(jump);
+            }
+            IFuncIf{cond, then, otherwise} => {
+                self.instr(slab.cs.get_instr(*cond), slab);
+                let jump_otherwise = self.ops.len();
+                self.emit(JumpIfFalse(0), 1, 0);
+                self.ic(then, slab);
+                let jump_end = self.ops.len();
+                self.emit(Jump(0), 1, 0);  // Only one of the branches pushes a value.
+                self.patch(jump_otherwise);
+                self.ic(otherwise, slab);
+                self.patch(jump_end);
+            }
+            ICall{names, args, body, ..} => {
+                for ic in args { self.ic(ic, slab); }
+                self.emit(Enter(names.clone()), args.len(), 0);
+                self.instr(slab.cs.get_instr(*body), slab);
+                self.emit(Leave, 0, 0);
+            }
+            IProgram{names, stmts} => {
+                self.names = names.clone();
+                for (k,(slot,ic)) in stmts.iter().enumerate() {
+                    self.ic(ic, slab);
+                    let last = k+1==stmts.len();  // The last value is the result.
+                    match slot {
+                        Some(i) => {
+                            self.emit(Store(*i), 1, 0);
+                            if last { self.emit(Local(*i), 0, 1); }
+                        }
+                        None => if !last { self.emit(Pop, 1, 0); },
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Bytecode {
+    /// Lowers a compiled `Instruction` (and the `Slab` data it refers to) into `Bytecode`.
+    pub fn new(instr:&Instruction, slab:&Slab) -> Self {
+        let mut lowerer = Lowerer{ ops:Vec::new(), depth:0, max:0, names:Vec::new() };
+        lowerer.instr(instr, slab);
+        let mut ops = lowerer.ops;
+        ops.shrink_to_fit();
+        Bytecode{ ops, max_depth:lowerer.max, names:lowerer.names }
+    }
+
+    /// Returns the list of operations.
+    #[inline]
+    pub fn ops(&self) -> &[Op] { &self.ops }
+
+    fn run(&self, stack:&mut [f64], slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+        let ops = &self.ops[..];
+        let mut sp = 0;  // Number of values on the stack.
+        let mut pc = 0;
+        let mut keybuf = String::new();  // Reused by all lookups in this run.
+        let mut locals = Vec::<f64>::new();  // The variables of a program and the arguments of user-defined functions.  Only allocates for those.
+        let mut frames = Vec::<(usize, &[String])>::new();  // The enclosing calls.
+        let mut base = 0;  // Where the current frame starts in 'locals'.
+        let mut names : &[String] = &self.names;  // The local names of the current frame, for print().
+
+        macro_rules! push { ($val:expr) => {{ let val=$val; stack[sp]=val; sp+=1; }} }
+        macro_rules! top { () => { stack[sp-1] } }
+        macro_rules! unary { ($f:expr) => {{ let x=top!(); top!()=$f(x); }} }
+        macro_rules! binary { ($f:expr) => {{ sp-=1; let r=stack[sp]; let l=top!(); top!()=$f(l,r); }} }
+        macro_rules! try_unary { ($f:expr) => {{ let x=top!(); top!()=$f(x)?; }} }
+        macro_rules! try_binary { ($f:expr) => {{ sp-=1; let r=stack[sp]; let l=top!(); top!()=$f(l,r)?; }} }
+
+        while let Some(op) = ops.get(pc) {
+            pc+=1;
+            match op {
+                Const(c) => push!(*c),
+                Op::SafeVar{var, ..} => push!(var.get()),
+                #[cfg(feature="unsafe-vars")]
+                UnsafeVar{ptr, ..} => push!(unsafe { **ptr }),
+                Slot{name, slot} => {
+                    // Fall back to a lookup by name if the Namespace doesn't supply the slot:
+                    match ns.slot(*slot).or_else(|| ns.lookup(name, Vec::new(), &mut keybuf)) {
+                        Some(f) => push!(f),
+                        None => return Err(Error::Undefined(name.to_string())),
+                    }
+                }
+                Var(name) => {
+                    match ns.lookup(name, Vec::new(), &mut keybuf) {
+                        Some(f) => push!(f),
+                        None => return Err(Error::Undefined(name.to_string())),
+                    }
+                }
+                Func{name, nargs} => {
+                    sp-=nargs;
+                    let args = stack[sp..sp+nargs].to_vec();
+                    match ns.lookup(name, args, &mut keybuf) {
+                        Some(f) => push!(f),
+                        None => return Err(Error::Undefined(name.to_string())),
+                    }
+                }
+                Local(i) => {
+                    match locals.get(base+i) {
+                        Some(f) => push!(*f),
+                        None => return Err(Error::Unreachable),
+                    }
+                }
+                Print(pf) => push!(pf.eval(slab, &mut LocalNamespace::new(&names[..locals.len()-base], &locals[base..], ns))?),
+
+                Add => binary!(|l,r| l+r),
+                Mul => binary!(|l,r| l*r),
+                Neg => unary!(|x:f64| -x),
+                Inv => unary!(|x:f64| 1.0/x),
+                Not => unary!(|x:f64| bool_to_f64!(f64_eq!(x,0.0))),
+                Mod => binary!(|l,r| l%r),
+                Exp => binary!(|l:f64,r| l.powf(r)),
+                LT => binary!(|l,r| bool_to_f64!(l<r)),
+                LTE => binary!(|l,r| bool_to_f64!(l<=r)),
+                EQ => binary!(|l:f64,r:f64| bool_to_f64!(f64_eq!(l,r))),
+                NE => binary!(|l:f64,r:f64| bool_to_f64!(f64_ne!(l,r))),
+                GTE => binary!(|l,r| bool_to_f64!(l>=r)),
+                GT => binary!(|l,r| bool_to_f64!(l>r)),
+                BitOr => try_binary!(bitor),
+                BitXor => try_binary!(bitxor),
+                BitAnd => try_binary!(bitand),
+                Shl => try_binary!(shl),
+                Shr => try_binary!(shr),
+                BitNot => try_unary!(bitnot),
+
+                Int => unary!(f64::trunc),
+                Ceil => unary!(f64::ceil),
+                Floor => unary!(f64::floor),
+                Abs => unary!(f64::abs),
+                Sign => unary!(f64::signum),
+                Log => binary!(log),
+                Round => binary!(|modulus:f64,of:f64| (of/modulus).round() * modulus),
+                Min => binary!(|l:f64,r:f64| if l.is_nan() || r.is_nan() { std::f64::NAN } else if l<r { l } else { r }),
+                Max => binary!(|l:f64,r:f64| if l.is_nan() || r.is_nan() { std::f64::NAN } else if l>r { l } else { r }),
+                Sin => unary!(f64::sin),
+                Cos => unary!(f64::cos),
+                Tan => unary!(f64::tan),
+                ASin => unary!(f64::asin),
+                ACos => unary!(f64::acos),
+                ATan => unary!(f64::atan),
+                SinH => unary!(f64::sinh),
+                CosH => unary!(f64::cosh),
+                TanH => unary!(f64::tanh),
+                ASinH => unary!(f64::asinh),
+                ACosH => unary!(f64::acosh),
+                ATanH => unary!(f64::atanh),
+
+                JumpIfTrueOrPop(target) => {
+                    if f64_ne!(top!(),0.0) { pc = *target; } else { sp-=1; }
+                }
+                JumpIfFalseOrPop(target) => {
+                    if f64_eq!(top!(),0.0) { pc = *target; } else { sp-=1; }
+                }
+                JumpIfFalse(target) => {
+                    sp-=1;
+                    if f64_eq!(stack[sp],0.0) { pc = *target; }
+                }
+                Jump(target) => pc = *target,
+
+                Enter(params) => {
+                    frames.push((base, names));
+                    base = locals.len();
+                    names = params;
+                    sp-=params.len();
+                    locals.extend_from_slice(&stack[sp..sp+params.len()]);
+                }
+                Leave => {
+                    locals.truncate(base);
+                    if let Some((b,n)) = frames.pop() { base = b; names = n; }
+                }
+                Store(i) => {
+                    // Only programs assign, and they are never inside a call, so this is frame 0:
+                    sp-=1;
+                    store_local(&mut locals, Some(*i), stack[sp]);
+                }
+                Pop => sp-=1,
+            }
+        }
+
+        if sp==1 { Ok(stack[0]) } else { Err(Error::Unreachable) }
+    }
+}
+
+impl Evaler for Bytecode {
+    fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
+        for op in &self.ops {
+            match op {
+                Var(name) | Slot{name, ..} | Func{name, ..} | Op::SafeVar{name, ..} => { dst.insert(name.clone()); }
+                #[cfg(feature="unsafe-vars")]
+                UnsafeVar{name, ..} => { dst.insert(name.clone()); }
+                Print(pf) => pf._var_names(slab,dst),
+                _ => (),
+            }
+        }
+    }
+
+    fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+        if self.max_depth<=INLINE_STACK {
+            let mut stack = [0.0; INLINE_STACK];
+            self.run(&mut stack, slab, ns)
+        } else {
+            let mut stack = vec![0.0; self.max_depth];
+            self.run(&mut stack, slab, ns)
+        }
+    }
+}
@@ -19,18 +19,36 @@
 //! * Built-in functions with constant arguments are evaluated.
 //! * Constant terms are combined.
 //! * Logical operator short-circuits are applied and no-op branches are discarded.
+//! * Bitwise operators with constant integer operands are evaluated.
+//! * Ternaries with a constant condition are replaced by the taken branch.
+//! * Program variables that are assigned a constant are replaced by the constant.
 //!
 //! ## Optimized Memory Layout and Execution
 //! * Variable-length `Expression`/`Value` AST nodes are converted into constant-sized `Instruction` nodes.
 //! * The `IC` enumeration helps to eliminate expensive function calls.
+//!
+//! # Self-Contained Compiled Expressions
+//!
+//! A compiled `Instruction` is only meaningful together with the `Slab` that it
+//! was compiled into.  If you need to store many compiled expressions, use a
+//! [`CompiledExpr`](struct.CompiledExpr.html), which packs an `Instruction`
+//! and just the `Slab` data that it needs into a tightly-sized container.
 
 
 
-use crate::slab::{ParseSlab, CompileSlab};
-use crate::parser::{Expression, ExprPair, Value, UnaryOp::{self, EPos, ENeg, ENot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH}, PrintFunc};
+use crate::error::Error;
+use crate::slab::{Slab, ParseSlab, CompileSlab};
+use crate::evaler::Evaler;
+use crate::batch::eval_batch;
+use crate::evalns::{EvalNamespace, SafeVar};
+use crate::number::Number;
+use crate::parser::{Expression, ExpressionI, ValueI, ExprPair, Value::{self, EConstant, EExactConstant, EUnaryOp, EStdFunc, EPrintFunc}, UnaryOp::{self, EPos, ENeg, ENot, EBitNot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EBitOr, EBitXor, EBitAnd, EShr, EShl, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH}, Statement, PrintFunc, UserFunc, ExpressionOrString::{EExpr, EStr}};
 #[cfg(feature="unsafe-vars")]
 use crate::parser::StdFunc::EUnsafeVar;
 
+use std::collections::BTreeSet;
+use std::fmt;
+
 
 /// `true` --> `1.0`,  `false` --> `0.0`
 #[macro_export]
@@ -88,6 +106,7 @@ pub enum Instruction {
     INeg(InstructionI),
     INot(InstructionI),
     IInv(InstructionI),
+    IBitNot(InstructionI),
 
     //---- Binary Math Ops:
     IAdd(InstructionI, IC),
@@ -109,8 +128,18 @@ pub enum Instruction {
     IOR(InstructionI, IC),
     IAND(InstructionI, IC),
 
+    //---- Binary Bitwise Ops:
+    IBitOr(IC, IC),
+    IBitXor(IC, IC),
+    IBitAnd(IC, IC),
+    IShl(IC, IC),
+    IShr(IC, IC),
+
     //---- Callables:
     IVar(String),
+    ISlot{name:String, slot:usize},  // A variable that was resolved to a slot index at compile time.  The name is used if the Namespace doesn't supply the slot.
+    ILocal(usize),  // A parameter of the user-defined function whose body is being evaluated, or a variable that was assigned by a program.
+    ISafeVar{name:String, var:SafeVar},
     #[cfg(feature="unsafe-vars")]
     IUnsafeVar{name:String, ptr:*const f64},
     IFunc{name:String, args:Vec<IC>},
@@ -124,6 +153,7 @@ pub enum Instruction {
     IFuncRound{modulus:IC, of:IC},
     IFuncMin(InstructionI, IC),
     IFuncMax(InstructionI, IC),
+    IFuncIf{cond:InstructionI, then:IC, otherwise:IC},
 
     IFuncSin(InstructionI),
     IFuncCos(InstructionI),
@@ -139,8 +169,14 @@ pub enum Instruction {
     IFuncATanH(InstructionI),
 
     IPrintFunc(PrintFunc),  // Not optimized (it would be pointless because of i/o bottleneck).
+
+    //---- User-defined Functions:
+    ICall{name:String, names:Vec<String>, args:Vec<IC>, body:InstructionI},  // The args are evaluated once, and the body refers to them with ILocal.  The name is only used by eval_traced().
+
+    //---- Programs:
+    IProgram{names:Vec<String>, stmts:Vec<(Option<usize>, IC)>},  // Each statement's value is stored in the numbered ILocal, if there is one.
 }
-use Instruction::{IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
+use Instruction::{IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram};
 #[cfg(feature="unsafe-vars")]
 use Instruction::IUnsafeVar;
 
@@ -159,9 +195,9 @@ pub trait Compiler {
 
 
 #[derive(Debug)]
-struct ExprSlice<'s> {
-    first: &'s Value,
-    pairs: Vec<&'s ExprPair>,
+pub(crate) struct ExprSlice<'s> {
+    pub(crate) first: &'s Value,
+    pub(crate) pairs: Vec<&'s ExprPair>,
 }
 impl<'s> ExprSlice<'s> {
     fn new(first:&Value) -> ExprSlice<'_> {
@@ -170,12 +206,12 @@ impl<'s> ExprSlice<'s> {
             pairs:Vec::with_capacity(8),
         }
     }
-    fn from_expr(expr:&Expression) -> ExprSlice<'_> {
+    pub(crate) fn from_expr(expr:&Expression) -> ExprSlice<'_> {
         let mut sl = ExprSlice::new(&expr.first);
         for exprpairref in expr.pairs.iter() { sl.pairs.push(exprpairref) }
         sl
     }
-    fn split(&self, bop:BinaryOp, dst:&mut Vec<ExprSlice<'s>>) {
+    pub(crate) fn split(&self, bop:BinaryOp, dst:&mut Vec<ExprSlice<'s>>) {
         dst.push(ExprSlice::new(&self.first));
         for exprpair in self.pairs.iter() {
             if exprpair.0==bop {
@@ -188,7 +224,7 @@ impl<'s> ExprSlice<'s> {
             }
         }
     }
-    fn split_multi(&self, search:&[BinaryOp], xsdst:&mut Vec<ExprSlice<'s>>, opdst:&mut Vec<&'s BinaryOp>) {
+    pub(crate) fn split_multi(&self, search:&[BinaryOp], xsdst:&mut Vec<ExprSlice<'s>>, opdst:&mut Vec<&'s BinaryOp>) {
         xsdst.push(ExprSlice::new(&self.first));
         for exprpair in self.pairs.iter() {
             if search.contains(&exprpair.0) {
@@ -240,16 +276,16 @@ macro_rules! f64_ne {
     };
 }
 fn neg_wrap(instr:Instruction, cslab:&mut CompileSlab) -> Instruction {
-    if let IConst(c) = instr {
-        IConst(-c)
-    } else if let INeg(i) = instr {
-        cslab.take_instr(i)
+    if let Some(c) = const_val(cslab,&instr).and_then(|c| fold1(cslab, c, Some(-c), Number::neg)) {
+        IConst(c)
+    } else if let (INeg(i), false) = (&instr, cslab.int_mode) {  // In integer mode, -(-x) overflows for the smallest i64.
+        cslab.take_instr(*i)
     } else {
         INeg(cslab.push_instr(instr))
     }
 }
 fn not_wrap(instr:Instruction, cslab:&mut CompileSlab) -> Instruction {
-    if let IConst(c) = instr {
+    if let Some(c) = const_val(cslab,&instr) {
         IConst(bool_to_f64!(f64_eq!(c,0.0)))
     } else if let INot(i) = instr {
         cslab.take_instr(i)
@@ -271,15 +307,22 @@ fn compile_mul(instrs:Vec<Instruction>, cslab:&mut CompileSlab) -> Instruction {
     let mut const_prod = 1.0;
     for instr in instrs {
         if let IConst(c) = instr {
-            const_prod *= c;  // Floats don't overflow.
-        } else {
-            if out_set {
-                out = IMul(cslab.push_instr(out), IC::I(cslab.push_instr(instr)));
-            } else {
-                out = instr;
-                out_set = true;
+            if let Some(prod) = checked_fold(const_prod, c, const_prod*c) {
+                const_prod = prod;
+                continue;
             }
         }
+        if out_set {
+            let li = cslab.push_instr(out);
+            let ric = match instr {
+                IConst(c) => IC::C(c),
+                _ => IC::I(cslab.push_instr(instr)),
+            };
+            out = IMul(li, ric);
+        } else {
+            out = instr;
+            out_set = true;
+        }
     }
     if f64_ne!(const_prod,1.0) {
         if out_set {
@@ -295,15 +338,22 @@ fn compile_add(instrs:Vec<Instruction>, cslab:&mut CompileSlab) -> Instruction {
     let mut const_sum = 0.0;
     for instr in instrs {
         if let IConst(c) = instr {
-            const_sum += c;  // Floats don't overflow.
-        } else {
-            if out_set {
-                out = IAdd(cslab.push_instr(out), IC::I(cslab.push_instr(instr)));
-            } else {
-                out = instr;
-                out_set = true;
+            if let Some(sum) = checked_fold(const_sum, c, const_sum+c) {
+                const_sum = sum;
+                continue;
             }
         }
+        if out_set {
+            let li = cslab.push_instr(out);
+            let ric = match instr {
+                IConst(c) => IC::C(c),
+                _ => IC::I(cslab.push_instr(instr)),
+            };
+            out = IAdd(li, ric);
+        } else {
+            out = instr;
+            out_set = true;
+        }
     }
     if f64_ne!(const_sum,0.0) {
         if out_set {
@@ -314,6 +364,52 @@ fn compile_add(instrs:Vec<Instruction>, cslab:&mut CompileSlab) -> Instruction {
     }
     out
 }
+// Returns None if folding two finite f64 constants overflows to infinity.  The
+// constant is then kept as a separate operand, because the unfolded expression
+// might not overflow, e.g. `(x * 1e200) * 1e200`.  This is only about f64:
+// integer mode folds with int_fold().
+#[inline]
+fn checked_fold(a:f64, b:f64, result:f64) -> Option<f64> {
+    if result.is_finite() || !a.is_finite() || !b.is_finite() { Some(result) } else { None }
+}
+// Folding is skipped if it would turn non-NaN inputs into NaN, and the
+// CompileSlab asks us not to.  (See CompileSlab::set_fold_nan().)
+#[inline]
+fn can_fold(cslab:&CompileSlab, inputs_nan:bool, result:f64) -> bool {
+    cslab.fold_nan || inputs_nan || !result.is_nan()
+}
+// These fold an operation on constants.  In integer mode (see
+// CompileSlab::set_int_mode()), they use the checked i64 arithmetic of
+// eval_number(), and return None if that fails (for example, with an overflow
+// or a division by zero), if an operand isn't an integer, or if the result
+// can't be stored exactly in an f64 constant.  The operation is then left for
+// the evaluation, which reports the error.  Otherwise, they return `float`,
+// which is None if the f64 operation must not be folded.
+#[inline]
+fn fold1(cslab:&CompileSlab, x:f64, float:Option<f64>, int:impl FnOnce(i64)->Result<i64,Error>) -> Option<f64> {
+    if cslab.int_mode { int_fold(int(i64::from_f64(x).ok()?)) } else { float }
+}
+#[inline]
+fn fold2(cslab:&CompileSlab, l:f64, r:f64, float:Option<f64>, int:impl FnOnce(i64,i64)->Result<i64,Error>) -> Option<f64> {
+    if cslab.int_mode { int_fold(int(i64::from_f64(l).ok()?, i64::from_f64(r).ok()?)) } else { float }
+}
+// Integers up to 2^53 are exact in an f64:
+fn int_fold(x:Result<i64,Error>) -> Option<f64> {
+    match x {
+        Ok(x) if (-(1i64<<53)..=1i64<<53).contains(&x) => Some(x as f64),
+        _ => None,
+    }
+}
+// The value of a constant, if it can be folded.  In integer mode, constants
+// that aren't integers are left for the evaluation to report.
+#[inline]
+fn const_val(cslab:&CompileSlab, instr:&Instruction) -> Option<f64> {
+    match instr {
+        IConst(c) => fold1(cslab, *c, Some(*c), Ok),
+        _ => None,
+    }
+}
+
 pub(crate) fn log(base:f64, n:f64) -> f64 {
     // Can't use floating point in 'match' patterns.  :(
     if f64_eq!(base,2.0) { return n.log2(); }
@@ -321,6 +417,49 @@ pub(crate) fn log(base:f64, n:f64) -> f64 {
     n.log(base)
 }
 
+// The bitwise operators work on the two's complement of `i64`s.  Their `f64`
+// operands must be integers within the range of an `i64`.
+fn int_operand(op:&str, x:f64) -> Result<i64,Error> {
+    if x.fract()!=0.0 || !x.is_finite() {
+        return Err(Error::WrongArgs(format!("bitwise operator `{}` requires integer operands, but got {}", op, x)));
+    }
+    // -2^63 is exact as an f64, but i64::MAX is not:
+    if !(-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&x) { return Err(Error::Overflow); }
+    Ok(x as i64)
+}
+fn shift_amount(op:&str, n:i64) -> Result<u32,Error> {
+    if (0..64).contains(&n) { Ok(n as u32) }
+    else { Err(Error::WrongArgs(format!("shift amount of `{}` must be between 0 and 63, but got {}", op, n))) }
+}
+// `x << n`.  Returns Error::Overflow if any bits are shifted out, including the sign bit.
+pub(crate) fn int_shl(x:i64, n:i64) -> Result<i64,Error> {
+    let n = shift_amount("<<", n)?;
+    let out = x << n;
+    if out >> n != x { return Err(Error::Overflow); }
+    Ok(out)
+}
+// `x >> n`.  This is an arithmetic shift, so it rounds towards negative infinity.
+pub(crate) fn int_shr(x:i64, n:i64) -> Result<i64,Error> {
+    Ok(x >> shift_amount(">>", n)?)
+}
+pub(crate) fn bitor(l:f64, r:f64) -> Result<f64,Error> { Ok((int_operand("|",l)? | int_operand("|",r)?) as f64) }
+pub(crate) fn bitxor(l:f64, r:f64) -> Result<f64,Error> { Ok((int_operand("xor",l)? ^ int_operand("xor",r)?) as f64) }
+pub(crate) fn bitand(l:f64, r:f64) -> Result<f64,Error> { Ok((int_operand("&",l)? & int_operand("&",r)?) as f64) }
+pub(crate) fn shl(l:f64, r:f64) -> Result<f64,Error> { Ok(int_shl(int_operand("<<",l)?, int_operand("<<",r)?)? as f64) }
+pub(crate) fn shr(l:f64, r:f64) -> Result<f64,Error> { Ok(int_shr(int_operand(">>",l)?, int_operand(">>",r)?)? as f64) }
+pub(crate) fn bitnot(x:f64) -> Result<f64,Error> { Ok(!int_operand("~",x)? as f64) }
+
+// Stores the value of a program's statement into its local slot.  Slots are
+// numbered in the order that they are first assigned, so a new slot is always
+// the next one.
+pub(crate) fn store_local<T>(locals:&mut Vec<T>, slot:Option<usize>, val:T) {
+    match slot {
+        Some(i) if i<locals.len() => locals[i] = val,
+        Some(_) => locals.push(val),
+        None => (),
+    }
+}
+
 // Can't inline recursive functions:
 fn push_mul_leaves(instrs:&mut Vec<Instruction>, cslab:&mut CompileSlab, li:InstructionI, ric:IC) {
     // Take 'r' before 'l' for a chance for more efficient memory usage:
@@ -405,19 +544,17 @@ impl Compiler for ExprSlice<'_> {
                     Some(xs) => xs.compile(pslab,cslab),
                     None => IConst(std::f64::NAN),  // unreachable
                 };
-                if let IConst(l) = out {
-                    if let IConst(r) = instr {
-                        out = match op {
-                            EEQ => IConst(bool_to_f64!(f64_eq!(l,r))),
-                            ENE => IConst(bool_to_f64!(f64_ne!(l,r))),
-                            ELT => IConst(bool_to_f64!(l<r)),
-                            EGT => IConst(bool_to_f64!(l>r)),
-                            ELTE => IConst(bool_to_f64!(l<=r)),
-                            EGTE => IConst(bool_to_f64!(l>=r)),
-                            _ => IConst(std::f64::NAN),  // unreachable
-                        };
-                        continue;
-                    }
+                if let (Some(l), Some(r)) = (const_val(cslab,&out), const_val(cslab,&instr)) {
+                    out = match op {
+                        EEQ => IConst(bool_to_f64!(f64_eq!(l,r))),
+                        ENE => IConst(bool_to_f64!(f64_ne!(l,r))),
+                        ELT => IConst(bool_to_f64!(l<r)),
+                        EGT => IConst(bool_to_f64!(l>r)),
+                        ELTE => IConst(bool_to_f64!(l<=r)),
+                        EGTE => IConst(bool_to_f64!(l>=r)),
+                        _ => IConst(std::f64::NAN),  // unreachable
+                    };
+                    continue;
                 }
                 out = match op {
                     EEQ => IEQ(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
@@ -432,6 +569,51 @@ impl Compiler for ExprSlice<'_> {
             return out;
         }
 
+        // The bitwise operators are evaluated left-to-right.  Both shifts have equal precedence:
+        if lowest_op==EBitOr || lowest_op==EBitXor || lowest_op==EBitAnd || lowest_op==EShl || lowest_op==EShr {
+            let mut ops = Vec::<&BinaryOp>::with_capacity(4);
+            let mut xss = Vec::<ExprSlice>::with_capacity(ops.len()+1);
+            if lowest_op==EShl || lowest_op==EShr {
+                self.split_multi(&[EShl, EShr], &mut xss, &mut ops);
+            } else {
+                self.split_multi(&[lowest_op], &mut xss, &mut ops);
+            }
+            let mut out = match xss.first() {
+                Some(xs) => xs.compile(pslab,cslab),
+                None => IConst(std::f64::NAN),  // unreachable
+            };
+            for (i,op) in ops.into_iter().enumerate() {
+                let instr = match xss.get(i+1) {
+                    Some(xs) => xs.compile(pslab,cslab),
+                    None => IConst(std::f64::NAN),  // unreachable
+                };
+                if let (IConst(l), IConst(r)) = (&out, &instr) {
+                    let (l, r) = (*l, *r);
+                    // Operands that aren't integers are left for eval() to report:
+                    let folded = match op {
+                        EBitOr => fold2(cslab, l, r, bitor(l,r).ok(), Number::bitor),
+                        EBitXor => fold2(cslab, l, r, bitxor(l,r).ok(), Number::bitxor),
+                        EBitAnd => fold2(cslab, l, r, bitand(l,r).ok(), Number::bitand),
+                        EShl => fold2(cslab, l, r, shl(l,r).ok(), Number::shl),
+                        _ => fold2(cslab, l, r, shr(l,r).ok(), Number::shr),
+                    };
+                    if let Some(c) = folded {
+                        out = IConst(c);
+                        continue;
+                    }
+                }
+                out = match op {
+                    EBitOr => IBitOr(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+                    EBitXor => IBitXor(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+                    EBitAnd => IBitAnd(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+                    EShl => IShl(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+                    EShr => IShr(instr_to_ic!(cslab,out), instr_to_ic!(cslab,instr)),
+                    _ => IConst(std::f64::NAN),  // unreachable
+                };
+            }
+            return out;
+        }
+
         match lowest_op {
             EOR => {
                 let mut xss = Vec::<ExprSlice>::with_capacity(4);
@@ -442,7 +624,7 @@ impl Compiler for ExprSlice<'_> {
                     if out_set {
                         out = IOR(cslab.push_instr(out), instr_to_ic!(cslab,instr));
                     } else {
-                        if let IConst(c) = instr {
+                        if let Some(c) = const_val(cslab,&instr) {
                             if f64_ne!(c,0.0) { return instr; }
                             // out = instr;     // Skip this 0 value (mostly so I don't complicate my logic in 'if out_set' since I can assume that any set value is non-const).
                             // out_set = true;
@@ -460,11 +642,11 @@ impl Compiler for ExprSlice<'_> {
                 let mut out = IConst(1.0); let mut out_set = false;
                 for xs in xss.iter() {
                     let instr = xs.compile(pslab,cslab);
-                    if let IConst(c) = instr {
+                    if let Some(c) = const_val(cslab,&instr) {
                         if f64_eq!(c,0.0) { return instr; }
                     }
                     if out_set {
-                        if let IConst(_) = out {
+                        if const_val(cslab,&out).is_some() {
                             // If we get here, we know that the const is non-zero.
                             out = instr;
                         } else {
@@ -477,6 +659,89 @@ impl Compiler for ExprSlice<'_> {
                 }
                 out
             }
+            // In integer mode, the operators are applied in the same order as eval_number() applies
+            // them, so that they overflow and round in the same places:  `+` right-to-left, `-`
+            // left-to-right, and `*` and `/` together, left-to-right.  Only constant operands that
+            // are next to each other are folded.
+            EAdd if cslab.int_mode => {
+                let mut xss = Vec::<ExprSlice>::with_capacity(4);
+                self.split(EAdd, &mut xss);
+                let mut out = IConst(0.0); let mut out_set = false;
+                for xs in xss.iter().rev() {
+                    let instr = xs.compile(pslab,cslab);
+                    if out_set {
+                        if let (Some(l), Some(r)) = (const_val(cslab,&instr), const_val(cslab,&out)) {
+                            if let Some(c) = fold2(cslab, l, r, None, Number::add) {
+                                out = IConst(c);
+                                continue;
+                            }
+                        }
+                        out = IAdd(cslab.push_instr(instr), instr_to_ic!(cslab,out));
+                    } else {
+                        out = instr;
+                        out_set = true;
+                    }
+                }
+                out
+            }
+            ESub if cslab.int_mode => {
+                // `a - b` is compiled to `a + -b`, so it overflows if `b` is the smallest i64.
+                let mut xss = Vec::<ExprSlice>::with_capacity(4);
+                self.split(ESub, &mut xss);
+                let mut out = IConst(0.0); let mut out_set = false;
+                for xs in xss.iter() {
+                    let instr = xs.compile(pslab,cslab);
+                    if out_set {
+                        if let (Some(l), Some(r)) = (const_val(cslab,&out), const_val(cslab,&instr)) {
+                            if let Some(c) = fold2(cslab, l, r, None, Number::sub) {
+                                out = IConst(c);
+                                continue;
+                            }
+                        }
+                        let instr = neg_wrap(instr,cslab);
+                        out = IAdd(cslab.push_instr(out), instr_to_ic!(cslab,instr));
+                    } else {
+                        out = instr;
+                        out_set = true;
+                    }
+                }
+                out
+            }
+            EMul | EDiv if cslab.int_mode => {
+                // `a / b` is compiled to `a * IInv(b)`, which NumberEvaler evaluates as an integer division:
+                let mut ops = Vec::<&BinaryOp>::with_capacity(4);
+                let mut xss = Vec::<ExprSlice>::with_capacity(ops.len()+1);
+                self.split_multi(&[EMul, EDiv], &mut xss, &mut ops);
+                let mut out = match xss.first() {
+                    Some(xs) => xs.compile(pslab,cslab),
+                    None => IConst(std::f64::NAN),  // unreachable
+                };
+                for (i,op) in ops.into_iter().enumerate() {
+                    let instr = match xss.get(i+1) {
+                        Some(xs) => xs.compile(pslab,cslab),
+                        None => IConst(std::f64::NAN),  // unreachable
+                    };
+                    if let (Some(l), Some(r)) = (const_val(cslab,&out), const_val(cslab,&instr)) {
+                        let folded = match op {
+                            EMul => fold2(cslab, l, r, None, Number::mul),
+                            _ => fold2(cslab, l, r, None, Number::div),
+                        };
+                        if let Some(c) = folded {
+                            out = IConst(c);
+                            continue;
+                        }
+                    }
+                    let ric = match op {
+                        EMul => instr_to_ic!(cslab,instr),
+                        _ => {
+                            let inv = IInv(cslab.push_instr(instr));
+                            IC::I(cslab.push_instr(inv))
+                        }
+                    };
+                    out = IMul(cslab.push_instr(out), ric);
+                }
+                out
+            }
             EAdd => {
                 let mut xss = Vec::<ExprSlice>::with_capacity(4);
                 self.split(EAdd, &mut xss);
@@ -587,9 +852,9 @@ impl Compiler for ExprSlice<'_> {
                 for xs in xss.iter() {
                     let instr = xs.compile(pslab,cslab);
                     if out_set {
-                        if let IConst(dividend) = out {
-                            if let IConst(divisor) = instr {
-                                out = IConst(dividend%divisor);
+                        if let (IConst(dividend), IConst(divisor)) = (&out, &instr) {
+                            if let Some(c) = fold2(cslab, *dividend, *divisor, Some(dividend%divisor), Number::rem) {
+                                out = IConst(c);
                                 continue;
                             }
                         }
@@ -608,9 +873,11 @@ impl Compiler for ExprSlice<'_> {
                 for xs in xss.into_iter().rev() {
                     let instr = xs.compile(pslab,cslab);
                     if out_set {
-                        if let IConst(power) = out {
-                            if let IConst(base) = instr {
-                                out = IConst(base.powf(power));
+                        if let (IConst(power), IConst(base)) = (&out, &instr) {
+                            let (power, base) = (*power, *base);
+                            let float = Some(base.powf(power)).filter(|&x| can_fold(cslab, base.is_nan() || power.is_nan(), x));
+                            if let Some(c) = fold2(cslab, base, power, float, Number::pow) {
+                                out = IConst(c);
                                 continue;
                             }
                         }
@@ -643,7 +910,7 @@ impl Compiler for ExprSlice<'_> {
 //              }
 //              IExp{base:cslab.push_instr(base), power:cslab.push_instr(power)}
 //          }
-            ENE | EEQ | EGTE | ELTE | EGT | ELT => IConst(std::f64::NAN),  // unreachable
+            ENE | EEQ | EGTE | ELTE | EGT | ELT | EBitOr | EBitXor | EBitAnd | EShr | EShl => IConst(std::f64::NAN),  // unreachable
         }
     }
 }
@@ -658,7 +925,7 @@ impl Compiler for Expression {
 impl Compiler for Value {
     fn compile(&self, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
         match self {
-            Value::EConstant(c) => IConst(*c),
+            Value::EConstant(c) | Value::EExactConstant(c, _) => IConst(*c),
             Value::EUnaryOp(u) => u.compile(pslab,cslab),
             Value::EStdFunc(f) => f.compile(pslab,cslab),
             Value::EPrintFunc(pf) => IPrintFunc(pf.clone()),
@@ -672,19 +939,18 @@ impl Compiler for UnaryOp {
             EPos(i) => get_val!(pslab,i).compile(pslab,cslab),
             ENeg(i) => {
                 let instr = get_val!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(-c)
-                } else {
-                    neg_wrap(instr,cslab)
-                }
+                neg_wrap(instr,cslab)
             }
             ENot(i) => {
+                let instr = get_val!(pslab,i).compile(pslab,cslab);
+                not_wrap(instr,cslab)
+            }
+            EBitNot(i) => {
                 let instr = get_val!(pslab,i).compile(pslab,cslab);
                 if let IConst(c) = instr {
-                    IConst(bool_to_f64!(f64_eq!(c,0.0)))
-                } else {
-                    not_wrap(instr,cslab)
+                    if let Some(n) = fold1(cslab, c, bitnot(c).ok(), Number::bitnot) { return IConst(n); }
                 }
+                IBitNot(cslab.push_instr(instr))
             }
             EParentheses(i) => get_expr!(pslab,i).compile(pslab,cslab),
         }
@@ -694,10 +960,51 @@ impl Compiler for UnaryOp {
 impl Compiler for StdFunc {
     fn compile(&self, pslab:&ParseSlab, cslab:&mut CompileSlab) -> Instruction {
         match self {
-            EVar(name) => IVar(name.clone()),
+            EVar(name) => {
+                if let Some(frame) = cslab.func_args.last() {
+                    // A parameter of the function whose body is being compiled:
+                    if let Some(i) = frame.iter().position(|(param,_)| param==name) {
+                        return match frame[i].1 {
+                            Some(c) => IConst(c),
+                            None => ILocal(i),
+                        };
+                    }
+                } else if let Some(i) = cslab.locals.iter().position(|(n,_)| n==name) {
+                    // A variable that the program has already assigned:
+                    return match cslab.locals[i].1 {
+                        Some(c) => IConst(c),
+                        None => ILocal(i),
+                    };
+                }
+                match cslab.slot_index(name) {
+                    Some(slot) => ISlot{name:name.clone(), slot},
+                    None => IVar(name.clone()),
+                }
+            }
+            ESafeVar{name,var} => ISafeVar{name:name.clone(), var:var.clone()},
             #[cfg(feature="unsafe-vars")]
             EUnsafeVar{name,ptr} => IUnsafeVar{name:name.clone(), ptr:*ptr},
             EFunc{name, args:xis} => {
+                if let Some(f) = pslab.funcs.get(name) {
+                    if f.params.len()==xis.len() {
+                        // The arguments of a user-defined function are compiled (and evaluated) once,
+                        // in the scope of the caller.  Constant arguments are folded into the body:
+                        let mut args = Vec::<IC>::with_capacity(xis.len());
+                        let mut frame = Vec::<(String, Option<f64>)>::with_capacity(xis.len());
+                        for (param,xi) in f.params.iter().zip(xis.iter()) {
+                            let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                            frame.push((param.clone(), if let IConst(c) = instr { Some(c) } else { None }));
+                            args.push(instr_to_ic!(cslab,instr));
+                        }
+                        cslab.func_args.push(frame);
+                        let body = pslab.get_expr(f.body).compile(pslab,cslab);
+                        cslab.func_args.pop();
+                        if let IConst(_) = body { return body; }
+                        return ICall{name:name.clone(), names:f.params.clone(), args, body:cslab.push_instr(body)};
+                    }
+                }
+                // Otherwise, a user-defined function was redefined with a different number of
+                // parameters after this call was parsed.  eval() reports that, like the interpreter.
                 let mut args = Vec::<IC>::with_capacity(xis.len());
                 for xi in xis {
                     let instr = get_expr!(pslab,xi).compile(pslab,cslab);
@@ -708,42 +1015,52 @@ impl Compiler for StdFunc {
 
             EFuncInt(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.trunc())
-                } else {
-                    IFuncInt(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.trunc()), Number::trunc) {
+                        Some(c) => IConst(c),
+                        None => IFuncInt(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncInt(cslab.push_instr(instr)),
                 }
             }
             EFuncCeil(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.ceil())
-                } else {
-                    IFuncCeil(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.ceil()), Number::ceil) {
+                        Some(c) => IConst(c),
+                        None => IFuncCeil(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncCeil(cslab.push_instr(instr)),
                 }
             }
             EFuncFloor(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.floor())
-                } else {
-                    IFuncFloor(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.floor()), Number::floor) {
+                        Some(c) => IConst(c),
+                        None => IFuncFloor(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncFloor(cslab.push_instr(instr)),
                 }
             }
             EFuncAbs(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.abs())
-                } else {
-                    IFuncAbs(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.abs()), Number::abs) {
+                        Some(c) => IConst(c),
+                        None => IFuncAbs(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncAbs(cslab.push_instr(instr)),
                 }
             }
             EFuncSign(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.signum())
-                } else {
-                    IFuncSign(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.signum()), Number::signum) {
+                        Some(c) => IConst(c),
+                        None => IFuncSign(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncSign(cslab.push_instr(instr)),
                 }
             }
             EFuncLog{base:baseopt, expr:i} => {
@@ -752,9 +1069,11 @@ impl Compiler for StdFunc {
                     None => IConst(10.0),
                 };
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(b) = base {
-                    if let IConst(n) = instr {
-                        return IConst(log(b,n));
+                if let (IConst(b), IConst(n)) = (&base, &instr) {
+                    let (b, n) = (*b, *n);
+                    let float = Some(log(b,n)).filter(|&x| can_fold(cslab, b.is_nan() || n.is_nan(), x));
+                    if let Some(c) = fold2(cslab, b, n, float, |b,n| n.log(b)) {
+                        return IConst(c);
                     }
                 }
                 IFuncLog{base:instr_to_ic!(cslab,base), of:instr_to_ic!(cslab,instr)}
@@ -765,9 +1084,10 @@ impl Compiler for StdFunc {
                     None => IConst(1.0),
                 };
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(m) = modulus {
-                    if let IConst(n) = instr {
-                        return IConst( (n/m).round() * m );  // Floats don't overflow.
+                if let (IConst(m), IConst(n)) = (&modulus, &instr) {
+                    let (m, n) = (*m, *n);
+                    if let Some(c) = fold2(cslab, m, n, Some( (n/m).round() * m ), |m,n| n.round(m)) {  // Floats don't overflow.
+                        return IConst(c);
                     }
                 }
                 IFuncRound{modulus:instr_to_ic!(cslab,modulus), of:instr_to_ic!(cslab,instr)}
@@ -778,7 +1098,7 @@ impl Compiler for StdFunc {
                 for i in is { rest.push(get_expr!(pslab,i).compile(pslab,cslab)); }
                 let mut out = IConst(0.0); let mut out_set = false;
                 let mut const_min = 0.0; let mut const_min_set = false;
-                if let IConst(f) = first {
+                if let Some(f) = const_val(cslab,&first) {
                     const_min = f;
                     const_min_set = true;
                 } else {
@@ -786,7 +1106,7 @@ impl Compiler for StdFunc {
                     out_set = true;
                 }
                 for instr in rest {
-                    if let IConst(f) = instr {
+                    if let Some(f) = const_val(cslab,&instr) {
                         if const_min_set {
                             if f<const_min { const_min=f; }
                         } else {
@@ -819,7 +1139,7 @@ impl Compiler for StdFunc {
                 for i in is { rest.push(get_expr!(pslab,i).compile(pslab,cslab)); }
                 let mut out = IConst(0.0); let mut out_set = false;
                 let mut const_max = 0.0; let mut const_max_set = false;
-                if let IConst(f) = first {
+                if let Some(f) = const_val(cslab,&first) {
                     const_max = f;
                     const_max_set = true;
                 } else {
@@ -827,7 +1147,7 @@ impl Compiler for StdFunc {
                     out_set = true;
                 }
                 for instr in rest {
-                    if let IConst(f) = instr {
+                    if let Some(f) = const_val(cslab,&instr) {
                         if const_max_set {
                             if f>const_max { const_max=f; }
                         } else {
@@ -854,107 +1174,420 @@ impl Compiler for StdFunc {
                 //assert!(out_set);
                 out
             }
+            EFuncIf{cond:ci, then:ti, otherwise:oi} => {
+                let cond = get_expr!(pslab,ci).compile(pslab,cslab);
+                if let Some(c) = const_val(cslab,&cond) {
+                    // Only compile the branch that will be taken:
+                    if f64_ne!(c,0.0) {
+                        return get_expr!(pslab,ti).compile(pslab,cslab);
+                    } else {
+                        return get_expr!(pslab,oi).compile(pslab,cslab);
+                    }
+                }
+                let then = get_expr!(pslab,ti).compile(pslab,cslab);
+                let otherwise = get_expr!(pslab,oi).compile(pslab,cslab);
+                IFuncIf{cond:cslab.push_instr(cond), then:instr_to_ic!(cslab,then), otherwise:instr_to_ic!(cslab,otherwise)}
+            }
+            EProgram(stmts) => {
+                // Each variable gets a local slot, in the order that they are assigned.
+                // Uses of a variable whose current value is constant are folded:
+                cslab.locals.clear();
+                let mut out = Vec::<(Option<usize>, IC)>::with_capacity(stmts.len());
+                let mut all_const = true;
+                for (k, Statement(name, xi)) in stmts.iter().enumerate() {
+                    let instr = get_expr!(pslab,xi).compile(pslab,cslab);
+                    let c = if let IConst(c) = instr { Some(c) } else { all_const=false; None };
+                    let slot = match name {
+                        Some(name) => Some(match cslab.locals.iter().position(|(n,_)| n==name) {
+                            Some(i) => { cslab.locals[i].1 = c; i }
+                            None => { cslab.locals.push((name.clone(), c)); cslab.locals.len()-1 }
+                        }),
+                        None => None,
+                    };
+                    // The values of unassigned statements are discarded, except for the last one:
+                    if slot.is_none() && c.is_some() && k+1<stmts.len() { continue; }
+                    out.push((slot, instr_to_ic!(cslab,instr)));
+                }
+                let names = cslab.locals.drain(..).map(|(n,_)| n).collect();
+                if all_const {
+                    if let Some((_, IC::C(c))) = out.last() { return IConst(*c); }
+                }
+                IProgram{names, stmts:out}
+            }
 
             EFuncE => IConst(std::f64::consts::E),
             EFuncPi => IConst(std::f64::consts::PI),
 
             EFuncSin(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.sin())
-                } else {
-                    IFuncSin(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.sin()), Number::sin) {
+                        Some(c) => IConst(c),
+                        None => IFuncSin(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncSin(cslab.push_instr(instr)),
                 }
             }
             EFuncCos(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.cos())
-                } else {
-                    IFuncCos(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.cos()), Number::cos) {
+                        Some(c) => IConst(c),
+                        None => IFuncCos(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncCos(cslab.push_instr(instr)),
                 }
             }
             EFuncTan(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.tan())
-                } else {
-                    IFuncTan(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.tan()), Number::tan) {
+                        Some(c) => IConst(c),
+                        None => IFuncTan(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncTan(cslab.push_instr(instr)),
                 }
             }
             EFuncASin(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.asin())
-                } else {
-                    IFuncASin(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.asin()).filter(|&x| can_fold(cslab, c.is_nan(), x)), Number::asin) {
+                        Some(c) => IConst(c),
+                        None => IFuncASin(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncASin(cslab.push_instr(instr)),
                 }
             }
             EFuncACos(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.acos())
-                } else {
-                    IFuncACos(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.acos()).filter(|&x| can_fold(cslab, c.is_nan(), x)), Number::acos) {
+                        Some(c) => IConst(c),
+                        None => IFuncACos(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncACos(cslab.push_instr(instr)),
                 }
             }
             EFuncATan(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.atan())
-                } else {
-                    IFuncATan(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.atan()), Number::atan) {
+                        Some(c) => IConst(c),
+                        None => IFuncATan(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncATan(cslab.push_instr(instr)),
                 }
             }
             EFuncSinH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.sinh())
-                } else {
-                    IFuncSinH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.sinh()), Number::sinh) {
+                        Some(c) => IConst(c),
+                        None => IFuncSinH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncSinH(cslab.push_instr(instr)),
                 }
             }
             EFuncCosH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.cosh())
-                } else {
-                    IFuncCosH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.cosh()), Number::cosh) {
+                        Some(c) => IConst(c),
+                        None => IFuncCosH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncCosH(cslab.push_instr(instr)),
                 }
             }
             EFuncTanH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.tanh())
-                } else {
-                    IFuncTanH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.tanh()), Number::tanh) {
+                        Some(c) => IConst(c),
+                        None => IFuncTanH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncTanH(cslab.push_instr(instr)),
                 }
             }
             EFuncASinH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.asinh())
-                } else {
-                    IFuncASinH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.asinh()), Number::asinh) {
+                        Some(c) => IConst(c),
+                        None => IFuncASinH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncASinH(cslab.push_instr(instr)),
                 }
             }
             EFuncACosH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.acosh())
-                } else {
-                    IFuncACosH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.acosh()).filter(|&x| can_fold(cslab, c.is_nan(), x)), Number::acosh) {
+                        Some(c) => IConst(c),
+                        None => IFuncACosH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncACosH(cslab.push_instr(instr)),
                 }
             }
             EFuncATanH(i) => {
                 let instr = get_expr!(pslab,i).compile(pslab,cslab);
-                if let IConst(c) = instr {
-                    IConst(c.atanh())
-                } else {
-                    IFuncATanH(cslab.push_instr(instr))
+                match instr {
+                    IConst(c) => match fold1(cslab, c, Some(c.atanh()).filter(|&x| can_fold(cslab, c.is_nan(), x)), Number::atanh) {
+                        Some(c) => IConst(c),
+                        None => IFuncATanH(cslab.push_instr(instr)),
+                    },
+                    _ => IFuncATanH(cslab.push_instr(instr)),
                 }
             }
         }
     }
 }
 
+
+/// A compiled expression that owns a tightly-sized copy of the `Slab` data it needs.
+///
+/// A `CompiledExpr` can be stored, cloned, and shared across threads (it is
+/// `Send + Sync` unless the `unsafe-vars` feature is enabled) without keeping
+/// the original `Slab` around.
+///
+/// # Examples
+///
+/// ```
+/// use fasteval::Compiler;  // use this trait so we can call compile().
+/// fn main() -> Result<(), fasteval::Error> {
+///     let parser = fasteval::Parser::new();
+///     let mut slab = fasteval::Slab::new();
+///
+///     // Compile several expressions, re-using one Slab:
+///     let mut cache = Vec::new();
+///     for expr_str in &["x + 1", "x * 2", "x^2 - 1"] {
+///         let compiled = parser.parse(expr_str, &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+///         cache.push(fasteval::CompiledExpr::new(&compiled, &slab));
+///     }
+///     drop(slab);
+///
+///     let mut map = fasteval::StringToF64Namespace::new();
+///     map.insert("x".to_string(), 3.0);
+///     let vals = cache.iter().map(|c| c.eval(&mut map)).collect::<Result<Vec<f64>,_>>()?;
+///     assert_eq!(vals, vec![4.0, 6.0, 8.0]);
+///
+///     Ok(())
+/// }
+/// ```
+pub struct CompiledExpr {
+    instr:Instruction,
+    slab :Slab,
+}
+
+impl CompiledExpr {
+    /// Copies `instr`, and everything it refers to within `slab`, into a new `CompiledExpr`.
+    pub fn new(instr:&Instruction, slab:&Slab) -> Self {
+        let mut dst = Slab::with_capacity(0);
+        dst.cs.slots = slab.cs.slots.clone();
+        let instr = instr.pack(slab, &mut dst);
+        dst.ps.exprs.shrink_to_fit();
+        dst.ps.vals.shrink_to_fit();
+        dst.ps.char_buf.shrink_to_fit();
+        dst.cs.instrs.shrink_to_fit();
+        CompiledExpr{ instr, slab:dst }
+    }
+
+    /// Evaluates this expression.
+    #[inline]
+    pub fn eval(&self, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+        if let IConst(c) = self.instr {
+            Ok(c)
+        } else {
+            self.instr.eval(&self.slab, ns)
+        }
+    }
+
+    /// Evaluates this expression for every row of `columns`.
+    /// (See [`eval_batch()`](../batch/fn.eval_batch.html).)
+    #[inline]
+    pub fn eval_batch(&self, columns:&[&[f64]], out:&mut [f64], ns:&mut impl EvalNamespace) -> Result<(),Error> {
+        eval_batch(&self.instr, &self.slab, columns, out, ns)
+    }
+
+    /// Returns a list of variables and custom functions that are used by this expression.
+    pub fn var_names(&self) -> BTreeSet<String> {
+        self.instr.var_names(&self.slab)
+    }
+
+    /// Returns the top-level `Instruction`.
+    #[inline]
+    pub fn instr(&self) -> &Instruction { &self.instr }
+
+    /// Returns the packed `Slab` that the `Instruction` refers to.
+    #[inline]
+    pub fn slab(&self) -> &Slab { &self.slab }
+}
+
+impl Clone for CompiledExpr {
+    fn clone(&self) -> Self {
+        Self::new(&self.instr, &self.slab)
+    }
+}
+
+impl fmt::Debug for CompiledExpr {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "CompiledExpr{{ instr:{:?}, slab:{:?} }}", self.instr, self.slab)
+    }
+}
+
+// The pack_*() functions copy AST nodes from one Slab to another, re-numbering
+// the indexes as they go.  Only reachable nodes are copied.
+
+fn pack_i(i:InstructionI, src:&Slab, dst:&mut Slab) -> InstructionI {
+    let instr = get_instr!(src.cs,i).pack(src,dst);
+    dst.cs.push_instr(instr)
+}
+fn pack_ic(ic:&IC, src:&Slab, dst:&mut Slab) -> IC {
+    match ic {
+        IC::C(c) => IC::C(*c),
+        IC::I(i) => IC::I(pack_i(*i,src,dst)),
+    }
+}
+fn pack_expr_i(xi:ExpressionI, src:&ParseSlab, dst:&mut ParseSlab) -> ExpressionI {
+    let expr = get_expr!(src,xi);
+    let mut pairs = Vec::with_capacity(expr.pairs.len());
+    let first = pack_val(&expr.first,src,dst);
+    for ExprPair(bop,val) in &expr.pairs {
+        pairs.push(ExprPair(*bop, pack_val(val,src,dst)));
+    }
+    dst.exprs.push(Expression{first, pairs});
+    ExpressionI(dst.exprs.len()-1)
+}
+fn pack_val_i(vi:ValueI, src:&ParseSlab, dst:&mut ParseSlab) -> ValueI {
+    let val = pack_val(get_val!(src,vi),src,dst);
+    dst.vals.push(val);
+    ValueI(dst.vals.len()-1)
+}
+fn pack_val(val:&Value, src:&ParseSlab, dst:&mut ParseSlab) -> Value {
+    match val {
+        EConstant(c) => EConstant(*c),
+        EExactConstant(c, text) => EExactConstant(*c, text.clone()),
+        EUnaryOp(EPos(vi)) => EUnaryOp(EPos(pack_val_i(*vi,src,dst))),
+        EUnaryOp(ENeg(vi)) => EUnaryOp(ENeg(pack_val_i(*vi,src,dst))),
+        EUnaryOp(ENot(vi)) => EUnaryOp(ENot(pack_val_i(*vi,src,dst))),
+        EUnaryOp(EBitNot(vi)) => EUnaryOp(EBitNot(pack_val_i(*vi,src,dst))),
+        EUnaryOp(EParentheses(xi)) => EUnaryOp(EParentheses(pack_expr_i(*xi,src,dst))),
+        EStdFunc(f) => EStdFunc(pack_stdfunc(f,src,dst)),
+        EPrintFunc(pf) => EPrintFunc(pack_printfunc(pf,src,dst)),
+    }
+}
+fn pack_printfunc(pf:&PrintFunc, src:&ParseSlab, dst:&mut ParseSlab) -> PrintFunc {
+    let mut out = Vec::with_capacity(pf.0.len());
+    for x_or_s in &pf.0 {
+        out.push(match x_or_s {
+            EExpr(xi) => EExpr(pack_expr_i(*xi,src,dst)),
+            EStr(s) => EStr(s.clone()),
+        });
+    }
+    PrintFunc(out)
+}
+fn pack_func(name:&str, src:&ParseSlab, dst:&mut ParseSlab) {
+    if let Some(f) = src.funcs.get(name) {
+        if !dst.funcs.contains_key(name) {
+            let body = pack_expr_i(f.body,src,dst);
+            dst.funcs.insert(name.to_string(), UserFunc{params:f.params.clone(), body, calls:f.calls.clone()});
+        }
+    }
+}
+fn pack_stdfunc(f:&StdFunc, src:&ParseSlab, dst:&mut ParseSlab) -> StdFunc {
+    // print() arguments are evaluated by the interpreter, so they need the user-defined functions that they call:
+    if let EFunc{name, ..} = f { pack_func(name,src,dst); }
+    let mut p = |xi:&ExpressionI| pack_expr_i(*xi,src,dst);
+    match f {
+        EVar(name) => EVar(name.clone()),
+        ESafeVar{name,var} => ESafeVar{name:name.clone(), var:var.clone()},
+        #[cfg(feature="unsafe-vars")]
+        EUnsafeVar{name,ptr} => EUnsafeVar{name:name.clone(), ptr:*ptr},
+        EFunc{name,args} => EFunc{name:name.clone(), args:args.iter().map(p).collect()},
+        EFuncInt(xi) => EFuncInt(p(xi)),
+        EFuncCeil(xi) => EFuncCeil(p(xi)),
+        EFuncFloor(xi) => EFuncFloor(p(xi)),
+        EFuncAbs(xi) => EFuncAbs(p(xi)),
+        EFuncSign(xi) => EFuncSign(p(xi)),
+        EFuncLog{base,expr} => EFuncLog{base:base.as_ref().map(&mut p), expr:p(expr)},
+        EFuncRound{modulus,expr} => EFuncRound{modulus:modulus.as_ref().map(&mut p), expr:p(expr)},
+        EFuncMin{first,rest} => EFuncMin{first:p(first), rest:rest.iter().map(p).collect()},
+        EFuncMax{first,rest} => EFuncMax{first:p(first), rest:rest.iter().map(p).collect()},
+        EFuncIf{cond,then,otherwise} => EFuncIf{cond:p(cond), then:p(then), otherwise:p(otherwise)},
+        EProgram(stmts) => EProgram(stmts.iter().map(|Statement(name,xi)| Statement(name.clone(), p(xi))).collect()),
+        EFuncE => EFuncE,
+        EFuncPi => EFuncPi,
+        EFuncSin(xi) => EFuncSin(p(xi)),
+        EFuncCos(xi) => EFuncCos(p(xi)),
+        EFuncTan(xi) => EFuncTan(p(xi)),
+        EFuncASin(xi) => EFuncASin(p(xi)),
+        EFuncACos(xi) => EFuncACos(p(xi)),
+        EFuncATan(xi) => EFuncATan(p(xi)),
+        EFuncSinH(xi) => EFuncSinH(p(xi)),
+        EFuncCosH(xi) => EFuncCosH(p(xi)),
+        EFuncTanH(xi) => EFuncTanH(p(xi)),
+        EFuncASinH(xi) => EFuncASinH(p(xi)),
+        EFuncACosH(xi) => EFuncACosH(p(xi)),
+        EFuncATanH(xi) => EFuncATanH(p(xi)),
+    }
+}
+
+impl Instruction {
+    fn pack(&self, src:&Slab, dst:&mut Slab) -> Instruction {
+        match self {
+            IConst(c) => IConst(*c),
+            INeg(i) => INeg(pack_i(*i,src,dst)),
+            INot(i) => INot(pack_i(*i,src,dst)),
+            IInv(i) => IInv(pack_i(*i,src,dst)),
+            IBitNot(i) => IBitNot(pack_i(*i,src,dst)),
+            IAdd(li,ric) => IAdd(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IMul(li,ric) => IMul(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IMod{dividend,divisor} => IMod{dividend:pack_ic(dividend,src,dst), divisor:pack_ic(divisor,src,dst)},
+            IExp{base,power} => IExp{base:pack_ic(base,src,dst), power:pack_ic(power,src,dst)},
+            ILT(lic,ric) => ILT(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            ILTE(lic,ric) => ILTE(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IEQ(lic,ric) => IEQ(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            INE(lic,ric) => INE(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IGTE(lic,ric) => IGTE(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IGT(lic,ric) => IGT(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IOR(li,ric) => IOR(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IAND(li,ric) => IAND(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IBitOr(lic,ric) => IBitOr(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IBitXor(lic,ric) => IBitXor(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IBitAnd(lic,ric) => IBitAnd(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IShl(lic,ric) => IShl(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IShr(lic,ric) => IShr(pack_ic(lic,src,dst), pack_ic(ric,src,dst)),
+            IVar(name) => IVar(name.clone()),
+            ISlot{name,slot} => ISlot{name:name.clone(), slot:*slot},
+            ILocal(i) => ILocal(*i),
+            ISafeVar{name,var} => ISafeVar{name:name.clone(), var:var.clone()},
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{name,ptr} => IUnsafeVar{name:name.clone(), ptr:*ptr},
+            IFunc{name,args} => IFunc{name:name.clone(), args:args.iter().map(|ic| pack_ic(ic,src,dst)).collect()},
+            IFuncInt(i) => IFuncInt(pack_i(*i,src,dst)),
+            IFuncCeil(i) => IFuncCeil(pack_i(*i,src,dst)),
+            IFuncFloor(i) => IFuncFloor(pack_i(*i,src,dst)),
+            IFuncAbs(i) => IFuncAbs(pack_i(*i,src,dst)),
+            IFuncSign(i) => IFuncSign(pack_i(*i,src,dst)),
+            IFuncLog{base,of} => IFuncLog{base:pack_ic(base,src,dst), of:pack_ic(of,src,dst)},
+            IFuncRound{modulus,of} => IFuncRound{modulus:pack_ic(modulus,src,dst), of:pack_ic(of,src,dst)},
+            IFuncMin(li,ric) => IFuncMin(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IFuncMax(li,ric) => IFuncMax(pack_i(*li,src,dst), pack_ic(ric,src,dst)),
+            IFuncIf{cond,then,otherwise} => IFuncIf{cond:pack_i(*cond,src,dst), then:pack_ic(then,src,dst), otherwise:pack_ic(otherwise,src,dst)},
+            IFuncSin(i) => IFuncSin(pack_i(*i,src,dst)),
+            IFuncCos(i) => IFuncCos(pack_i(*i,src,dst)),
+            IFuncTan(i) => IFuncTan(pack_i(*i,src,dst)),
+            IFuncASin(i) => IFuncASin(pack_i(*i,src,dst)),
+            IFuncACos(i) => IFuncACos(pack_i(*i,src,dst)),
+            IFuncATan(i) => IFuncATan(pack_i(*i,src,dst)),
+            IFuncSinH(i) => IFuncSinH(pack_i(*i,src,dst)),
+            IFuncCosH(i) => IFuncCosH(pack_i(*i,src,dst)),
+            IFuncTanH(i) => IFuncTanH(pack_i(*i,src,dst)),
+            IFuncASinH(i) => IFuncASinH(pack_i(*i,src,dst)),
+            IFuncACosH(i) => IFuncACosH(pack_i(*i,src,dst)),
+            IFuncATanH(i) => IFuncATanH(pack_i(*i,src,dst)),
+            IPrintFunc(pf) => IPrintFunc(pack_printfunc(pf,&src.ps,&mut dst.ps)),
+            ICall{name,names,args,body} => ICall{name:name.clone(), names:names.clone(), args:args.iter().map(|ic| pack_ic(ic,src,dst)).collect(), body:pack_i(*body,src,dst)},
+            IProgram{names,stmts} => IProgram{names:names.clone(), stmts:stmts.iter().map(|(slot,ic)| (*slot, pack_ic(ic,src,dst))).collect()},
+        }
+    }
+}
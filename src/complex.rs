@@ -0,0 +1,365 @@
+//! This module evaluates compiled `Instruction`s with complex numbers.
+//!
+//! `eval_complex()` evaluates the same `Instruction`s as the normal `f64`
+//! evaluator, so you can use the normal `Parser` and `Compiler`.  Values are
+//! [`Complex`](struct.Complex.html) numbers, and variables and custom
+//! functions are supplied by an `EvalNamespace<Complex>`.
+//!
+//! Before compiling, call
+//! [`CompileSlab::set_fold_nan(false)`](../slab/struct.CompileSlab.html#method.set_fold_nan)
+//! so that the compiler leaves things like `(-1)^0.5` for `eval_complex()`,
+//! rather than folding them to a real `NaN`.
+//!
+//! # Complex Built-ins
+//!
+//! In addition to the normal built-in functions, these are available:
+//!
+//! ```text
+//!   * i           -- The imaginary unit.  (This takes precedence over a Namespace variable named 'i'.)
+//!   * re(z)       -- The real part.
+//!   * im(z)       -- The imaginary part.
+//!   * arg(z)      -- The argument (phase angle), in the range (-π, π].
+//!   * conj(z)     -- The complex conjugate.
+//!   * sqrt(z)     -- The principal square root.
+//!   * exp(z)      -- e^z
+//!   * pi, e       -- The same as pi() and e(), so you can write exp(i*pi).
+//!                    (A Namespace variable named 'pi' or 'e' takes precedence.)
+//! ```
+//!
+//! The normal built-ins work with complex values:  `abs()` is the magnitude,
+//! `sign()` is `z/abs(z)`, `int()`, `ceil()`, `floor()` and `round()` apply
+//! to each part separately, and `log()`, `^` and the trigonometric functions
+//! return the principal value.  Comparisons (`<`, `min()`, etc.) and `%` only
+//! work with real values, and return an `Error` otherwise.  `==` and `!=`
+//! compare both parts.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! use fasteval::Complex;
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     slab.cs.set_fold_nan(false);
+//!     let mut ns = |name:&str, _args:Vec<Complex>| -> Option<Complex> {
+//!         match name {
+//!             "z" => Some(Complex::new(3.0, 4.0)),
+//!             _ => None,
+//!         }
+//!     };
+//!
+//!     let instr = fasteval::Parser::new().parse("abs(z) + z * i", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     assert_eq!(fasteval::eval_complex(&instr, &slab, &mut ns)?, Complex::new(1.0, 3.0));
+//!
+//!     let instr = fasteval::Parser::new().parse("(-4)^0.5", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     assert_eq!(fasteval::eval_complex(&instr, &slab, &mut ns)?, Complex::new(0.0, 2.0));
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evalns::EvalNamespace;
+use crate::number::{Number, eval_instr_number};
+use crate::compiler::{log, Instruction};
+
+use std::cmp::Ordering;
+use std::f64::consts::{FRAC_PI_2, PI, E};
+use std::fmt;
+
+/// A complex number: `re + im*i`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    /// The real part.
+    pub re:f64,
+    /// The imaginary part.
+    pub im:f64,
+}
+
+const I:Complex = Complex{re:0.0, im:1.0};
+const ONE:Complex = Complex{re:1.0, im:0.0};
+
+impl Complex {
+    /// Creates a new `Complex` number.
+    #[inline]
+    pub fn new(re:f64, im:f64) -> Self { Complex{re, im} }
+
+    /// Returns the magnitude.
+    #[inline]
+    pub fn norm(self) -> f64 { self.re.hypot(self.im) }
+    /// Returns the argument (phase angle), in the range (-π, π].
+    #[inline]
+    pub fn arg(self) -> f64 { self.im.atan2(self.re) }
+    /// Returns the complex conjugate.
+    #[inline]
+    pub fn conj(self) -> Self { Complex{re:self.re, im:-self.im} }
+
+    // Returns the real part if the imaginary part is zero.
+    #[inline]
+    fn real(self) -> Option<f64> {
+        if self.im==0.0 { Some(self.re) } else { None }
+    }
+    // Returns the real part if it is within [lo, hi] and the imaginary part is zero.
+    #[inline]
+    fn real_in(self, lo:f64, hi:f64) -> Option<f64> {
+        self.real().filter(|&x| lo<=x && x<=hi)
+    }
+
+    fn plus(self, o:Self) -> Self { Complex{re:self.re+o.re, im:self.im+o.im} }
+    fn minus(self, o:Self) -> Self { Complex{re:self.re-o.re, im:self.im-o.im} }
+    fn times(self, o:Self) -> Self {
+        if o.im==0.0 { return Complex{re:self.re*o.re, im:self.im*o.re}; }
+        Complex{re:self.re*o.re - self.im*o.im, im:self.re*o.im + self.im*o.re}
+    }
+    fn over(self, o:Self) -> Self {
+        if o.im==0.0 { return Complex{re:self.re/o.re, im:self.im/o.re}; }
+        let d = o.re*o.re + o.im*o.im;
+        Complex{re:(self.re*o.re + self.im*o.im)/d, im:(self.im*o.re - self.re*o.im)/d}
+    }
+    fn scale(self, k:f64) -> Self { Complex{re:self.re*k, im:self.im*k} }
+
+    /// Returns `e^self`.
+    pub fn exp(self) -> Self {
+        if let Some(x) = self.real() { return Complex::from(x.exp()); }
+        let r = self.re.exp();
+        Complex{re:r*self.im.cos(), im:r*self.im.sin()}
+    }
+    /// Returns the principal natural logarithm.
+    pub fn ln(self) -> Self {
+        if let Some(x) = self.real_in(0.0, std::f64::INFINITY) { return Complex::from(x.ln()); }
+        Complex{re:self.norm().ln(), im:self.arg()}
+    }
+    /// Returns the principal square root.
+    pub fn sqrt(self) -> Self {
+        if let Some(x) = self.real_in(0.0, std::f64::INFINITY) { return Complex::from(x.sqrt()); }
+        let r = self.norm();
+        let re = ((r+self.re)/2.0).sqrt();
+        let im = ((r-self.re)/2.0).sqrt();
+        Complex{re, im:if self.im<0.0 { -im } else { im }}
+    }
+    /// Returns the principal value of `self^power`.
+    pub fn powc(self, power:Self) -> Self {
+        if let (Some(b), Some(p)) = (self.real(), power.real()) {
+            if b>=0.0 || p.fract()==0.0 { return Complex::from(b.powf(p)); }
+        }
+        if power.re==0.0 && power.im==0.0 { return ONE; }
+        if power.re==0.5 && power.im==0.0 { return self.sqrt(); }  // More accurate.
+        if self.re==0.0 && self.im==0.0 {
+            return if power.re>0.0 { Complex::default() } else { Complex::from(std::f64::NAN) };
+        }
+        self.ln().times(power).exp()
+    }
+}
+
+impl From<f64> for Complex {
+    #[inline]
+    fn from(re:f64) -> Self { Complex{re, im:0.0} }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.im==0.0 { return write!(f, "{}", self.re); }
+        if self.re==0.0 { return write!(f, "{}i", self.im); }
+        if self.im<0.0 { write!(f, "{}-{}i", self.re, -self.im) } else { write!(f, "{}+{}i", self.re, self.im) }
+    }
+}
+
+impl Number for Complex {
+    #[inline]
+    fn from_f64(x:f64) -> Result<Self,Error> { Ok(Complex::from(x)) }
+    /// Returns the real part, or `NaN` if the imaginary part is not zero.
+    #[inline]
+    fn to_f64(self) -> f64 { self.real().unwrap_or(std::f64::NAN) }
+    #[inline]
+    fn from_bool(b:bool) -> Self { Complex::from(bool_to_f64!(b)) }
+    #[inline]
+    fn is_zero(self) -> bool { f64_eq!(self.re,0.0) && f64_eq!(self.im,0.0) }
+    #[inline]
+    fn is_nonzero(self) -> bool { f64_ne!(self.re,0.0) || f64_ne!(self.im,0.0) }
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> {
+        match (self.real(), other.real()) {
+            (Some(l), Some(r)) => Ok(l.partial_cmp(&r)),
+            _ => Err(Error::WrongArgs(format!("cannot compare complex numbers {} and {}", self, other))),
+        }
+    }
+    #[inline]
+    fn approx_eq(self, other:Self) -> bool { f64_eq!(self.re,other.re) && f64_eq!(self.im,other.im) }
+    #[inline]
+    fn approx_ne(self, other:Self) -> bool { f64_ne!(self.re,other.re) || f64_ne!(self.im,other.im) }
+
+    #[inline]
+    fn neg(self) -> Result<Self,Error> { Ok(Complex{re:-self.re, im:-self.im}) }
+    #[inline]
+    fn add(self, other:Self) -> Result<Self,Error> { Ok(self.plus(other)) }
+    #[inline]
+    fn sub(self, other:Self) -> Result<Self,Error> { Ok(self.minus(other)) }
+    #[inline]
+    fn mul(self, other:Self) -> Result<Self,Error> { Ok(self.times(other)) }
+    #[inline]
+    fn div(self, other:Self) -> Result<Self,Error> { Ok(self.over(other)) }
+    fn rem(self, other:Self) -> Result<Self,Error> {
+        match (self.real(), other.real()) {
+            (Some(l), Some(r)) => Ok(Complex::from(l%r)),
+            _ => Err(Error::WrongArgs(format!("cannot calculate the remainder of complex numbers {} and {}", self, other))),
+        }
+    }
+    #[inline]
+    fn pow(self, other:Self) -> Result<Self,Error> { Ok(self.powc(other)) }
+
+    fn round(self, modulus:Self) -> Result<Self,Error> {
+        let q = self.over(modulus);
+        Ok(Complex{re:q.re.round(), im:q.im.round()}.times(modulus))
+    }
+    fn log(self, base:Self) -> Result<Self,Error> {
+        if let (Some(b), Some(n)) = (base.real_in(0.0, std::f64::INFINITY), self.real_in(0.0, std::f64::INFINITY)) {
+            return Ok(Complex::from(log(b,n)));
+        }
+        Ok(self.ln().over(base.ln()))
+    }
+
+    #[inline]
+    fn trunc(self) -> Result<Self,Error> { Ok(Complex{re:self.re.trunc(), im:self.im.trunc()}) }
+    #[inline]
+    fn ceil(self) -> Result<Self,Error> { Ok(Complex{re:self.re.ceil(), im:self.im.ceil()}) }
+    #[inline]
+    fn floor(self) -> Result<Self,Error> { Ok(Complex{re:self.re.floor(), im:self.im.floor()}) }
+    #[inline]
+    fn abs(self) -> Result<Self,Error> { Ok(Complex::from(self.norm())) }
+    fn signum(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.signum())); }
+        Ok(self.scale(1.0/self.norm()))
+    }
+
+    fn sin(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.sin())); }
+        Ok(Complex{re:self.re.sin()*self.im.cosh(), im:self.re.cos()*self.im.sinh()})
+    }
+    fn cos(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.cos())); }
+        Ok(Complex{re:self.re.cos()*self.im.cosh(), im:-self.re.sin()*self.im.sinh()})
+    }
+    fn tan(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.tan())); }
+        Ok(self.sin()?.over(self.cos()?))
+    }
+    fn asin(self) -> Result<Self,Error> {
+        if let Some(x) = self.real_in(-1.0, 1.0) { return Ok(Complex::from(x.asin())); }
+        if let Some(x) = self.real() { return Ok(Complex{re:FRAC_PI_2.copysign(x), im:x.abs().acosh()}); }  // Same branch as C99's casin().
+        // -i * ln(iz + sqrt(1 - z^2))
+        let w = I.times(self).plus(ONE.minus(self.times(self)).sqrt()).ln();
+        Ok(Complex{re:w.im, im:-w.re})
+    }
+    fn acos(self) -> Result<Self,Error> {
+        if let Some(x) = self.real_in(-1.0, 1.0) { return Ok(Complex::from(x.acos())); }
+        Ok(Complex::from(FRAC_PI_2).minus(self.asin()?))
+    }
+    fn atan(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.atan())); }
+        // i/2 * (ln(1 - iz) - ln(1 + iz))
+        let iz = I.times(self);
+        let w = ONE.minus(iz).ln().minus(ONE.plus(iz).ln());
+        Ok(Complex{re:-w.im/2.0, im:w.re/2.0})
+    }
+    fn sinh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.sinh())); }
+        Ok(Complex{re:self.re.sinh()*self.im.cos(), im:self.re.cosh()*self.im.sin()})
+    }
+    fn cosh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.cosh())); }
+        Ok(Complex{re:self.re.cosh()*self.im.cos(), im:self.re.sinh()*self.im.sin()})
+    }
+    fn tanh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.tanh())); }
+        Ok(self.sinh()?.over(self.cosh()?))
+    }
+    fn asinh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real() { return Ok(Complex::from(x.asinh())); }
+        // ln(z + sqrt(z^2 + 1))
+        Ok(self.plus(self.times(self).plus(ONE).sqrt()).ln())
+    }
+    fn acosh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real_in(1.0, std::f64::INFINITY) { return Ok(Complex::from(x.acosh())); }
+        // ln(z + sqrt(z + 1) * sqrt(z - 1))
+        Ok(self.plus(self.plus(ONE).sqrt().times(self.minus(ONE).sqrt())).ln())
+    }
+    fn atanh(self) -> Result<Self,Error> {
+        if let Some(x) = self.real_in(-1.0, 1.0) { return Ok(Complex::from(x.atanh())); }
+        if let Some(x) = self.real() { return Ok(Complex{re:((x+1.0)/(x-1.0)).ln()/2.0, im:FRAC_PI_2}); }  // Same branch as C99's catanh().
+        // (ln(1 + z) - ln(1 - z)) / 2
+        Ok(ONE.plus(self).ln().minus(ONE.minus(self).ln()).scale(0.5))
+    }
+}
+
+/// Adds the [complex built-ins](index.html#complex-built-ins) to another Namespace.
+///
+/// `eval_complex()` uses this automatically.  It is also useful for evaluating
+/// parsed `Expression`s with [`NumberEvaler`](../number/trait.NumberEvaler.html).
+pub struct ComplexNamespace<NS>(pub NS);
+
+impl<NS> EvalNamespace<Complex> for ComplexNamespace<NS> where NS:EvalNamespace<Complex> {
+    fn lookup(&mut self, name:&str, args:Vec<Complex>, keybuf:&mut String) -> Option<Complex> {
+        lookup_with_builtins(&mut self.0, name, args, keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<Complex> {
+        self.0.slot(i)
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.0.print(line)
+    }
+}
+
+// The same as ComplexNamespace, but borrows the inner Namespace.
+struct BorrowedComplexNamespace<'a,NS>(&'a mut NS);
+
+impl<NS> EvalNamespace<Complex> for BorrowedComplexNamespace<'_,NS> where NS:EvalNamespace<Complex> {
+    fn lookup(&mut self, name:&str, args:Vec<Complex>, keybuf:&mut String) -> Option<Complex> {
+        lookup_with_builtins(self.0, name, args, keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<Complex> {
+        self.0.slot(i)
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.0.print(line)
+    }
+}
+
+fn lookup_with_builtins(ns:&mut impl EvalNamespace<Complex>, name:&str, args:Vec<Complex>, keybuf:&mut String) -> Option<Complex> {
+    if let Some(z) = builtin(name, &args) { return Some(z); }
+    let is_var = args.is_empty();
+    ns.lookup(name, args, keybuf).or_else(|| if is_var { constant(name) } else { None })
+}
+
+fn builtin(name:&str, args:&[Complex]) -> Option<Complex> {
+    match (name, args) {
+        ("i", []) => Some(I),
+        ("re", [z]) => Some(Complex::from(z.re)),
+        ("im", [z]) => Some(Complex::from(z.im)),
+        ("arg", [z]) => Some(Complex::from(z.arg())),
+        ("conj", [z]) => Some(z.conj()),
+        ("sqrt", [z]) => Some(z.sqrt()),
+        ("exp", [z]) => Some(z.exp()),
+        _ => None,
+    }
+}
+
+// Unlike the other built-ins, these are common variable names, so they are
+// only used if the Namespace doesn't define them:
+fn constant(name:&str) -> Option<Complex> {
+    match name {
+        "pi" => Some(Complex::from(PI)),
+        "e" => Some(Complex::from(E)),
+        _ => None,
+    }
+}
+
+/// Evaluates `instr` with complex numbers.
+///
+/// [See the `complex` module documentation.](index.html)
+pub fn eval_complex(instr:&Instruction, slab:&Slab, ns:&mut impl EvalNamespace<Complex>) -> Result<Complex,Error> {
+    eval_instr_number(instr, slab, &mut BorrowedComplexNamespace(ns))
+}
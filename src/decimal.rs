@@ -0,0 +1,500 @@
+//! This module evaluates parsed `Expression`s with exact decimal arithmetic.
+//!
+//! Binary floating-point can't represent most decimal fractions, so a money
+//! formula like `price * 1.075` usually doesn't give the result that you
+//! would calculate by hand.  A [`Decimal`](struct.Decimal.html) stores an
+//! integer and the number of digits after the decimal point, so values like
+//! `1.075` are stored exactly.
+//!
+//! `eval_decimal()` evaluates a parsed `Expression` with `Decimal` values,
+//! using a [`DecimalContext`](struct.DecimalContext.html) that sets the scale
+//! (the maximum number of digits after the decimal point) and the
+//! [`Rounding`](enum.Rounding.html) mode:
+//!
+//! * `+`, `-`, `*`, `%`, comparisons, `min()`, `max()`, `abs()`, `sign()`,
+//!   `int()`, `ceil()`, `floor()` and `^` with a non-negative integer exponent
+//!   are exact, unless the result has more digits than the scale allows.  Then
+//!   it is rounded.
+//! * `/` rounds the result to the scale.
+//! * `round(modulus, x)` rounds `x` to an exact multiple of `modulus`, using
+//!   the rounding mode of the context.
+//! * `^` with a fractional exponent, `log()`, the trigonometric functions and
+//!   constants like `pi()` are calculated with `f64`, and then converted.
+//!
+//! Numeric literals keep their exact value, even if it has more digits than
+//! the scale.  (Literals that have more digits than an `f64` can hold are read
+//! from their source text.)  Division by zero returns an `Error`, and results
+//! that don't fit return `Error::Overflow`.
+//!
+//! Values are stored in an `i128`, and the scale can be at most
+//! [`MAX_SCALE`](constant.MAX_SCALE.html), so there are always at least 20
+//! digits available before the decimal point.
+//!
+//! `Decimal` implements the [`Number`](../number/trait.Number.html) trait, so
+//! you can also evaluate with `NumberEvaler::eval_number()`.  That uses the
+//! context of the enclosing `eval_decimal()` call (so it can be used in custom
+//! functions), or `DecimalContext::default()` otherwise.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::{Decimal, DecimalContext, Rounding};
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut ns = |name:&str, _args:Vec<Decimal>| -> Option<Decimal> {
+//!         match name {
+//!             "price" => Some(Decimal::new(1999, 2)),  // 19.99
+//!             _ => None,
+//!         }
+//!     };
+//!
+//!     let expr_ref = fasteval::Parser::new().parse("price * 1.075", &mut slab.ps)?.from(&slab.ps);
+//!     let val = fasteval::eval_decimal(expr_ref, &slab, &mut ns, DecimalContext::default())?;
+//!     assert_eq!(val.to_string(), "21.48925");
+//!
+//!     let expr_ref = fasteval::Parser::new().parse("round(0.01, price * 1.075)", &mut slab.ps)?.from(&slab.ps);
+//!     let val = fasteval::eval_decimal(expr_ref, &slab, &mut ns, DecimalContext::new(2, Rounding::HalfUp))?;
+//!     assert_eq!(val.to_string(), "21.49");
+//!
+//!     let expr_ref = fasteval::Parser::new().parse("10 / 3", &mut slab.ps)?.from(&slab.ps);
+//!     let val = fasteval::eval_decimal(expr_ref, &slab, &mut ns, DecimalContext::new(4, Rounding::Down))?;
+//!     assert_eq!(val.to_string(), "3.3333");
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evalns::EvalNamespace;
+use crate::parser::Expression;
+use crate::number::{Number, NumberEvaler};
+use crate::compiler::log;
+
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// The largest scale (number of digits after the decimal point) that a
+/// `Decimal` can have.
+pub const MAX_SCALE:u32 = 18;
+
+/// How a `Decimal` result is rounded when it has too many digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round towards zero.
+    Down,
+    /// Round away from zero.
+    Up,
+    /// Round towards negative infinity.
+    Floor,
+    /// Round towards positive infinity.
+    Ceiling,
+    /// Round to the nearest value.  Ties are rounded away from zero, like `f64::round()`.
+    HalfUp,
+    /// Round to the nearest value.  Ties are rounded towards zero.
+    HalfDown,
+    /// Round to the nearest value.  Ties are rounded to the even neighbor.
+    /// This is also known as "banker's rounding".
+    HalfEven,
+}
+
+/// The scale and rounding mode used by `Decimal` arithmetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalContext {
+    /// The maximum number of digits after the decimal point.  This must not
+    /// be greater than [`MAX_SCALE`](constant.MAX_SCALE.html).
+    pub scale:u32,
+    /// How results with more digits are rounded.
+    pub rounding:Rounding,
+}
+
+const DEFAULT_CONTEXT:DecimalContext = DecimalContext{scale:MAX_SCALE, rounding:Rounding::HalfEven};
+
+thread_local! {
+    static CONTEXT:Cell<DecimalContext> = const { Cell::new(DEFAULT_CONTEXT) };
+}
+
+impl DecimalContext {
+    /// Creates a new `DecimalContext`.
+    #[inline]
+    pub const fn new(scale:u32, rounding:Rounding) -> Self { DecimalContext{scale, rounding} }
+
+    /// Returns the context that `Decimal` arithmetic currently uses on this thread.
+    #[inline]
+    pub fn current() -> Self { CONTEXT.with(|c| c.get()) }
+}
+
+impl Default for DecimalContext {
+    /// `MAX_SCALE` digits, with `Rounding::HalfEven`.
+    #[inline]
+    fn default() -> Self { DEFAULT_CONTEXT }
+}
+
+// Restores the previous context when eval_decimal() returns (or panics).
+struct RestoreContext(DecimalContext);
+
+impl Drop for RestoreContext {
+    fn drop(&mut self) { CONTEXT.with(|c| c.set(self.0)); }
+}
+
+/// An exact decimal number: `mantissa * 10^-scale`.
+///
+/// Values are compared numerically, so `1.5` and `1.50` are equal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decimal {
+    mant :i128,
+    scale:u32,
+}
+
+const ONE:Decimal = Decimal{mant:1, scale:0};
+
+#[inline]
+fn pow10(n:u32) -> i128 { 10i128.pow(n) }
+
+// Decides whether a truncated quotient `q` should move one step away from zero,
+// given the remainder `r` of the division by `d` (0 < r < d).
+fn away(rounding:Rounding, q_odd:bool, r:u128, d:u128, neg:bool) -> bool {
+    let half = r.cmp(&(d-r));
+    match rounding {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Floor => neg,
+        Rounding::Ceiling => !neg,
+        Rounding::HalfUp => half!=Ordering::Less,
+        Rounding::HalfDown => half==Ordering::Greater,
+        Rounding::HalfEven => half==Ordering::Greater || half==Ordering::Equal && q_odd,
+    }
+}
+
+// Multiplies two u128s into a 256-bit (hi, lo) pair.
+fn wide_mul(a:u128, b:u128) -> (u128, u128) {
+    const M:u128 = (1<<64)-1;
+    let (a1, a0, b1, b0) = (a>>64, a&M, b>>64, b&M);
+    let (p00, p01, p10, p11) = (a0*b0, a0*b1, a1*b0, a1*b1);
+    let mid = (p00>>64) + (p01&M) + (p10&M);
+    ((p11 + (p01>>64) + (p10>>64) + (mid>>64)), (p00&M | mid<<64))
+}
+
+// Returns `a*b/d`, rounded to an integer, or `None` if it doesn't fit in an `i128`.
+// The product is calculated with 256 bits, so it can't overflow.  `d` must not be zero.
+fn mul_div(a:i128, b:i128, d:i128, rounding:Rounding) -> Option<i128> {
+    let neg = (a<0) ^ (b<0) ^ (d<0);
+    let ud = d.unsigned_abs();
+    let (mut q, r) = match a.checked_mul(b) {
+        Some(p) => (p.unsigned_abs()/ud, p.unsigned_abs()%ud),
+        None => {
+            // Binary long division.  r<d<=2^127, so r<<1 can't overflow.
+            let (hi, lo) = wide_mul(a.unsigned_abs(), b.unsigned_abs());
+            let bits = if hi!=0 { 256-hi.leading_zeros() } else { 128-lo.leading_zeros() };
+            let (mut q, mut r) = (0u128, 0u128);
+            for i in (0..bits).rev() {
+                let bit = if i>=128 { hi>>(i-128) } else { lo>>i } & 1;
+                r = r<<1 | bit;
+                if q>>127!=0 { return None; }
+                q <<= 1;
+                if r>=ud { r-=ud; q|=1; }
+            }
+            (q, r)
+        }
+    };
+    if r!=0 && away(rounding, q&1==1, r, ud, neg) { q = q.checked_add(1)?; }
+    if neg {
+        if q>1<<127 { return None; }
+        Some((q as i128).wrapping_neg())
+    } else if q>i128::MAX as u128 {
+        None
+    } else {
+        Some(q as i128)
+    }
+}
+
+impl Decimal {
+    /// Creates the `Decimal` `mantissa * 10^-scale`.  For example,
+    /// `Decimal::new(1999, 2)` is `19.99`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scale` is greater than [`MAX_SCALE`](constant.MAX_SCALE.html).
+    #[inline]
+    pub fn new(mantissa:i128, scale:u32) -> Self {
+        assert!(scale<=MAX_SCALE, "Decimal scale must not be greater than {}", MAX_SCALE);
+        Decimal{mant:mantissa, scale}
+    }
+
+    /// Returns the mantissa.
+    #[inline]
+    pub fn mantissa(self) -> i128 { self.mant }
+    /// Returns the scale (the number of digits after the decimal point).
+    #[inline]
+    pub fn scale(self) -> u32 { self.scale }
+
+    /// Rounds to at most `scale` digits after the decimal point.
+    pub fn round_to(self, scale:u32, rounding:Rounding) -> Self {
+        if scale>=self.scale { return self; }
+        let d = pow10(self.scale-scale);
+        let (q, r) = (self.mant/d, self.mant%d);
+        let mant = if r!=0 && away(rounding, q&1!=0, r.unsigned_abs(), d as u128, self.mant<0) { q + self.mant.signum() } else { q };
+        Decimal{mant, scale}
+    }
+
+    /// Removes trailing zeros after the decimal point.  `1.500` becomes `1.5`.
+    pub fn normalize(self) -> Self {
+        let mut out = self;
+        while out.scale>0 && out.mant%10==0 {
+            out.mant /= 10;
+            out.scale -= 1;
+        }
+        out
+    }
+
+    // Converts the f64 result of an inexact function, and rounds it to the current context.
+    fn from_f64_result(x:f64) -> Result<Self,Error> {
+        let d = Self::from_f64(x)?;
+        Decimal::fit(d.mant, d.scale)
+    }
+
+    // Rounds a result to the scale of the current context, if it has more digits.
+    fn fit(mant:i128, scale:u32) -> Result<Self,Error> {
+        let ctx = DecimalContext::current();
+        if scale<=ctx.scale { return Ok(Decimal{mant, scale}); }
+        let mant = mul_div(mant, 1, pow10(scale-ctx.scale), ctx.rounding).ok_or(Error::Overflow)?;
+        Ok(Decimal{mant, scale:ctx.scale})
+    }
+
+    // Returns both mantissas at the same scale.
+    fn align(self, other:Self) -> Result<(i128,i128,u32),Error> {
+        let scale = self.scale.max(other.scale);
+        let a = self.mant.checked_mul(pow10(scale-self.scale)).ok_or(Error::Overflow)?;
+        let b = other.mant.checked_mul(pow10(scale-other.scale)).ok_or(Error::Overflow)?;
+        Ok((a, b, scale))
+    }
+
+    // Returns the value as an integer, if it doesn't have a fractional part.
+    fn integer(self) -> Option<i128> {
+        let d = pow10(self.scale);
+        if self.mant%d==0 { Some(self.mant/d) } else { None }
+    }
+
+    // Parses [+-]digits[.digits][(e|E)[+-]digits].  Digits beyond MAX_SCALE
+    // are rounded with the rounding mode of the current context.
+    fn parse(text:&str) -> Result<Self,Error> {
+        let err = || Error::ParseF64(text.to_string());
+        let (num, exp) = match text.find(&['e', 'E'][..]) {
+            Some(i) => (&text[..i], text[i+1..].parse::<i32>().map_err(|_| err())?),
+            None => (text, 0),
+        };
+        let (neg, num) = match num.as_bytes().first() {
+            Some(b'-') => (true, &num[1..]),
+            Some(b'+') => (false, &num[1..]),
+            _ => (false, num),
+        };
+        let (int, frac) = match num.find('.') {
+            Some(i) => (&num[..i], &num[i+1..]),
+            None => (num, ""),
+        };
+        if int.is_empty() && frac.is_empty() || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) { return Err(err()); }
+
+        let digits = format!("{}{}", int, frac);
+        let digits = digits.trim_start_matches('0');
+        let scale = frac.len() as i64 - i64::from(exp);
+        let rounding = DecimalContext::current().rounding;
+        let (mant, scale) = if scale<0 {
+            if digits.len() as i64 - scale > 39 { return Err(Error::Overflow); }
+            let mant = if digits.is_empty() { 0 } else { digits.parse::<i128>().map_err(|_| Error::Overflow)? };
+            (mant.checked_mul(10i128.checked_pow((-scale) as u32).ok_or(Error::Overflow)?).ok_or(Error::Overflow)?, 0)
+        } else if scale<=i64::from(MAX_SCALE) {
+            (if digits.is_empty() { 0 } else { digits.parse::<i128>().map_err(|_| Error::Overflow)? }, scale as u32)
+        } else {
+            // Drop the extra digits.  The first dropped digit and whether any
+            // of the others are non-zero are enough to decide the rounding.
+            let drop = (scale - i64::from(MAX_SCALE)) as usize;
+            let (keep, dropped) = if drop>=digits.len() { ("", format!("{:0>width$}", digits, width=drop)) } else { (&digits[..digits.len()-drop], digits[digits.len()-drop..].to_string()) };
+            let q = if keep.is_empty() { 0 } else { keep.parse::<i128>().map_err(|_| Error::Overflow)? };
+            let first = u128::from(dropped.as_bytes()[0]-b'0');
+            let sticky = u128::from(dropped.bytes().skip(1).any(|b| b!=b'0'));
+            let r = first*10 + sticky;
+            let q = if r!=0 && away(rounding, q&1!=0, r, 100, neg) { q.checked_add(1).ok_or(Error::Overflow)? } else { q };
+            (q, MAX_SCALE)
+        };
+        Ok(Decimal{mant:if neg { -mant } else { mant }, scale})
+    }
+
+    // Raises to a non-negative integer power by repeated squaring.
+    fn powi(self, mut n:i128) -> Result<Self,Error> {
+        let mut base = self;
+        let mut out = ONE;
+        while n>0 {
+            if n&1==1 { out = out.mul(base)?; }
+            n >>= 1;
+            if n>0 { base = base.mul(base)?; }
+        }
+        Ok(out)
+    }
+}
+
+impl PartialEq for Decimal {
+    #[inline]
+    fn eq(&self, other:&Self) -> bool { self.cmp(other)==Ordering::Equal }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    #[inline]
+    fn partial_cmp(&self, other:&Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other:&Self) -> Ordering {
+        match self.scale.cmp(&other.scale) {
+            Ordering::Equal => self.mant.cmp(&other.mant),
+            // If scaling up overflows, that value has the larger magnitude:
+            Ordering::Less => match self.mant.checked_mul(pow10(other.scale-self.scale)) {
+                Some(a) => a.cmp(&other.mant),
+                None => if self.mant>0 { Ordering::Greater } else { Ordering::Less },
+            }
+            Ordering::Greater => match other.mant.checked_mul(pow10(self.scale-other.scale)) {
+                Some(b) => self.mant.cmp(&b),
+                None => if other.mant>0 { Ordering::Less } else { Ordering::Greater },
+            }
+        }
+    }
+}
+
+impl From<i64> for Decimal {
+    #[inline]
+    fn from(x:i64) -> Self { Decimal{mant:i128::from(x), scale:0} }
+}
+
+impl FromStr for Decimal {
+    type Err = Error;
+    /// Parses a decimal number like `-12.345` or `1.2e-3`.  Digits beyond
+    /// `MAX_SCALE` are rounded.
+    fn from_str(s:&str) -> Result<Self,Error> { Decimal::parse(s) }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let digits = self.mant.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if self.mant<0 { write!(f, "-")?; }
+        if scale==0 { return write!(f, "{}", digits); }
+        let digits = format!("{:0>width$}", digits, width=scale+1);
+        let (int, frac) = digits.split_at(digits.len()-scale);
+        write!(f, "{}.{}", int, frac)
+    }
+}
+
+// The results of these functions are calculated with f64, and rounded to the current context:
+macro_rules! inexact {
+    ($($f:ident),*) => {
+        $(
+            #[inline]
+            fn $f(self) -> Result<Self,Error> { Decimal::from_f64_result(self.to_f64().$f()) }
+        )*
+    };
+}
+
+impl Number for Decimal {
+    const DIV_ROUNDS : bool = true;
+
+    /// Converts the shortest decimal representation of `x` that round-trips.
+    /// `NaN` and infinity return an `Error`.
+    fn from_f64(x:f64) -> Result<Self,Error> {
+        if !x.is_finite() { return Err(Error::WrongArgs(format!("{} can't be converted to a decimal", x))); }
+        Decimal::parse(&format!("{:e}", x))
+    }
+    #[inline]
+    fn from_literal(text:&str) -> Result<Self,Error> { Decimal::parse(text) }
+    #[inline]
+    fn to_f64(self) -> f64 { self.to_string().parse().unwrap_or(std::f64::NAN) }
+    #[inline]
+    fn from_bool(b:bool) -> Self { Decimal{mant:if b { 1 } else { 0 }, scale:0} }
+    #[inline]
+    fn is_zero(self) -> bool { self.mant==0 }
+    #[inline]
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> { Ok(Some(self.cmp(&other))) }
+
+    #[inline]
+    fn neg(self) -> Result<Self,Error> { Ok(Decimal{mant:self.mant.checked_neg().ok_or(Error::Overflow)?, scale:self.scale}) }
+    fn add(self, other:Self) -> Result<Self,Error> {
+        let (a, b, scale) = self.align(other)?;
+        Decimal::fit(a.checked_add(b).ok_or(Error::Overflow)?, scale)
+    }
+    fn sub(self, other:Self) -> Result<Self,Error> {
+        let (a, b, scale) = self.align(other)?;
+        Decimal::fit(a.checked_sub(b).ok_or(Error::Overflow)?, scale)
+    }
+    fn mul(self, other:Self) -> Result<Self,Error> {
+        let ctx = DecimalContext::current();
+        let scale = self.scale + other.scale;
+        let target = scale.min(ctx.scale);
+        let mant = mul_div(self.mant, other.mant, pow10(scale-target), ctx.rounding).ok_or(Error::Overflow)?;
+        Ok(Decimal{mant, scale:target})
+    }
+    fn div(self, other:Self) -> Result<Self,Error> {
+        if other.mant==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        let ctx = DecimalContext::current();
+        // Operands with more digits than the scale are rounded first, so that the exponent below isn't negative:
+        let a = self.round_to(ctx.scale, ctx.rounding);
+        let mant = mul_div(a.mant, pow10(other.scale + ctx.scale - a.scale), other.mant, ctx.rounding).ok_or(Error::Overflow)?;
+        Ok(Decimal{mant, scale:ctx.scale})
+    }
+    fn rem(self, other:Self) -> Result<Self,Error> {
+        if other.mant==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        let (a, b, scale) = self.align(other)?;
+        Decimal::fit(a.checked_rem(b).unwrap_or(0), scale)
+    }
+    fn pow(self, other:Self) -> Result<Self,Error> {
+        match other.integer() {
+            Some(n) if n>=0 => self.powi(n),
+            Some(n) => {
+                if self.mant==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+                let p = self.powi(n.checked_neg().ok_or(Error::Overflow)?)?;
+                if p.mant==0 { return Err(Error::Overflow); }
+                ONE.div(p)
+            }
+            None => Decimal::from_f64_result(self.to_f64().powf(other.to_f64())),
+        }
+    }
+
+    fn round(self, modulus:Self) -> Result<Self,Error> {
+        if modulus.mant==0 { return Err(Error::WrongArgs("round() modulus must not be zero".to_string())); }
+        let (a, m, _) = self.align(modulus)?;
+        let q = mul_div(a, 1, m, DecimalContext::current().rounding).ok_or(Error::Overflow)?;
+        Decimal::fit(q.checked_mul(modulus.mant).ok_or(Error::Overflow)?, modulus.scale)
+    }
+
+    #[inline]
+    fn log(self, base:Self) -> Result<Self,Error> { Decimal::from_f64_result(log(base.to_f64(), self.to_f64())) }
+
+    #[inline]
+    fn trunc(self) -> Result<Self,Error> { Ok(self.round_to(0, Rounding::Down)) }
+    #[inline]
+    fn ceil(self) -> Result<Self,Error> { Ok(self.round_to(0, Rounding::Ceiling)) }
+    #[inline]
+    fn floor(self) -> Result<Self,Error> { Ok(self.round_to(0, Rounding::Floor)) }
+    #[inline]
+    fn abs(self) -> Result<Self,Error> { Ok(Decimal{mant:self.mant.checked_abs().ok_or(Error::Overflow)?, scale:self.scale}) }
+    #[inline]
+    fn signum(self) -> Result<Self,Error> { Ok(Decimal{mant:self.mant.signum(), scale:0}) }
+
+    inexact!(sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, asinh, acosh, atanh);
+}
+
+/// Evaluates a parsed `Expression` with exact `Decimal` arithmetic, using the
+/// scale and rounding mode of `ctx`.
+///
+/// Variables and custom functions are supplied by an `EvalNamespace<Decimal>`,
+/// such as a closure.
+///
+/// Returns an `Error` if `ctx.scale` is greater than
+/// [`MAX_SCALE`](constant.MAX_SCALE.html), or if there are any problems during
+/// evaluation, such as division by zero.
+pub fn eval_decimal(expr:&Expression, slab:&Slab, ns:&mut impl EvalNamespace<Decimal>, ctx:DecimalContext) -> Result<Decimal,Error> {
+    if ctx.scale>MAX_SCALE { return Err(Error::WrongArgs(format!("decimal scale {} is greater than the maximum of {}", ctx.scale, MAX_SCALE))); }
+    let _restore = RestoreContext(CONTEXT.with(|c| c.replace(ctx)));
+    expr.eval_number(slab, ns)
+}
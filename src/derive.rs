@@ -0,0 +1,561 @@
+//! This module calculates symbolic derivatives of parsed `Expression`s.
+//!
+//! `derive()` walks the `Expression`/`Value`/`StdFunc` tree and builds a new
+//! `Expression` for the derivative in the same `ParseSlab`.  The result is a
+//! normal parsed `Expression`, so it can be evaluated directly, or compiled
+//! to let the `Compiler`'s constant folding simplify it.
+//!
+//! Functions that are piecewise-constant (`int()`, `ceil()`, `floor()`,
+//! `round()`, `sign()`, the comparison operators, `!`, and the bitwise
+//! operators) have a derivative of `0`, ignoring their discontinuities.  `abs()`, `min()`, `max()`, `%`, `&&`,
+//! `||` and `? :` are differentiated piecewise, by following whichever
+//! argument the original expression would select.  User-defined functions are
+//! differentiated with the chain rule, by defining their partial derivatives
+//! as new functions, which are named like `f'x`.  Other custom functions can
+//! only be differentiated if their arguments don't depend on the variable.
+//!
+//! Derivatives are usually much bigger than the original expression, so you
+//! might need a `Slab` with a bigger capacity.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::with_capacity(256);
+//!     let mut map = fasteval::StringToF64Namespace::new();
+//!     map.insert("x".to_string(), 2.0);
+//!
+//!     let expr_i = fasteval::Parser::new().parse("x^3 + 3*sin(x)", &mut slab.ps)?;
+//!     let deriv_i = fasteval::derive(expr_i, "x", &mut slab.ps)?;
+//!
+//!     // d/dx (x^3 + 3*sin(x)) = 3*x^2 + 3*cos(x)
+//!     let val = deriv_i.from(&slab.ps).eval(&slab, &mut map)?;
+//!     assert_eq!(val, 3.0*2.0f64.powi(2) + 3.0*2.0f64.cos());
+//!
+//!     // Compile the derivative to fold the constants away:
+//!     let instr = deriv_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     assert_eq!(instr.eval(&slab, &mut map)?, val);
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::ParseSlab;
+use crate::compiler::ExprSlice;
+use crate::parser::{Expression, ExpressionI, ExprPair, Value::{self, EConstant, EExactConstant, EUnaryOp, EStdFunc, EPrintFunc}, UnaryOp::{EPos, ENeg, ENot, EBitNot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EBitOr, EBitXor, EBitAnd, EShr, EShl, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH}, UserFunc, Statement, ExpressionOrString::EExpr};
+#[cfg(feature="unsafe-vars")]
+use crate::parser::StdFunc::EUnsafeVar;
+
+/// Calculates the derivative of the `Expression` at `expr_i` with respect to
+/// the variable `var`.
+///
+/// The new `Expression` is stored in `pslab`, and its index is returned.
+///
+/// [See the `derive` module documentation.](index.html)
+pub fn derive(expr_i:ExpressionI, var:&str, pslab:&mut ParseSlab) -> Result<ExpressionI,Error> {
+    let mut deriver = Deriver{ var, pslab, locals:Vec::new() };
+    let d = deriver.expr(expr_i)?;
+    deriver.or_zero(d)
+}
+
+// Removes the partial derivatives that derive() defined for the user-defined
+// function 'name', and for the functions that call it, directly or indirectly.
+// Parser::define() calls this before 'name' is redefined, because their bodies
+// were derived from the old definition.  Other partials are still valid.
+pub(crate) fn forget_partials(pslab:&mut ParseSlab, name:&str) {
+    let mut stale = vec![name.to_string()];
+    let mut i = 0;
+    while let Some(callee) = stale.get(i).cloned() {
+        for (fname,f) in pslab.funcs.iter() {
+            if !fname.contains('\'') && f.calls.contains(&callee) && !stale.contains(fname) { stale.push(fname.clone()); }
+        }
+        i += 1;
+    }
+    // The partials are named like `f'x` and `f'x'y`:
+    pslab.funcs.retain(|fname,_| match fname.find('\'') {
+        Some(i) => !stale.iter().any(|s| s==&fname[..i]),
+        None => true,
+    });
+}
+
+// A binary tree with the same structure that the Compiler finds in a flat
+// Expression.  It owns its Values so that we can push to the ParseSlab while
+// we walk it.
+enum Tree {
+    Leaf(Value),
+    Bin(BinaryOp, Box<Tree>, Box<Tree>),
+}
+
+impl Tree {
+    fn from_slice(xs:&ExprSlice) -> Self {
+        let mut lowest_op = match xs.pairs.first() {
+            Some(p0) => p0.0,
+            None => return Tree::Leaf(xs.first.clone()),
+        };
+        for exprpair in xs.pairs.iter() {
+            if exprpair.0<lowest_op { lowest_op=exprpair.0 }
+        }
+
+        let mut xss = Vec::<ExprSlice>::with_capacity(4);
+        let mut ops = Vec::<&BinaryOp>::with_capacity(4);
+        match lowest_op {
+            // All comparisons have equal precedence:
+            EEQ | ENE | ELT | EGT | ELTE | EGTE => xs.split_multi(&[EEQ, ENE, ELT, EGT, ELTE, EGTE], &mut xss, &mut ops),
+            EShl | EShr => xs.split_multi(&[EShl, EShr], &mut xss, &mut ops),
+            _ => {
+                xs.split(lowest_op, &mut xss);
+                for _ in 1..xss.len() { ops.push(&lowest_op); }
+            }
+        }
+
+        let mut trees = xss.iter().map(Tree::from_slice);
+        if lowest_op==EExp {
+            // Right-to-Left Associativity
+            let mut trees = trees.rev();
+            let mut out = trees.next().unwrap_or(Tree::Leaf(EConstant(std::f64::NAN)));  // unwrap_or is unreachable
+            for t in trees { out = Tree::Bin(EExp, Box::new(t), Box::new(out)); }
+            out
+        } else {
+            let mut out = trees.next().unwrap_or(Tree::Leaf(EConstant(std::f64::NAN)));  // unwrap_or is unreachable
+            for (t,op) in trees.zip(ops) { out = Tree::Bin(*op, Box::new(out), Box::new(t)); }
+            out
+        }
+    }
+}
+
+// A derivative.  `None` means zero, which lets us skip a lot of useless terms.
+type D = Option<ExpressionI>;
+
+struct Deriver<'a> {
+    var   :&'a str,
+    pslab :&'a mut ParseSlab,
+    locals:Vec<(String,bool)>,  // The variables that a program has assigned so far, and whether their derivative is stored.
+}
+
+impl Deriver<'_> {
+    //---- Expression builders:
+
+    fn push(&mut self, first:Value) -> Result<ExpressionI,Error> {
+        self.pslab.push_expr(Expression{first, pairs:Vec::new()})
+    }
+    fn num(&mut self, c:f64) -> Result<ExpressionI,Error> {
+        self.push(EConstant(c))
+    }
+    fn func(&mut self, f:StdFunc) -> Result<ExpressionI,Error> {
+        self.push(EStdFunc(f))
+    }
+    fn bin(&mut self, l:ExpressionI, op:BinaryOp, r:ExpressionI) -> Result<ExpressionI,Error> {
+        let l = self.atom(l);
+        let r = self.atom(r);
+        self.pslab.push_expr(Expression{first:l, pairs:vec![ExprPair(op, r)]})
+    }
+    // Wraps an Expression in parentheses, unless it is a single constant or function call.
+    fn atom(&self, x:ExpressionI) -> Value {
+        let expr = get_expr!(self.pslab,x);
+        match expr.first {
+            EConstant(_) | EExactConstant(..) | EStdFunc(_) if expr.pairs.is_empty() => expr.first.clone(),
+            _ => EUnaryOp(EParentheses(x)),
+        }
+    }
+    fn neg(&mut self, x:ExpressionI) -> Result<ExpressionI,Error> {
+        let val_i = self.pslab.push_val(EUnaryOp(EParentheses(x)))?;
+        self.push(EUnaryOp(ENeg(val_i)))
+    }
+    fn pow(&mut self, x:ExpressionI, power:f64) -> Result<ExpressionI,Error> {
+        let power = self.num(power)?;
+        self.bin(x, EExp, power)
+    }
+    fn ln(&mut self, x:ExpressionI) -> Result<ExpressionI,Error> {
+        let e = self.func(EFuncE)?;
+        self.func(EFuncLog{base:Some(e), expr:x})
+    }
+    fn emit(&mut self, t:&Tree) -> Result<ExpressionI,Error> {
+        match t {
+            Tree::Leaf(v) => self.push(v.clone()),
+            Tree::Bin(op,l,r) => {
+                let l = self.emit(l)?;
+                let r = self.emit(r)?;
+                self.bin(l, *op, r)
+            }
+        }
+    }
+
+    //---- Derivative builders:
+
+    fn or_zero(&mut self, d:D) -> Result<ExpressionI,Error> {
+        match d {
+            Some(d) => Ok(d),
+            None => self.num(0.0),
+        }
+    }
+    fn add(&mut self, a:D, b:D) -> Result<D,Error> {
+        match (a,b) {
+            (Some(a), Some(b)) => Ok(Some(self.bin(a, EAdd, b)?)),
+            (a, None) => Ok(a),
+            (None, b) => Ok(b),
+        }
+    }
+    fn sub(&mut self, a:D, b:D) -> Result<D,Error> {
+        match (a,b) {
+            (Some(a), Some(b)) => Ok(Some(self.bin(a, ESub, b)?)),
+            (a, None) => Ok(a),
+            (None, Some(b)) => Ok(Some(self.neg(b)?)),
+        }
+    }
+    fn scale(&mut self, d:D, x:ExpressionI) -> Result<D,Error> {
+        match d {
+            Some(d) => Ok(Some(self.bin(x, EMul, d)?)),
+            None => Ok(None),
+        }
+    }
+    fn times(&mut self, d:D, t:&Tree) -> Result<D,Error> {
+        match d {
+            Some(_) => { let x=self.emit(t)?; self.scale(d, x) }
+            None => Ok(None),
+        }
+    }
+    fn over(&mut self, d:D, x:ExpressionI) -> Result<D,Error> {
+        match d {
+            Some(d) => Ok(Some(self.bin(d, EDiv, x)?)),
+            None => Ok(None),
+        }
+    }
+    fn cond(&mut self, cond:ExpressionI, then:D, otherwise:D) -> Result<D,Error> {
+        if then.is_none() && otherwise.is_none() { return Ok(None); }
+        let then = self.or_zero(then)?;
+        let otherwise = self.or_zero(otherwise)?;
+        Ok(Some(self.func(EFuncIf{cond, then, otherwise})?))
+    }
+
+    //---- Derivatives:
+
+    fn expr(&mut self, expr_i:ExpressionI) -> Result<D,Error> {
+        let tree = Tree::from_slice(&ExprSlice::from_expr(get_expr!(self.pslab,expr_i)));
+        self.tree(&tree)
+    }
+
+    fn tree(&mut self, t:&Tree) -> Result<D,Error> {
+        let (op,l,r) = match t {
+            Tree::Leaf(v) => return self.value(v),
+            Tree::Bin(op,l,r) => (*op,l,r),
+        };
+        let dl = self.tree(l)?;
+        let dr = self.tree(r)?;
+        match op {
+            EAdd => self.add(dl, dr),
+            ESub => self.sub(dl, dr),
+            EMul => {
+                let a = self.times(dl, r)?;
+                let b = self.times(dr, l)?;
+                self.add(a, b)
+            }
+            EDiv => {
+                // (l/r)' = (l'*r - l*r') / r^2
+                if dr.is_none() {
+                    let r = self.emit(r)?;
+                    return self.over(dl, r);
+                }
+                let a = self.times(dl, r)?;
+                let b = self.times(dr, l)?;
+                let num = self.sub(a, b)?;
+                let r = self.emit(r)?;
+                let r2 = self.pow(r, 2.0)?;
+                self.over(num, r2)
+            }
+            EMod => {
+                // l % r = l - r*int(l/r)
+                if dr.is_none() { return Ok(dl); }
+                let l = self.emit(l)?;
+                let r = self.emit(r)?;
+                let q = self.bin(l, EDiv, r)?;
+                let q = self.func(EFuncInt(q))?;
+                let b = self.scale(dr, q)?;
+                self.sub(dl, b)
+            }
+            EExp => {
+                let le = self.emit(l)?;
+                if let Tree::Leaf(EConstant(c)) = **r {
+                    // (l^c)' = c * l^(c-1) * l'
+                    if f64_eq!(c,1.0) { return Ok(dl); }
+                    let p = if f64_eq!(c,2.0) { le } else { self.pow(le, c-1.0)? };
+                    let d = self.scale(dl, p)?;
+                    let c = self.num(c)?;
+                    return self.scale(d, c);
+                }
+                let re = self.emit(r)?;
+                if dr.is_none() {
+                    // (l^c)' = c * l^(c-1) * l'
+                    let one = self.num(1.0)?;
+                    let c1 = self.bin(re, ESub, one)?;
+                    let p = self.bin(le, EExp, c1)?;
+                    let d = self.scale(dl, p)?;
+                    return self.scale(d, re);
+                }
+                // (l^r)' = l^r * (r'*ln(l) + r*l'/l)
+                let p = self.bin(le, EExp, re)?;
+                let lnl = self.ln(le)?;
+                let a = self.scale(dr, lnl)?;
+                let b = self.over(dl, le)?;
+                let b = self.scale(b, re)?;
+                let sum = self.add(a, b)?;
+                self.scale(sum, p)
+            }
+            EEQ | ENE | ELT | EGT | ELTE | EGTE => Ok(None),
+            EBitOr | EBitXor | EBitAnd | EShl | EShr => Ok(None),
+            EOR => {
+                let l = self.emit(l)?;
+                self.cond(l, dl, dr)
+            }
+            EAND => {
+                let l = self.emit(l)?;
+                self.cond(l, dr, dl)
+            }
+        }
+    }
+
+    fn value(&mut self, v:&Value) -> Result<D,Error> {
+        match v {
+            EConstant(_) | EExactConstant(..) => Ok(None),
+            EUnaryOp(EPos(val_i)) => { let v=get_val!(self.pslab,val_i).clone(); self.value(&v) }
+            EUnaryOp(ENeg(val_i)) => {
+                let v = get_val!(self.pslab,val_i).clone();
+                match self.value(&v)? {
+                    Some(d) => Ok(Some(self.neg(d)?)),
+                    None => Ok(None),
+                }
+            }
+            EUnaryOp(ENot(_)) | EUnaryOp(EBitNot(_)) => Ok(None),
+            EUnaryOp(EParentheses(expr_i)) => self.expr(*expr_i),
+            EStdFunc(f) => self.stdfunc(f),
+            EPrintFunc(pf) => {
+                // print() returns the value of its last expression:
+                match pf.0.iter().rev().find_map(|x_or_s| if let EExpr(expr_i) = x_or_s { Some(*expr_i) } else { None }) {
+                    Some(expr_i) => self.expr(expr_i),
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    // The chain rule for a user-defined function: f(a, b)' = f'a(a, b) * a' + f'b(a, b) * b'
+    // The partial derivatives are defined as new user-defined functions, so that
+    // their parameters are bound to the arguments.  If the body uses the
+    // variable directly, f'var(a, b) is added too.
+    fn user_func(&mut self, name:&str, args:&[ExpressionI]) -> Result<D,Error> {
+        let params = match self.pslab.funcs.get(name) {
+            Some(f) => f.params.clone(),
+            None => return Err(Error::Unreachable),
+        };
+        if params.len()!=args.len() {
+            return Err(Error::NotDifferentiable(format!("custom function `{}`", name)));
+        }
+        let mut out = None;
+        for (param,arg) in params.iter().zip(args) {
+            let da = self.expr(*arg)?;
+            if da.is_none() { continue; }
+            if let Some(partial) = self.partial(name, param)? {
+                let call = self.func(EFunc{name:partial, args:args.to_vec()})?;
+                let term = self.scale(da, call)?;
+                out = self.add(out, term)?;
+            }
+        }
+        if !params.iter().any(|p| p==self.var) {
+            let var = self.var;
+            if let Some(partial) = self.partial(name, var)? {
+                let call = self.func(EFunc{name:partial, args:args.to_vec()})?;
+                out = self.add(out, Some(call))?;
+            }
+        }
+        Ok(out)
+    }
+    // Defines the partial derivative of the user-defined function 'name' with
+    // respect to 'wrt', and returns its name, or None if it is zero.
+    fn partial(&mut self, name:&str, wrt:&str) -> Result<Option<String>,Error> {
+        let pname = format!("{}'{}", name, wrt);  // This can't collide with a name that the parser accepts.
+        if self.pslab.funcs.contains_key(&pname) { return Ok(Some(pname)); }
+        let f = match self.pslab.funcs.get(name) {
+            Some(f) => f.clone(),
+            None => return Err(Error::Unreachable),
+        };
+        let d = Deriver{ var:wrt, pslab:self.pslab, locals:Vec::new() }.expr(f.body)?;  // Function bodies don't see the locals of a program.
+        Ok(match d {
+            Some(body) => {
+                self.pslab.add_func(pname.clone(), UserFunc{params:f.params, body, calls:Vec::new()});
+                Some(pname)
+            }
+            None => None,
+        })
+    }
+
+    // A program is derived into another program:  Before each assignment
+    // `x = e`, it stores the derivative of 'e' in the local `x'var`, unless it
+    // is zero.  The other statements only matter for their print() output, so
+    // they are skipped, except for the last one.
+    fn program(&mut self, stmts:&[Statement]) -> Result<D,Error> {
+        let mut out = Vec::<Statement>::with_capacity(stmts.len()*2);
+        let mut d = None;
+        for Statement(name,expr_i) in stmts {
+            match name {
+                Some(name) => {
+                    let de = self.expr(*expr_i)?;
+                    let stored = self.locals.iter().any(|(n,stored)| n==name && *stored);
+                    if de.is_some() || stored {
+                        let de = self.or_zero(de)?;
+                        out.push(Statement(Some(format!("{}'{}", name, self.var)), de));
+                    }
+                    out.push(Statement(Some(name.clone()), *expr_i));
+                    match self.locals.iter_mut().find(|(n,_)| n==name) {
+                        Some(local) => local.1 = local.1 || de.is_some(),
+                        None => self.locals.push((name.clone(), de.is_some())),
+                    }
+                    d = if de.is_some() || stored { Some(self.func(EVar(format!("{}'{}", name, self.var)))?) } else { None };
+                }
+                None => d = self.expr(*expr_i)?,
+            }
+        }
+        let d = self.or_zero(d)?;
+        out.push(Statement(None, d));
+        self.locals.clear();
+        Ok(Some(self.func(EProgram(out))?))
+    }
+
+    fn stdfunc(&mut self, f:&StdFunc) -> Result<D,Error> {
+        // Many functions are of the form f(g(x)), so we can use the chain rule: f'(g(x)) * g'(x)
+        macro_rules! chain {
+            ($x:ident, $dx:ident, $body:block) => {{
+                let $dx = self.expr(*$x)?;
+                if $dx.is_none() { return Ok(None); }
+                let $x = *$x;
+                $body
+            }};
+        }
+
+        match f {
+            EVar(name) if self.locals.iter().any(|(n,_)| n==name) => {
+                match self.locals.iter().find(|(n,_)| n==name) {
+                    Some((_,true)) => Ok(Some(self.func(EVar(format!("{}'{}", name, self.var)))?)),
+                    _ => Ok(None),
+                }
+            }
+            EVar(name) | ESafeVar{name, ..} => Ok(if name==self.var { Some(self.num(1.0)?) } else { None }),
+            #[cfg(feature="unsafe-vars")]
+            EUnsafeVar{name, ..} => Ok(if name==self.var { Some(self.num(1.0)?) } else { None }),
+            EFunc{name, args} if self.pslab.funcs.contains_key(name) => self.user_func(name, args),
+            EFunc{name, args} => {
+                for expr_i in args {
+                    if self.expr(*expr_i)?.is_some() {
+                        return Err(Error::NotDifferentiable(format!("custom function `{}`", name)));
+                    }
+                }
+                Ok(None)
+            }
+
+            EFuncInt(_) | EFuncCeil(_) | EFuncFloor(_) | EFuncSign(_) | EFuncRound{..} | EFuncE | EFuncPi => Ok(None),
+            EFuncAbs(x) => chain!(x, dx, {
+                let s = self.func(EFuncSign(x))?;
+                self.scale(dx, s)
+            }),
+            EFuncLog{base, expr:x} => {
+                // log_b(x) = ln(x)/ln(b)
+                let dx = self.expr(*x)?;
+                let db = match base { Some(b) => self.expr(*b)?, None => None };
+                if dx.is_none() && db.is_none() { return Ok(None); }
+                let b = match base { Some(b) => *b, None => self.num(10.0)? };
+                let lnb = self.ln(b)?;
+                let xlnb = self.bin(*x, EMul, lnb)?;
+                let a = self.over(dx, xlnb)?;
+                if db.is_none() { return Ok(a); }
+                // ... - ln(x) * b' / (b * ln(b)^2)
+                let lnx = self.ln(*x)?;
+                let lnb2 = self.pow(lnb, 2.0)?;
+                let blnb2 = self.bin(b, EMul, lnb2)?;
+                let c = self.scale(db, lnx)?;
+                let c = self.over(c, blnb2)?;
+                self.sub(a, c)
+            }
+            EFuncMin{first, rest} | EFuncMax{first, rest} => {
+                // Follow the argument that is selected:  if(a==min(a,b,c), a', if(b==min(a,b,c), b', c'))
+                let mut args = Vec::with_capacity(rest.len()+1);
+                args.push(*first);
+                args.extend_from_slice(rest);
+                let mut dargs = Vec::with_capacity(args.len());
+                for expr_i in &args { dargs.push(self.expr(*expr_i)?); }
+                if dargs.iter().all(Option::is_none) { return Ok(None); }
+
+                let m = self.func(f.clone())?;
+                let mut d = dargs.pop().unwrap_or(None);
+                while let Some(darg) = dargs.pop() {
+                    let eq = self.bin(args[dargs.len()], EEQ, m)?;
+                    d = self.cond(eq, darg, d)?;
+                }
+                Ok(d)
+            }
+            EProgram(stmts) => self.program(stmts),
+            EFuncIf{cond, then, otherwise} => {
+                let dthen = self.expr(*then)?;
+                let dotherwise = self.expr(*otherwise)?;
+                self.cond(*cond, dthen, dotherwise)
+            }
+
+            EFuncSin(x) => chain!(x, dx, {
+                let c = self.func(EFuncCos(x))?;
+                self.scale(dx, c)
+            }),
+            EFuncCos(x) => chain!(x, dx, {
+                let s = self.func(EFuncSin(x))?;
+                let s = self.neg(s)?;
+                self.scale(dx, s)
+            }),
+            EFuncTan(x) => chain!(x, dx, {
+                let c = self.func(EFuncCos(x))?;
+                let c2 = self.pow(c, 2.0)?;
+                self.over(dx, c2)
+            }),
+            EFuncASin(x) | EFuncACos(x) => chain!(x, dx, {
+                // 1 / sqrt(1-x^2)
+                let one = self.num(1.0)?;
+                let x2 = self.pow(x, 2.0)?;
+                let den = self.bin(one, ESub, x2)?;
+                let den = self.pow(den, 0.5)?;
+                let d = self.over(dx, den)?;
+                if let EFuncACos(_) = f { self.sub(None, d) } else { Ok(d) }
+            }),
+            EFuncATan(x) => chain!(x, dx, {
+                let one = self.num(1.0)?;
+                let x2 = self.pow(x, 2.0)?;
+                let den = self.bin(one, EAdd, x2)?;
+                self.over(dx, den)
+            }),
+            EFuncSinH(x) => chain!(x, dx, {
+                let c = self.func(EFuncCosH(x))?;
+                self.scale(dx, c)
+            }),
+            EFuncCosH(x) => chain!(x, dx, {
+                let s = self.func(EFuncSinH(x))?;
+                self.scale(dx, s)
+            }),
+            EFuncTanH(x) => chain!(x, dx, {
+                let c = self.func(EFuncCosH(x))?;
+                let c2 = self.pow(c, 2.0)?;
+                self.over(dx, c2)
+            }),
+            EFuncASinH(x) | EFuncACosH(x) => chain!(x, dx, {
+                // 1 / sqrt(x^2+1)  or  1 / sqrt(x^2-1)
+                let one = self.num(1.0)?;
+                let x2 = self.pow(x, 2.0)?;
+                let den = self.bin(x2, if let EFuncASinH(_) = f { EAdd } else { ESub }, one)?;
+                let den = self.pow(den, 0.5)?;
+                self.over(dx, den)
+            }),
+            EFuncATanH(x) => chain!(x, dx, {
+                let one = self.num(1.0)?;
+                let x2 = self.pow(x, 2.0)?;
+                let den = self.bin(one, ESub, x2)?;
+                self.over(dx, den)
+            }),
+        }
+    }
+}
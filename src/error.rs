@@ -1,5 +1,21 @@
 //! This module contains `fasteval`'s Error type: an `enum` that contains all errors
 //! that can be produced by the `fasteval` API.
+//!
+//! # Examples
+//!
+//! Parse errors know where they occurred, so they can be rendered with a
+//! caret pointing at the problem:
+//!
+//! ```
+//! let expr_str = "1 + * 2";
+//! let err = fasteval::Parser::new().parse(expr_str, &mut fasteval::Slab::new().ps).unwrap_err();
+//!
+//! assert_eq!(err.to_string(), "expected a value at line 1, column 5");
+//! assert_eq!(err.render(expr_str),
+//! "expected a value at line 1, column 5
+//! 1 + * 2
+//!     ^");
+//! ```
 
 use std::fmt;
 
@@ -38,6 +54,11 @@ pub enum Error {
     /// when the UTF8 error occurred.
     Utf8ErrorWhileParsing(String),
 
+    /// A string contains an invalid escape sequence.
+    ///
+    /// The `String` field contains the escape sequence, like `\q`.
+    InvalidEscape(String),
+
     /// The expression string input was too long.
     ///
     /// This is a safety check that prevents malicious inputs that would
@@ -78,10 +99,121 @@ pub enum Error {
     /// You can define variables/functions with a Namespace.
     Undefined(String),
 
+    /// A user-defined function could not be defined.
+    ///
+    /// The `String` field tells you what is wrong with the definition.
+    InvalidDefinition(String),
+
+    /// An arithmetic operation overflowed.
+    ///
+    /// This is returned by number types that can't represent every result,
+    /// such as `i64` and [`Decimal`](../decimal/struct.Decimal.html), instead of
+    /// wrapping around.
+    Overflow,
+
+    /// An expression could not be differentiated.
+    ///
+    /// The `String` field tells you which part of the expression has no
+    /// known derivative.
+    NotDifferentiable(String),
+
+    /// An evaluation used more steps or Namespace calls than its
+    /// [`Budget`](../budget/struct.Budget.html) allows.
+    ///
+    /// This is a safety check that prevents malicious inputs that would
+    /// be expensive to evaluate.
+    BudgetExceeded,
+
+    /// An evaluation was stopped by the cancellation flag of its
+    /// [`Budget`](../budget/struct.Budget.html).
+    Cancelled,
+
     /// This error should never occur because it is only produced by code paths
     /// that should never execute.  This is more performant than using the
     /// `unreachable!()` macro.
     Unreachable,
+
+    /// A parse error, annotated with the location where parsing failed.
+    ///
+    /// All errors returned by [`Parser::parse()`](../parser/struct.Parser.html#method.parse)
+    /// are wrapped in this variant.  Use [`kind()`](#method.kind) to get the
+    /// inner error, and [`pos()`](#method.pos) to get the location.
+    Located(Box<Error>, Pos),
+}
+
+/// The location within an expression string where a parse error occurred.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pos {
+    /// Byte offset from the beginning of the expression string.
+    pub offset:usize,
+    /// Line number, starting from 1.
+    pub line  :usize,
+    /// Column number (in chars, not bytes), starting from 1.
+    pub col   :usize,
+}
+
+impl Pos {
+    /// Calculates the line and column of the byte `offset` within `src`.
+    pub fn new(src:&str, offset:usize) -> Self {
+        let mut line = 1;  let mut col = 1;
+        for &b in src.as_bytes().iter().take(offset) {
+            if b==b'\n' {
+                line += 1;
+                col = 1;
+            } else if b&0xc0 != 0x80 {  // Don't count UTF8 continuation bytes.
+                col += 1;
+            }
+        }
+        Pos{offset, line, col}
+    }
+}
+
+impl Error {
+    /// Wraps this error with the location of `offset` within `src`.
+    ///
+    /// Errors that already have a location are returned unchanged.
+    pub(crate) fn at(self, src:&str, offset:usize) -> Self {
+        match self {
+            Error::Located(..) => self,
+            _ => Error::Located(Box::new(self), Pos::new(src, offset)),
+        }
+    }
+
+    /// Returns the underlying error, without location information.
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Located(err, _) => err.kind(),
+            _ => self,
+        }
+    }
+
+    /// Returns the location where a parse error occurred, if it is known.
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            Error::Located(_, pos) => Some(*pos),
+            _ => None,
+        }
+    }
+
+    /// Renders this error as a message followed by the offending line of
+    /// `expr_str`, with a caret underneath the location of the error.
+    ///
+    /// `expr_str` should be the same string that was passed to `parse()`.
+    /// If the location is not known, only the message is returned.
+    pub fn render(&self, expr_str:&str) -> String {
+        let mut out = self.to_string();
+        if let Some(pos) = self.pos() {
+            let line = expr_str.lines().nth(pos.line-1).unwrap_or("");
+            out.push('\n');
+            out.push_str(line);
+            out.push('\n');
+            for c in line.chars().take(pos.col-1) {
+                out.push(if c=='\t' { '\t' } else { ' ' });  // Keep tabs so the caret lines up.
+            }
+            out.push('^');
+        }
+        out
+    }
 }
 
 impl std::error::Error for Error {
@@ -90,7 +222,29 @@ impl std::error::Error for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self)  // Re-use Debug for now...
+        match self {
+            Error::SlabOverflow => write!(f, "too many expressions, values, or instructions for the slab"),
+            Error::AlreadyExists => write!(f, "an entry with the same name already exists"),
+            Error::EOF => write!(f, "unexpected end of input"),
+            Error::EofWhileParsing(s) => write!(f, "unexpected end of input while parsing {}", s),
+            Error::Utf8ErrorWhileParsing(s) => write!(f, "invalid UTF-8 while parsing {}", s),
+            Error::InvalidEscape(s) => write!(f, "invalid escape sequence `{}` in string", s),
+            Error::TooLong => write!(f, "expression is too long"),
+            Error::TooDeep => write!(f, "expression is nested too deeply"),
+            Error::UnparsedTokensRemaining(s) => write!(f, "unexpected input after the end of the expression: `{}`", s),
+            Error::InvalidValue => write!(f, "expected a value"),
+            Error::ParseF64(s) => write!(f, "invalid number `{}`", s),
+            Error::Expected(s) => write!(f, "expected {}", s),
+            Error::WrongArgs(s) => write!(f, "{}", s),
+            Error::Undefined(s) => write!(f, "undefined variable `{}`", s),
+            Error::InvalidDefinition(s) => write!(f, "invalid function definition: {}", s),
+            Error::Overflow => write!(f, "arithmetic overflow"),
+            Error::NotDifferentiable(s) => write!(f, "cannot differentiate {}", s),
+            Error::BudgetExceeded => write!(f, "evaluation budget exceeded"),
+            Error::Cancelled => write!(f, "evaluation was cancelled"),
+            Error::Unreachable => write!(f, "internal error: entered unreachable code"),
+            Error::Located(err, pos) => write!(f, "{} at line {}, column {}", err, pos.line, pos.col),
+        }
     }
 }
 
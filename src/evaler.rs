@@ -10,23 +10,24 @@ use crate as fasteval;
 
 use crate::error::Error;
 use crate::slab::Slab;
-use crate::evalns::EvalNamespace;
+use crate::evalns::{EvalNamespace, LocalNamespace};
+use crate::hooks::{Hooks, NoHooks, HookedEvaler};
+use crate::number::NumberEvaler;
+use crate::trace::instruction_label;
 use crate::parser::{Expression,
-                    Value::{self, EConstant, EUnaryOp, EStdFunc, EPrintFunc},
-                    UnaryOp::{self, EPos, ENeg, ENot, EParentheses},
-                    BinaryOp::{self, EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND},
-                    StdFunc::{self, EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH},
-                    PrintFunc,
-                    ExpressionOrString::{EExpr, EStr},
-                    remove_no_panic};
+                    Value::{self, EConstant, EExactConstant, EUnaryOp, EStdFunc, EPrintFunc},
+                    UnaryOp::{self, EPos, ENeg, ENot, EBitNot, EParentheses},
+                    StdFunc::{self, EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH},
+                    Statement, PrintFunc,
+                    ExpressionOrString::{EExpr, EStr}};
 #[cfg(feature="unsafe-vars")]
 use crate::parser::StdFunc::EUnsafeVar;
-use crate::compiler::{log, IC, Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc}};
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, bitnot, store_local, IC, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}, InstructionI};
 #[cfg(feature="unsafe-vars")]
 use crate::compiler::Instruction::IUnsafeVar;
 
+use std::cell::RefCell;
 use std::collections::BTreeSet;
-use std::f64::consts;
 use std::fmt;
 
 
@@ -39,7 +40,7 @@ use std::fmt;
 /// Only use this for compiled expressions.  (If you use it for interpreted
 /// expressions, it will work but will always be slower than calling `eval()` directly.)
 ///
-/// This macro is able to eliminate function calls for constants and Unsafe Variables.
+/// This macro is able to eliminate function calls for constants, Safe Variables and Unsafe Variables.
 /// Since evaluation is a performance-critical operation, saving some function
 /// calls actually makes a huge performance difference.
 ///
@@ -48,6 +49,8 @@ macro_rules! eval_compiled {
     ($evaler:ident, $slab_ref:expr, $ns_mut:expr) => {
         if let fasteval::IConst(c) = $evaler {
             c
+        } else if let fasteval::ISafeVar{ref var, ..} = $evaler {
+            var.get()
         } else {
             #[cfg(feature="unsafe-vars")]
             {
@@ -78,7 +81,7 @@ macro_rules! eval_compiled {
 /// Only use this for compiled expressions.  (If you use it for interpreted
 /// expressions, it will work but will always be slower than calling `eval()` directly.)
 ///
-/// This macro is able to eliminate function calls for constants and Unsafe Variables.
+/// This macro is able to eliminate function calls for constants, Safe Variables and Unsafe Variables.
 /// Since evaluation is a performance-critical operation, saving some function
 /// calls actually makes a huge performance difference.
 ///
@@ -87,6 +90,8 @@ macro_rules! eval_compiled_ref {
     ($evaler:ident, $slab_ref:expr, $ns_mut:expr) => {
         if let fasteval::IConst(c) = $evaler {
             *c
+        } else if let fasteval::ISafeVar{var, ..} = $evaler {
+            var.get()
         } else {
             #[cfg(feature="unsafe-vars")]
             {
@@ -109,30 +114,87 @@ macro_rules! eval_compiled_ref {
     };
 }
 
-macro_rules! eval_ic_ref {
-    ($ic:ident, $slab_ref:ident, $ns_mut:expr) => {
-        match $ic {
-            IC::C(c) => *c,
-            IC::I(i) => {
-                let instr_ref = get_instr!($slab_ref.cs,i);
-
+// Like eval_compiled_ref!(), but for the recursion within the crate, which
+// passes the hooks of eval_traced() and eval_budgeted() along:
+macro_rules! eval_instr_ref {
+    ($instr_ref:expr, $slab_ref:ident, $ns_mut:expr, $hooks:expr) => {
+        {
+            let instr_ref = $instr_ref;
+            if let fasteval::IConst(c) = instr_ref {
+                *c
+            } else if let fasteval::ISafeVar{var, ..} = instr_ref {
+                var.get()
+            } else {
                 #[cfg(feature="unsafe-vars")]
                 {
                     if let fasteval::IUnsafeVar{ptr, ..} = instr_ref {
                         unsafe { **ptr }
                     } else {
-                        instr_ref.eval($slab_ref, $ns_mut)?
+                        instr_ref.eval_hooked($slab_ref, $ns_mut, $hooks)?
                     }
                 }
 
                 #[cfg(not(feature="unsafe-vars"))]
-                instr_ref.eval($slab_ref, $ns_mut)?
+                instr_ref.eval_hooked($slab_ref, $ns_mut, $hooks)?
+            }
+        }
+    };
+}
+
+macro_rules! eval_ic_ref {
+    ($ic:ident, $slab_ref:ident, $ns_mut:expr, $hooks:expr) => {
+        match $ic {
+            IC::C(c) => *c,
+            IC::I(i) => {
+                let instr_ref = get_instr!($slab_ref.cs,i);
+
+                if let fasteval::ISafeVar{var, ..} = instr_ref {
+                    var.get()
+                } else {
+                    #[cfg(feature="unsafe-vars")]
+                    {
+                        if let fasteval::IUnsafeVar{ptr, ..} = instr_ref {
+                            unsafe { **ptr }
+                        } else {
+                            instr_ref.eval_hooked($slab_ref, $ns_mut, $hooks)?
+                        }
+                    }
+
+                    #[cfg(not(feature="unsafe-vars"))]
+                    instr_ref.eval_hooked($slab_ref, $ns_mut, $hooks)?
+                }
             }
         }
     }
 }
 
 
+// These record the steps of an evaluation for eval_traced().  Evaluation only
+// pays for them when `hooks.tracing()` is true, and the steps themselves are
+// recorded by #[inline(never)] functions so that they don't make the stack
+// frames of the untraced recursion any bigger.
+//
+// Records the value of an operand:
+macro_rules! traced {
+    ($hooks:ident, $val:expr) => {
+        {
+            let val = $val;
+            if $hooks.tracing() { $hooks.trace_arg(crate::number::Number::to_f64(val)); }
+            val
+        }
+    };
+}
+// Evaluates one step, whose label is calculated from its children and its recorded operands:
+macro_rules! trace_step {
+    ($hooks:ident, $eval:expr, $label:expr) => {
+        {
+            $hooks.trace_enter();
+            let val = $eval;
+            $hooks.trace_exit(&$label, &val.clone().map(crate::number::Number::to_f64));
+            val
+        }
+    };
+}
 
 /// You must `use` this trait so you can call `.eval()`.
 pub trait Evaler : fmt::Debug {
@@ -164,221 +226,56 @@ impl Evaler for Expression {
         }
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
-        // Order of operations: 1) ^  2) */  3) +-
-        // Exponentiation should be processed right-to-left.  Think of what 2^3^4 should mean:
-        //     2^(3^4)=2417851639229258349412352   <--- I choose this one.  https://codeplea.com/exponentiation-associativity-options
-        //     (2^3)^4=4096
-        // Direction of processing doesn't matter for Addition and Multiplication:
-        //     (((3+4)+5)+6)==(3+(4+(5+6))), (((3*4)*5)*6)==(3*(4*(5*6)))
-        // ...But Subtraction and Division must be processed left-to-right:
-        //     (((6-5)-4)-3)!=(6-(5-(4-3))), (((6/5)/4)/3)!=(6/(5/(4/3)))
-
-
-        // // ---- Go code, for comparison ----
-        // // vals,ops:=make([]float64, len(e)/2+1),make([]BinaryOp, len(e)/2)
-        // // for i:=0; i<len(e); i+=2 {
-        // //     vals[i/2]=ns.EvalBubble(e[i].(evaler))
-        // //     if i<len(e)-1 { ops[i/2]=e[i+1].(BinaryOp) }
-        // // }
-
-        // if self.0.len()%2!=1 { return Err(KErr::new("Expression len should always be odd")) }
-        // let mut vals : Vec<f64>      = Vec::with_capacity(self.0.len()/2+1);
-        // let mut ops  : Vec<BinaryOp> = Vec::with_capacity(self.0.len()/2  );
-        // for (i,tok) in self.0.iter().enumerate() {
-        //     match tok {
-        //         EValue(val) => {
-        //             if i%2==1 { return Err(KErr::new("Found value at odd index")) }
-        //             match ns.eval_bubble(val) {
-        //                 Ok(f) => vals.push(f),
-        //                 Err(e) => return Err(e.pre(&format!("eval_bubble({:?})",val))),
-        //             }
-        //         }
-        //         EBinaryOp(bop) => {
-        //             if i%2==0 { return Err(KErr::new("Found binaryop at even index")) }
-        //             ops.push(*bop);
-        //         }
-        //     }
-        // }
-
-        // Code for new Expression data structure:
-        let mut vals = Vec::<f64>::with_capacity(self.pairs.len()+1);
-        let mut ops  = Vec::<BinaryOp>::with_capacity(self.pairs.len());
-        vals.push(self.first.eval(slab,ns)?);
-        for pair in self.pairs.iter() {
-            ops.push(pair.0);
-            vals.push(pair.1.eval(slab,ns)?);
-        }
-
-
-        // ---- Go code, for comparison ----
-        // evalOp:=func(i int) {
-        //     result:=ops[i]._Eval(vals[i], vals[i+1])
-        //     vals=append(append(vals[:i], result), vals[i+2:]...)
-        //     ops=append(ops[:i], ops[i+1:]...)
-        // }
-        // rtol:=func(s BinaryOp) { for i:=len(ops)-1; i>=0; i-- { if ops[i]==s { evalOp(i) } } }
-        // ltor:=func(s BinaryOp) {
-        //     loop:
-        //     for i:=0; i<len(ops); i++ { if ops[i]==s { evalOp(i); goto loop } }  // Need to restart processing when modifying from the left.
-        // }
-
-        #[inline(always)]
-        fn rtol(vals:&mut Vec<f64>, ops:&mut Vec<BinaryOp>, search:BinaryOp) {
-            for i in (0..ops.len()).rev() {
-                let op = match ops.get(i) {
-                    Some(op) => *op,
-                    None => EOR,  // unreachable
-                };
-                if op==search {
-                    let res = op.binaryop_eval(vals.get(i), vals.get(i+1));
-                    match vals.get_mut(i) {
-                        Some(val_ref) => *val_ref=res,
-                        None => (),  // unreachable
-                    };
-                    remove_no_panic(vals, i+1);
-                    remove_no_panic(ops, i);
-                }
-            }
-        }
-        #[inline(always)]
-        fn ltor(vals:&mut Vec<f64>, ops:&mut Vec<BinaryOp>, search:BinaryOp) {
-            let mut i = 0;
-            loop {
-                match ops.get(i) {
-                    None => break,
-                    Some(op) => {
-                        if *op==search {
-                            let res = op.binaryop_eval(vals.get(i), vals.get(i+1));
-                            match vals.get_mut(i) {
-                                Some(val_ref) => *val_ref=res,
-                                None => (),  // unreachable
-                            };
-                            remove_no_panic(vals, i+1);
-                            remove_no_panic(ops, i);
-                        } else {
-                            i=i+1;
-                        }
-                    }
-                }
-            }
-        }
-        #[inline(always)]
-        fn ltor_multi(vals:&mut Vec<f64>, ops:&mut Vec<BinaryOp>, search:&[BinaryOp]) {
-            let mut i = 0;
-            loop {
-                match ops.get(i) {
-                    None => break,
-                    Some(op) => {
-                        if search.contains(op) {
-                            let res = op.binaryop_eval(vals.get(i), vals.get(i+1));
-                            match vals.get_mut(i) {
-                                Some(val_ref) => *val_ref=res,
-                                None => (),  // unreachable
-                            };
-                            remove_no_panic(vals, i+1);
-                            remove_no_panic(ops, i);
-                        } else {
-                            i=i+1;
-                        }
-                    }
-                }
-            }
-        }
-
-        // Keep the order of these statements in-sync with parser.rs BinaryOp priority values:
-        rtol(&mut vals, &mut ops, EExp);  // https://codeplea.com/exponentiation-associativity-options
-        ltor(&mut vals, &mut ops, EMod);
-        ltor(&mut vals, &mut ops, EDiv);
-        rtol(&mut vals, &mut ops, EMul);
-        ltor(&mut vals, &mut ops, ESub);
-        rtol(&mut vals, &mut ops, EAdd);
-        ltor_multi(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE]);  // TODO: Implement Python-style a<b<c ternary comparison... might as well generalize to N comparisons.
-        ltor(&mut vals, &mut ops, EAND);
-        ltor(&mut vals, &mut ops, EOR);
-
-        if !ops.is_empty() { return Err(Error::Unreachable); }
-        if vals.len()!=1 { return Err(Error::Unreachable); }
-        match vals.first() {
-            Some(val) => Ok(*val),
-            None => Err(Error::Unreachable),
-        }
+        self.eval_number(slab,ns)
     }
 }
 
 impl Evaler for Value {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
         match self {
-            EConstant(_) => (),
+            EConstant(_) | EExactConstant(..) => (),
             EUnaryOp(u) => u._var_names(slab,dst),
             EStdFunc(f) => f._var_names(slab,dst),
             EPrintFunc(f) => f._var_names(slab,dst),
         };
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
-        match self {
-            EConstant(c) => Ok(*c),
-            EUnaryOp(u) => u.eval(slab,ns),
-            EStdFunc(f) => f.eval(slab,ns),
-            EPrintFunc(f) => f.eval(slab,ns),
-        }
+        self.eval_number(slab,ns)
     }
 }
 
 impl Evaler for UnaryOp {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
         match self {
-            EPos(val_i) | ENeg(val_i) | ENot(val_i) => get_val!(slab.ps,val_i)._var_names(slab,dst),
+            EPos(val_i) | ENeg(val_i) | ENot(val_i) | EBitNot(val_i) => get_val!(slab.ps,val_i)._var_names(slab,dst),
             EParentheses(expr_i) => get_expr!(slab.ps,expr_i)._var_names(slab,dst),
         }
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
-        match self {
-            EPos(val_i) => get_val!(slab.ps,val_i).eval(slab,ns),
-            ENeg(val_i) => Ok(-get_val!(slab.ps,val_i).eval(slab,ns)?),
-            ENot(val_i) => Ok(bool_to_f64!(f64_eq!(get_val!(slab.ps,val_i).eval(slab,ns)?,0.0))),
-            EParentheses(expr_i) => get_expr!(slab.ps,expr_i).eval(slab,ns),
-        }
+        self.eval_number(slab,ns)
     }
 }
 
-impl BinaryOp {
-    // Non-standard eval interface (not generalized yet):
-    fn binaryop_eval(self, left_opt:Option<&f64>, right_opt:Option<&f64>) -> f64 {  // Passing 'self' by value is more efficient than pass-by-reference.
-        let left = match left_opt {
-            Some(l) => *l,
-            None => return std::f64::NAN,
-        };
-        let right = match right_opt {
-            Some(r) => *r,
-            None => return std::f64::NAN,
-        };
-        match self {
-            EAdd => left+right,  // Floats don't overflow.
-            ESub => left-right,
-            EMul => left*right,
-            EDiv => left/right,
-            EMod => left%right, //left - (left/right).trunc()*right
-            EExp => left.powf(right),
-            ELT => bool_to_f64!(left<right),
-            ELTE => bool_to_f64!(left<=right),
-            EEQ => bool_to_f64!(f64_eq!(left,right)),
-            ENE => bool_to_f64!(f64_ne!(left,right)),
-            EGTE => bool_to_f64!(left>=right),
-            EGT => bool_to_f64!(left>right),
-            EOR => if f64_ne!(left,0.0) { left }
-                   else { right },
-            EAND => if f64_eq!(left,0.0) { left }
-                    else { right },
-        }
-    }
+thread_local! {
+    // A reusable buffer for the keys that the caching Namespaces build from a
+    // variable name and its arguments.
+    pub(crate) static KEYBUF : RefCell<String> = RefCell::new(String::with_capacity(64));
 }
 
 macro_rules! eval_var {
-    ($ns:ident, $name:ident, $args:expr, $keybuf:expr) => {
-        match $ns.lookup($name,$args,$keybuf) {
-            Some(f) => Ok(f),
-            None => Err(Error::Undefined($name.to_string())),
-        }
+    ($ns:ident, $name:ident, $args:expr) => {
+        crate::evaler::KEYBUF.with(|keybuf| {
+            // A Namespace can evaluate other expressions during a lookup, in
+            // which case the buffer is already borrowed:
+            let val = match keybuf.try_borrow_mut() {
+                Ok(mut keybuf) => $ns.lookup($name,$args,&mut keybuf),
+                Err(_) => $ns.lookup($name,$args,&mut String::new()),
+            };
+            match val {
+                Some(f) => Ok(f),
+                None => Err(crate::error::Error::Undefined($name.to_string())),
+            }
+        })
     };
 }
 
@@ -388,8 +285,22 @@ impl Evaler for StdFunc {
             #[cfg(feature="unsafe-vars")]
             EUnsafeVar{name, ..} => { dst.insert(name.clone()); }
 
+            ESafeVar{name, ..} => { dst.insert(name.clone()); }
             EVar(s) => { dst.insert(s.clone()); }
-            EFunc{name, ..} => { dst.insert(name.clone()); }
+            EFunc{name, args} => {
+                match slab.ps.funcs.get(name) {
+                    // Report the free variables of a user-defined function, but not its parameters:
+                    Some(f) => {
+                        for xi in args { get_expr!(slab.ps,xi)._var_names(slab,dst); }
+                        let mut body = BTreeSet::new();
+                        slab.ps.get_expr(f.body)._var_names(slab,&mut body);
+                        for n in body {
+                            if !f.params.contains(&n) { dst.insert(n); }
+                        }
+                    }
+                    None => { dst.insert(name.clone()); }
+                }
+            }
 
             EFuncInt(xi) | EFuncCeil(xi) | EFuncFloor(xi) | EFuncAbs(xi) | EFuncSign(xi) | EFuncSin(xi) | EFuncCos(xi) | EFuncTan(xi) | EFuncASin(xi) | EFuncACos(xi) | EFuncATan(xi) | EFuncSinH(xi) | EFuncCosH(xi) | EFuncTanH(xi) | EFuncASinH(xi) | EFuncACosH(xi) | EFuncATanH(xi) => get_expr!(slab.ps,xi)._var_names(slab,dst),
 
@@ -408,84 +319,28 @@ impl Evaler for StdFunc {
                     get_expr!(slab.ps,xi)._var_names(slab,dst);
                 }
             }
+            EFuncIf{cond,then,otherwise} => {
+                // Report both branches, even though only one will be evaluated:
+                get_expr!(slab.ps,cond)._var_names(slab,dst);
+                get_expr!(slab.ps,then)._var_names(slab,dst);
+                get_expr!(slab.ps,otherwise)._var_names(slab,dst);
+            }
+            EProgram(stmts) => {
+                // Report the variables that are used before the program assigns them:
+                let mut assigned = Vec::<&String>::new();
+                for Statement(name, xi) in stmts {
+                    let mut names = BTreeSet::new();
+                    get_expr!(slab.ps,xi)._var_names(slab,&mut names);
+                    for n in names {
+                        if !assigned.contains(&&n) { dst.insert(n); }
+                    }
+                    if let Some(name) = name { assigned.push(name); }
+                }
+            }
         };
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
-        match self {
-            // These match arms are ordered in a way that I feel should deliver good performance.
-            // (I don't think this ordering actually affects the generated code, though.)
-
-            #[cfg(feature="unsafe-vars")]
-            EUnsafeVar{ptr, ..} => unsafe { Ok(**ptr) },
-
-            EVar(name) => eval_var!(ns, name, Vec::new(), unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }),
-            EFunc{name, args:xis} => {
-                let mut args = Vec::with_capacity(xis.len());
-                for xi in xis {
-                    args.push(get_expr!(slab.ps,xi).eval(slab,ns)?)
-                }
-                eval_var!(ns, name, args, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) })
-            }
-
-            EFuncLog{base:base_opt, expr:expr_i} => {
-                let base = match base_opt {
-                    Some(b_expr_i) => get_expr!(slab.ps,b_expr_i).eval(slab,ns)?,
-                    None => 10.0,
-                };
-                let n = get_expr!(slab.ps,expr_i).eval(slab,ns)?;
-                Ok(log(base,n))
-            }
-
-            EFuncSin(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.sin()),
-            EFuncCos(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.cos()),
-            EFuncTan(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.tan()),
-            EFuncASin(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.asin()),
-            EFuncACos(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.acos()),
-            EFuncATan(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.atan()),
-            EFuncSinH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.sinh()),
-            EFuncCosH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.cosh()),
-            EFuncTanH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.tanh()),
-            EFuncASinH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.asinh()),
-            EFuncACosH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.acosh()),
-            EFuncATanH(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.atanh()),
-
-            EFuncRound{modulus:modulus_opt, expr:expr_i} => {
-                let modulus = match modulus_opt {
-                    Some(m_expr_i) => get_expr!(slab.ps,m_expr_i).eval(slab,ns)?,
-                    None => 1.0,
-                };
-                Ok((get_expr!(slab.ps,expr_i).eval(slab,ns)?/modulus).round() * modulus)
-            }
-
-            EFuncAbs(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.abs()),
-            EFuncSign(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.signum()),
-            EFuncInt(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.trunc()),
-            EFuncCeil(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.ceil()),
-            EFuncFloor(expr_i) => Ok(get_expr!(slab.ps,expr_i).eval(slab,ns)?.floor()),
-            EFuncMin{first:first_i, rest} => {
-                let mut min = get_expr!(slab.ps,first_i).eval(slab,ns)?;
-                let mut saw_nan = min.is_nan();
-                for x_i in rest.iter() {
-                    min = min.min(get_expr!(slab.ps,x_i).eval(slab,ns)?);
-                    saw_nan = saw_nan || min.is_nan();
-                }
-                if saw_nan { Ok(std::f64::NAN)
-                } else { Ok(min) }
-            }
-            EFuncMax{first:first_i, rest} => {
-                let mut max = get_expr!(slab.ps,first_i).eval(slab,ns)?;
-                let mut saw_nan = max.is_nan();
-                for x_i in rest.iter() {
-                    max = max.max(get_expr!(slab.ps,x_i).eval(slab,ns)?);
-                    saw_nan = saw_nan || max.is_nan();
-                }
-                if saw_nan { Ok(std::f64::NAN)
-                } else { Ok(max) }
-            }
-
-            EFuncE => Ok(consts::E),
-            EFuncPi => Ok(consts::PI),
-        }
+        self.eval_number(slab,ns)
     }
 }
 
@@ -499,41 +354,24 @@ impl Evaler for PrintFunc {
         }
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
-        let mut val = 0f64;
-
-        fn process_str(s:&str) -> String {
-            s.replace("\\n","\n").replace("\\t","\t")
-        }
-
-        if let Some(EStr(fmtstr)) = self.0.first() {
-            if fmtstr.contains('%') {
-                // printf mode:
-
-                //let fmtstr = process_str(fmtstr);
-
-                return Err(Error::WrongArgs("printf formatting is not yet implemented".to_string()));  // TODO: Make a pure-rust sprintf libarary.
-
-                //return Ok(val);
-            }
-        }
+        self.eval_number(slab,ns)
+    }
+}
 
-        // Normal Mode:
-        let mut out = String::with_capacity(16);
-        for (i,a) in self.0.iter().enumerate() {
-            if i>0 { out.push(' '); }
-            match a {
-                EExpr(e_i) => {
-                    val = get_expr!(slab.ps,e_i).eval(slab,ns)?;
-                    out.push_str(&val.to_string());
+// Parsed expressions are evaluated by eval_number() with f64:
+macro_rules! impl_hooked_evaler {
+    ($($t:ty),*) => {
+        $(
+            impl HookedEvaler for $t {
+                #[inline]
+                fn eval_hooked(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+                    self.eval_number_hooked(slab,ns,hooks)
                 }
-                EStr(s) => out.push_str(&process_str(s))
             }
-        }
-        eprintln!("{}", out);
-
-        Ok(val)
-    }
+        )*
+    };
 }
+impl_hooked_evaler!(Expression, Value, UnaryOp, StdFunc, PrintFunc);
 
 impl Evaler for Instruction {
     fn _var_names(&self, slab:&Slab, dst:&mut BTreeSet<String>) {
@@ -542,13 +380,15 @@ impl Evaler for Instruction {
             IUnsafeVar{name, ..} => { dst.insert(name.clone()); }
 
             IVar(s) => { dst.insert(s.clone()); }
+            ISlot{name, ..} => { dst.insert(name.clone()); }
+            ISafeVar{name, ..} => { dst.insert(name.clone()); }
             IFunc{name, ..} => { dst.insert(name.clone()); }
 
-            IConst(_) => (),
+            IConst(_) | ILocal(_) => (),
 
-            INeg(ii) | INot(ii) | IInv(ii) | IFuncInt(ii) | IFuncCeil(ii) | IFuncFloor(ii) | IFuncAbs(ii) | IFuncSign(ii) | IFuncSin(ii) | IFuncCos(ii) | IFuncTan(ii) | IFuncASin(ii) | IFuncACos(ii) | IFuncATan(ii) | IFuncSinH(ii) | IFuncCosH(ii) | IFuncTanH(ii) | IFuncASinH(ii) | IFuncACosH(ii) | IFuncATanH(ii) => get_instr!(slab.cs,ii)._var_names(slab,dst),
+            INeg(ii) | INot(ii) | IInv(ii) | IBitNot(ii) | IFuncInt(ii) | IFuncCeil(ii) | IFuncFloor(ii) | IFuncAbs(ii) | IFuncSign(ii) | IFuncSin(ii) | IFuncCos(ii) | IFuncTan(ii) | IFuncASin(ii) | IFuncACos(ii) | IFuncATan(ii) | IFuncSinH(ii) | IFuncCosH(ii) | IFuncTanH(ii) | IFuncASinH(ii) | IFuncACosH(ii) | IFuncATanH(ii) => get_instr!(slab.cs,ii)._var_names(slab,dst),
 
-            ILT(lic,ric) | ILTE(lic,ric) | IEQ(lic,ric) | INE(lic,ric) | IGTE(lic,ric) | IGT(lic,ric) | IMod{dividend:lic, divisor:ric} | IExp{base:lic, power:ric} | IFuncLog{base:lic, of:ric} | IFuncRound{modulus:lic, of:ric} => {
+            ILT(lic,ric) | ILTE(lic,ric) | IEQ(lic,ric) | INE(lic,ric) | IGTE(lic,ric) | IGT(lic,ric) | IMod{dividend:lic, divisor:ric} | IExp{base:lic, power:ric} | IBitOr(lic,ric) | IBitXor(lic,ric) | IBitAnd(lic,ric) | IShl(lic,ric) | IShr(lic,ric) | IFuncLog{base:lic, of:ric} | IFuncRound{modulus:lic, of:ric} => {
                 let mut iconst : Instruction;
                 ic_to_instr!(slab.cs,iconst,lic)._var_names(slab,dst);
                 ic_to_instr!(slab.cs,iconst,ric)._var_names(slab,dst);
@@ -560,77 +400,164 @@ impl Evaler for Instruction {
                 ic_to_instr!(slab.cs,iconst,ric)._var_names(slab,dst);
             }
 
+            IFuncIf{cond, then, otherwise} => {
+                get_instr!(slab.cs,cond)._var_names(slab,dst);
+                let mut iconst : Instruction;
+                ic_to_instr!(slab.cs,iconst,then)._var_names(slab,dst);
+                ic_to_instr!(slab.cs,iconst,otherwise)._var_names(slab,dst);
+            }
+
             IPrintFunc(pf) => pf._var_names(slab,dst),
+
+            ICall{names, args, body, ..} => {
+                let mut iconst : Instruction;
+                for ic in args {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+                // Report the free variables of the body, but not the parameters:
+                let mut body_names = BTreeSet::new();
+                get_instr!(slab.cs,body)._var_names(slab,&mut body_names);
+                for n in body_names {
+                    if !names.contains(&n) { dst.insert(n); }
+                }
+            }
+            IProgram{stmts, ..} => {
+                let mut iconst : Instruction;
+                for (_,ic) in stmts {
+                    ic_to_instr!(slab.cs,iconst,ic)._var_names(slab,dst);
+                }
+            }
         }
     }
     fn eval(&self, slab:&Slab, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+        self.eval_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl HookedEvaler for Instruction {
+    #[inline]
+    fn eval_hooked(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+        if !hooks.step() { return Err(Error::BudgetExceeded); }
+        if hooks.tracing() { return self.eval_traced(slab,ns,hooks); }
+        self.eval_instr(slab,ns,hooks)
+    }
+}
+impl Instruction {
+    #[inline(never)]
+    fn eval_traced(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+        let local = match self {
+            // Constants and inlined variables are shown in the labels of the Instructions that use them,
+            // and print() and programs record their own steps:
+            IConst(c) => return Ok(*c),
+            ISafeVar{var, ..} => return Ok(var.get()),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => return unsafe { Ok(**ptr) },
+            IPrintFunc(_) | IProgram{..} => return self.eval_other(slab,ns,hooks),
+            ILocal(i) => Some(ns.local_name(*i).unwrap_or("").to_string()),
+            _ => None,
+        };
+        trace_step!(hooks, self.eval_instr(slab,ns,hooks), |children,_| match &local {
+            Some(name) => name.clone(),
+            None => instruction_label(self,slab,children),
+        })
+    }
+
+    #[inline(always)]
+    fn eval_instr(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
         match self {
             // I have manually ordered these match arms in a way that I feel should deliver good performance.
             // (I don't think this ordering actually affects the generated code, though.)
 
             IMul(li,ric) => {
-                Ok( eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns) *
-                    eval_ic_ref!(ric,slab,ns) )
+                Ok( eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, hooks) *
+                    eval_ic_ref!(ric,slab,ns,hooks) )
             }
             IAdd(li,ric) => {
-                Ok( eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns) +
-                    eval_ic_ref!(ric, slab, ns) )
+                Ok( eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, hooks) +
+                    eval_ic_ref!(ric, slab, ns, hooks) )
             }
             IExp{base, power} => {
-                Ok( eval_ic_ref!(base, slab, ns).powf(
-                    eval_ic_ref!(power, slab, ns) ) )
+                Ok( eval_ic_ref!(base, slab, ns, hooks).powf(
+                    eval_ic_ref!(power, slab, ns, hooks) ) )
+            }
+
+            INeg(i) => Ok(-eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks)),
+            IInv(i) => Ok(1.0/eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks)),
+
+            ISlot{name, slot} => {
+                match ns.slot(*slot) {
+                    Some(f) => Ok(f),
+                    // The Namespace doesn't supply this slot, so look it up by name:
+                    None => eval_var!(ns, name, Vec::new()),
+                }
             }
+            IVar(name) => eval_var!(ns, name, Vec::new()),
+            ILocal(i) => ns.local(*i).ok_or(Error::Unreachable),
 
-            INeg(i) => Ok(-eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns)),
-            IInv(i) => Ok(1.0/eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns)),
+            // Put these last because you should be using the eval_compiled*!() macros to eliminate function calls.
+            IConst(c) => Ok(*c),
+            ISafeVar{var, ..} => Ok(var.get()),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => unsafe { Ok(**ptr) },
 
-            IVar(name) => eval_var!(ns, name, Vec::new(), unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) }),
+            _ => self.eval_other(slab,ns,hooks),
+        }
+    }
+
+    // The less common Instructions are evaluated here, so that their temporaries
+    // don't make every recursive eval() call use more stack.
+    #[inline(never)]
+    fn eval_other(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+        match self {
             IFunc{name, args:ics} => {
+                // A user-defined function that was redefined with a different
+                // number of parameters after the call was parsed.  The compiler
+                // leaves it for us to report, like the interpreter does:
+                if let Some(f) = slab.ps.funcs.get(name) { f.check_args(name, ics.len())?; }
                 let mut args = Vec::with_capacity(ics.len());
                 for ic in ics {
-                    args.push( eval_ic_ref!(ic, slab, ns) );
+                    args.push( eval_ic_ref!(ic, slab, ns, hooks) );
                 }
-                eval_var!(ns, name, args, unsafe{ &mut *(&slab.ps.char_buf as *const _ as *mut _) })
+                eval_var!(ns, name, args)
             },
 
             IFuncLog{base:baseic, of:ofic} => {
-                let base = eval_ic_ref!(baseic, slab, ns);
-                let of = eval_ic_ref!(ofic, slab, ns);
+                let base = eval_ic_ref!(baseic, slab, ns, hooks);
+                let of = eval_ic_ref!(ofic, slab, ns, hooks);
                 Ok(log(base,of))
             }
 
-            IFuncSin(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).sin() ),
-            IFuncCos(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).cos() ),
-            IFuncTan(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).tan() ),
-            IFuncASin(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).asin() ),
-            IFuncACos(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).acos() ),
-            IFuncATan(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).atan() ),
-            IFuncSinH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).sinh() ),
-            IFuncCosH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).cosh() ),
-            IFuncTanH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).tanh() ),
-            IFuncASinH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).asinh() ),
-            IFuncACosH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).acosh() ),
-            IFuncATanH(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).atanh() ),
+            IFuncSin(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).sin() ),
+            IFuncCos(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).cos() ),
+            IFuncTan(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).tan() ),
+            IFuncASin(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).asin() ),
+            IFuncACos(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).acos() ),
+            IFuncATan(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).atan() ),
+            IFuncSinH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).sinh() ),
+            IFuncCosH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).cosh() ),
+            IFuncTanH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).tanh() ),
+            IFuncASinH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).asinh() ),
+            IFuncACosH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).acosh() ),
+            IFuncATanH(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).atanh() ),
 
             IFuncRound{modulus:modic, of:ofic} => {
-                let modulus = eval_ic_ref!(modic, slab, ns);
-                let of = eval_ic_ref!(ofic, slab, ns);
+                let modulus = eval_ic_ref!(modic, slab, ns, hooks);
+                let of = eval_ic_ref!(ofic, slab, ns, hooks);
                 Ok( (of/modulus).round() * modulus )
             }
             IMod{dividend, divisor} => {
-                Ok( eval_ic_ref!(dividend, slab, ns) %
-                    eval_ic_ref!(divisor, slab, ns) )
+                Ok( eval_ic_ref!(dividend, slab, ns, hooks) %
+                    eval_ic_ref!(divisor, slab, ns, hooks) )
             }
 
-            IFuncAbs(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).abs() ),
-            IFuncSign(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).signum() ),
-            IFuncInt(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).trunc() ),
-            IFuncCeil(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).ceil() ),
-            IFuncFloor(i) => Ok( eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns).floor() ),
+            IFuncAbs(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).abs() ),
+            IFuncSign(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).signum() ),
+            IFuncInt(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).trunc() ),
+            IFuncCeil(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).ceil() ),
+            IFuncFloor(i) => Ok( eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks).floor() ),
             IFuncMin(li,ric) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns);
-                let right = eval_ic_ref!(ric, slab, ns);
-                if left.is_nan() || right.is_nan() { return Ok(std::f64::NAN) }  // I need to implement NAN checks myself because the f64.min() function says that if one number is NaN, the other will be returned.
+                let left = eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, hooks);
+                let right = eval_ic_ref!(ric, slab, ns, hooks);
+                if left.is_nan() || right.is_nan() { return Ok(f64::NAN) }  // I need to implement NAN checks myself because the f64.min() function says that if one number is NaN, the other will be returned.
                 if left<right {
                     Ok(left)
                 } else {
@@ -638,9 +565,9 @@ impl Evaler for Instruction {
                 }
             }
             IFuncMax(li,ric) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,li), slab, ns);
-                let right = eval_ic_ref!(ric, slab, ns);
-                if left.is_nan() || right.is_nan() { return Ok(std::f64::NAN) }
+                let left = eval_instr_ref!(get_instr!(slab.cs,li), slab, ns, hooks);
+                let right = eval_ic_ref!(ric, slab, ns, hooks);
+                if left.is_nan() || right.is_nan() { return Ok(f64::NAN) }
                 if left>right {
                     Ok(left)
                 } else {
@@ -650,55 +577,100 @@ impl Evaler for Instruction {
 
 
             IEQ(left, right) => {
-                Ok( bool_to_f64!(f64_eq!(eval_ic_ref!(left, slab, ns),
-                                         eval_ic_ref!(right, slab, ns))) )
+                Ok( bool_to_f64!(f64_eq!(eval_ic_ref!(left, slab, ns, hooks),
+                                         eval_ic_ref!(right, slab, ns, hooks))) )
             }
             INE(left, right) => {
-                Ok( bool_to_f64!(f64_ne!(eval_ic_ref!(left, slab, ns),
-                                         eval_ic_ref!(right, slab, ns))) )
+                Ok( bool_to_f64!(f64_ne!(eval_ic_ref!(left, slab, ns, hooks),
+                                         eval_ic_ref!(right, slab, ns, hooks))) )
             }
             ILT(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) <
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, hooks) <
+                                 eval_ic_ref!(right, slab, ns, hooks)) )
             }
             ILTE(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) <=
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, hooks) <=
+                                 eval_ic_ref!(right, slab, ns, hooks)) )
             }
             IGTE(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) >=
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, hooks) >=
+                                 eval_ic_ref!(right, slab, ns, hooks)) )
             }
             IGT(left, right) => {
-                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns) >
-                                 eval_ic_ref!(right, slab, ns)) )
+                Ok( bool_to_f64!(eval_ic_ref!(left, slab, ns, hooks) >
+                                 eval_ic_ref!(right, slab, ns, hooks)) )
             }
 
-            INot(i) => Ok(bool_to_f64!(f64_eq!(eval_compiled_ref!(get_instr!(slab.cs,i), slab, ns),0.0))),
+            INot(i) => Ok(bool_to_f64!(f64_eq!(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks),0.0))),
             IAND(lefti, rightic) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,lefti), slab, ns);
+                let left = eval_instr_ref!(get_instr!(slab.cs,lefti), slab, ns, hooks);
                 if f64_eq!(left,0.0) { Ok(left) }
                 else {
-                    Ok(eval_ic_ref!(rightic, slab, ns))
+                    Ok(eval_ic_ref!(rightic, slab, ns, hooks))
                 }
             }
             IOR(lefti, rightic) => {
-                let left = eval_compiled_ref!(get_instr!(slab.cs,lefti), slab, ns);
+                let left = eval_instr_ref!(get_instr!(slab.cs,lefti), slab, ns, hooks);
                 if f64_ne!(left,0.0) { Ok(left) }
                 else {
-                    Ok(eval_ic_ref!(rightic, slab, ns))
+                    Ok(eval_ic_ref!(rightic, slab, ns, hooks))
+                }
+            }
+            IBitOr(left, right) => bitor(eval_ic_ref!(left, slab, ns, hooks), eval_ic_ref!(right, slab, ns, hooks)),
+            IBitXor(left, right) => bitxor(eval_ic_ref!(left, slab, ns, hooks), eval_ic_ref!(right, slab, ns, hooks)),
+            IBitAnd(left, right) => bitand(eval_ic_ref!(left, slab, ns, hooks), eval_ic_ref!(right, slab, ns, hooks)),
+            IShl(left, right) => shl(eval_ic_ref!(left, slab, ns, hooks), eval_ic_ref!(right, slab, ns, hooks)),
+            IShr(left, right) => shr(eval_ic_ref!(left, slab, ns, hooks), eval_ic_ref!(right, slab, ns, hooks)),
+            IBitNot(i) => bitnot(eval_instr_ref!(get_instr!(slab.cs,i), slab, ns, hooks)),
+            IFuncIf{cond, then, otherwise} => {
+                if f64_ne!(eval_instr_ref!(get_instr!(slab.cs,cond), slab, ns, hooks),0.0) {
+                    Ok(eval_ic_ref!(then, slab, ns, hooks))
+                } else {
+                    Ok(eval_ic_ref!(otherwise, slab, ns, hooks))
                 }
             }
 
 
-            IPrintFunc(pf) => pf.eval(slab,ns),
-
+            IPrintFunc(pf) => pf.eval_number_hooked(slab,ns,hooks),
+            ICall{names, args, body, ..} => eval_call(names, args, *body, slab, ns, hooks),
+            IProgram{names, stmts} => eval_program(names, stmts, slab, ns, hooks),
 
-            // Put these last because you should be using the eval_compiled*!() macros to eliminate function calls.
-            IConst(c) => Ok(*c),
-            #[cfg(feature="unsafe-vars")]
-            IUnsafeVar{ptr, ..} => unsafe { Ok(**ptr) },
+            _ => Err(Error::Unreachable),
         }
     }
 }
 
+// Each argument of a user-defined function is evaluated once, and then the body
+// refers to it with ILocal.  This is kept out of Instruction::eval() so that the
+// argument buffer doesn't make every recursive eval() call use more stack.
+#[inline(never)]
+fn eval_call(names:&[String], ics:&[IC], body:InstructionI, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+    let mut args = Vec::with_capacity(ics.len());
+    for ic in ics {
+        args.push( eval_ic_ref!(ic, slab, ns, hooks) );
+    }
+    Ok(eval_instr_ref!(get_instr!(slab.cs,body), slab, &mut LocalNamespace::new(names, &args, ns), hooks))
+}
+
+// Evaluates the statements of a compiled program in order, storing the values
+// of assignments in its local variables.  The value of the last statement is
+// the value of the program.
+#[inline(never)]
+fn eval_program(names:&[String], stmts:&[(Option<usize>, IC)], slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error> {
+    let mut vals = Vec::<f64>::with_capacity(names.len());
+    let mut val = 0.0;
+    for (slot,ic) in stmts {
+        // The local names are only used by print(), which is interpreted, and by eval_traced():
+        let lns = &mut LocalNamespace::new(&names[..vals.len()], &vals, ns);
+        val = match slot {
+            Some(slot) if hooks.tracing() => trace_step!(hooks, match ic {
+                IC::C(c) => Ok(*c),
+                IC::I(i) => get_instr!(slab.cs,i).eval_hooked(slab, lns, hooks),
+            }, |_,_| format!("set {}", names.get(*slot).map_or("", |n| n.as_str())))?,
+            _ => eval_ic_ref!(ic, slab, lns, hooks),
+        };
+        store_local(&mut vals, *slot, val);
+    }
+    Ok(val)
+}
+
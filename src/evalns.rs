@@ -17,6 +17,15 @@
 //!   Each layer is a separate 'scope'.  Higher layers take precedence
 //!   over lower layers.  Very useful for creating scoped higher-level-languages.
 //!   Type alias: [LayeredStringToF64Namespace](#layeredstringtof64namespace)
+//! * [`SlotNamespace`](#slotnamespace) -- Supplies the values of variables that
+//!   were resolved to slots at compile time as a `&[f64]`, and delegates
+//!   everything else to another Namespace.
+//! * [`SinkNamespace`](#sinknamespace) -- Sends the output of `print()` to a
+//!   [`PrintSink`](trait.PrintSink.html) instead of stderr, and delegates
+//!   everything else to another Namespace.
+//!
+//! Variables can also be bound directly into an expression at parse time with a
+//! [`SafeVar`](struct.SafeVar.html), in which case no Namespace lookup occurs.
 //!
 //! # Examples
 //!
@@ -169,6 +178,50 @@
 //! }
 //! ```
 //!
+//! ## SlotNamespace
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     slab.cs.add_slot("x".to_string());  // Slot 0.
+//!
+//!     let compiled = fasteval::Parser::new().parse("x * (x + y)", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!
+//!     // 'x' is read from the slots, and 'y' is looked up in the map:
+//!     let mut map = fasteval::StringToF64Namespace::new();
+//!     map.insert("y".to_string(), 1.0);
+//!     let mut ns = fasteval::SlotNamespace::new(&[2.0], map);
+//!
+//!     let val = compiled.eval(&slab, &mut ns)?;
+//!     assert_eq!(val, 6.0);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## SinkNamespace
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let expr = fasteval::Parser::new().parse(r#"print("x is", x) * 2"#, &mut slab.ps)?.from(&slab.ps);
+//!
+//!     // Collect the output in a Vec instead of writing it to stderr:
+//!     let mut lines = Vec::<String>::new();
+//!     let mut ns = fasteval::SinkNamespace::new(|_:&str, _:Vec<f64>| Some(3.0), &mut lines);
+//!     let val = expr.eval(&slab, &mut ns)?;
+//!     assert_eq!(val, 6.0);
+//!     assert_eq!(lines, vec!["x is 3"]);
+//!
+//!     // Or discard it:
+//!     let mut ns = fasteval::SinkNamespace::new(|_:&str, _:Vec<f64>| Some(3.0), fasteval::NullSink);
+//!     assert_eq!(expr.eval(&slab, &mut ns)?, 6.0);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
 //! ## Custom Namespace Types
 //!
 //! If the pre-defined Namespace types aren't perfect for your application, you
@@ -190,15 +243,75 @@
 use crate::error::Error;
 
 use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 //---- Types:
 
 /// All `fasteval` Namespaces must implement the `EvalNamespace` trait.
-pub trait EvalNamespace {
+///
+/// The type parameter is the number type that is being evaluated.  It is `f64`
+/// for everything except [`NumberEvaler`](../number/trait.NumberEvaler.html).
+pub trait EvalNamespace<N=f64> {
     /// Perform a variable/function lookup. 
     ///
     /// May return cached values.
-    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64>;
+    fn lookup(&mut self, name:&str, args:Vec<N>, keybuf:&mut String) -> Option<N>;
+
+    /// Read the value of a variable slot.
+    ///
+    /// Slots are variables that were resolved to an index at compile time.
+    /// (See [`CompileSlab::add_slot()`](../slab/struct.CompileSlab.html#method.add_slot).)
+    /// The default implementation has no slots and always returns `None`,
+    /// so slot variables are looked up by name with `lookup()` instead.
+    #[inline]
+    fn slot(&mut self, _i:usize) -> Option<N> { None }
+
+    /// Perform a lookup that skips local names: the parameters of user-defined
+    /// functions, and the variables that are assigned by a program.
+    ///
+    /// This is used while the body of a user-defined function is evaluated,
+    /// so that its free variables don't see the local names of the caller.
+    /// Namespaces should not override it.
+    #[doc(hidden)]
+    #[inline]
+    fn lookup_global(&mut self, name:&str, args:Vec<N>, keybuf:&mut String) -> Option<N> {
+        self.lookup(name,args,keybuf)
+    }
+
+    /// Get the value of a local variable of the compiled function body or
+    /// program that is being evaluated.
+    ///
+    /// Namespaces should not override it.
+    #[doc(hidden)]
+    #[inline]
+    fn local(&mut self, _i:usize) -> Option<N> { None }
+
+    /// Get the name of a compiled program's local variable.
+    ///
+    /// Namespaces should not override it.
+    #[doc(hidden)]
+    #[inline]
+    fn local_name(&self, _i:usize) -> Option<&str> { None }
+
+    /// Handle a line of output from `print()`.
+    ///
+    /// The default implementation writes it to stderr.  Use a
+    /// [`SinkNamespace`](struct.SinkNamespace.html) to send it somewhere else.
+    #[inline]
+    fn print(&mut self, line:&str) { eprintln!("{}", line); }
+}
+
+/// A destination for the output of `print()`.
+///
+/// Each call to `print()` produces one line, without the trailing newline.
+/// `PrintSink` is implemented by [`StderrSink`](struct.StderrSink.html),
+/// [`NullSink`](struct.NullSink.html), and `Vec<String>`, which collects the
+/// lines.  Use it with a [`SinkNamespace`](struct.SinkNamespace.html).
+pub trait PrintSink {
+    /// Handle a line of output.
+    fn print(&mut self, line:&str);
 }
 
 /// Cache operations for `EvalNamespace`s.
@@ -244,6 +357,76 @@ pub struct CachedCallbackNamespace<'a> {
     cb   :Box<dyn FnMut(&str, Vec<f64>)->Option<f64> + 'a>,  // I think a reference would be more efficient than a Box, but then I would need to use a funky 'let cb=|n|{}; Namespace::new(&cb)' syntax.  The Box results in a super convenient pass-the-cb-by-value API interface.
 }
 
+/// `SlotNamespace` supplies slot values from a `&[f64]`, and delegates all
+/// other lookups to an inner Namespace.
+///
+/// [See module-level documentation for example.](index.html#slotnamespace)
+///
+pub struct SlotNamespace<'a,NS> {
+    slots:&'a [f64],
+    ns   :NS,
+}
+
+/// `SinkNamespace` sends the output of `print()` to a
+/// [`PrintSink`](trait.PrintSink.html), and delegates all lookups to an inner
+/// Namespace.
+///
+/// It works for interpreted and compiled expressions, and for all of the
+/// evaluators that print.  Pass the sink by `&mut` reference to keep access
+/// to it, or read it from the `sink` field afterwards.
+///
+/// [See module-level documentation for example.](index.html#sinknamespace)
+///
+pub struct SinkNamespace<NS,S> {
+    pub ns  :NS,
+    pub sink:S,
+}
+
+/// A [`PrintSink`](trait.PrintSink.html) that writes to stderr, like `print()`
+/// normally does.
+pub struct StderrSink;
+
+/// A [`PrintSink`](trait.PrintSink.html) that discards the output.
+pub struct NullSink;
+
+/// A `SafeVar` is a shared `f64` variable that can be bound into an expression
+/// at parse time.
+///
+/// It is a safe alternative to [Unsafe Variables](../index.html#unsafe-variables):
+/// Register a `SafeVar` with
+/// [`ParseSlab::add_safe_var()`](../slab/struct.ParseSlab.html#method.add_safe_var)
+/// before `parse()`, and the expression will hold its own handle to the
+/// variable, so it can never outlive the value.  Use `set()` to change the value
+/// between evaluations; no Namespace lookup occurs.
+///
+/// Clones of a `SafeVar` refer to the same value.  `SafeVar` is `Send` and `Sync`.
+///
+/// # Examples
+///
+/// ```
+/// use fasteval::Evaler;    // use this trait so we can call eval().
+/// use fasteval::Compiler;  // use this trait so we can call compile().
+/// fn main() -> Result<(), fasteval::Error> {
+///     let mut slab = fasteval::Slab::new();
+///
+///     let deg = fasteval::SafeVar::new(0.0);
+///     slab.ps.add_safe_var("deg".to_string(), &deg);  // Must be registered before 'parse()'.
+///
+///     let compiled = fasteval::Parser::new().parse("deg/360 * 2*pi()", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+///
+///     let mut ns = fasteval::EmptyNamespace;
+///     for d in 0..360 {
+///         deg.set(d as f64);
+///         let val = fasteval::eval_compiled!(compiled, &slab, &mut ns);
+///         assert!((val - (d as f64).to_radians()).abs() < 1e-12);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct SafeVar(Arc<AtomicU64>);
+
 //// I am commenting these out until I need them in real-life.
 //// (I don't want to add things to the public API until necessary.)
 // pub struct CachedLayeredNamespace<'a> {
@@ -343,18 +526,18 @@ impl EvalNamespace for LayeredStringToF64Namespace {
 }
 
 // I'm not making a type alias for this because of the un-name-ability of closures:
-impl<F> EvalNamespace for F where F:FnMut(&str,Vec<f64>)->Option<f64> {
+impl<N,F> EvalNamespace<N> for F where F:FnMut(&str,Vec<N>)->Option<N> {
     #[inline]
-    fn lookup(&mut self, name:&str, args:Vec<f64>, _keybuf:&mut String) -> Option<f64> {
+    fn lookup(&mut self, name:&str, args:Vec<N>, _keybuf:&mut String) -> Option<N> {
         self(name,args)
     }
 }
 
 
-impl EvalNamespace for EmptyNamespace {
+impl<N> EvalNamespace<N> for EmptyNamespace {
     /// Always returns `None`, indicating that the variable is undefined.
     #[inline]
-    fn lookup(&mut self, _name:&str, _args:Vec<f64>, _keybuf:&mut String) -> Option<f64> { None }
+    fn lookup(&mut self, _name:&str, _args:Vec<N>, _keybuf:&mut String) -> Option<N> { None }
 }
 
 impl EvalNamespace for CachedCallbackNamespace<'_> {
@@ -396,6 +579,148 @@ impl<'a> CachedCallbackNamespace<'a> {
     }
 }
 
+impl<NS> EvalNamespace for SlotNamespace<'_,NS> where NS:EvalNamespace {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
+        self.ns.lookup(name,args,keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<f64> {
+        self.slots.get(i).copied()
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.ns.print(line)
+    }
+}
+impl<'a,NS> SlotNamespace<'a,NS> where NS:EvalNamespace {
+    #[inline]
+    pub fn new(slots:&'a [f64], ns:NS) -> Self {
+        SlotNamespace{ slots, ns }
+    }
+}
+
+impl<N,NS,S> EvalNamespace<N> for SinkNamespace<NS,S> where NS:EvalNamespace<N>, S:PrintSink {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<N>, keybuf:&mut String) -> Option<N> {
+        self.ns.lookup(name,args,keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<N> {
+        self.ns.slot(i)
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.sink.print(line)
+    }
+}
+impl<NS,S> SinkNamespace<NS,S> where S:PrintSink {
+    #[inline]
+    pub fn new(ns:NS, sink:S) -> Self {
+        SinkNamespace{ ns, sink }
+    }
+}
+
+impl PrintSink for StderrSink {
+    #[inline]
+    fn print(&mut self, line:&str) { eprintln!("{}", line); }
+}
+impl PrintSink for NullSink {
+    #[inline]
+    fn print(&mut self, _line:&str) {}
+}
+impl PrintSink for Vec<String> {
+    #[inline]
+    fn print(&mut self, line:&str) { self.push(line.to_string()); }
+}
+impl<S:PrintSink+?Sized> PrintSink for &mut S {
+    #[inline]
+    fn print(&mut self, line:&str) { (**self).print(line) }
+}
+
+// Binds local names while a user-defined function or a program is evaluated:
+// the parameters of the function, or the variables that the program has
+// assigned so far.  Everything else is looked up in the enclosing Namespace,
+// skipping the local names of any enclosing scopes.  Compiled function bodies
+// and programs refer to their local names by index instead of by name.
+//
+// The inner Namespace is a trait object so that nested calls don't create an
+// infinitely-nested generic type.
+pub(crate) struct LocalNamespace<'a,N> {
+    names :&'a [String],
+    values:&'a [N],
+    ns    :&'a mut dyn EvalNamespace<N>,
+}
+
+impl<N:Clone> EvalNamespace<N> for LocalNamespace<'_,N> {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<N>, keybuf:&mut String) -> Option<N> {
+        if args.is_empty() {
+            if let Some(i) = self.names.iter().position(|p| p==name) {
+                return self.values.get(i).cloned();
+            }
+        }
+        self.ns.lookup_global(name,args,keybuf)
+    }
+    #[inline]
+    fn slot(&mut self, i:usize) -> Option<N> {
+        self.ns.slot(i)
+    }
+    #[inline]
+    fn lookup_global(&mut self, name:&str, args:Vec<N>, keybuf:&mut String) -> Option<N> {
+        self.ns.lookup_global(name,args,keybuf)
+    }
+    #[inline]
+    fn local(&mut self, i:usize) -> Option<N> {
+        self.values.get(i).cloned()
+    }
+    #[inline]
+    fn local_name(&self, i:usize) -> Option<&str> {
+        self.names.get(i).map(|s| s.as_str())
+    }
+    #[inline]
+    fn print(&mut self, line:&str) {
+        self.ns.print(line)
+    }
+}
+impl<'a,N> LocalNamespace<'a,N> {
+    #[inline]
+    pub(crate) fn new(names:&'a [String], values:&'a [N], ns:&'a mut dyn EvalNamespace<N>) -> Self {
+        LocalNamespace{ names, values, ns }
+    }
+}
+
+impl SafeVar {
+    /// Creates a new variable with the initial value `val`.
+    #[inline]
+    pub fn new(val:f64) -> Self {
+        SafeVar(Arc::new(AtomicU64::new(val.to_bits())))
+    }
+
+    /// Returns the current value.
+    #[inline]
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Sets the value.  All expressions that use this variable will see the new value.
+    #[inline]
+    pub fn set(&self, val:f64) {
+        self.0.store(val.to_bits(), Ordering::Relaxed);
+    }
+}
+impl PartialEq for SafeVar {
+    /// Two `SafeVar`s are equal if they refer to the same value.
+    fn eq(&self, other:&Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl fmt::Debug for SafeVar {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "SafeVar({:?})", self.get())
+    }
+}
+
 //// I am not ready to make this part of the public API yet.
 // impl EvalNamespace for CachedLayeredNamespace<'_> {
 //     fn lookup(&mut self, name:&str, args:Vec<f64>, keybuf:&mut String) -> Option<f64> {
@@ -0,0 +1,44 @@
+// This module lets `eval_traced()` and `eval_budgeted()` watch an evaluation.
+//
+// The evaluators pass a `Hooks` down their recursion next to the Namespace,
+// so the hooks are not part of the public `EvalNamespace` trait, and the
+// wrapper Namespaces don't need to forward them.  `eval()` passes `NoHooks`,
+// whose methods are empty and are optimized away.
+//
+// This module is private, so its traits can't be named or implemented
+// outside of the crate.  `HookedEvaler` limits `eval_traced()` and
+// `eval_budgeted()` to the evaluators that call the hooks.
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evalns::EvalNamespace;
+use crate::trace::Trace;
+
+pub trait Hooks {
+    // Called before each step of an evaluation.  Returns false if the
+    // evaluation must stop.  The hooks keep the reason, so evaluators only
+    // need to return an error, which keeps their stack frames small.
+    #[inline(always)]
+    fn step(&mut self) -> bool { true }
+
+    // These record the steps of an evaluation.  Evaluators only call the
+    // others if tracing() returns true.
+    #[inline(always)]
+    fn tracing(&self) -> bool { false }
+    #[inline(always)]
+    fn trace_enter(&mut self) {}
+    #[inline(always)]
+    fn trace_arg(&mut self, _val:f64) {}
+    #[inline(always)]
+    fn trace_exit(&mut self, _label:&dyn Fn(&[Trace],&[f64])->String, _val:&Result<f64,Error>) {}
+}
+
+// The hooks of a plain eval():
+pub struct NoHooks;
+impl Hooks for NoHooks {}
+
+// The evaluators that call the hooks: parsed Expressions (and their parts)
+// and compiled Instructions.
+pub trait HookedEvaler {
+    fn eval_hooked(&self, slab:&Slab, ns:&mut impl EvalNamespace, hooks:&mut impl Hooks) -> Result<f64,Error>;
+}
@@ -0,0 +1,558 @@
+//! This module evaluates compiled `Instruction`s with interval arithmetic.
+//!
+//! Instead of a single number, every variable is an `Interval` `[lo, hi]`, and
+//! `eval_interval()` calculates an `Interval` that is guaranteed to contain
+//! every possible result of the expression.  This is useful for bounding the
+//! output of an expression before doing something expensive with it.
+//!
+//! The result is guaranteed to enclose the true result, but it may be wider
+//! than necessary.  For example, `x - x` is `[-1, 1]` when `x` is `[0, 1]`,
+//! because the two `x`s are treated independently.
+//!
+//! Floating-point rounding is accounted for: the bounds of `+ - * /` are
+//! rounded outward exactly, and the bounds of other functions are widened by
+//! one ULP (assuming that the platform's math library is accurate to within
+//! one ULP).
+//!
+//! # Special Cases
+//!
+//! * Functions are only evaluated on the part of their input where they are
+//!   defined.  For example, `log([-1, 100])` is `[-inf, 2]`.  If there is no
+//!   such part, the result is empty, which is represented by `NaN` bounds.
+//! * Comparisons, `!`, `&&` and `||` return `[1, 1]` when they are certainly
+//!   true, `[0, 0]` when they are certainly false, and `[0, 1]` when they
+//!   might be either.  If the condition of `&&`, `||` or `? :` is uncertain,
+//!   the result encloses both branches.
+//! * Dividing by an interval that contains `0` produces an unbounded result.
+//! * The bitwise operators are only evaluated on the integers in their
+//!   operands.  Unless both operands are single integers, the bounds are
+//!   rough, and they are unbounded for negative operands of `&`, `|` and `xor`.
+//! * `print()` is not evaluated (so nothing is printed), and its result is
+//!   `[-inf, inf]`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! use fasteval::Interval;
+//! use std::collections::BTreeMap;
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut map = BTreeMap::new();
+//!     map.insert("x".to_string(), Interval::new(-1.0, 2.0));
+//!     map.insert("y".to_string(), Interval::new(3.0, 4.0));
+//!
+//!     let instr = fasteval::Parser::new().parse("2*x + y", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     let bounds = fasteval::eval_interval(&instr, &slab, &mut map)?;
+//!     assert_eq!(bounds, Interval::new(1.0, 8.0));
+//!
+//!     let instr = fasteval::Parser::new().parse("x < y", &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!     assert_eq!(fasteval::eval_interval(&instr, &slab, &mut map)?, Interval::point(1.0));  // Certainly true.
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, store_local, IC, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+
+use std::collections::BTreeMap;
+use std::f64::consts::{PI, FRAC_PI_2};
+use std::f64::{INFINITY, NEG_INFINITY, NAN};
+use std::fmt;
+
+/// A closed interval of `f64`s: `[lo, hi]`.
+///
+/// An empty interval is represented with `NaN` bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// The lower bound.
+    pub lo:f64,
+    /// The upper bound.
+    pub hi:f64,
+}
+
+impl Interval {
+    /// Creates a new `Interval`.  If `lo > hi`, the bounds are swapped.
+    #[inline]
+    pub fn new(lo:f64, hi:f64) -> Self {
+        if lo>hi { Interval{lo:hi, hi:lo} } else { Interval{lo, hi} }
+    }
+    /// Creates an `Interval` that contains a single value.
+    #[inline]
+    pub fn point(x:f64) -> Self { Interval{lo:x, hi:x} }
+    /// Creates the `Interval` that contains all values: `[-inf, inf]`.
+    #[inline]
+    pub fn entire() -> Self { Interval{lo:NEG_INFINITY, hi:INFINITY} }
+    /// Creates an empty `Interval`.
+    #[inline]
+    pub fn empty() -> Self { Interval{lo:NAN, hi:NAN} }
+
+    /// Returns `true` if the `Interval` is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.lo.is_nan() || self.hi.is_nan() }
+    /// Returns `true` if `x` is in the `Interval`.
+    #[inline]
+    pub fn contains(&self, x:f64) -> bool { self.lo<=x && x<=self.hi }
+    /// Returns the smallest `Interval` that contains both `self` and `other`.
+    pub fn hull(&self, other:&Interval) -> Self {
+        if self.is_empty() { return *other; }
+        if other.is_empty() { return *self; }
+        Interval{lo:self.lo.min(other.lo), hi:self.hi.max(other.hi)}
+    }
+
+    // Widens the bounds by one ULP, to cover the error of a function that is
+    // not correctly rounded.
+    fn widen(lo:f64, hi:f64) -> Self {
+        if lo.is_nan() || hi.is_nan() { return Interval::empty(); }
+        Interval{lo:next_down(lo), hi:next_up(hi)}
+    }
+    // Applies a non-decreasing function to both bounds.
+    fn increasing(&self, f:impl Fn(f64)->f64) -> Self {
+        if self.is_empty() { return *self; }
+        Interval::widen(f(self.lo), f(self.hi))
+    }
+    // Applies a non-decreasing, piecewise-constant function to both bounds.
+    // No widening is necessary because the results are exact.
+    fn steps(&self, f:impl Fn(f64)->f64) -> Self {
+        Interval{lo:f(self.lo), hi:f(self.hi)}
+    }
+    // Restricts the `Interval` to the domain of a function.
+    fn clamp(&self, lo:f64, hi:f64) -> Self {
+        let lo = self.lo.max(lo);
+        let hi = self.hi.min(hi);
+        if lo>hi || self.is_empty() { Interval::empty() } else { Interval{lo, hi} }
+    }
+    // Applies a two-argument function to all the bound combinations, and
+    // returns the hull.  Only valid for functions that are monotonic in each argument.
+    fn corners(&self, other:&Interval, f:impl Fn(f64,f64,bool)->f64) -> Self {
+        if self.is_empty() || other.is_empty() { return Interval::empty(); }
+        let vals = [f(self.lo,other.lo,false), f(self.lo,other.hi,false), f(self.hi,other.lo,false), f(self.hi,other.hi,false)];
+        let lo = vals.iter().cloned().fold(INFINITY, f64::min);
+        let vals = [f(self.lo,other.lo,true), f(self.lo,other.hi,true), f(self.hi,other.lo,true), f(self.hi,other.hi,true)];
+        let hi = vals.iter().cloned().fold(NEG_INFINITY, f64::max);
+        Interval{lo, hi}
+    }
+
+    fn neg(&self) -> Self { Interval{lo:-self.hi, hi:-self.lo} }
+    fn add(&self, other:&Interval) -> Self {
+        Interval{lo:add_rounded(self.lo, other.lo, false), hi:add_rounded(self.hi, other.hi, true)}
+    }
+    fn mul(&self, other:&Interval) -> Self {
+        self.corners(other, mul_rounded)
+    }
+    fn inv(&self) -> Self {
+        if self.is_empty() { return *self; }
+        if self.lo>0.0 || self.hi<0.0 { return Interval{lo:div_rounded(1.0, self.hi, false), hi:div_rounded(1.0, self.lo, true)}; }
+        if self.lo==0.0 && self.hi>0.0 { return Interval{lo:div_rounded(1.0, self.hi, false), hi:INFINITY}; }
+        if self.hi==0.0 && self.lo<0.0 { return Interval{lo:NEG_INFINITY, hi:div_rounded(1.0, self.lo, true)}; }
+        Interval::entire()
+    }
+    fn div(&self, other:&Interval) -> Self {
+        if other.lo>0.0 || other.hi<0.0 {
+            self.corners(other, div_rounded)
+        } else {
+            self.mul(&other.inv())
+        }
+    }
+
+    fn powf(&self, power:&Interval) -> Self {
+        if self.is_empty() || power.is_empty() { return Interval::empty(); }
+        if power.lo==power.hi && power.lo.fract()==0.0 {
+            // Integer powers are defined for negative bases:
+            let n = power.lo;
+            if n==0.0 { return Interval::point(1.0); }
+            if n<0.0 { return self.powf(&Interval::point(-n)).inv(); }
+            let (lo,hi) = (self.lo.powf(n), self.hi.powf(n));
+            if n%2.0==1.0 || self.lo>=0.0 { return Interval::widen(lo, hi); }  // Odd or positive: increasing
+            if self.hi<=0.0 { return Interval::widen(hi, lo); }  // Even and negative: decreasing
+            return Interval{lo:0.0, hi:next_up(lo.max(hi))};
+        }
+        // Non-integer powers are only defined for non-negative bases:
+        let base = self.clamp(0.0, INFINITY);
+        let mut i = base.corners(power, |b,p,_| b.powf(p));
+        if self.lo<0.0 && power.lo.ceil()<=power.hi.floor() {
+            // ...but negative bases are still defined for the integers in the
+            // power, with either sign:
+            let m = Interval{lo:(-self.hi).max(0.0), hi:-self.lo}.corners(power, |b,p,_| b.powf(p)).hi;
+            i = i.hull(&Interval{lo:-m, hi:m});
+        }
+        Interval::widen(i.lo, i.hi)
+    }
+
+    // Finds whether `self` might contain `offset + k*period` for some integer `k`.
+    // Errs on the side of "yes".
+    fn touches(&self, offset:f64, period:f64) -> bool {
+        let k = ((self.lo-offset)/period).ceil();
+        let x = offset + k*period;
+        x <= self.hi + period*1e-9
+    }
+    fn sin(&self) -> Self {
+        if self.is_empty() { return *self; }
+        if self.hi-self.lo >= 2.0*PI || self.lo.is_infinite() { return Interval{lo:-1.0, hi:1.0}; }
+        let i = Interval::widen(self.lo.sin().min(self.hi.sin()), self.lo.sin().max(self.hi.sin()));
+        let hi = if self.touches(FRAC_PI_2, 2.0*PI) { 1.0 } else { i.hi.min(1.0) };
+        let lo = if self.touches(-FRAC_PI_2, 2.0*PI) { -1.0 } else { i.lo.max(-1.0) };
+        Interval{lo, hi}
+    }
+    fn cos(&self) -> Self {
+        if self.is_empty() { return *self; }
+        if self.hi-self.lo >= 2.0*PI || self.lo.is_infinite() { return Interval{lo:-1.0, hi:1.0}; }
+        let i = Interval::widen(self.lo.cos().min(self.hi.cos()), self.lo.cos().max(self.hi.cos()));
+        let hi = if self.touches(0.0, 2.0*PI) { 1.0 } else { i.hi.min(1.0) };
+        let lo = if self.touches(PI, 2.0*PI) { -1.0 } else { i.lo.max(-1.0) };
+        Interval{lo, hi}
+    }
+    fn tan(&self) -> Self {
+        if self.is_empty() { return *self; }
+        if self.hi-self.lo >= PI || self.lo.is_infinite() || self.touches(FRAC_PI_2, PI) { return Interval::entire(); }
+        self.increasing(f64::tan)
+    }
+
+    // Restricts the `Interval` to the integers in the range of an `i64`,
+    // which is the domain of the bitwise operators.
+    fn ints(&self) -> Self {
+        let i = self.clamp(-9_223_372_036_854_775_808.0, next_down(9_223_372_036_854_775_808.0));
+        if i.is_empty() || i.lo.ceil()>i.hi.floor() { return Interval::empty(); }
+        Interval{lo:i.lo.ceil(), hi:i.hi.floor()}
+    }
+    // The shift amount of `<<` and `>>` must be a single integer for a useful bound.
+    fn shift_scale(&self, sign:f64) -> Option<f64> {
+        if self.lo==self.hi && self.lo>=0.0 && self.lo<64.0 { Some((sign*self.lo).exp2()) } else { None }
+    }
+
+    // Is the value certainly zero, certainly non-zero, or unknown?
+    // (Uses the same tolerance as the normal evaluator.)
+    fn truth(&self) -> Option<bool> {
+        let tol = 8.0*std::f64::EPSILON;
+        if self.lo>tol || self.hi< -tol { return Some(true); }
+        if self.lo>= -tol && self.hi<=tol { return Some(false); }
+        None
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+fn bool_interval(b:Option<bool>) -> Interval {
+    match b {
+        Some(b) => Interval::point(bool_to_f64!(b)),
+        None => Interval{lo:0.0, hi:1.0},
+    }
+}
+
+// Evaluates a bitwise operator exactly if both operands are single integers.
+// Invalid operands, like a shift amount that is too big, produce an empty result.
+fn bit_points(l:&Interval, r:&Interval, f:impl Fn(f64,f64)->Result<f64,Error>) -> Option<Interval> {
+    if l.is_empty() || r.is_empty() { return Some(Interval::empty()); }
+    if l.lo==l.hi && r.lo==r.hi { return Some(f(l.lo, r.lo).map(Interval::point).unwrap_or_else(|_| Interval::empty())); }
+    None
+}
+// The smallest `2^k - 1` that is at least `x`.  `|` and `xor` of non-negative
+// integers can't have more bits than their operands.
+fn bit_mask(x:f64) -> f64 {
+    let mut m = 1.0;
+    while m<=x { m*=2.0; }
+    m-1.0
+}
+
+fn next_up(x:f64) -> f64 {
+    if x.is_nan() || x==INFINITY { return x; }
+    if x==0.0 { return f64::from_bits(1); }
+    let bits = x.to_bits();
+    if x>0.0 { f64::from_bits(bits+1) } else { f64::from_bits(bits-1) }
+}
+fn next_down(x:f64) -> f64 {
+    -next_up(-x)
+}
+
+// These use error-free transformations to find out whether the rounded
+// result is above or below the exact result, and adjust it by one ULP if
+// necessary.  Exact results are not changed.
+fn add_rounded(a:f64, b:f64, up:bool) -> f64 {
+    let s = a+b;
+    if !s.is_finite() { return s; }
+    let bb = s-a;
+    let err = (a-(s-bb)) + (b-bb);  // TwoSum
+    if up && err>0.0 { next_up(s) } else if !up && err<0.0 { next_down(s) } else { s }
+}
+fn mul_rounded(a:f64, b:f64, up:bool) -> f64 {
+    if a==0.0 || b==0.0 { return 0.0; }  // Even if the other side is infinite.
+    let p = a*b;
+    if !p.is_finite() { return p; }
+    let err = a.mul_add(b, -p);
+    if up && err>0.0 { next_up(p) } else if !up && err<0.0 { next_down(p) } else { p }
+}
+fn div_rounded(a:f64, b:f64, up:bool) -> f64 {
+    let q = a/b;
+    if !q.is_finite() || q==0.0 { return if q==0.0 && a!=0.0 { if up { next_up(q) } else { next_down(q) } } else { q }; }
+    let r = -q.mul_add(b, -a);  // a - q*b, exactly.
+    let err = if b>0.0 { r } else { -r };  // The sign of r/b.
+    if up && err>0.0 { next_up(q) } else if !up && err<0.0 { next_down(q) } else { q }
+}
+
+/// Provides the `Interval`s of variables and custom functions for `eval_interval()`.
+///
+/// This is the interval version of [`EvalNamespace`](../evalns/trait.EvalNamespace.html).
+/// It is implemented for closures and for `BTreeMap<String,Interval>`.
+pub trait IntervalNamespace {
+    /// Perform a variable/function lookup.
+    fn lookup(&mut self, name:&str, args:Vec<Interval>) -> Option<Interval>;
+
+    /// Read the `Interval` of a variable slot.
+    ///
+    /// The default implementation has no slots and always returns `None`,
+    /// so slot variables are looked up by name with `lookup()` instead.
+    #[inline]
+    fn slot(&mut self, _i:usize) -> Option<Interval> { None }
+}
+
+impl<F> IntervalNamespace for F where F:FnMut(&str,Vec<Interval>)->Option<Interval> {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<Interval>) -> Option<Interval> {
+        self(name,args)
+    }
+}
+
+impl IntervalNamespace for BTreeMap<String,Interval> {
+    #[inline]
+    fn lookup(&mut self, name:&str, args:Vec<Interval>) -> Option<Interval> {
+        if !args.is_empty() { return None; }
+        self.get(name).copied()
+    }
+}
+
+/// Evaluates `instr` with interval arithmetic.
+///
+/// [See the `interval` module documentation.](index.html)
+pub fn eval_interval(instr:&Instruction, slab:&Slab, ns:&mut impl IntervalNamespace) -> Result<Interval,Error> {
+    IntervalEvaler{ slab, ns, locals:Vec::new() }.instr(instr)
+}
+
+struct IntervalEvaler<'a,NS> {
+    slab  :&'a Slab,
+    ns    :&'a mut NS,
+    locals:Vec<Interval>,  // The arguments of the user-defined function whose body is being evaluated, or the variables of the program.
+}
+
+impl<NS> IntervalEvaler<'_,NS> where NS:IntervalNamespace {
+    #[inline]
+    fn ic(&mut self, ic:&IC) -> Result<Interval,Error> {
+        match ic {
+            IC::C(c) => Ok(Interval::point(*c)),
+            IC::I(i) => self.instr(get_instr!(self.slab.cs,i)),
+        }
+    }
+
+    fn instr(&mut self, instr:&Instruction) -> Result<Interval,Error> {
+        macro_rules! i {
+            ($i:ident) => { self.instr(get_instr!(self.slab.cs,$i))? };
+        }
+        macro_rules! ic {
+            ($ic:ident) => { self.ic($ic)? };
+        }
+
+        Ok(match instr {
+            IConst(c) => Interval::point(*c),
+            IVar(name) => {
+                match self.ns.lookup(name, Vec::new()) {
+                    Some(x) => x,
+                    None => return Err(Error::Undefined(name.to_string())),
+                }
+            }
+            ISlot{name, slot} => {
+                match self.ns.slot(*slot).or_else(|| self.ns.lookup(name, Vec::new())) {
+                    Some(x) => x,
+                    None => return Err(Error::Undefined(name.to_string())),
+                }
+            }
+            ISafeVar{var, ..} => Interval::point(var.get()),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => Interval::point(unsafe { **ptr }),
+            IFunc{name, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics { args.push(ic!(ic)); }
+                match self.ns.lookup(name, args) {
+                    Some(x) => x,
+                    None => return Err(Error::Undefined(name.to_string())),
+                }
+            }
+            IPrintFunc(_) => Interval::entire(),
+            ILocal(i) => match self.locals.get(*i) {
+                Some(x) => *x,
+                None => return Err(Error::Unreachable),
+            },
+            ICall{args:ics, body, ..} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics { args.push(ic!(ic)); }
+                let locals = std::mem::replace(&mut self.locals, args);
+                let res = self.instr(get_instr!(self.slab.cs,body));
+                self.locals = locals;
+                res?
+            }
+            IProgram{stmts, ..} => {
+                let mut val = Interval::entire();
+                for (slot,ic) in stmts {
+                    val = ic!(ic);
+                    store_local(&mut self.locals, *slot, val);
+                }
+                val
+            }
+
+            INeg(i) => i!(i).neg(),
+            IInv(i) => i!(i).inv(),
+            INot(i) => bool_interval(i!(i).truth().map(|b| !b)),
+            IAdd(li, ric) => i!(li).add(&ic!(ric)),
+            IMul(li, ric) => i!(li).mul(&ic!(ric)),
+            IMod{dividend, divisor} => {
+                let (x, y) = (ic!(dividend), ic!(divisor));
+                if x.is_empty() || y.is_empty() || y.lo==0.0 && y.hi==0.0 { return Ok(Interval::empty()); }
+                // The result has the sign of the dividend, and is smaller than the divisor:
+                let m = y.lo.abs().max(y.hi.abs());
+                if y.lo==y.hi && (x.lo>=0.0 || x.hi<=0.0) && (x.lo/m).trunc()==(x.hi/m).trunc() {
+                    // Within a single period, it is increasing:
+                    Interval::widen(x.lo%m, x.hi%m).clamp(x.lo.min(0.0), x.hi.max(0.0))
+                } else {
+                    Interval{lo:x.lo.max(-m).min(0.0), hi:x.hi.min(m).max(0.0)}
+                }
+            }
+            IExp{base, power} => ic!(base).powf(&ic!(power)),
+
+            ILT(l, r) => { let (l,r)=(ic!(l),ic!(r)); bool_interval(if l.hi<r.lo { Some(true) } else if l.lo>=r.hi { Some(false) } else { None }) }
+            ILTE(l, r) => { let (l,r)=(ic!(l),ic!(r)); bool_interval(if l.hi<=r.lo { Some(true) } else if l.lo>r.hi { Some(false) } else { None }) }
+            IGT(l, r) => { let (l,r)=(ic!(l),ic!(r)); bool_interval(if l.lo>r.hi { Some(true) } else if l.hi<=r.lo { Some(false) } else { None }) }
+            IGTE(l, r) => { let (l,r)=(ic!(l),ic!(r)); bool_interval(if l.lo>=r.hi { Some(true) } else if l.hi<r.lo { Some(false) } else { None }) }
+            IEQ(l, r) => bool_interval(ic!(l).add(&ic!(r).neg()).truth().map(|b| !b)),
+            INE(l, r) => bool_interval(ic!(l).add(&ic!(r).neg()).truth()),
+
+            IAND(li, ric) => {
+                let l = i!(li);
+                match l.truth() {
+                    Some(false) => l,
+                    Some(true) => ic!(ric),
+                    None => ic!(ric).hull(&l.clamp(-8.0*std::f64::EPSILON, 8.0*std::f64::EPSILON)),
+                }
+            }
+            IOR(li, ric) => {
+                let l = i!(li);
+                match l.truth() {
+                    Some(true) => l,
+                    Some(false) => ic!(ric),
+                    None => ic!(ric).hull(&l),
+                }
+            }
+            IFuncIf{cond, then, otherwise} => {
+                match i!(cond).truth() {
+                    Some(true) => ic!(then),
+                    Some(false) => ic!(otherwise),
+                    None => ic!(then).hull(&ic!(otherwise)),
+                }
+            }
+
+            IBitNot(i) => {
+                let x = i!(i).ints();
+                Interval::widen(-x.hi-1.0, -x.lo-1.0)  // ~x == -x-1
+            }
+            IBitOr(l, r) => {
+                let (l, r) = (ic!(l).ints(), ic!(r).ints());
+                match bit_points(&l, &r, bitor) {
+                    Some(x) => x,
+                    None if l.lo>=0.0 && r.lo>=0.0 => Interval{lo:l.lo.max(r.lo), hi:bit_mask(l.hi.max(r.hi))},
+                    None => Interval::entire(),
+                }
+            }
+            IBitXor(l, r) => {
+                let (l, r) = (ic!(l).ints(), ic!(r).ints());
+                match bit_points(&l, &r, bitxor) {
+                    Some(x) => x,
+                    None if l.lo>=0.0 && r.lo>=0.0 => Interval{lo:0.0, hi:bit_mask(l.hi.max(r.hi))},
+                    None => Interval::entire(),
+                }
+            }
+            IBitAnd(l, r) => {
+                let (l, r) = (ic!(l).ints(), ic!(r).ints());
+                match bit_points(&l, &r, bitand) {
+                    Some(x) => x,
+                    // A non-negative operand is a mask for the result:
+                    None if l.lo>=0.0 && r.lo>=0.0 => Interval{lo:0.0, hi:l.hi.min(r.hi)},
+                    None if l.lo>=0.0 => Interval{lo:0.0, hi:l.hi},
+                    None if r.lo>=0.0 => Interval{lo:0.0, hi:r.hi},
+                    None => Interval::entire(),
+                }
+            }
+            IShl(l, r) => {
+                let (l, r) = (ic!(l).ints(), ic!(r).ints());
+                match bit_points(&l, &r, shl) {
+                    Some(x) => x,
+                    None => match r.shift_scale(1.0) {
+                        Some(m) => l.mul(&Interval::point(m)).ints(),  // Overflow is an Error, so those values are excluded.
+                        None => Interval::entire(),
+                    }
+                }
+            }
+            IShr(l, r) => {
+                let (l, r) = (ic!(l).ints(), ic!(r).ints());
+                match bit_points(&l, &r, shr) {
+                    Some(x) => x,
+                    None => match r.shift_scale(-1.0) {
+                        Some(m) => l.mul(&Interval::point(m)).steps(f64::floor),
+                        None => Interval::entire(),
+                    }
+                }
+            }
+
+            IFuncInt(i) => i!(i).steps(f64::trunc),
+            IFuncCeil(i) => i!(i).steps(f64::ceil),
+            IFuncFloor(i) => i!(i).steps(f64::floor),
+            IFuncSign(i) => i!(i).steps(f64::signum),
+            IFuncRound{modulus, of} => {
+                let (m, x) = (ic!(modulus), ic!(of));
+                x.div(&m).steps(f64::round).mul(&m)
+            }
+            IFuncAbs(i) => {
+                let x = i!(i);
+                if x.lo>=0.0 { x } else if x.hi<=0.0 { x.neg() } else { Interval{lo:0.0, hi:x.hi.max(-x.lo)} }
+            }
+            IFuncLog{base, of} => {
+                let (b, x) = (ic!(base), ic!(of));
+                let x = x.clamp(0.0, INFINITY);
+                if b.lo==b.hi && b.lo>1.0 {
+                    x.increasing(|x| log(b.lo,x))
+                } else {
+                    x.increasing(f64::ln).div(&b.clamp(0.0, INFINITY).increasing(f64::ln))
+                }
+            }
+            IFuncMin(li, ric) => {
+                let (l, r) = (i!(li), ic!(ric));
+                if l.is_empty() || r.is_empty() { Interval::empty() } else { Interval{lo:l.lo.min(r.lo), hi:l.hi.min(r.hi)} }
+            }
+            IFuncMax(li, ric) => {
+                let (l, r) = (i!(li), ic!(ric));
+                if l.is_empty() || r.is_empty() { Interval::empty() } else { Interval{lo:l.lo.max(r.lo), hi:l.hi.max(r.hi)} }
+            }
+
+            IFuncSin(i) => i!(i).sin(),
+            IFuncCos(i) => i!(i).cos(),
+            IFuncTan(i) => i!(i).tan(),
+            IFuncASin(i) => { let i=i!(i).clamp(-1.0, 1.0).increasing(f64::asin); i.clamp(-FRAC_PI_2-1e-15, FRAC_PI_2+1e-15) }
+            IFuncACos(i) => { let x=i!(i).clamp(-1.0, 1.0); Interval::widen(x.hi.acos(), x.lo.acos()).clamp(0.0, PI+1e-15) }
+            IFuncATan(i) => i!(i).increasing(f64::atan),
+            IFuncSinH(i) => i!(i).increasing(f64::sinh),
+            IFuncCosH(i) => {
+                let x = i!(i);
+                let hi = x.lo.abs().max(x.hi.abs());
+                if x.contains(0.0) { Interval::widen(1.0, hi.cosh()).clamp(1.0, INFINITY) } else { Interval::widen(x.lo.abs().min(x.hi.abs()).cosh(), hi.cosh()).clamp(1.0, INFINITY) }
+            }
+            IFuncTanH(i) => i!(i).increasing(f64::tanh).clamp(-1.0, 1.0),
+            IFuncASinH(i) => i!(i).increasing(f64::asinh),
+            IFuncACosH(i) => i!(i).clamp(1.0, INFINITY).increasing(f64::acosh).clamp(0.0, INFINITY),
+            IFuncATanH(i) => i!(i).clamp(-1.0, 1.0).increasing(f64::atanh),
+        })
+    }
+}
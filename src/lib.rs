@@ -5,12 +5,21 @@
 //! * Safe execution of untrusted expressions.
 //! * Works with stable Rust.
 //! * Supports interpretation (i.e. parse & eval) as well as compiled execution (i.e. parse, compile, eval).
-//! * Supports Variables and Custom Functions.
+//! * Supports Variables and Custom Functions, and functions that are defined
+//!   within the expression language, like `f(x, y) = x^2 + y`.
 //! * `fasteval` is a good base for building higher-level languages.
 //! * Supports many built-in functions and constants.
 //! * Supports all the standard algebraic unary and binary operators (+ - * / ^ %),
-//!   as well as comparisons (< <= == != >= >) and logical operators (&& ||) with
-//!   short-circuit support.
+//!   as well as comparisons (< <= == != >= >), logical operators (&& ||) with
+//!   short-circuit support, bitwise operators (& | xor << >> ~) for integers,
+//!   and the ternary operator (? :).
+//! * Symbolic differentiation of expressions with [`derive()`](derive/index.html),
+//!   and automatic differentiation of compiled expressions with [`eval_grad()`](autodiff/index.html).
+//! * Interval arithmetic with [`eval_interval()`](interval/index.html), to bound the result of an expression.
+//! * Explanations of how a result was computed with [`eval_traced()`](trace/index.html).
+//! * Evaluation with number types other than `f64` (such as `f32`) through the [`Number`](number/trait.Number.html) trait,
+//!   with [complex numbers](complex/index.html), and with [exact decimals](decimal/index.html)
+//!   for financial formulas.
 //! * Easy integration into many different types of applications, including scoped evaluation.
 //! * Very fast performance.
 //!
@@ -24,9 +33,14 @@
 //!
 //! ```text
 //!   * print(...strings and values...) -- Prints to stderr.  Very useful to 'probe' an expression.
+//!                                        (Use a `SinkNamespace` to send the output elsewhere.)
 //!                                        Evaluates to the last value.
 //!                                        Example: `print("x is", x, "and y is", y)`
 //!                                        Example: `x + print("y:", y) + z == x+y+z`
+//!                                        If the first string contains a '%', it is a printf-style
+//!                                        format string:  `print("x = %.3f, n = %5d", x, n)`
+//!                                        Strings can be quoted with "..." or '...', and they
+//!                                        can contain the escapes \" \' \\ \n \t \r \0 and \u{263A}.
 //!
 //!   * log(base=10, val) -- Logarithm with optional 'base' as first argument.
 //!                          If not provided, 'base' defaults to '10'.
@@ -47,6 +61,11 @@
 //!   * min(val, ...) -- Example: `min(1, -2, 3, -4) == -4`
 //!   * max(val, ...) -- Example: `max(1, -2, 3, -4) == 3`
 //!
+//!   * if(cond, then, otherwise) -- Evaluates 'then' if 'cond' is non-zero, otherwise
+//!                                  evaluates 'otherwise'.  Only one branch is evaluated.
+//!                                  Same as the ternary operator: `cond ? then : otherwise`
+//!                                  Example: `if(x > 0, x, -x) == abs(x)`
+//!
 //!   * sin(radians)    * asin(val)
 //!   * cos(radians)    * acos(val)
 //!   * tan(radians)    * atan(val)
@@ -57,9 +76,15 @@
 //!
 //! ## Operators
 //!
-//! The `and` and `or` operators are enabled by default, but if your
+//! The `and`, `or` and `xor` operators are enabled by default, but if your
 //! application wants to use those words for something else, they can be
 //! disabled by turning off the `alpha-keywords` feature (`cargo build --no-default-features`).
+//! (`xor` has no symbolic spelling, because `^` is already used for exponentiation.)
+//!
+//! The bitwise operators and unary `~` (bitwise NOT) work on the two's
+//! complement `i64` representation of their operands, which must be integers.
+//! Other operands produce an `Error`.  Unlike C, the bitwise operators bind
+//! more tightly than comparisons, so `flags & 4 == 0` means `(flags & 4) == 0`.
 //!
 //! ```text
 //! Listed in order of precedence:
@@ -70,9 +95,14 @@
 //!                          *               Multiplication
 //!                          -               Subtraction
 //!                          +               Addition
+//!                          << >>           Bit shifts (both have equal precedence)
+//!                          &               Bitwise AND
+//!                          xor             Bitwise XOR
+//!                          |               Bitwise OR
 //!                          == != < <= >= > Comparisons (all have equal precedence)
 //!                          && and          Logical AND with short-circuit
-//!     (Lowest Precedence)  || or           Logical OR with short-circuit
+//!                          || or           Logical OR with short-circuit
+//!     (Lowest Precedence)  ? :             Ternary (right-associative)
 //!
 //! ```
 //!
@@ -96,8 +126,17 @@
 //!             1.23M        = 1230000
 //!             1.23G        = 1230000000
 //!             1.23T        = 1230000000000
+//!
+//!     Hexadecimal, Octal, Binary: 0x1F, 0o17, 0b1010
+//!
+//!     Digit Separators: 1_000_000, 1_500k, 0xFFFF_0000
 //! ```
 //!
+//! Underscores are only allowed between two digits.  Hexadecimal, octal and
+//! binary literals are integers, and can't have a fractional part, an exponent,
+//! or a suffix (`0x1E3` is 483, not 1000).  They must not be directly followed
+//! by a letter, so write `0x1F and x` rather than `0x1Fand x`.
+//!
 //! # Examples
 //!
 //! ## Easy evaluation
@@ -218,6 +257,40 @@
 //! }
 //! ```
 //!
+//! ## User-defined functions
+//! Small helper functions can also be defined in the expression language
+//! itself, with [`Parser::define()`](parser/struct.Parser.html#method.define),
+//! instead of with Rust closures.  The definitions are stored in the
+//! [`Slab`](slab/index.html), so they must be defined before the expressions
+//! that call them are parsed:
+//!
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let parser = fasteval::Parser::new();
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut map = fasteval::StringToF64Namespace::new();
+//!     map.insert("scale".to_string(), 10.0);
+//!
+//!     parser.define("f(x, y) = x^2 + y", &mut slab.ps)?;
+//!     parser.define("g(x) = f(x, 1) * scale", &mut slab.ps)?;  // Names that aren't parameters come from the Namespace.
+//!
+//!     let expr_ref = parser.parse("f(2, 3) * 4 + g(3)", &mut slab.ps)?.from(&slab.ps);
+//!     assert_eq!(expr_ref.eval(&slab, &mut map)?, 128.0);
+//!
+//!     // The compiler folds constant arguments into the body, so `f(2, 3) * 4` is folded to `28`:
+//!     let compiled = expr_ref.compile(&slab.ps, &mut slab.cs);
+//!     assert_eq!(compiled.eval(&slab, &mut map)?, 128.0);
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Parameters shadow variables with the same name.  A function can call the
+//! functions that were defined before it, but recursion is not allowed, and
+//! calls with the wrong number of arguments are rejected by the parser.
+//!
 //! ## Re-use the Slab to go faster
 //! If we perform the parse and eval ourselves (without relying on the 'ez'
 //! interface), then we can re-use the [`Slab`](slab/index.html) allocation for
@@ -290,6 +363,45 @@
 //! }
 //! ```
 //!
+//! ## Variable Slots
+//! Compiled Normal Variables are looked up by name on every evaluation.  If
+//! you know your variable names ahead of time, you can register them as
+//! *slots* before compiling.  Each slot variable is resolved to an index at
+//! compile time, and evaluation just reads that index from a `&[f64]`.  This
+//! is almost as fast as the Unsafe Variables described below, but it is
+//! completely safe.
+//! ```
+//! use fasteval::Evaler;    // use this trait so we can call eval().
+//! use fasteval::Compiler;  // use this trait so we can call compile().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let parser = fasteval::Parser::new();
+//!     let mut slab = fasteval::Slab::new();
+//!
+//!     // Slots must be registered before 'compile()'.
+//!     let deg_slot = slab.cs.add_slot("deg".to_string());
+//!
+//!     let expr_str = "sin(deg/360 * 2*pi())";
+//!     let compiled = parser.parse(expr_str, &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+//!
+//!     let mut slots = [0.0];
+//!     for deg in 0..360 {
+//!         slots[deg_slot] = deg as f64;
+//!         // Variables that are not slots are delegated to the inner Namespace:
+//!         let mut ns = fasteval::SlotNamespace::new(&slots, fasteval::EmptyNamespace);
+//!         let val = fasteval::eval_compiled!(compiled, &slab, &mut ns);
+//!         assert!((val - (deg as f64).to_radians().sin()).abs() < 1e-12);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! For large expressions with many variables, you can also lower a compiled
+//! `Instruction` into flat [`Bytecode`](bytecode/index.html), which avoids
+//! walking the instruction tree during evaluation.  And if you need to
+//! evaluate the same expression over many rows of data, [`eval_batch()`](batch/index.html) can
+//! read the slot values directly from columns, a whole chunk of rows at a time.
+//!
 //! ## Unsafe Variables
 //! If your variables *must* be as fast as possible and you are willing to be
 //! very careful, you can build with the `unsafe-vars` feature (`cargo build
@@ -297,6 +409,10 @@
 //! unsafe variables perform 2x-4x faster than the compiled form above.  This
 //! feature is not enabled by default because it slightly slows down other
 //! non-variable operations.
+//!
+//! If you can't use `unsafe` code, [`SafeVar`](evalns/struct.SafeVar.html)
+//! offers the same register-before-parse workflow with shared handles
+//! instead of raw pointers, and is always available.
 //! ```
 //! use fasteval::Evaler;    // use this trait so we can call eval().
 //! use fasteval::Compiler;  // use this trait so we can call compile().
@@ -393,6 +509,10 @@
 //! All limits can be customized at parse time.  If any limits are exceeded,
 //! [`parse()`](https://docs.rs/fasteval/latest/fasteval/parser/struct.Parser.html#method.parse) will return an
 //! [Error](https://docs.rs/fasteval/latest/fasteval/error/enum.Error.html).
+//!
+//! Evaluation can be limited too:  [`eval_budgeted()`](budget/fn.eval_budgeted.html) stops an
+//! evaluation that takes too many steps or Namespace lookups, or that is
+//! cancelled by another thread.
 //! 
 //! Note that it *is* possible for you (the developer) to define custom functions
 //! which might perform dangerous operations.  It is your responsibility to make
@@ -586,12 +706,10 @@
 //! # Future Work
 //! Here are some features that I might add in the future:
 //!
-//! * Dynamic `sprintf` string formatting for the `print()` built-in expression function.
 //! * FFI so this library can be used from other languages.
-//! * Ability to copy the contents of a `Slab` into a perfectly-sized container
-//!   (`PackedSlab`) to reduce wasted memory.
-//! * Support for other number types other than `f64`, such as Integers, Big Integers,
+//! * Built-in support for other number types other than `f64`, such as Integers, Big Integers,
 //!   Arbitrary Precision Numbers, Complex Numbers, etc. like [rclc](https://crates.io/crates/rclc).
+//!   (You can already add your own with the [`Number`](number/trait.Number.html) trait.)
 //!
 //! # List of Projects that use `fasteval`
 //!
@@ -615,18 +733,41 @@ pub mod slab;
 pub mod parser;
 #[macro_use]
 pub mod compiler;
+#[macro_use]
 pub mod evaler;
+pub mod bytecode;
+pub mod batch;
+pub mod derive;
+pub mod autodiff;
+pub mod interval;
+pub mod number;
+pub mod complex;
+pub mod decimal;
+mod printf;
+mod hooks;
+pub mod trace;
+pub mod budget;
 pub mod evalns;
 pub mod ez;
 
-pub use self::error::Error;
-pub use self::parser::{Parser, Expression, ExpressionI, Value, ValueI};
-pub use self::compiler::{Compiler, Instruction::{self, IConst}, InstructionI};
+pub use self::error::{Error, Pos};
+pub use self::parser::{Parser, Expression, ExpressionI, Value, ValueI, UserFunc};
+pub use self::compiler::{Compiler, CompiledExpr, Instruction::{self, IConst, ISafeVar}, InstructionI};
 #[cfg(feature="unsafe-vars")]
 pub use self::compiler::Instruction::IUnsafeVar;
 pub use self::evaler::Evaler;
+pub use self::bytecode::Bytecode;
+pub use self::batch::eval_batch;
+pub use self::derive::derive;
+pub use self::autodiff::{eval_grad, Dual};
+pub use self::interval::{eval_interval, Interval, IntervalNamespace};
+pub use self::number::{Number, NumberEvaler};
+pub use self::complex::{eval_complex, Complex, ComplexNamespace};
+pub use self::decimal::{eval_decimal, Decimal, DecimalContext, Rounding};
 pub use self::slab::Slab;
-pub use self::evalns::{EvalNamespace, Cached, EmptyNamespace, StringToF64Namespace, StrToF64Namespace, StringToCallbackNamespace, StrToCallbackNamespace, LayeredStringToF64Namespace, CachedCallbackNamespace};
+pub use self::evalns::{EvalNamespace, Cached, EmptyNamespace, StringToF64Namespace, StrToF64Namespace, StringToCallbackNamespace, StrToCallbackNamespace, LayeredStringToF64Namespace, CachedCallbackNamespace, SlotNamespace, SinkNamespace, PrintSink, StderrSink, NullSink, SafeVar};
+pub use self::trace::{eval_traced, Trace};
+pub use self::budget::{eval_budgeted, eval_traced_budgeted, Budget};
 pub use self::ez::ez_eval;
 
 
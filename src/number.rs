@@ -0,0 +1,944 @@
+//! This module evaluates expressions with number types other than `f64`.
+//!
+//! `NumberEvaler` evaluates parsed `Expression`s and compiled `Instruction`s with any type that implements
+//! the [`Number`](trait.Number.html) trait, such as `f32`, `i64` or your own
+//! fixed-point type.  Variables and custom functions are supplied by an
+//! `EvalNamespace<N>`, which is implemented for closures
+//! (`FnMut(&str,Vec<N>)->Option<N>`) and `EmptyNamespace`.
+//!
+//! This is also the interpreter behind [`Evaler::eval()`](../evaler/trait.Evaler.html#tymethod.eval)
+//! for parsed `Expression`s: that is simply `eval_number()` with `N=f64`, so
+//! the two can't disagree.
+//!
+//! # Integer Mode
+//!
+//! When evaluated with `i64`, expressions use integer arithmetic, which is
+//! useful for counters and quotas, where floats are wrong:
+//!
+//! * `/` is integer division, rounded towards zero, and `%` is the remainder,
+//!   which has the same sign as the dividend.  Division by zero is an `Error`.
+//! * `^` requires a non-negative exponent.
+//! * The bitwise operators work on all 64 bits, without a round-trip through `f64`.
+//! * Overflow returns [`Error::Overflow`](../error/enum.Error.html#variant.Overflow)
+//!   instead of wrapping around.
+//! * Literals and function results must be integers.  For example, `1.5` and
+//!   `pi()` return an `Error`.
+//!
+//! To compile an expression for integer mode, call
+//! [`CompileSlab::set_int_mode(true)`](../slab/struct.CompileSlab.html#method.set_int_mode)
+//! first.  Then the compiler folds constants with the same checked `i64`
+//! arithmetic, and leaves the operations that fail, like `2^63` or `1/0`, for
+//! the evaluation to report.
+//!
+//! # Limitations
+//!
+//! * Numeric literals are parsed as `f64`, and then converted with
+//!   `Number::from_f64()`.  Literals with more than 15 significant digits
+//!   are converted from their source text with `Number::from_literal()`
+//!   instead, so that exact types like [`Decimal`](../decimal/index.html)
+//!   don't lose any digits.
+//! * The compiler folds constants with `f64` arithmetic (or with `i64`
+//!   arithmetic in integer mode), and rewrites subtraction and division, so
+//!   evaluate the parsed `Expression`s of exact types like `Decimal` instead
+//!   of compiled `Instruction`s.  Constants are stored as `f64`s in compiled
+//!   `Instruction`s, so integers beyond 2^53 are only exact in parsed
+//!   `Expression`s.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::NumberEvaler;  // use this trait so we can call eval_number().
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut ns = |name:&str, _args:Vec<f32>| -> Option<f32> {
+//!         match name {
+//!             "x" => Some(0.1),
+//!             _ => None,
+//!         }
+//!     };
+//!
+//!     let expr_ref = fasteval::Parser::new().parse("x * 3", &mut slab.ps)?.from(&slab.ps);
+//!     let val : f32 = expr_ref.eval_number(&slab, &mut ns)?;
+//!     assert_eq!(val, 0.3);  // This would not be equal with f64.
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evalns::{EvalNamespace, LocalNamespace};
+use crate::hooks::{Hooks, NoHooks};
+use crate::printf::{sprintf, PrintArg};
+use crate::trace::{binaryop_label, unaryop_label, stdfunc_label, print_label};
+use crate::parser::{Expression,
+                    Value::{self, EConstant, EExactConstant, EUnaryOp, EStdFunc, EPrintFunc},
+                    UnaryOp::{self, EPos, ENeg, ENot, EBitNot, EParentheses},
+                    BinaryOp::{self, EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND, EBitOr, EBitXor, EBitAnd, EShl, EShr},
+                    StdFunc::{self, EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH},
+                    Statement, PrintFunc,
+                    ExpressionOrString::{EExpr, EStr},
+                    remove_no_panic};
+#[cfg(feature="unsafe-vars")]
+use crate::parser::StdFunc::EUnsafeVar;
+use crate::compiler::{log, bitor, bitxor, bitand, shl, shr, bitnot, int_shl, int_shr, store_local, IC, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::f64::consts;
+use std::fmt;
+
+/// A number type that expressions can be evaluated with.
+///
+/// Only the basic arithmetic operations are required.  The other functions
+/// have default implementations that convert to `f64` and back, so you only
+/// need to implement the ones that your type can do better.
+///
+/// All operations return a `Result`, so that types like integers can report
+/// errors such as overflow.
+pub trait Number : Copy + PartialEq + fmt::Debug + fmt::Display {
+    /// `true` if `div()` rounds its result, like integer division.
+    ///
+    /// Then `*` and `/` are applied together, left-to-right, so that `4 * 5 / 2`
+    /// is `10` instead of `4 * 2`.  Otherwise `/` is applied before `*`, which
+    /// is what compiled `f64` expressions do, so the default is `false`.
+    const DIV_ROUNDS : bool = false;
+
+    /// Converts an `f64` to this type.  This is used for numeric literals,
+    /// built-in constants like `pi()`, and `SafeVar`s.
+    fn from_f64(x:f64) -> Result<Self,Error>;
+    /// Converts the source text of a numeric literal to this type.
+    ///
+    /// This is only used for literals that have more digits than an `f64`
+    /// can represent exactly.  Types that can store such values exactly, like
+    /// [`Decimal`](../decimal/struct.Decimal.html), should override this.
+    #[inline]
+    fn from_literal(text:&str) -> Result<Self,Error> {
+        Self::from_f64(text.parse::<f64>().map_err(|_| Error::ParseF64(text.to_string()))?)
+    }
+    /// Converts this type to an `f64`.
+    fn to_f64(self) -> f64;
+    /// Converts the result of a comparison to this type.
+    fn from_bool(b:bool) -> Self;
+    /// Returns `true` if this value is zero (i.e. false).
+    fn is_zero(self) -> bool;
+    /// Returns `true` if this value is non-zero (i.e. true).
+    ///
+    /// This is usually the same as `!is_zero()`, but `f64` treats `NaN` as neither.
+    #[inline]
+    fn is_nonzero(self) -> bool { !self.is_zero() }
+    /// Compares two values.  Returns `None` if they can't be ordered.
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error>;
+    /// Equality test used by `==`.
+    #[inline]
+    fn approx_eq(self, other:Self) -> bool { self==other }
+    /// Inequality test used by `!=`.
+    #[inline]
+    fn approx_ne(self, other:Self) -> bool { !self.approx_eq(other) }
+
+    /// `-self`
+    fn neg(self) -> Result<Self,Error>;
+    /// `self + other`
+    fn add(self, other:Self) -> Result<Self,Error>;
+    /// `self - other`
+    fn sub(self, other:Self) -> Result<Self,Error>;
+    /// `self * other`
+    fn mul(self, other:Self) -> Result<Self,Error>;
+    /// `self / other`
+    fn div(self, other:Self) -> Result<Self,Error>;
+    /// `self % other`
+    fn rem(self, other:Self) -> Result<Self,Error>;
+    /// `self ^ other`
+    #[inline]
+    fn pow(self, other:Self) -> Result<Self,Error> { Self::from_f64(self.to_f64().powf(other.to_f64())) }
+
+    /// `self | other`
+    #[inline]
+    fn bitor(self, other:Self) -> Result<Self,Error> { Self::from_f64(bitor(self.to_f64(), other.to_f64())?) }
+    /// `self xor other`
+    #[inline]
+    fn bitxor(self, other:Self) -> Result<Self,Error> { Self::from_f64(bitxor(self.to_f64(), other.to_f64())?) }
+    /// `self & other`
+    #[inline]
+    fn bitand(self, other:Self) -> Result<Self,Error> { Self::from_f64(bitand(self.to_f64(), other.to_f64())?) }
+    /// `self << other`
+    #[inline]
+    fn shl(self, other:Self) -> Result<Self,Error> { Self::from_f64(shl(self.to_f64(), other.to_f64())?) }
+    /// `self >> other`
+    #[inline]
+    fn shr(self, other:Self) -> Result<Self,Error> { Self::from_f64(shr(self.to_f64(), other.to_f64())?) }
+    /// `~self`
+    #[inline]
+    fn bitnot(self) -> Result<Self,Error> { Self::from_f64(bitnot(self.to_f64())?) }
+
+    /// `min(self, other)`
+    #[inline]
+    fn min(self, other:Self) -> Result<Self,Error> {
+        match self.compare(other)? {
+            Some(Ordering::Greater) => Ok(other),
+            Some(_) => Ok(self),
+            None => Err(Error::WrongArgs("min() arguments can't be compared".to_string())),
+        }
+    }
+    /// `max(self, other)`
+    #[inline]
+    fn max(self, other:Self) -> Result<Self,Error> {
+        match self.compare(other)? {
+            Some(Ordering::Less) => Ok(other),
+            Some(_) => Ok(self),
+            None => Err(Error::WrongArgs("max() arguments can't be compared".to_string())),
+        }
+    }
+    /// `round(modulus, self)`
+    #[inline]
+    fn round(self, modulus:Self) -> Result<Self,Error> {
+        let modulus = modulus.to_f64();
+        Self::from_f64((self.to_f64()/modulus).round() * modulus)
+    }
+    /// `log(base, self)`
+    #[inline]
+    fn log(self, base:Self) -> Result<Self,Error> { Self::from_f64(log(base.to_f64(), self.to_f64())) }
+
+    /// `int(self)`
+    #[inline]
+    fn trunc(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().trunc()) }
+    /// `ceil(self)`
+    #[inline]
+    fn ceil(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().ceil()) }
+    /// `floor(self)`
+    #[inline]
+    fn floor(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().floor()) }
+    /// `abs(self)`
+    #[inline]
+    fn abs(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().abs()) }
+    /// `sign(self)`
+    #[inline]
+    fn signum(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().signum()) }
+
+    /// `sin(self)`
+    #[inline]
+    fn sin(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().sin()) }
+    /// `cos(self)`
+    #[inline]
+    fn cos(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().cos()) }
+    /// `tan(self)`
+    #[inline]
+    fn tan(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().tan()) }
+    /// `asin(self)`
+    #[inline]
+    fn asin(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().asin()) }
+    /// `acos(self)`
+    #[inline]
+    fn acos(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().acos()) }
+    /// `atan(self)`
+    #[inline]
+    fn atan(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().atan()) }
+    /// `sinh(self)`
+    #[inline]
+    fn sinh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().sinh()) }
+    /// `cosh(self)`
+    #[inline]
+    fn cosh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().cosh()) }
+    /// `tanh(self)`
+    #[inline]
+    fn tanh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().tanh()) }
+    /// `asinh(self)`
+    #[inline]
+    fn asinh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().asinh()) }
+    /// `acosh(self)`
+    #[inline]
+    fn acosh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().acosh()) }
+    /// `atanh(self)`
+    #[inline]
+    fn atanh(self) -> Result<Self,Error> { Self::from_f64(self.to_f64().atanh()) }
+}
+
+impl Number for f64 {
+    #[inline]
+    fn from_f64(x:f64) -> Result<Self,Error> { Ok(x) }
+    #[inline]
+    fn to_f64(self) -> f64 { self }
+    #[inline]
+    fn from_bool(b:bool) -> Self { bool_to_f64!(b) }
+    #[inline]
+    fn is_zero(self) -> bool { f64_eq!(self,0.0) }
+    #[inline]
+    fn is_nonzero(self) -> bool { f64_ne!(self,0.0) }
+    #[inline]
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> { Ok(self.partial_cmp(&other)) }
+    #[inline]
+    fn approx_eq(self, other:Self) -> bool { f64_eq!(self,other) }
+    #[inline]
+    fn approx_ne(self, other:Self) -> bool { f64_ne!(self,other) }
+
+    #[inline]
+    fn neg(self) -> Result<Self,Error> { Ok(-self) }
+    #[inline]
+    fn add(self, other:Self) -> Result<Self,Error> { Ok(self+other) }
+    #[inline]
+    fn sub(self, other:Self) -> Result<Self,Error> { Ok(self-other) }
+    #[inline]
+    fn mul(self, other:Self) -> Result<Self,Error> { Ok(self*other) }
+    #[inline]
+    fn div(self, other:Self) -> Result<Self,Error> { Ok(self/other) }
+    #[inline]
+    fn rem(self, other:Self) -> Result<Self,Error> { Ok(self%other) }
+
+    // Same as the normal evaler: NaN if either side is NaN.
+    #[inline]
+    fn min(self, other:Self) -> Result<Self,Error> { Ok(if self.is_nan() || other.is_nan() { std::f64::NAN } else { f64::min(self,other) }) }
+    #[inline]
+    fn max(self, other:Self) -> Result<Self,Error> { Ok(if self.is_nan() || other.is_nan() { std::f64::NAN } else { f64::max(self,other) }) }
+}
+
+impl Number for f32 {
+    #[inline]
+    fn from_f64(x:f64) -> Result<Self,Error> { Ok(x as f32) }
+    #[inline]
+    fn to_f64(self) -> f64 { f64::from(self) }
+    #[inline]
+    fn from_bool(b:bool) -> Self { if b { 1.0 } else { 0.0 } }
+    #[inline]
+    fn is_zero(self) -> bool { self.abs() <= 8.0*std::f32::EPSILON }
+    #[inline]
+    fn is_nonzero(self) -> bool { self.abs() > 8.0*std::f32::EPSILON }
+    #[inline]
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> { Ok(self.partial_cmp(&other)) }
+    #[inline]
+    fn approx_eq(self, other:Self) -> bool { (self-other).is_zero() }
+    #[inline]
+    fn approx_ne(self, other:Self) -> bool { (self-other).is_nonzero() }
+
+    #[inline]
+    fn neg(self) -> Result<Self,Error> { Ok(-self) }
+    #[inline]
+    fn add(self, other:Self) -> Result<Self,Error> { Ok(self+other) }
+    #[inline]
+    fn sub(self, other:Self) -> Result<Self,Error> { Ok(self-other) }
+    #[inline]
+    fn mul(self, other:Self) -> Result<Self,Error> { Ok(self*other) }
+    #[inline]
+    fn div(self, other:Self) -> Result<Self,Error> { Ok(self/other) }
+    #[inline]
+    fn rem(self, other:Self) -> Result<Self,Error> { Ok(self%other) }
+    #[inline]
+    fn pow(self, other:Self) -> Result<Self,Error> { Ok(self.powf(other)) }
+
+    #[inline]
+    fn min(self, other:Self) -> Result<Self,Error> { Ok(if self.is_nan() || other.is_nan() { std::f32::NAN } else { f32::min(self,other) }) }
+    #[inline]
+    fn max(self, other:Self) -> Result<Self,Error> { Ok(if self.is_nan() || other.is_nan() { std::f32::NAN } else { f32::max(self,other) }) }
+}
+
+// Integer mode.  Overflow returns Error::Overflow instead of wrapping, and `/`
+// is integer division.
+impl Number for i64 {
+    const DIV_ROUNDS : bool = true;
+
+    /// Returns an `Error` if `x` is not an integer, and `Error::Overflow` if
+    /// it is out of range.
+    fn from_f64(x:f64) -> Result<Self,Error> {
+        if x.fract()!=0.0 || x.is_nan() { return Err(Error::WrongArgs(format!("{} is not an integer", x))); }
+        // -2^63 is exact as an f64, but i64::MAX is not:
+        if !(-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0).contains(&x) { return Err(Error::Overflow); }
+        Ok(x as i64)
+    }
+    /// Integer literals are converted exactly, even if they are too large for an `f64`.
+    #[inline]
+    fn from_literal(text:&str) -> Result<Self,Error> {
+        match text.parse::<i64>() {
+            Ok(i) => Ok(i),
+            Err(_) => Self::from_f64(text.parse::<f64>().map_err(|_| Error::ParseF64(text.to_string()))?),
+        }
+    }
+    #[inline]
+    fn to_f64(self) -> f64 { self as f64 }
+    #[inline]
+    fn from_bool(b:bool) -> Self { if b { 1 } else { 0 } }
+    #[inline]
+    fn is_zero(self) -> bool { self==0 }
+    #[inline]
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> { Ok(Some(self.cmp(&other))) }
+
+    #[inline]
+    fn neg(self) -> Result<Self,Error> { self.checked_neg().ok_or(Error::Overflow) }
+    #[inline]
+    fn add(self, other:Self) -> Result<Self,Error> { self.checked_add(other).ok_or(Error::Overflow) }
+    #[inline]
+    fn sub(self, other:Self) -> Result<Self,Error> { self.checked_sub(other).ok_or(Error::Overflow) }
+    #[inline]
+    fn mul(self, other:Self) -> Result<Self,Error> { self.checked_mul(other).ok_or(Error::Overflow) }
+    /// Integer division, rounded towards zero.
+    #[inline]
+    fn div(self, other:Self) -> Result<Self,Error> {
+        if other==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        self.checked_div(other).ok_or(Error::Overflow)
+    }
+    /// The remainder of integer division.  It has the same sign as `self`.
+    #[inline]
+    fn rem(self, other:Self) -> Result<Self,Error> {
+        if other==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        self.checked_rem(other).ok_or(Error::Overflow)
+    }
+    /// Returns an `Error` if the exponent is negative.
+    fn pow(self, other:Self) -> Result<Self,Error> {
+        if other<0 { return Err(Error::WrongArgs(format!("negative exponent {} in integer mode", other))); }
+        match u32::try_from(other) {
+            Ok(exp) => self.checked_pow(exp).ok_or(Error::Overflow),
+            // Huge exponents only work for these bases:
+            Err(_) => match self {
+                0 | 1 => Ok(self),
+                -1 => Ok(if other%2==0 { 1 } else { -1 }),
+                _ => Err(Error::Overflow),
+            }
+        }
+    }
+
+    /// Rounds to the nearest multiple of `modulus`.  Ties are rounded away from zero.
+    fn round(self, modulus:Self) -> Result<Self,Error> {
+        if modulus==0 { return Err(Error::WrongArgs("round() modulus must not be zero".to_string())); }
+        let (q, r) = (self.wrapping_div(modulus), self.wrapping_rem(modulus));
+        if r==0 { return Ok(self); }
+        let q = if r.unsigned_abs() >= modulus.unsigned_abs()-r.unsigned_abs() {
+            // Move away from zero:
+            if (self<0) == (modulus<0) { q.checked_add(1) } else { q.checked_sub(1) }.ok_or(Error::Overflow)?
+        } else {
+            q
+        };
+        q.checked_mul(modulus).ok_or(Error::Overflow)
+    }
+    #[inline]
+    fn trunc(self) -> Result<Self,Error> { Ok(self) }
+    #[inline]
+    fn ceil(self) -> Result<Self,Error> { Ok(self) }
+    #[inline]
+    fn floor(self) -> Result<Self,Error> { Ok(self) }
+    #[inline]
+    fn abs(self) -> Result<Self,Error> { self.checked_abs().ok_or(Error::Overflow) }
+    #[inline]
+    fn signum(self) -> Result<Self,Error> { Ok(self.signum()) }
+
+    #[inline]
+    fn bitor(self, other:Self) -> Result<Self,Error> { Ok(self | other) }
+    #[inline]
+    fn bitxor(self, other:Self) -> Result<Self,Error> { Ok(self ^ other) }
+    #[inline]
+    fn bitand(self, other:Self) -> Result<Self,Error> { Ok(self & other) }
+    #[inline]
+    fn shl(self, other:Self) -> Result<Self,Error> { int_shl(self, other) }
+    #[inline]
+    fn shr(self, other:Self) -> Result<Self,Error> { int_shr(self, other) }
+    #[inline]
+    fn bitnot(self) -> Result<Self,Error> { Ok(!self) }
+}
+
+/// You must `use` this trait so you can call `.eval_number()`.
+pub trait NumberEvaler {
+    /// Evaluate this `Expression` or `Instruction` with the number type `N`.
+    ///
+    /// Returns a `fasteval::Error` if there are any problems, such as undefined variables.
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error>;
+}
+
+impl NumberEvaler for Expression {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        self.eval_number_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl Expression {
+    pub(crate) fn eval_number_hooked<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        if !hooks.step() { return Err(Error::BudgetExceeded); }
+
+        // Order of operations: 1) ^  2) */  3) +-
+        // Exponentiation should be processed right-to-left.  Think of what 2^3^4 should mean:
+        //     2^(3^4)=2417851639229258349412352   <--- I choose this one.  https://codeplea.com/exponentiation-associativity-options
+        //     (2^3)^4=4096
+        // Direction of processing doesn't matter for Addition and Multiplication:
+        //     (((3+4)+5)+6)==(3+(4+(5+6))), (((3*4)*5)*6)==(3*(4*(5*6)))
+        // ...But Subtraction and Division must be processed left-to-right:
+        //     (((6-5)-4)-3)!=(6-(5-(4-3))), (((6/5)/4)/3)!=(6/(5/(4/3)))
+
+        // Parentheses and function arguments are usually a single Value, which
+        // doesn't need the buffers below:
+        if self.pairs.is_empty() { return self.first.eval_number_hooked(slab,ns,hooks); }
+
+        let mut vals = Vec::<N>::with_capacity(self.pairs.len()+1);
+        let mut ops  = Vec::<BinaryOp>::with_capacity(self.pairs.len());
+        vals.push(self.first.eval_number_hooked(slab,ns,hooks)?);
+        for pair in self.pairs.iter() {
+            ops.push(pair.0);
+            vals.push(pair.1.eval_number_hooked(slab,ns,hooks)?);
+        }
+
+        #[inline(never)]
+        fn apply_traced<N:Number>(op:BinaryOp, left:N, right:N, hooks:&mut impl Hooks) -> Result<N,Error> {
+            trace_step!(hooks, op.binaryop_eval_number(left, right), |_,_| binaryop_label(op, left.to_f64(), right.to_f64()))
+        }
+        #[inline(always)]
+        fn apply<N:Number>(vals:&mut Vec<N>, ops:&mut Vec<BinaryOp>, i:usize, hooks:&mut impl Hooks) -> Result<(),Error> {
+            let res = match (ops.get(i), vals.get(i), vals.get(i+1)) {
+                (Some(op), Some(left), Some(right)) if hooks.tracing() => apply_traced(*op, *left, *right, hooks)?,
+                (Some(op), Some(left), Some(right)) => op.binaryop_eval_number(*left, *right)?,
+                _ => return Err(Error::Unreachable),
+            };
+            if let Some(val_ref) = vals.get_mut(i) { *val_ref=res; }
+            remove_no_panic(vals, i+1);
+            remove_no_panic(ops, i);
+            Ok(())
+        }
+        #[inline(always)]
+        fn rtol<N:Number>(vals:&mut Vec<N>, ops:&mut Vec<BinaryOp>, search:BinaryOp, hooks:&mut impl Hooks) -> Result<(),Error> {
+            for i in (0..ops.len()).rev() {
+                if ops.get(i)==Some(&search) { apply(vals, ops, i, hooks)?; }
+            }
+            Ok(())
+        }
+        #[inline(always)]
+        fn ltor<N:Number>(vals:&mut Vec<N>, ops:&mut Vec<BinaryOp>, search:&[BinaryOp], hooks:&mut impl Hooks) -> Result<(),Error> {
+            let mut i = 0;
+            while let Some(op) = ops.get(i) {
+                if search.contains(op) { apply(vals, ops, i, hooks)?; } else { i+=1; }
+            }
+            Ok(())
+        }
+
+        // Keep the order of these statements in-sync with parser.rs BinaryOp priority values.
+        // The one exception is that '*' and '/' are applied together, left-to-right, like
+        // in C and Python, for types whose division rounds.  Other types keep the compiler's
+        // order, because `1e308*10/10` overflows if the '*' goes first.
+        rtol(&mut vals, &mut ops, EExp, hooks)?;  // https://codeplea.com/exponentiation-associativity-options
+        ltor(&mut vals, &mut ops, &[EMod], hooks)?;
+        if N::DIV_ROUNDS {
+            ltor(&mut vals, &mut ops, &[EMul, EDiv], hooks)?;
+        } else {
+            ltor(&mut vals, &mut ops, &[EDiv], hooks)?;
+            rtol(&mut vals, &mut ops, EMul, hooks)?;
+        }
+        ltor(&mut vals, &mut ops, &[ESub], hooks)?;
+        rtol(&mut vals, &mut ops, EAdd, hooks)?;
+        ltor(&mut vals, &mut ops, &[EShl, EShr], hooks)?;
+        ltor(&mut vals, &mut ops, &[EBitAnd], hooks)?;
+        ltor(&mut vals, &mut ops, &[EBitXor], hooks)?;
+        ltor(&mut vals, &mut ops, &[EBitOr], hooks)?;
+        ltor(&mut vals, &mut ops, &[ELT, EGT, ELTE, EGTE, EEQ, ENE], hooks)?;  // TODO: Implement Python-style a<b<c ternary comparison... might as well generalize to N comparisons.
+        ltor(&mut vals, &mut ops, &[EAND], hooks)?;
+        ltor(&mut vals, &mut ops, &[EOR], hooks)?;
+
+        if !ops.is_empty() || vals.len()!=1 { return Err(Error::Unreachable); }
+        match vals.first() {
+            Some(val) => Ok(*val),
+            None => Err(Error::Unreachable),
+        }
+    }
+}
+
+impl NumberEvaler for Value {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        self.eval_number_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl Value {
+    pub(crate) fn eval_number_hooked<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        match self {
+            EConstant(c) => N::from_f64(*c),
+            EExactConstant(_, text) => N::from_literal(text),
+            EUnaryOp(u) => u.eval_number_hooked(slab,ns,hooks),
+            EStdFunc(f) => f.eval_number_hooked(slab,ns,hooks),
+            EPrintFunc(f) => f.eval_number_hooked(slab,ns,hooks),
+        }
+    }
+}
+
+impl NumberEvaler for UnaryOp {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        self.eval_number_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl UnaryOp {
+    pub(crate) fn eval_number_hooked<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        if !hooks.step() { return Err(Error::BudgetExceeded); }
+        if hooks.tracing() { return self.eval_traced(slab,ns,hooks); }
+        self.eval_op(slab,ns,hooks)
+    }
+
+    #[inline(never)]
+    fn eval_traced<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        match self {
+            ENeg(_) | ENot(_) | EBitNot(_) => trace_step!(hooks, self.eval_op(slab,ns,hooks), |_,args| unaryop_label(self,args)),
+            EPos(_) | EParentheses(_) => self.eval_op(slab,ns,hooks),
+        }
+    }
+
+    #[inline(always)]
+    fn eval_op<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        match self {
+            EPos(val_i) => get_val!(slab.ps,val_i).eval_number_hooked(slab,ns,hooks),
+            ENeg(val_i) => traced!(hooks, get_val!(slab.ps,val_i).eval_number_hooked(slab,ns,hooks)?).neg(),
+            ENot(val_i) => Ok(N::from_bool(traced!(hooks, get_val!(slab.ps,val_i).eval_number_hooked(slab,ns,hooks)?).is_zero())),
+            EBitNot(val_i) => traced!(hooks, get_val!(slab.ps,val_i).eval_number_hooked(slab,ns,hooks)?).bitnot(),
+            EParentheses(expr_i) => get_expr!(slab.ps,expr_i).eval_number_hooked(slab,ns,hooks),
+        }
+    }
+}
+
+impl BinaryOp {
+    fn binaryop_eval_number<N:Number>(self, left:N, right:N) -> Result<N,Error> {
+        Ok(match self {
+            EAdd => left.add(right)?,
+            ESub => left.sub(right)?,
+            EMul => left.mul(right)?,
+            EDiv => left.div(right)?,
+            EMod => left.rem(right)?,
+            EExp => left.pow(right)?,
+            ELT => N::from_bool(left.compare(right)? == Some(Ordering::Less)),
+            ELTE => N::from_bool(matches!(left.compare(right)?, Some(Ordering::Less) | Some(Ordering::Equal))),
+            EEQ => N::from_bool(left.approx_eq(right)),
+            ENE => N::from_bool(left.approx_ne(right)),
+            EGTE => N::from_bool(matches!(left.compare(right)?, Some(Ordering::Greater) | Some(Ordering::Equal))),
+            EGT => N::from_bool(left.compare(right)? == Some(Ordering::Greater)),
+            EOR => if left.is_nonzero() { left } else { right },
+            EAND => if left.is_zero() { left } else { right },
+            EBitOr => left.bitor(right)?,
+            EBitXor => left.bitxor(right)?,
+            EBitAnd => left.bitand(right)?,
+            EShl => left.shl(right)?,
+            EShr => left.shr(right)?,
+        })
+    }
+}
+
+impl NumberEvaler for StdFunc {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        self.eval_number_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl StdFunc {
+    pub(crate) fn eval_number_hooked<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        if !hooks.step() { return Err(Error::BudgetExceeded); }
+        if hooks.tracing() { return self.eval_traced(slab,ns,hooks); }
+        self.eval_func(slab,ns,hooks)
+    }
+
+    #[inline(never)]
+    fn eval_traced<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        match self {
+            // Programs record their own steps:
+            EFuncE | EFuncPi | EProgram(_) => self.eval_func(slab,ns,hooks),
+            _ => trace_step!(hooks, self.eval_func(slab,ns,hooks), |_,args| stdfunc_label(self,args)),
+        }
+    }
+
+    #[inline(always)]
+    fn eval_func<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        macro_rules! arg {
+            ($i:ident) => { traced!(hooks, get_expr!(slab.ps,$i).eval_number_hooked(slab,ns,hooks)?) };
+        }
+
+        match self {
+            #[cfg(feature="unsafe-vars")]
+            EUnsafeVar{ptr, ..} => N::from_f64(unsafe { **ptr }),
+
+            ESafeVar{var, ..} => N::from_f64(var.get()),
+            EVar(name) => eval_var!(ns, name, Vec::new()),
+            EFunc{name, args:xis} => {
+                let mut args = Vec::with_capacity(xis.len());
+                for xi in xis {
+                    args.push(arg!(xi));
+                }
+                if let Some(f) = slab.ps.funcs.get(name) {
+                    f.check_args(name, args.len())?;
+                    return slab.ps.get_expr(f.body).eval_number_hooked(slab, &mut LocalNamespace::new(&f.params, &args, ns), hooks);
+                }
+                eval_var!(ns, name, args)
+            }
+
+            EFuncLog{base:base_opt, expr:expr_i} => {
+                let base = match base_opt {
+                    Some(b_expr_i) => arg!(b_expr_i),
+                    None => N::from_f64(10.0)?,
+                };
+                arg!(expr_i).log(base)
+            }
+            EFuncRound{modulus:modulus_opt, expr:expr_i} => {
+                let modulus = match modulus_opt {
+                    Some(m_expr_i) => arg!(m_expr_i),
+                    None => N::from_f64(1.0)?,
+                };
+                arg!(expr_i).round(modulus)
+            }
+
+            EFuncSin(expr_i) => arg!(expr_i).sin(),
+            EFuncCos(expr_i) => arg!(expr_i).cos(),
+            EFuncTan(expr_i) => arg!(expr_i).tan(),
+            EFuncASin(expr_i) => arg!(expr_i).asin(),
+            EFuncACos(expr_i) => arg!(expr_i).acos(),
+            EFuncATan(expr_i) => arg!(expr_i).atan(),
+            EFuncSinH(expr_i) => arg!(expr_i).sinh(),
+            EFuncCosH(expr_i) => arg!(expr_i).cosh(),
+            EFuncTanH(expr_i) => arg!(expr_i).tanh(),
+            EFuncASinH(expr_i) => arg!(expr_i).asinh(),
+            EFuncACosH(expr_i) => arg!(expr_i).acosh(),
+            EFuncATanH(expr_i) => arg!(expr_i).atanh(),
+
+            EFuncAbs(expr_i) => arg!(expr_i).abs(),
+            EFuncSign(expr_i) => arg!(expr_i).signum(),
+            EFuncInt(expr_i) => arg!(expr_i).trunc(),
+            EFuncCeil(expr_i) => arg!(expr_i).ceil(),
+            EFuncFloor(expr_i) => arg!(expr_i).floor(),
+            EFuncMin{first:first_i, rest} => {
+                let mut min = arg!(first_i);
+                for x_i in rest.iter() {
+                    min = min.min(arg!(x_i))?;
+                }
+                Ok(min)
+            }
+            EFuncMax{first:first_i, rest} => {
+                let mut max = arg!(first_i);
+                for x_i in rest.iter() {
+                    max = max.max(arg!(x_i))?;
+                }
+                Ok(max)
+            }
+            EFuncIf{cond, then, otherwise} => {
+                // Only the taken branch is evaluated:
+                if arg!(cond).is_nonzero() {
+                    Ok(arg!(then))
+                } else {
+                    Ok(arg!(otherwise))
+                }
+            }
+            EProgram(stmts) => eval_program(stmts, slab, ns, hooks),
+
+            EFuncE => N::from_f64(consts::E),
+            EFuncPi => N::from_f64(consts::PI),
+        }
+    }
+}
+
+// Evaluates the statements of a program in order.  The names and values of
+// the variables that have been assigned so far are bound by a LocalNamespace.
+#[inline(never)]
+fn eval_program<N:Number>(stmts:&[Statement], slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+    let mut names = Vec::<String>::with_capacity(stmts.len());
+    let mut vals = Vec::<N>::with_capacity(stmts.len());
+    let mut val = N::from_f64(0.0)?;
+    for Statement(name, xi) in stmts {
+        let lns = &mut LocalNamespace::new(&names, &vals, ns);
+        val = match name {
+            Some(name) if hooks.tracing() => trace_step!(hooks, get_expr!(slab.ps,xi).eval_number_hooked(slab,lns,hooks), |_,_| format!("set {}", name))?,
+            _ => get_expr!(slab.ps,xi).eval_number_hooked(slab,lns,hooks)?,
+        };
+        if let Some(name) = name {
+            match names.iter().position(|n| n==name) {
+                Some(i) => vals[i] = val,
+                None => { names.push(name.clone()); vals.push(val); }
+            }
+        }
+    }
+    Ok(val)
+}
+
+impl NumberEvaler for PrintFunc {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        self.eval_number_hooked(slab,ns,&mut NoHooks)
+    }
+}
+impl PrintFunc {
+    pub(crate) fn eval_number_hooked<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        if !hooks.step() { return Err(Error::BudgetExceeded); }
+        if hooks.tracing() { return self.eval_traced(slab,ns,hooks); }
+        self.eval_print(slab,ns,hooks)
+    }
+
+    #[inline(never)]
+    fn eval_traced<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        trace_step!(hooks, self.eval_print(slab,ns,hooks), |_,args| print_label(self,args))
+    }
+
+    #[inline(always)]
+    fn eval_print<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>, hooks:&mut impl Hooks) -> Result<N,Error> {
+        let mut val = N::from_f64(0.0)?;
+        if let Some(EStr(fmtstr)) = self.0.first() {
+            if fmtstr.contains('%') {
+                let mut args = Vec::<PrintArg<N>>::with_capacity(self.0.len()-1);
+                for a in &self.0[1..] {
+                    match a {
+                        EExpr(e_i) => {
+                            val = traced!(hooks, get_expr!(slab.ps,e_i).eval_number_hooked(slab,ns,hooks)?);
+                            args.push(PrintArg::Num(val));
+                        }
+                        EStr(s) => args.push(PrintArg::Str(s)),
+                    }
+                }
+                ns.print(&sprintf(fmtstr, &args)?);
+                return Ok(val);
+            }
+        }
+
+        let mut out = String::with_capacity(16);
+        for (i,a) in self.0.iter().enumerate() {
+            if i>0 { out.push(' '); }
+            match a {
+                EExpr(e_i) => {
+                    val = traced!(hooks, get_expr!(slab.ps,e_i).eval_number_hooked(slab,ns,hooks)?);
+                    out.push_str(&val.to_string());
+                }
+                EStr(s) => out.push_str(s),
+            }
+        }
+        ns.print(&out);
+
+        Ok(val)
+    }
+}
+
+impl NumberEvaler for Instruction {
+    #[inline]
+    fn eval_number<N:Number>(&self, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+        eval_instr_number(self, slab, ns)
+    }
+}
+
+// Evaluates compiled `Instruction`s with the number type `N`.  This is also
+// the evaluator behind `eval_complex()`.  `eval_interval()` and `eval_grad()`
+// have their own, because an `Interval` evaluates both branches of an
+// uncertain condition, and a `Dual` can't be a `Number` (it isn't `Copy`).
+pub(crate) fn eval_instr_number<N:Number>(instr:&Instruction, slab:&Slab, ns:&mut impl EvalNamespace<N>) -> Result<N,Error> {
+    InstrEvaler{ slab, ns, names:Vec::new(), locals:Vec::new() }.instr(instr)
+}
+
+struct InstrEvaler<'a,N,NS> {
+    slab  :&'a Slab,
+    ns    :&'a mut NS,
+    names :Vec<String>,  // The names of the local variables, for print().
+    locals:Vec<N>,  // The arguments of the user-defined function whose body is being evaluated, or the variables of the program.
+}
+
+impl<N,NS> InstrEvaler<'_,N,NS> where N:Number, NS:EvalNamespace<N> {
+    #[inline]
+    fn ic(&mut self, ic:&IC) -> Result<N,Error> {
+        match ic {
+            IC::C(c) => N::from_f64(*c),
+            IC::I(i) => self.instr(get_instr!(self.slab.cs,i)),
+        }
+    }
+
+    fn instr(&mut self, instr:&Instruction) -> Result<N,Error> {
+        macro_rules! i {
+            ($i:ident) => { self.instr(get_instr!(self.slab.cs,$i))? };
+        }
+        macro_rules! ic {
+            ($ic:ident) => { self.ic($ic)? };
+        }
+
+        match instr {
+            IConst(c) => N::from_f64(*c),
+            IVar(name) => { let ns = &mut *self.ns; eval_var!(ns, name, Vec::new()) }
+            ISlot{name, slot} => match self.ns.slot(*slot) {
+                Some(x) => Ok(x),
+                None => { let ns = &mut *self.ns; eval_var!(ns, name, Vec::new()) }
+            },
+            ISafeVar{var, ..} => N::from_f64(var.get()),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => N::from_f64(unsafe { **ptr }),
+            IFunc{name, args:ics} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics { args.push(ic!(ic)); }
+                let ns = &mut *self.ns;
+                eval_var!(ns, name, args)
+            }
+            IPrintFunc(pf) => pf.eval_number(self.slab, &mut LocalNamespace::new(&self.names[..self.locals.len()], &self.locals, self.ns)),
+            ILocal(i) => self.locals.get(*i).copied().ok_or(Error::Unreachable),
+            ICall{names, args:ics, body, ..} => {
+                let mut args = Vec::with_capacity(ics.len());
+                for ic in ics { args.push(ic!(ic)); }
+                let locals = std::mem::replace(&mut self.locals, args);
+                let names = std::mem::replace(&mut self.names, names.clone());
+                let res = self.instr(get_instr!(self.slab.cs,body));
+                self.locals = locals;
+                self.names = names;
+                res
+            }
+            IProgram{names, stmts} => {
+                self.names = names.clone();
+                let mut val = N::from_f64(0.0)?;
+                for (slot,ic) in stmts {
+                    val = ic!(ic);
+                    store_local(&mut self.locals, *slot, val);
+                }
+                Ok(val)
+            }
+
+            INeg(i) => i!(i).neg(),
+            INot(i) => Ok(N::from_bool(i!(i).is_zero())),
+            IInv(i) => N::from_f64(1.0)?.div(i!(i)),
+            IAdd(li, ric) => i!(li).add(ic!(ric)),
+            IMul(li, ric) => {
+                let l = i!(li);
+                // The integer mode of the compiler compiles `a / b` to `a * IInv(b)`:
+                if N::DIV_ROUNDS {
+                    if let IC::I(ri) = ric {
+                        if let IInv(di) = get_instr!(self.slab.cs,ri) { return l.div(i!(di)); }
+                    }
+                }
+                l.mul(ic!(ric))
+            }
+            IMod{dividend, divisor} => ic!(dividend).rem(ic!(divisor)),
+            IExp{base, power} => ic!(base).pow(ic!(power)),
+
+            ILT(l, r) => Ok(N::from_bool(ic!(l).compare(ic!(r))? == Some(Ordering::Less))),
+            ILTE(l, r) => Ok(N::from_bool(matches!(ic!(l).compare(ic!(r))?, Some(Ordering::Less) | Some(Ordering::Equal)))),
+            IEQ(l, r) => Ok(N::from_bool(ic!(l).approx_eq(ic!(r)))),
+            INE(l, r) => Ok(N::from_bool(ic!(l).approx_ne(ic!(r)))),
+            IGTE(l, r) => Ok(N::from_bool(matches!(ic!(l).compare(ic!(r))?, Some(Ordering::Greater) | Some(Ordering::Equal)))),
+            IGT(l, r) => Ok(N::from_bool(ic!(l).compare(ic!(r))? == Some(Ordering::Greater))),
+            IOR(li, ric) => {
+                let l = i!(li);
+                if l.is_nonzero() { Ok(l) } else { self.ic(ric) }
+            }
+            IAND(li, ric) => {
+                let l = i!(li);
+                if l.is_zero() { Ok(l) } else { self.ic(ric) }
+            }
+            IFuncIf{cond, then, otherwise} => {
+                if i!(cond).is_nonzero() { self.ic(then) } else { self.ic(otherwise) }
+            }
+            IBitOr(l, r) => ic!(l).bitor(ic!(r)),
+            IBitXor(l, r) => ic!(l).bitxor(ic!(r)),
+            IBitAnd(l, r) => ic!(l).bitand(ic!(r)),
+            IShl(l, r) => ic!(l).shl(ic!(r)),
+            IShr(l, r) => ic!(l).shr(ic!(r)),
+            IBitNot(i) => i!(i).bitnot(),
+
+            IFuncInt(i) => i!(i).trunc(),
+            IFuncCeil(i) => i!(i).ceil(),
+            IFuncFloor(i) => i!(i).floor(),
+            IFuncAbs(i) => i!(i).abs(),
+            IFuncSign(i) => i!(i).signum(),
+            IFuncLog{base, of} => { let b = ic!(base); ic!(of).log(b) }
+            IFuncRound{modulus, of} => { let m = ic!(modulus); ic!(of).round(m) }
+            IFuncMin(li, ric) => i!(li).min(ic!(ric)),
+            IFuncMax(li, ric) => i!(li).max(ic!(ric)),
+
+            IFuncSin(i) => i!(i).sin(),
+            IFuncCos(i) => i!(i).cos(),
+            IFuncTan(i) => i!(i).tan(),
+            IFuncASin(i) => i!(i).asin(),
+            IFuncACos(i) => i!(i).acos(),
+            IFuncATan(i) => i!(i).atan(),
+            IFuncSinH(i) => i!(i).sinh(),
+            IFuncCosH(i) => i!(i).cosh(),
+            IFuncTanH(i) => i!(i).tanh(),
+            IFuncASinH(i) => i!(i).asinh(),
+            IFuncACosH(i) => i!(i).acosh(),
+            IFuncATanH(i) => i!(i).atanh(),
+        }
+    }
+}
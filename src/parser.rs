@@ -2,30 +2,57 @@
 //!
 //! # fasteval Algebra Grammar
 //! ```text
-//! Expression: Value (BinaryOp Value)*
+//! Expression: Value (BinaryOp Value)* (? Expression : Expression)?
 //!
 //! Value: Constant || UnaryOp || PrintFunc || StdFunc
 //!
 //! Constant: [+-]?[0-9]*(\.[0-9]+)?( ([eE][+-]?[0-9]+) || [pnuµmkKMGT] )?  || [+-]?(NaN || inf)
+//!           || [+-]?0[xX][0-9a-fA-F]+  ||  [+-]?0o[0-7]+  ||  [+-]?0[bB][01]+
 //!
-//! UnaryOp: +Value || -Value || (Expression) || [Expression] || !Value
+//! Digits can be separated by underscores, like `1_000_000` or `0xFFFF_0000`.
 //!
-//! BinaryOp: + || - || * || / || % || ^ || < || <= || == || != || >= || > || (or || '||') || (and || '&&')
+//! UnaryOp: +Value || -Value || (Expression) || [Expression] || !Value || ~Value
+//!
+//! BinaryOp: + || - || * || / || % || ^ || < || <= || == || != || >= || > || (or || '||') || (and || '&&') || & || '|' || xor || << || >>
 //!
 //! VarName: [a-zA-Z_][a-zA-Z_0-9]*
 //!
 //! StdFunc: VarName((Expression,)*)?  ||  VarName[(Expression,)*]?
 //!
+//! The ternary `cond ? a : b` has the lowest precedence, is right-associative,
+//! and is parsed into the same `StdFunc` as `if(cond, a, b)`.
+//!
 //! PrintFunc: print(ExpressionOrString,*)
 //!
 //! ExpressionOrString: Expression || String
 //!
-//! String: ".*"
+//! String: "([^"\\] || Escape)*"  ||  '([^'\\] || Escape)*'
+//!
+//! Escape: \" || \' || \\ || \n || \t || \r || \0 || \u{[0-9a-fA-F]{1,6}}
+//!
+//! Comment: #.* || //.* || /* ... */
+//!
+//! Program: Statement ((; || newline) Statement)*
+//!
+//! Statement: (VarName = Expression)  ||  Expression
+//!
+//! Definition: VarName((VarName,)*) = Expression  ||  VarName[(VarName,)*] = Expression
 //! ```
+//!
+//! `Parser::parse()` accepts a Program, which is usually just a single
+//! Expression.  A newline only ends a Statement where the Expression can't
+//! continue, so a line that starts with a BinaryOp continues the line before it.
+//! Comments can appear anywhere that a space can.  `#` and `//` comments
+//! extend to the end of the line, and they count towards `expr_len_limit`
+//! like everything else.
+//! `Parser::define()` accepts a Definition.
 
 
 use crate::error::Error;
+use crate::evalns::SafeVar;
 use crate::slab::ParseSlab;
+use crate::printf;
+use crate::derive::forget_partials;
 
 use std::str::{from_utf8, from_utf8_unchecked};
 use std::ptr;
@@ -50,40 +77,44 @@ pub struct ValueI(pub usize);
 /// An `Expression` is the top node of a parsed AST.
 ///
 /// It can be `compile()`d or `eval()`d.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Expression {
     pub(crate) first: Value,
     pub(crate) pairs: Vec<ExprPair>,  // cap=8
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) struct ExprPair(pub BinaryOp, pub Value);
 
 /// A `Value` can be a Constant, a UnaryOp, a StdFunc, or a PrintFunc.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     EConstant(f64),
+    /// A constant with more digits than an `f64` can represent exactly.  The
+    /// source text is kept for exact number types, like `Decimal` and `i64`.
+    EExactConstant(f64, String),
     EUnaryOp(UnaryOp),
     EStdFunc(StdFunc),
     EPrintFunc(PrintFunc),
 }
-use Value::{EConstant, EUnaryOp, EStdFunc, EPrintFunc};
+use Value::{EConstant, EExactConstant, EUnaryOp, EStdFunc, EPrintFunc};
 
 /// Unary Operators
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum UnaryOp {
     EPos(ValueI),
     ENeg(ValueI),
     ENot(ValueI),
+    EBitNot(ValueI),
     EParentheses(ExpressionI),
 }
-use UnaryOp::{EPos, ENeg, ENot, EParentheses};
+use UnaryOp::{EPos, ENeg, ENot, EBitNot, EParentheses};
 
 /// Binary Operators
 #[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
 pub enum BinaryOp {
     // Sorted in order of precedence (low-priority to high-priority):
-    // Keep this order in-sync with evaler.rs.  (Search for 'rtol' and 'ltor'.)
+    // Keep this order in-sync with number.rs.  (Search for 'rtol' and 'ltor'.)
     EOR    =  1,  // Lowest Priority
     EAND   =  2,
     ENE    =  3,
@@ -92,19 +123,29 @@ pub enum BinaryOp {
     ELTE   =  6,
     EGT    =  7,
     ELT    =  8,
-    EAdd   =  9,
-    ESub   = 10,
-    EMul   = 11,
-    EDiv   = 12,
-    EMod   = 13,
-    EExp   = 14,  // Highest Priority
+    // The bitwise operators bind tighter than comparisons (like in Python,
+    // unlike in C), so that `x & mask == 0` means `(x & mask) == 0`:
+    EBitOr =  9,
+    EBitXor= 10,
+    EBitAnd= 11,
+    EShr   = 12,  // Shifts have equal precedence, and are evaluated left-to-right.
+    EShl   = 13,
+    EAdd   = 14,
+    ESub   = 15,
+    EMul   = 16,
+    EDiv   = 17,
+    EMod   = 18,
+    EExp   = 19,  // Highest Priority
 }
-use BinaryOp::{EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND};
+use BinaryOp::{EAdd, ESub, EMul, EDiv, EMod, EExp, ELT, ELTE, EEQ, ENE, EGTE, EGT, EOR, EAND, EBitOr, EBitAnd, EShl, EShr};
+#[cfg(feature="alpha-keywords")]
+use BinaryOp::EBitXor;
 
 /// A Function Call with Standard Syntax.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum StdFunc {
     EVar(String),
+    ESafeVar{name:String, var:SafeVar},
     #[cfg(feature="unsafe-vars")]
     EUnsafeVar{name:String, ptr:*const f64},
     EFunc{name:String, args:Vec<ExpressionI>},  // cap=4
@@ -118,6 +159,8 @@ pub enum StdFunc {
     EFuncRound{modulus:Option<ExpressionI>, expr:ExpressionI},
     EFuncMin{first:ExpressionI, rest:Vec<ExpressionI>},  // cap=4
     EFuncMax{first:ExpressionI, rest:Vec<ExpressionI>},  // cap=4
+    EFuncIf{cond:ExpressionI, then:ExpressionI, otherwise:ExpressionI},
+    EProgram(Vec<Statement>),  // Only at the top level.  cap=8
 
     EFuncE,
     EFuncPi,
@@ -135,10 +178,44 @@ pub enum StdFunc {
     EFuncACosH(ExpressionI),
     EFuncATanH(ExpressionI),
 }
-use StdFunc::{EVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH};
+use StdFunc::{EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH};
 #[cfg(feature="unsafe-vars")]
 use StdFunc::EUnsafeVar;
 
+/// A statement of a program, like `x = a * 2`.  The value of the `Expression`
+/// is assigned to the local variable, if there is one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement(pub Option<String>, pub ExpressionI);
+
+/// A user-defined function, like `f(x, y) = x^2 + y`.
+///
+/// User-defined functions are created by
+/// [`Parser::define()`](struct.Parser.html#method.define) and are stored in
+/// the `ParseSlab`.  A call to one is parsed as a normal `EFunc`, which is
+/// resolved by name: `eval()` evaluates the body with the parameters bound
+/// to the arguments, and `compile()` inlines the body.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UserFunc {
+    pub(crate) params:Vec<String>,
+    pub(crate) body  :ExpressionI,
+    pub(crate) calls :Vec<String>,  // The names of the functions called by 'body'.
+}
+
+impl UserFunc {
+    /// The names of the parameters.
+    #[inline]
+    pub fn params(&self) -> &[String] { &self.params }
+
+    /// The body of the function, within the `ParseSlab`.
+    #[inline]
+    pub fn body(&self) -> ExpressionI { self.body }
+
+    pub(crate) fn check_args(&self, name:&str, n:usize) -> Result<(),Error> {
+        if n==self.params.len() { return Ok(()); }
+        Err(Error::WrongArgs(format!("function `{}` takes {} argument{}", name, self.params.len(), if self.params.len()==1 { "" } else { "s" })))
+    }
+}
+
 /// Represents a `print()` function call in the `fasteval` expression AST.
 #[derive(Debug, PartialEq)]
 pub struct PrintFunc(pub Vec<ExpressionOrString>);  // cap=8
@@ -168,6 +245,12 @@ impl Clone for PrintFunc {
 
 
 
+// Returns true if 'name' is parsed as a builtin function when it is called.
+fn is_builtin_func(name:&str) -> bool {
+    matches!(name, "print" | "int" | "ceil" | "floor" | "abs" | "sign" | "log" | "round" | "min" | "max" | "if" | "e" | "pi"
+                 | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "sinh" | "cosh" | "tanh" | "asinh" | "acosh" | "atanh")
+}
+
 enum Token<T> {
     Pass,
     Bite(T),
@@ -245,12 +328,51 @@ macro_rules! is_space {
 macro_rules! spaces {
     ($bs:ident) => {
         while let Some(b) = peek!($bs) {
-            if !is_space!(b) { break }
+            if !is_space!(b) {
+                if b==b'#' || (b==b'/' && (peek_is!($bs,1,b'/') || peek_is!($bs,1,b'*'))) { skip_comment($bs)?; continue; }
+                break
+            }
             skip!($bs);  // We normally don't have long strings of whitespace, so it is more efficient to put this single-skip inside this loop rather than a skip_n afterwards.
         }
     };
 }
 
+// Skips a `#` or `//` comment up to the end of the line (but not the newline
+// itself, which can end a Statement), or a `/* */` comment.
+#[inline(never)]
+fn skip_comment(bs:&mut &[u8]) -> Result<(),Error> {
+    if peek_is!(bs,1,b'*') {
+        match bs[2..].windows(2).position(|w| w==b"*/") {
+            Some(i) => { *bs = &bs[2+i+2..]; }
+            None => return Err(Error::EofWhileParsing("comment".to_string())),
+        }
+    } else {
+        let n = bs.iter().position(|&b| b==b'\n').unwrap_or(bs.len());
+        skip_n!(bs,n);
+    }
+    Ok(())
+}
+
+// Checks whether the whitespace and comments at the end of 'src' include a
+// newline.  A newline inside of a `/* */` comment doesn't count.
+fn ends_with_newline(mut src:&[u8]) -> bool {
+    loop {
+        while let Some(&b) = src.last() {
+            if b==b'\n' { return true; }
+            if !is_space!(b) { break; }
+            src = &src[..src.len()-1];
+        }
+        if !src.ends_with(b"*/") { return false; }
+        // Block comments don't nest, so this one starts at the first `/*` after the previous `*/`:
+        let end = src.len()-2;
+        let prev = src[..end].windows(2).rposition(|w| w==b"*/").map(|i| i+2).unwrap_or(0);
+        match src[prev..end].windows(2).position(|w| w==b"/*") {
+            Some(i) => src = &src[..prev+i],
+            None => return false,
+        }
+    }
+}
+
 
 pub const DEFAULT_EXPR_LEN_LIMIT  : usize = 4096;
 pub const DEFAULT_EXPR_DEPTH_LIMIT: usize = 32;
@@ -274,8 +396,23 @@ impl Parser {
             None => false,
         }
     }
+    // A keyword like `or` must not be the start of a longer name, like `orange`:
+    #[cfg(feature="alpha-keywords")]
+    fn is_keyword_end(bs:&[u8], len:usize) -> bool {
+        !Self::is_varname_byte_opt(peek_n!(bs,len),len)
+    }
 
     /// Use this function to parse an expression String.  The `Slab` will be cleared first.
+    ///
+    /// The String can also be a program: a sequence of statements that are
+    /// separated by `;` or newlines.  A statement like `x = a * 2` assigns a
+    /// local variable, which shadows the Namespace in the statements after it.
+    /// The value of a program is the value of its last statement.
+    ///
+    /// # Errors
+    ///
+    /// All errors are returned as [`Error::Located`](../error/enum.Error.html#variant.Located),
+    /// which records the byte offset, line, and column where parsing failed.
     #[inline]
     pub fn parse(&self, expr_str:&str, slab:&mut ParseSlab) -> Result<ExpressionI,Error> {
         slab.clear();
@@ -290,15 +427,215 @@ impl Parser {
     /// prolong the mut ref.  / That's why we return an ExpressionI instead.
     #[inline]
     pub fn parse_noclear(&self, expr_str:&str, slab:&mut ParseSlab) -> Result<ExpressionI,Error> {
-        if expr_str.len()>self.expr_len_limit { return Err(Error::TooLong); }  // Restrict length for safety
+        if expr_str.len()>self.expr_len_limit { return Err(Error::TooLong.at(expr_str, self.expr_len_limit)); }  // Restrict length for safety
         let mut bs = expr_str.as_bytes();
-        self.read_expression(slab, &mut bs, 0, true)
+        // When an error occurs, 'bs' is left pointing at the problematic input:
+        self.read_program(slab, &mut bs).map_err(|err| err.at(expr_str, expr_str.len()-bs.len()))
+    }
+
+    /// Parses a user-defined function, like `f(x, y) = x^2 + y`, and stores it
+    /// in the `ParseSlab` so that expressions can call it.  An existing function
+    /// with the same name is replaced.
+    ///
+    /// Functions must be defined before the expressions that call them are
+    /// parsed.  The body can use the parameters, other variables from the
+    /// Namespace, and functions that are already defined, but it can't call
+    /// itself, directly or indirectly.  Definitions are kept when the
+    /// `ParseSlab` is cleared by `parse()`; use
+    /// [`ParseSlab::clear_funcs()`](../slab/struct.ParseSlab.html#method.clear_funcs)
+    /// to remove them.  Everything in the `ParseSlab` at that point is kept
+    /// along with the definition, so it counts towards the Slab's capacity
+    /// until then.
+    ///
+    /// A call evaluates each argument once, in the scope of the caller, and
+    /// then evaluates the body with the parameters bound to those values, so
+    /// `f(print("side", x))` prints once.  Compiled calls behave the same: the
+    /// compiler inlines the body and folds constant arguments into it.  A
+    /// `print()` within the body can use the parameters too.
+    ///
+    /// # Errors
+    ///
+    /// All errors are returned as [`Error::Located`](../error/enum.Error.html#variant.Located).
+    /// Definitions that use a builtin name, repeat a parameter, or recurse produce
+    /// an [`InvalidDefinition`](../error/enum.Error.html#variant.InvalidDefinition) error.
+    /// So does a redefinition that changes the number of parameters of a
+    /// function that other definitions call.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fasteval::Evaler;  // use this trait so we can call eval().
+    /// fn main() -> Result<(), fasteval::Error> {
+    ///     let parser = fasteval::Parser::new();
+    ///     let mut slab = fasteval::Slab::new();
+    ///
+    ///     parser.define("f(x, y) = x^2 + y", &mut slab.ps)?;
+    ///     let val = parser.parse("f(2, 3) * 4", &mut slab.ps)?.from(&slab.ps).eval(&slab, &mut fasteval::EmptyNamespace)?;
+    ///     assert_eq!(val, 28.0);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn define(&self, def_str:&str, slab:&mut ParseSlab) -> Result<(),Error> {
+        if def_str.len()>self.expr_len_limit { return Err(Error::TooLong.at(def_str, self.expr_len_limit)); }  // Restrict length for safety
+        let mut bs = def_str.as_bytes();
+        self.read_definition(slab, &mut bs).map_err(|err| err.at(def_str, def_str.len()-bs.len()))
+    }
+
+    fn read_definition(&self, slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<(),Error> {
+        spaces!(bs);
+        let name_bs = *bs;
+        let name = match Self::read_varname(bs)? {
+            Bite(name) => name,
+            Pass => return Err(Error::Expected("function name".to_string())),
+        };
+        if is_builtin_func(&name) {
+            *bs = name_bs;
+            return Err(Error::InvalidDefinition(format!("`{}` is a builtin function", name)));
+        }
+        let close_parenth = match Self::read_open_parenthesis(bs)? {
+            Bite(b'[') => b']',
+            Bite(_) => b')',
+            Pass => return Err(Error::Expected("'(' or '['".to_string())),
+        };
+
+        let mut params = Vec::<String>::with_capacity(4);
+        loop {
+            spaces!(bs);
+            if peek!(bs)==Some(close_parenth) {
+                skip!(bs);
+                break;
+            }
+            if !params.is_empty() {
+                match peek!(bs) {
+                    Some(b',') => { skip!(bs); }
+                    Some(_) => return Err(Error::Expected(format!("',' or '{}'", close_parenth as char))),
+                    None => return Err(Error::EofWhileParsing(name)),
+                }
+                spaces!(bs);
+            }
+            let param_bs = *bs;
+            match Self::read_varname(bs)? {
+                Bite(param) => {
+                    if params.contains(&param) {
+                        *bs = param_bs;
+                        return Err(Error::InvalidDefinition(format!("parameter `{}` is repeated", param)));
+                    }
+                    params.push(param);
+                }
+                Pass => {
+                    if bs.is_empty() { return Err(Error::EofWhileParsing(name)); }
+                    return Err(Error::Expected("parameter name".to_string()));
+                }
+            }
+        }
+
+        spaces!(bs);
+        if !peek_is!(bs,0,b'=') || peek_is!(bs,1,b'=') { return Err(Error::Expected("'='".to_string())); }
+        skip!(bs);
+
+        // The parameters shadow Safe Variables and other functions while the body is parsed:
+        let (exprs_start, vals_start) = (slab.exprs.len(), slab.vals.len());
+        slab.locals = params;
+        let body = self.read_expression(slab, bs, 0, true);
+        let params = std::mem::take(&mut slab.locals);
+        let body = body?;
+
+        // Everything in the body was pushed after 'exprs_start' and 'vals_start':
+        let mut calls = Vec::<String>::new();
+        let mut add_call = |val:&Value| {
+            if let EStdFunc(EFunc{name, ..}) = val {
+                if !calls.contains(name) { calls.push(name.clone()); }
+            }
+        };
+        for expr in &slab.exprs[exprs_start..] {
+            add_call(&expr.first);
+            for ExprPair(_, val) in &expr.pairs { add_call(val); }
+        }
+        for val in &slab.vals[vals_start..] { add_call(val); }
+
+        // Calls are inlined by the compiler, so recursion is not possible:
+        let mut todo = calls.clone();
+        let mut seen = Vec::<String>::new();
+        while let Some(callee) = todo.pop() {
+            if callee==name {
+                *bs = name_bs;
+                return Err(Error::InvalidDefinition(format!("function `{}` calls itself", name)));
+            }
+            if let Some(f) = slab.funcs.get(&callee) {
+                if !seen.contains(&callee) { todo.extend(f.calls.iter().cloned()); }
+            }
+            seen.push(callee);
+        }
+
+        // Other definitions were parsed with the old number of parameters:
+        if let Some(old) = slab.funcs.get(&name) {
+            if old.params.len()!=params.len() {
+                if let Some((caller,_)) = slab.funcs.iter().find(|(_,f)| f.calls.contains(&name)) {
+                    *bs = name_bs;
+                    return Err(Error::InvalidDefinition(format!("function `{}` calls `{}` with {} argument{}", caller, name, old.params.len(), if old.params.len()==1 { "" } else { "s" })));
+                }
+            }
+        }
+
+        forget_partials(slab, &name);
+        slab.add_func(name, UserFunc{params, body, calls});
+        Ok(())
+    }
+
+    fn read_program(&self, slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<ExpressionI,Error> {
+        let src = *bs;
+        let mut stmts = Vec::<Statement>::with_capacity(8);
+        let res = loop {
+            let name = Self::read_assignment(bs)?;
+            let expr_i = match self.read_expression(slab,bs,0,false) {
+                Ok(expr_i) => expr_i,
+                Err(err) => break Err(err),
+            };
+            if let Some(name) = &name {
+                if !slab.locals.contains(name) { slab.locals.push(name.clone()); }
+            }
+            stmts.push(Statement(name, expr_i));
+
+            // 'read_expression' has already skipped the whitespace and comments after the statement:
+            let mut sep = ends_with_newline(&src[..src.len()-bs.len()]);
+            while peek_is!(bs,0,b';') {
+                skip!(bs);
+                spaces!(bs);
+                sep = true;
+            }
+            if bs.is_empty() { break Ok(()); }
+            if !sep {
+                let bs_str = from_utf8(bs).unwrap_or("Utf8Error while handling UnparsedTokensRemaining error");
+                break Err(Error::UnparsedTokensRemaining(bs_str.to_string()));
+            }
+        };
+        slab.locals.clear();
+        res?;
+
+        // A single expression is not wrapped in a program, so that it doesn't have any overhead:
+        if stmts.len()==1 && stmts[0].0.is_none() { return Ok(stmts[0].1); }
+        slab.push_expr(Expression{first:EStdFunc(EProgram(stmts)), pairs:Vec::new()})
+    }
+
+    // Reads the 'x =' of an assignment, or nothing.
+    fn read_assignment(bs:&mut &[u8]) -> Result<Option<String>,Error> {
+        let start = *bs;
+        if let Bite(name) = Self::read_varname(bs)? {
+            spaces!(bs);
+            if peek_is!(bs,0,b'=') && !peek_is!(bs,1,b'=') {
+                skip!(bs);
+                return Ok(Some(name));
+            }
+        }
+        *bs = start;
+        Ok(None)
     }
 
     fn read_expression(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, expect_eof:bool) -> Result<ExpressionI,Error> {
         if depth>self.expr_depth_limit { return Err(Error::TooDeep); }
 
-        let first = self.read_value(slab,bs,depth)?;
+        let mut first = self.read_value(slab,bs,depth)?;
         let mut pairs = Vec::<ExprPair>::with_capacity(8);
         loop {
             match self.read_binaryop(bs)? {
@@ -310,6 +647,21 @@ impl Parser {
             }
         }
         spaces!(bs);
+        if peek_is!(bs,0,b'?') {
+            // Ternary: Everything we have read so far is the condition.
+            skip!(bs);
+            let cond = slab.push_expr(Expression{first, pairs})?;
+            let then = self.read_expression(slab,bs,depth+1,false)?;
+            spaces!(bs);
+            match peek!(bs) {
+                Some(b':') => { skip!(bs); }
+                Some(_) => return Err(Error::Expected("':'".to_string())),
+                None => return Err(Error::EofWhileParsing("ternary".to_string())),
+            }
+            let otherwise = self.read_expression(slab,bs,depth+1,false)?;  // Right-associative.
+            first = EStdFunc(EFuncIf{cond, then, otherwise});
+            pairs = Vec::new();
+        }
         if expect_eof && !bs.is_empty() {
             let bs_str = match from_utf8(bs) {
                 Ok(s) => s,
@@ -325,7 +677,7 @@ impl Parser {
 
         match Self::read_const(slab,bs)? {
             Pass => {}
-            Bite(c) => return Ok(EConstant(c)),
+            Bite(c) => return Ok(c),
         }
         match self.read_unaryop(slab,bs,depth)? {
             Pass => {}
@@ -342,31 +694,41 @@ impl Parser {
         Err(Error::InvalidValue)
     }
 
-    fn read_const(slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<Token<f64>,Error> {
+    fn read_const(slab:&mut ParseSlab, bs:&mut &[u8]) -> Result<Token<Value>,Error> {
         spaces!(bs);
 
-        let mut toklen=0;  let mut sign_ok=true;  let mut specials_ok=true;  let mut suffix_ok=true;  let mut saw_val=false;
+        match Self::read_radix_const(bs)? {
+            Pass => {}
+            Bite(c) => return Ok(Bite(c)),
+        }
+
+        let mut toklen=0;  let mut sign_ok=true;  let mut specials_ok=true;  let mut suffix_ok=true;  let mut saw_val=false;  let mut digits=0;  let mut underscores=false;
         loop {
             match peek_n!(bs, toklen) {
                 None => break,
                 Some(b) => {
                     if b'0'<=b && b<=b'9' || b==b'.' {
+                        if suffix_ok && b!=b'.' { digits+=1; }
                         saw_val = true;
                         sign_ok=false; specials_ok=false;
-                        toklen = toklen+1;
+                        toklen += 1;
+                    } else if b==b'_' && toklen>0 && matches!(peek_n!(bs,toklen-1), Some(b'0'..=b'9')) && matches!(peek_n!(bs,toklen+1), Some(b'0'..=b'9')) {
+                        // Digit separators are only allowed between two digits:
+                        underscores = true;
+                        toklen += 1;
                     } else if sign_ok && (b==b'-' || b==b'+') {
                         sign_ok = false;
-                        toklen = toklen+1;
+                        toklen += 1;
                     } else if saw_val && (b==b'e' || b==b'E') {
                         suffix_ok = false;
                         sign_ok = true;
-                        toklen = toklen+1;
+                        toklen += 1;
                     } else if specials_ok && ( b==b'N' && peek_is!(bs,toklen+1,b'a') && peek_is!(bs,toklen+2,b'N')  ||  b==b'i' && peek_is!(bs,toklen+1,b'n') && peek_is!(bs,toklen+2,b'f') ) {
                         #[cfg(feature="alpha-keywords")]
                         {
                             saw_val = true;
                             suffix_ok = false;
-                            toklen = toklen+3;
+                            toklen += 3;
                         }
                         break;
                     } else {
@@ -378,37 +740,102 @@ impl Parser {
 
         if !saw_val { return Ok(Pass); }
 
-        let mut tok = unsafe { from_utf8_unchecked(&bs[..toklen]) };
+        let mut exp = 0;  let mut suffixlen = 0;
         if suffix_ok {
-            match peek_n!(bs,toklen) {
-                None => (),
-                Some(b) => {
-                    let (exp,suffixlen) = match b {
-                        b'k' | b'K' => (3,1),
-                        b'M' => (6,1),
-                        b'G' => (9,1),
-                        b'T' => (12,1),
-                        b'm' => (-3,1),
-                        b'u' | b'\xb5' => (-6,1),  // ASCII-encoded 'µ'
-                        b'\xc2' if peek_is!(bs,toklen+1,b'\xb5') => (-6,2),  // UTF8-encoded 'µ'
-                        b'n' => (-9,1),
-                        b'p' => (-12,1),
-                        _ => (0,0),
-                    };
-                    if exp!=0 {
-                        slab.char_buf.clear();
-                        slab.char_buf.push_str(tok);
-                        slab.char_buf.push('e');
-                        slab.char_buf.push_str(&exp.to_string());
-                        tok = &slab.char_buf;
-
-                        toklen = toklen+suffixlen;
-                    }
-                }
+            if let Some(b) = peek_n!(bs,toklen) {
+                let (e,l) = match b {
+                    b'k' | b'K' => (3,1),
+                    b'M' => (6,1),
+                    b'G' => (9,1),
+                    b'T' => (12,1),
+                    b'm' => (-3,1),
+                    b'u' | b'\xb5' => (-6,1),  // ASCII-encoded 'µ'
+                    b'\xc2' if peek_is!(bs,toklen+1,b'\xb5') => (-6,2),  // UTF8-encoded 'µ'
+                    b'n' => (-9,1),
+                    b'p' => (-12,1),
+                    _ => (0,0),
+                };
+                exp = e;  suffixlen = l;
             }
         }
 
+        let text = unsafe { from_utf8_unchecked(&bs[..toklen]) };
+        let tok : &str = if underscores || exp!=0 {
+            slab.char_buf.clear();
+            slab.char_buf.extend(text.chars().filter(|&c| c!='_'));
+            if exp!=0 {
+                slab.char_buf.push('e');
+                slab.char_buf.push_str(&exp.to_string());
+            }
+            &slab.char_buf
+        } else {
+            text
+        };
+        toklen += suffixlen;
+
         let val = tok.parse::<f64>().map_err(|_| { Error::ParseF64(tok.to_string()) })?;
+        // Any literal with 15 significant digits or fewer can be recovered exactly from
+        // its f64 value.  Keep the text of longer ones for exact number types:
+        let val = if digits>15 { EExactConstant(val, tok.to_string()) } else { EConstant(val) };
+        skip_n!(bs,toklen);
+
+        Ok(Bite(val))
+    }
+
+    // Reads a hexadecimal (0x1F), octal (0o17) or binary (0b1010) integer.
+    // SI suffixes are not allowed, because 'b' and some of the suffixes are digits.
+    fn read_radix_const(bs:&mut &[u8]) -> Result<Token<Value>,Error> {
+        let mut toklen = 0;
+        let neg = match peek!(bs) {
+            Some(b'-') => { toklen=1; true }
+            Some(b'+') => { toklen=1; false }
+            _ => false,
+        };
+        if !peek_is!(bs,toklen,b'0') { return Ok(Pass); }
+        let radix = match peek_n!(bs,toklen+1) {
+            Some(b'x') | Some(b'X') => 16,
+            Some(b'o') => 8,
+            Some(b'b') | Some(b'B') => 2,
+            _ => return Ok(Pass),
+        };
+        let digit = |b:Option<u8>| b.and_then(|b| (b as char).to_digit(radix));
+        // The prefix must be followed by a digit.  Otherwise, this is a '0' followed
+        // by something else, like the 'xor' in '0xor 1':
+        if digit(peek_n!(bs,toklen+2)).is_none() { return Ok(Pass); }
+        toklen += 2;
+
+        let mut val : Option<u64> = Some(0);
+        loop {
+            let b = peek_n!(bs,toklen);
+            if let Some(d) = digit(b) {
+                val = val.and_then(|v| v.checked_mul(u64::from(radix))).and_then(|v| v.checked_add(u64::from(d)));
+                toklen += 1;
+            } else if b==Some(b'_') && digit(peek_n!(bs,toklen+1)).is_some() {
+                toklen += 1;
+            } else {
+                break;
+            }
+        }
+        // Reject trailing letters, like the 'G' in '0x1G' or the suffix in '0x10k':
+        let mut end = toklen;
+        while let Some(b) = peek_n!(bs,end) {
+            if !(b.is_ascii_alphanumeric() || b==b'_' || b==b'.') { break }
+            end += 1;
+        }
+        let tok = unsafe { from_utf8_unchecked(&bs[..end]) };
+        let val = match val {
+            Some(v) if end==toklen => v,
+            _ => return Err(Error::ParseF64(tok.to_string())),
+        };
+
+        let f = if neg { -(val as f64) } else { val as f64 };
+        // Integers above 2^53 can't all be represented exactly by an f64.
+        // Keep their decimal text for exact number types:
+        let val = if val > 1<<53 {
+            EExactConstant(f, if neg { format!("-{}", val) } else { val.to_string() })
+        } else {
+            EConstant(f)
+        };
         skip_n!(bs,toklen);
 
         Ok(Bite(val))
@@ -520,14 +947,22 @@ impl Parser {
                     skip!(bs);
                     let xi = self.read_expression(slab,bs,depth+1,false)?;
                     spaces!(bs);
-                    if read!(bs,"parentheses")? != b')' { return Err(Error::Expected(")".to_string())); }
+                    match peek!(bs) {
+                        Some(b')') => { skip!(bs); }
+                        Some(_) => return Err(Error::Expected("')'".to_string())),
+                        None => return Err(Error::EofWhileParsing("parentheses".to_string())),
+                    }
                     Ok(Bite(EParentheses(xi)))
                 }
                 b'[' => {
                     skip!(bs);
                     let xi = self.read_expression(slab,bs,depth+1,false)?;
                     spaces!(bs);
-                    if read!(bs,"square brackets")? != b']' { return Err(Error::Expected("]".to_string())); }
+                    match peek!(bs) {
+                        Some(b']') => { skip!(bs); }
+                        Some(_) => return Err(Error::Expected("']'".to_string())),
+                        None => return Err(Error::EofWhileParsing("square brackets".to_string())),
+                    }
                     Ok(Bite(EParentheses(xi)))
                 }
                 b'!' => {
@@ -535,6 +970,11 @@ impl Parser {
                     let v = self.read_value(slab,bs,depth+1)?;
                     Ok(Bite(ENot(slab.push_val(v)?)))
                 }
+                b'~' => {
+                    skip!(bs);
+                    let v = self.read_value(slab,bs,depth+1)?;
+                    Ok(Bite(EBitNot(slab.push_val(v)?)))
+                }
                 _ => Ok(Pass),
             }
         }
@@ -553,30 +993,40 @@ impl Parser {
                 b'^' => { skip!(bs); Ok(Bite(EExp)) }
                 b'<' => { skip!(bs);
                           if peek_is!(bs,0,b'=') { skip!(bs); Ok(Bite(ELTE)) }
+                          else if peek_is!(bs,0,b'<') { skip!(bs); Ok(Bite(EShl)) }
                           else { Ok(Bite(ELT)) } }
                 b'>' => { skip!(bs);
                           if peek_is!(bs,0,b'=') { skip!(bs); Ok(Bite(EGTE)) }
+                          else if peek_is!(bs,0,b'>') { skip!(bs); Ok(Bite(EShr)) }
                           else { Ok(Bite(EGT)) } }
                 b'=' if peek_is!(bs,1,b'=') => { skip_n!(bs,2);
                                                 Ok(Bite(EEQ)) }
                 b'!' if peek_is!(bs,1,b'=') => { skip_n!(bs,2);
                                                 Ok(Bite(ENE)) }
                 #[cfg(feature="alpha-keywords")]
-                b'o' if peek_is!(bs,1,b'r') => { skip_n!(bs,2);
-                                                Ok(Bite(EOR)) }
+                b'o' if peek_is!(bs,1,b'r') && Self::is_keyword_end(bs,2) => { skip_n!(bs,2);
+                                                                             Ok(Bite(EOR)) }
                 b'|' if peek_is!(bs,1,b'|') => { skip_n!(bs,2);
                                                 Ok(Bite(EOR)) }
                 #[cfg(feature="alpha-keywords")]
-                b'a' if peek_is!(bs,1,b'n') && peek_is!(bs,2,b'd') => { skip_n!(bs,3);
-                                                                      Ok(Bite(EAND)) }
+                b'a' if peek_is!(bs,1,b'n') && peek_is!(bs,2,b'd') && Self::is_keyword_end(bs,3) => { skip_n!(bs,3);
+                                                                                                  Ok(Bite(EAND)) }
                 b'&' if peek_is!(bs,1,b'&') => { skip_n!(bs,2);
                                                 Ok(Bite(EAND)) }
+                b'&' => { skip!(bs); Ok(Bite(EBitAnd)) }
+                b'|' => { skip!(bs); Ok(Bite(EBitOr)) }
+                // `^` is already exponentiation, so `xor` is the only spelling:
+                #[cfg(feature="alpha-keywords")]
+                b'x' if peek_is!(bs,1,b'o') && peek_is!(bs,2,b'r') && Self::is_keyword_end(bs,3) => { skip_n!(bs,3);
+                                                                                                  Ok(Bite(EBitXor)) }
                 _ => Ok(Pass),
             }
         }
     }
 
     fn read_callable(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize) -> Result<Token<Value>,Error> {
+        spaces!(bs);
+        let name_bs = *bs;  // Remember where the name starts so we can report errors there.
         match Self::read_varname(bs)? {
             Pass => Ok(Pass),
             Bite(varname) => {
@@ -584,6 +1034,17 @@ impl Parser {
                     Pass => {
                         // VarNames without Parenthesis are always treated as custom 0-arg functions.
 
+                        if slab.locals.contains(&varname) {
+                            return Ok(Bite(EStdFunc(EVar(varname))));
+                        }
+                        if let Some(f) = slab.funcs.get(&varname) {
+                            if f.params.is_empty() { return Ok(Bite(EStdFunc(EFunc{name:varname, args:Vec::new()}))); }
+                        }
+
+                        if let Some(var) = slab.safe_vars.get(&varname) {
+                            return Ok(Bite(EStdFunc(ESafeVar{name:varname, var:var.clone()})));
+                        }
+
                         #[cfg(feature="unsafe-vars")]
                         match slab.unsafe_vars.get(&varname) {
                             None => Ok(Bite(EStdFunc(EVar(varname)))),
@@ -597,7 +1058,7 @@ impl Parser {
                         // VarNames with Parenthesis are first matched against builtins, then custom.
                         match varname.as_ref() {
                             "print" => Ok(Bite(EPrintFunc(self.read_printfunc(slab,bs,depth,open_parenth)?))),
-                            _ => Ok(Bite(EStdFunc(self.read_func(varname,slab,bs,depth,open_parenth)?))),
+                            _ => Ok(Bite(EStdFunc(self.read_func(varname,name_bs,slab,bs,depth,open_parenth)?))),
                         }
                     }
                 }
@@ -609,7 +1070,7 @@ impl Parser {
         spaces!(bs);
 
         let mut toklen = 0;
-        while Self::is_varname_byte_opt(peek_n!(bs,toklen),toklen) { toklen=toklen+1; }
+        while Self::is_varname_byte_opt(peek_n!(bs,toklen),toklen) { toklen += 1; }
 
         if toklen==0 { return Ok(Pass); }
 
@@ -630,7 +1091,7 @@ impl Parser {
         }
     }
 
-    fn read_func(&self, fname:String, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<StdFunc,Error> {
+    fn read_func<'b>(&self, fname:String, fname_bs:&'b [u8], slab:&mut ParseSlab, bs:&mut &'b [u8], depth:usize, open_parenth:u8) -> Result<StdFunc,Error> {
         let close_parenth = match open_parenth {
             b'(' => b')',
             b'[' => b']',
@@ -649,9 +1110,10 @@ impl Parser {
                 None => return Err(Error::EofWhileParsing(fname)),
             }
             if !args.is_empty() {
-                match read!(bs) {
-                    Ok(b',') | Ok(b';') => {
+                match peek!(bs) {
+                    Some(b',') | Some(b';') => {
                         // I accept ',' or ';' because the TV API disallows the ',' char in symbols... so I'm using ';' as a compromise.
+                        skip!(bs);
                     }
                     _ => return Err(Error::Expected("',' or ';'".to_string())),
                 }
@@ -660,41 +1122,41 @@ impl Parser {
         }
 
         let fname_str = fname.as_str();
-        match fname_str {
+        let out = match fname_str {
             "int" => {
                 if args.len()==1 { Ok(EFuncInt(match args.pop() {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("int: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `int` takes 1 argument".to_string())) }
             }
             "ceil" => {
                 if args.len()==1 { Ok(EFuncCeil(match args.pop() {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("ceil: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `ceil` takes 1 argument".to_string())) }
             }
             "floor" => {
                 if args.len()==1 { Ok(EFuncFloor(match args.pop() {
                                                      Some(xi) => xi,
                                                      None => return Err(Error::Unreachable),
                                                  }))
-                } else { Err(Error::WrongArgs("floor: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `floor` takes 1 argument".to_string())) }
             }
             "abs" => {
                 if args.len()==1 { Ok(EFuncAbs(match args.pop() {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("abs: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `abs` takes 1 argument".to_string())) }
             }
             "sign" => {
                 if args.len()==1 { Ok(EFuncSign(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("sign: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `sign` takes 1 argument".to_string())) }
             }
             "log" => {
                 if args.len()==1 { Ok(EFuncLog{base:None, expr:match args.pop() {
@@ -711,7 +1173,7 @@ impl Parser {
                                               None => return Err(Error::Unreachable),
                                           }),
                                 expr})
-                } else { Err(Error::WrongArgs("expected log(x) or log(base,x)".to_string())) }
+                } else { Err(Error::WrongArgs("function `log` takes 1 or 2 arguments: log(x) or log(base,x)".to_string())) }
             }
             "round" => {
                 if args.len()==1 { Ok(EFuncRound{modulus:None, expr:match args.pop() {
@@ -728,7 +1190,7 @@ impl Parser {
                                                    None => return Err(Error::Unreachable),
                                                }),
                                   expr})
-                } else { Err(Error::WrongArgs("round: expected round(x) or round(modulus,x)".to_string())) }
+                } else { Err(Error::WrongArgs("function `round` takes 1 or 2 arguments: round(x) or round(modulus,x)".to_string())) }
             }
             "min" => {
                 if !args.is_empty() {
@@ -736,7 +1198,7 @@ impl Parser {
                         Some(first) => Ok(EFuncMin{first, rest:args}),
                         None => Err(Error::Unreachable),
                     }
-                } else { Err(Error::WrongArgs("min: expected one or more args".to_string())) }
+                } else { Err(Error::WrongArgs("function `min` takes 1 or more arguments".to_string())) }
             }
             "max" => {
                 if !args.is_empty() {
@@ -744,16 +1206,25 @@ impl Parser {
                         Some(first) => Ok(EFuncMax{first, rest:args}),
                         None => Err(Error::Unreachable),
                     }
-                } else { Err(Error::WrongArgs("max: expected one or more args".to_string())) }
+                } else { Err(Error::WrongArgs("function `max` takes 1 or more arguments".to_string())) }
+            }
+
+            "if" => {
+                if args.len()==3 {
+                    match (args.pop(), args.pop(), args.pop()) {
+                        (Some(otherwise), Some(then), Some(cond)) => Ok(EFuncIf{cond, then, otherwise}),
+                        _ => Err(Error::Unreachable),
+                    }
+                } else { Err(Error::WrongArgs("function `if` takes 3 arguments: if(cond, then, otherwise)".to_string())) }
             }
 
             "e" => {
                 if args.is_empty() { Ok(EFuncE)
-                } else { Err(Error::WrongArgs("e: expected no args".to_string())) }
+                } else { Err(Error::WrongArgs("function `e` takes no arguments".to_string())) }
             }
             "pi" => {
                 if args.is_empty() { Ok(EFuncPi)
-                } else { Err(Error::WrongArgs("pi: expected no args".to_string())) }
+                } else { Err(Error::WrongArgs("function `pi` takes no arguments".to_string())) }
             }
 
             "sin" => {
@@ -761,87 +1232,99 @@ impl Parser {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("sin: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `sin` takes 1 argument".to_string())) }
             }
             "cos" => {
                 if args.len()==1 { Ok(EFuncCos(match args.pop() {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("cos: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `cos` takes 1 argument".to_string())) }
             }
             "tan" => {
                 if args.len()==1 { Ok(EFuncTan(match args.pop() {
                                                    Some(xi) => xi,
                                                    None => return Err(Error::Unreachable),
                                                }))
-                } else { Err(Error::WrongArgs("tan: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `tan` takes 1 argument".to_string())) }
             }
             "asin" => {
                 if args.len()==1 { Ok(EFuncASin(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("asin: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `asin` takes 1 argument".to_string())) }
             }
             "acos" => {
                 if args.len()==1 { Ok(EFuncACos(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("acos: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `acos` takes 1 argument".to_string())) }
             }
             "atan" => {
                 if args.len()==1 { Ok(EFuncATan(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("atan: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `atan` takes 1 argument".to_string())) }
             }
             "sinh" => {
                 if args.len()==1 { Ok(EFuncSinH(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("sinh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `sinh` takes 1 argument".to_string())) }
             }
             "cosh" => {
                 if args.len()==1 { Ok(EFuncCosH(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("cosh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `cosh` takes 1 argument".to_string())) }
             }
             "tanh" => {
                 if args.len()==1 { Ok(EFuncTanH(match args.pop() {
                                                     Some(xi) => xi,
                                                     None => return Err(Error::Unreachable),
                                                 }))
-                } else { Err(Error::WrongArgs("tanh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `tanh` takes 1 argument".to_string())) }
             }
             "asinh" => {
                 if args.len()==1 { Ok(EFuncASinH(match args.pop() {
                                                      Some(xi) => xi,
                                                      None => return Err(Error::Unreachable),
                                                  }))
-                } else { Err(Error::WrongArgs("asinh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `asinh` takes 1 argument".to_string())) }
             }
             "acosh" => {
                 if args.len()==1 { Ok(EFuncACosH(match args.pop() {
                                                      Some(xi) => xi,
                                                      None => return Err(Error::Unreachable),
                                                  }))
-                } else { Err(Error::WrongArgs("acosh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `acosh` takes 1 argument".to_string())) }
             }
             "atanh" => {
                 if args.len()==1 { Ok(EFuncATanH(match args.pop() {
                                                      Some(xi) => xi,
                                                      None => return Err(Error::Unreachable),
                                                  }))
-                } else { Err(Error::WrongArgs("atanh: expected one arg".to_string())) }
+                } else { Err(Error::WrongArgs("function `atanh` takes 1 argument".to_string())) }
             }
 
+            _ if slab.funcs.contains_key(fname_str) => {
+                match slab.funcs.get(fname_str) {
+                    Some(f) => f.check_args(fname_str, args.len()).map(|_| EFunc{name:fname, args}),
+                    None => Err(Error::Unreachable),
+                }
+            }
             _ => {
+                if args.is_empty() {
+                    if let Some(var) = slab.safe_vars.get(fname_str) {
+                        return Ok(ESafeVar{name:fname, var:var.clone()});
+                    }
+                }
+
                 #[cfg(feature="unsafe-vars")]
                 match slab.unsafe_vars.get(fname_str) {
                     None => Ok(EFunc{name:fname, args}),
@@ -851,7 +1334,11 @@ impl Parser {
                 #[cfg(not(feature="unsafe-vars"))]
                 Ok(EFunc{name:fname, args})
             }
-        }
+        };
+
+        // Argument errors are reported at the function name:
+        if out.is_err() { *bs = fname_bs; }
+        out
     }
 
     fn read_printfunc(&self, slab:&mut ParseSlab, bs:&mut &[u8], depth:usize, open_parenth:u8) -> Result<PrintFunc,Error> {
@@ -861,6 +1348,7 @@ impl Parser {
             _ => return Err(Error::Expected("'(' or '['".to_string())),
         };
         let mut args = Vec::<ExpressionOrString>::with_capacity(8);
+        let mut fmt_bs = *bs;
         loop {
             spaces!(bs);
             match peek!(bs) {
@@ -873,14 +1361,26 @@ impl Parser {
                 None => { return Err(Error::EofWhileParsing("print".to_string())); }
             }
             if !args.is_empty() {
-                match read!(bs) {
-                    Ok(b',') | Ok(b';') => {}
+                match peek!(bs) {
+                    Some(b',') | Some(b';') => { skip!(bs); }
                     _ => { return Err(Error::Expected("',' or ';'".to_string())); }
                 }
             }
+            if args.is_empty() { fmt_bs = *bs; }
             args.push(self.read_expressionorstring(slab,bs,depth+1)?);
         }
 
+        // A format string is checked against the arguments here, and errors are reported at the format string:
+        if let Some(EStr(fmtstr)) = args.first() {
+            if fmtstr.contains('%') {
+                if let Err(err) = printf::check(fmtstr, &args[1..]) {
+                    *bs = fmt_bs;
+                    spaces!(bs);
+                    return Err(err);
+                }
+            }
+        }
+
         Ok(PrintFunc(args))
     }
 
@@ -892,31 +1392,87 @@ impl Parser {
         Ok(EExpr(self.read_expression(slab,bs,depth+1,false)?))
     }
 
-    // TODO: Improve this logic, especially to handle embedded quotes:
+    // Strings are delimited by `"` or `'`, and the escape sequences are decoded here:
     fn read_string(bs:&mut &[u8]) -> Result<Token<String>,Error> {
         spaces!(bs);
 
-        match peek!(bs) {
+        let quote_bs = *bs;  // String errors are reported at the opening quote.
+        let quote = match peek!(bs) {
             None => return Err(Error::EofWhileParsing("opening quote of string".to_string())),
-            Some(b'"') => { skip!(bs); }
+            Some(b) if b==b'"' || b==b'\'' => { skip!(bs); b }
             Some(_) => { return Ok(Pass) }
+        };
+
+        let mut out = Vec::<u8>::with_capacity(16);
+        loop {
+            let esc_bs = *bs;  // ...except for invalid escapes, which are reported at the backslash.
+            let b = match read!(bs) {
+                Ok(b) => b,
+                Err(_) => {
+                    *bs = quote_bs;
+                    return Err(Error::EofWhileParsing("string".to_string()));
+                }
+            };
+            if b==quote { break; }
+            if b!=b'\\' { out.push(b); continue; }
+
+            match peek!(bs) {
+                Some(b'"') => out.push(b'"'),
+                Some(b'\'') => out.push(b'\''),
+                Some(b'\\') => out.push(b'\\'),
+                Some(b'n') => out.push(b'\n'),
+                Some(b't') => out.push(b'\t'),
+                Some(b'r') => out.push(b'\r'),
+                Some(b'0') => out.push(0),
+                Some(b'u') => {
+                    match Self::read_unicode_escape(bs) {
+                        Some(c) => {
+                            let mut buf = [0u8; 4];
+                            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                            continue;  // 'read_unicode_escape' has already skipped the escape.
+                        }
+                        None => {
+                            let escape = &esc_bs[..esc_bs.len()-bs.len()];
+                            *bs = esc_bs;
+                            return Err(Error::InvalidEscape(String::from_utf8_lossy(escape).to_string()));
+                        }
+                    }
+                }
+                None => {
+                    *bs = quote_bs;
+                    return Err(Error::EofWhileParsing("string".to_string()));
+                }
+                Some(_) => {
+                    *bs = esc_bs;
+                    let escape = from_utf8(esc_bs).ok().and_then(|s| s[1..].chars().next()).unwrap_or(char::REPLACEMENT_CHARACTER);
+                    return Err(Error::InvalidEscape(format!("\\{}", escape)));
+                }
+            }
+            skip!(bs);
         }
 
-        let mut toklen = 0;
-        while match peek_n!(bs,toklen) {
-            None => false,
-            Some(b'"') => false,
-            Some(_) => true,
-        } { toklen=toklen+1; }
+        match String::from_utf8(out) {
+            Ok(s) => Ok(Bite(s)),
+            Err(..) => {
+                *bs = quote_bs;
+                Err(Error::Utf8ErrorWhileParsing("string".to_string()))
+            }
+        }
+    }
 
-        let out = from_utf8(&bs[..toklen]).map_err(|_| Error::Utf8ErrorWhileParsing("string".to_string()))?;
+    // Reads the 'u{...}' of a `\u{...}` escape, with 1 to 6 hex digits.
+    // On failure, 'bs' is left at the end of the invalid part.
+    fn read_unicode_escape(bs:&mut &[u8]) -> Option<char> {
+        skip!(bs);  // 'u'
+        if !peek_is!(bs,0,b'{') { return None; }
+        skip!(bs);
+        let mut toklen = 0;
+        while peek_n!(bs,toklen).map_or(false, |b| b.is_ascii_hexdigit()) { toklen+=1; }
+        let digits = unsafe { from_utf8_unchecked(&bs[..toklen]) };  // We just checked that these are ASCII.
         skip_n!(bs, toklen);
-        match read!(bs) {
-            Err(Error::EOF) => Err(Error::EofWhileParsing("string".to_string())),
-            Err(_) => Err(Error::Unreachable),
-            Ok(b'"') => Ok(Bite(out.to_string())),
-            Ok(_) => Err(Error::Unreachable),
-        }
+        if toklen==0 || toklen>6 || !peek_is!(bs,0,b'}') { return None; }
+        skip!(bs);
+        u32::from_str_radix(digits, 16).ok().and_then(std::char::from_u32)
     }
 }
 
@@ -1013,12 +1569,18 @@ mod internal_tests {
         let b=b'1';  assert!(!is_space!(b));
         let b=b'.';  assert!(!is_space!(b));
 
-        {
+        (|| -> Result<(),Error> {
             let bsarr = b"  abc 123   ";
             let bs = &mut &bsarr[..];
             spaces!(bs);
             assert_eq!(bs, b"abc 123   ");
-        }
+
+            let bsarr = b" # abc\n /* 1\n2 */ // 3\n456";
+            let bs = &mut &bsarr[..];
+            spaces!(bs);
+            assert_eq!(bs, b"456");
+            Ok(())
+        })().unwrap();
     }
 
     #[test]
@@ -0,0 +1,331 @@
+//! printf-style formatting for the `print()` built-in function.
+//!
+//! If the first argument of `print()` is a string that contains a `%`, it is
+//! a format string, like C's `printf()`.  Each conversion is replaced by the
+//! next argument:
+//!
+//! ```text
+//! %[flags][width][.precision]conversion
+//!
+//! conversion:  d i     an integer
+//!              x X o   an integer in hex or octal.  Negative integers are shown in two's complement.
+//!              f F     fixed-point notation, with 6 digits after the point by default
+//!              e E     exponential notation, like 1.500000e+03
+//!              g G     the shorter of %f and %e, with 6 significant digits by default
+//!              s       a string, or a number formatted like print() normally does
+//!              %%      a literal '%'
+//!
+//! flags:       -       align to the left within the width
+//!              +       always show the sign of a number
+//!              (space) show a space instead of a '+' sign
+//!              0       pad numbers with zeros instead of spaces
+//!              #       alternate form: 0x and 0 prefixes, and keep the point and trailing zeros
+//! ```
+//!
+//! Integer conversions produce an error if the argument isn't an integer.
+//! The parser checks that the conversions match the arguments, so most
+//! mistakes are found before evaluation.
+
+use crate::error::Error;
+use crate::number::Number;
+use crate::parser::ExpressionOrString::{self, EStr};
+
+// Limits the size of the output for safety, like `expr_len_limit` does for the input.
+const MAX_WIDTH:usize = 1024;
+
+pub(crate) enum PrintArg<'a,N> {
+    Num(N),
+    Str(&'a str),
+}
+
+#[derive(Default)]
+struct Spec {
+    left :bool,
+    plus :bool,
+    space:bool,
+    zero :bool,
+    alt  :bool,
+    width:usize,
+    prec :Option<usize>,
+    conv :char,
+}
+
+enum Piece<'a> {
+    Lit(&'a str),
+    Conv(Spec),
+}
+
+// Splits a format string into literal text and conversions.
+fn parse(fmt:&str) -> Result<Vec<Piece<'_>>,Error> {
+    let mut pieces = Vec::<Piece>::with_capacity(8);
+    let mut rest = fmt;
+    while let Some(i) = rest.find('%') {
+        if i>0 { pieces.push(Piece::Lit(&rest[..i])); }
+        let start = &rest[i..];
+        let mut chars = start[1..].char_indices().peekable();
+        let mut spec = Spec::default();
+        while let Some(&(_,c)) = chars.peek() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '0' => spec.zero = true,
+                '#' => spec.alt = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = read_num(&mut chars);
+        if let Some(&(_,'.')) = chars.peek() {
+            chars.next();
+            spec.prec = Some(read_num(&mut chars));
+        }
+        let (j,conv) = match chars.next() {
+            Some(x) => x,
+            None => return Err(Error::WrongArgs(format!("incomplete conversion `{}` in print() format string", start))),
+        };
+        let conv_str = &start[..1+j+conv.len_utf8()];
+        match conv {
+            'd' | 'i' | 'x' | 'X' | 'o' | 'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 's' => {}
+            '%' if conv_str=="%%" => { pieces.push(Piece::Lit("%")); rest = &start[2..]; continue; }
+            _ => return Err(Error::WrongArgs(format!("invalid conversion `{}` in print() format string", conv_str))),
+        }
+        if spec.width>MAX_WIDTH || spec.prec.unwrap_or(0)>MAX_WIDTH {
+            return Err(Error::WrongArgs(format!("the width and precision of `{}` must be at most {}", conv_str, MAX_WIDTH)));
+        }
+        spec.conv = conv;
+        pieces.push(Piece::Conv(spec));
+        rest = &start[conv_str.len()..];
+    }
+    if !rest.is_empty() { pieces.push(Piece::Lit(rest)); }
+    Ok(pieces)
+}
+
+fn read_num(chars:&mut std::iter::Peekable<std::str::CharIndices>) -> usize {
+    let mut n = 0usize;
+    while let Some(&(_,c)) = chars.peek() {
+        match c.to_digit(10) {
+            Some(d) => n = n.saturating_mul(10).saturating_add(d as usize),
+            None => break,
+        }
+        chars.next();
+    }
+    n
+}
+
+/// Checks a format string against the arguments that follow it, at parse time.
+pub(crate) fn check(fmt:&str, args:&[ExpressionOrString]) -> Result<(),Error> {
+    let pieces = parse(fmt)?;
+    let specs = pieces.iter().filter_map(|piece| if let Piece::Conv(spec) = piece { Some(spec) } else { None }).collect::<Vec<_>>();
+    if specs.len()!=args.len() {
+        return Err(Error::WrongArgs(format!("print() format string expects {} argument{}, but got {}", specs.len(), if specs.len()==1 { "" } else { "s" }, args.len())));
+    }
+    for (spec,arg) in specs.iter().zip(args) {
+        if let EStr(_) = arg {
+            if spec.conv!='s' { return Err(Error::WrongArgs(format!("print() conversion `%{}` requires a number, but got a string", spec.conv))); }
+        }
+    }
+    Ok(())
+}
+
+/// Formats the arguments according to a format string.
+pub(crate) fn sprintf<N:Number>(fmt:&str, args:&[PrintArg<N>]) -> Result<String,Error> {
+    let mut out = String::with_capacity(fmt.len()+16);
+    let mut args = args.iter();
+    for piece in parse(fmt)? {
+        let spec = match piece {
+            Piece::Lit(s) => { out.push_str(s); continue; }
+            Piece::Conv(spec) => spec,
+        };
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => return Err(Error::WrongArgs("not enough arguments for the print() format string".to_string())),
+        };
+        match (spec.conv, arg) {
+            ('s', PrintArg::Str(s)) => fmt_str(&mut out, &spec, s),
+            ('s', PrintArg::Num(n)) => fmt_str(&mut out, &spec, &n.to_string()),
+            (_, PrintArg::Str(_)) => return Err(Error::WrongArgs(format!("print() conversion `%{}` requires a number", spec.conv))),
+            ('d', PrintArg::Num(n)) | ('i', PrintArg::Num(n)) | ('x', PrintArg::Num(n)) | ('X', PrintArg::Num(n)) | ('o', PrintArg::Num(n)) => {
+                fmt_int(&mut out, &spec, to_int(&spec, *n)?)?
+            }
+            (_, PrintArg::Num(n)) => fmt_float(&mut out, &spec, n.to_f64()),
+        }
+    }
+    Ok(out)
+}
+
+// Integer types format exactly through Display.  Other types must hold an integral value.
+fn to_int<N:Number>(spec:&Spec, n:N) -> Result<i128,Error> {
+    if let Ok(i) = n.to_string().parse::<i128>() { return Ok(i); }
+    let f = n.to_f64();
+    if f.fract()==0.0 && f.abs()<1e38 { return Ok(f as i128); }
+    Err(Error::WrongArgs(format!("print() conversion `%{}` requires an integer, but got {}", spec.conv, n)))
+}
+
+fn fmt_str(out:&mut String, spec:&Spec, s:&str) {
+    let s = match spec.prec {
+        Some(p) => match s.char_indices().nth(p) {
+            Some((i,_)) => &s[..i],
+            None => s,
+        },
+        None => s,
+    };
+    pad(out, spec, "", s, false);
+}
+
+fn sign(spec:&Spec, negative:bool) -> &'static str {
+    if negative { "-" } else if spec.plus { "+" } else if spec.space { " " } else { "" }
+}
+
+fn fmt_int(out:&mut String, spec:&Spec, i:i128) -> Result<(),Error> {
+    let (prefix, mut digits) = match spec.conv {
+        'x' | 'X' | 'o' => {
+            let u = if i<0 {
+                if i<i128::from(i64::MIN) { return Err(Error::Overflow); }
+                u128::from(i as i64 as u64)
+            } else { i as u128 };
+            let digits = if spec.conv=='o' { format!("{:o}", u) } else if spec.conv=='x' { format!("{:x}", u) } else { format!("{:X}", u) };
+            let prefix = match spec.conv {
+                'x' if spec.alt && u!=0 => "0x",
+                'X' if spec.alt && u!=0 => "0X",
+                _ => "",
+            };
+            (prefix, digits)
+        }
+        _ => (sign(spec, i<0), i.unsigned_abs().to_string()),
+    };
+    if let Some(p) = spec.prec {
+        if p==0 && i==0 { digits.clear(); }
+        while digits.len()<p { digits.insert(0,'0'); }
+    }
+    if spec.conv=='o' && spec.alt && !digits.starts_with('0') { digits.insert(0,'0'); }
+    pad(out, spec, prefix, &digits, spec.prec.is_none());
+    Ok(())
+}
+
+fn fmt_float(out:&mut String, spec:&Spec, x:f64) {
+    let upper = spec.conv.is_ascii_uppercase();
+    let prefix = sign(spec, x.is_sign_negative() && !x.is_nan());
+    if !x.is_finite() {
+        let body = match (x.is_nan(), upper) {
+            (true, false) => "nan",
+            (true, true) => "NAN",
+            (false, false) => "inf",
+            (false, true) => "INF",
+        };
+        pad(out, spec, prefix, body, false);
+        return;
+    }
+    let x = x.abs();
+    let prec = spec.prec.unwrap_or(6);
+    let mut body = match spec.conv {
+        'f' | 'F' => format!("{:.*}", prec, x),
+        'e' | 'E' => exp(x, prec),
+        _ => {
+            // %g uses %e if the exponent is less than -4 or at least the precision:
+            let p = if prec==0 { 1 } else { prec };
+            let e = exp(x, p-1);
+            let exponent : i32 = e[e.find('e').unwrap_or(0)+1..].parse().unwrap_or(0);
+            let mut body = if exponent < -4 || exponent >= p as i32 { e } else { format!("{:.*}", (p as i32-1-exponent) as usize, x) };
+            if !spec.alt {
+                // Remove trailing zeros from the fraction:
+                let end = body.find('e').unwrap_or(body.len());
+                let (mantissa, exponent) = body.split_at(end);
+                if mantissa.contains('.') {
+                    let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+                    body = format!("{}{}", mantissa, exponent);
+                }
+            }
+            body
+        }
+    };
+    if spec.alt && !body.contains('.') {
+        let i = body.find('e').unwrap_or(body.len());
+        body.insert(i,'.');
+    }
+    if upper { body = body.to_ascii_uppercase(); }
+    pad(out, spec, prefix, &body, true);
+}
+
+// Rust formats exponents like `1.5e3`, but printf uses `1.5e+03`:
+fn exp(x:f64, prec:usize) -> String {
+    let s = format!("{:.*e}", prec, x);
+    match s.find('e') {
+        Some(i) => {
+            let (mantissa, exponent) = s.split_at(i);
+            let exponent = &exponent[1..];
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ('-', digits),
+                None => ('+', exponent),
+            };
+            format!("{}e{}{:0>2}", mantissa, sign, digits)
+        }
+        None => s,
+    }
+}
+
+fn pad(out:&mut String, spec:&Spec, prefix:&str, body:&str, zero_ok:bool) {
+    let len = prefix.chars().count() + body.chars().count();
+    let fill = spec.width.saturating_sub(len);
+    if spec.left {
+        out.push_str(prefix);
+        out.push_str(body);
+        out.push_str(&" ".repeat(fill));
+    } else if spec.zero && zero_ok {
+        out.push_str(prefix);
+        out.push_str(&"0".repeat(fill));
+        out.push_str(body);
+    } else {
+        out.push_str(&" ".repeat(fill));
+        out.push_str(prefix);
+        out.push_str(body);
+    }
+}
+
+#[cfg(test)]
+mod internal_tests {
+    use super::*;
+
+    fn f(fmt:&str, args:&[PrintArg<f64>]) -> String {
+        sprintf(fmt, args).unwrap()
+    }
+    use PrintArg::{Num, Str};
+
+    #[test]
+    fn conversions() {
+        assert_eq!(f("%d|%i|%5d|%-5d|%05d|%+d|% d|%.3d", &[Num(42.0), Num(-42.0), Num(42.0), Num(42.0), Num(-42.0), Num(42.0), Num(42.0), Num(7.0)]),
+                   "42|-42|   42|42   |-0042|+42| 42|007");
+        assert_eq!(f("%x|%X|%#x|%o|%#o|%x|%#x", &[Num(255.0), Num(255.0), Num(255.0), Num(8.0), Num(8.0), Num(-1.0), Num(0.0)]),
+                   "ff|FF|0xff|10|010|ffffffffffffffff|0");
+        assert_eq!(f("%f|%.3f|%.0f|%#.0f|%8.2f|%-8.2f|%08.2f|%+.1f", &[Num(3.14159), Num(2.0/3.0), Num(2.5), Num(3.0), Num(-3.14159), Num(1.5), Num(-1.5), Num(0.25)]),
+                   "3.141590|0.667|2|3.|   -3.14|1.50    |-0001.50|+0.2");
+        assert_eq!(f("%e|%.2e|%E|%.0e|%e", &[Num(1500.0), Num(0.000123), Num(-1.0e100), Num(5.0), Num(0.0)]),
+                   "1.500000e+03|1.23e-04|-1.000000E+100|5e+00|0.000000e+00");
+        assert_eq!(f("%g|%g|%g|%g|%g|%.3g|%G|%#g|%g", &[Num(100000.0), Num(1000000.0), Num(0.0001), Num(0.00001), Num(1.5), Num(3.14159), Num(1e-10), Num(1.5), Num(0.0)]),
+                   "100000|1e+06|0.0001|1e-05|1.5|3.14|1E-10|1.50000|0");
+        assert_eq!(f("%s|%5s|%-5s|%.2s|%s", &[Str("abc"), Str("µ"), Str("ab"), Str("abc"), Num(2.5)]),
+                   "abc|    µ|ab   |ab|2.5");
+        assert_eq!(f("%f|%5.1F|%d%%|%+f", &[Num(f64::INFINITY), Num(f64::NAN), Num(50.0), Num(f64::NEG_INFINITY)]),
+                   "inf|  NAN|50%|-inf");
+        assert_eq!(f("100%% of %s", &[Str("it")]), "100% of it");
+    }
+
+    #[test]
+    fn integers() {
+        // Integer types are formatted exactly:
+        assert_eq!(sprintf("%d %x", &[Num(9007199254740993i64), Num(-2i64)]), Ok("9007199254740993 fffffffffffffffe".to_string()));
+        assert_eq!(sprintf("%d", &[Num(1e20)]), Ok("100000000000000000000".to_string()));
+        assert_eq!(sprintf("%d", &[Num(2.5)]), Err(Error::WrongArgs("print() conversion `%d` requires an integer, but got 2.5".to_string())));
+        assert_eq!(sprintf("%x", &[Num(f64::NAN)]), Err(Error::WrongArgs("print() conversion `%x` requires an integer, but got NaN".to_string())));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |fmt:&str| sprintf::<f64>(fmt, &[]).unwrap_err();
+        assert_eq!(err("%q"), Error::WrongArgs("invalid conversion `%q` in print() format string".to_string()));
+        assert_eq!(err("%5%"), Error::WrongArgs("invalid conversion `%5%` in print() format string".to_string()));
+        assert_eq!(err("abc %-0"), Error::WrongArgs("incomplete conversion `%-0` in print() format string".to_string()));
+        assert_eq!(err("%2000d"), Error::WrongArgs("the width and precision of `%2000d` must be at most 1024".to_string()));
+        assert_eq!(err("%d"), Error::WrongArgs("not enough arguments for the print() format string".to_string()));
+    }
+}
@@ -53,15 +53,14 @@
 
 use crate::error::Error;
 use crate::parser::{ExpressionI, ValueI,
-                    Expression,  Value};
+                    Expression,  Value, UserFunc};
 use crate::compiler::{Instruction::{self, IConst}, InstructionI};
+use crate::evalns::SafeVar;
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::mem;
 
-#[cfg(feature="unsafe-vars")]
-use std::collections::BTreeMap;
-
 
 // Eliminate function call overhead:
 macro_rules! get_expr {
@@ -124,6 +123,13 @@ pub struct Slab {
 
 /// `ParseSlab` is where `parse()` results are stored, located at `Slab.ps`.
 ///
+/// # Safe Variable Registration with `add_safe_var()`
+///
+/// [`SafeVar`](../evalns/struct.SafeVar.html)s must be registered *before*
+/// calling `parse()`, for the same reasons as the Unsafe Variables below.
+/// Unlike Unsafe Variables, they are always available and need no `unsafe`
+/// code: the parsed expression holds its own handle to each `SafeVar`.
+///
 /// # Unsafe Variable Registration with `add_unsafe_var()`
 ///
 /// (This is documented here because the
@@ -216,14 +222,66 @@ pub struct ParseSlab {
     pub(crate) def_expr   :Expression,
     pub(crate) def_val    :Value,
     pub(crate) char_buf   :String,
+    pub(crate) safe_vars  :BTreeMap<String, SafeVar>,
     #[cfg(feature="unsafe-vars")]
     pub(crate) unsafe_vars:BTreeMap<String, *const f64>,
+    pub(crate) funcs      :BTreeMap<String, UserFunc>,
+    pub(crate) locals     :Vec<String>,  // The parameters of the function, or the variables of the program, that is being parsed.
+    pub(crate) keep_exprs :usize,  // clear() keeps the bodies of user-defined functions,
+    pub(crate) keep_vals  :usize,  // which are stored before these indexes.
 }
 
 /// `CompileSlab` is where `compile()` results are stored, located at `Slab.cs`.
+///
+/// # Variable Slots with `add_slot()`
+///
+/// Normal Variables are looked up by name, with a call to
+/// [`EvalNamespace::lookup()`](../evalns/trait.EvalNamespace.html#tymethod.lookup),
+/// every time they are evaluated.  If you register a variable name as a
+/// *slot* before calling `compile()`, the variable is resolved to an integer
+/// index at compile time, and evaluation just reads that index from a
+/// `&[f64]` supplied by a [`SlotNamespace`](../evalns/struct.SlotNamespace.html).
+///
+/// Slots are entirely safe: if the Namespace doesn't supply a slot (for
+/// example, because it is out of range), the variable is looked up by name,
+/// like a Normal Variable.
+///
+/// ## Examples
+///
+/// ```
+/// use fasteval::Evaler;    // use this trait so we can call eval().
+/// use fasteval::Compiler;  // use this trait so we can call compile().
+///
+/// fn main() -> Result<(), fasteval::Error> {
+///     let mut slab = fasteval::Slab::new();
+///
+///     // Slots must be registered before 'compile()'.
+///     let x = slab.cs.add_slot("x".to_string());
+///     let y = slab.cs.add_slot("y".to_string());
+///     assert_eq!((x,y), (0,1));
+///
+///     let expr_ref = fasteval::Parser::new().parse("x^2 + y", &mut slab.ps)?.from(&slab.ps);
+///     let compiled = expr_ref.compile(&slab.ps, &mut slab.cs);
+///
+///     let mut vals = [0.0, 0.0];
+///     for i in 0..10 {
+///         vals[x] = i as f64;
+///         vals[y] = 1.0;
+///         let mut ns = fasteval::SlotNamespace::new(&vals, fasteval::EmptyNamespace);
+///         assert_eq!(fasteval::eval_compiled!(compiled, &slab, &mut ns), (i*i+1) as f64);
+///     }
+///
+///     Ok(())
+/// }
+/// ```
 pub struct CompileSlab {
     pub(crate) instrs   :Vec<Instruction>,
     pub(crate) def_instr:Instruction,
+    pub(crate) slots    :Vec<String>,
+    pub(crate) fold_nan :bool,
+    pub(crate) int_mode :bool,
+    pub(crate) func_args:Vec<Vec<(String, Option<f64>)>>,  // The parameters of the user-defined functions that are being compiled, and their values if the argument is constant.
+    pub(crate) locals   :Vec<(String, Option<f64>)>,  // The variables of the program that is being compiled, and their values if they are constant.
 }
 
 impl ParseSlab {
@@ -283,10 +341,44 @@ impl ParseSlab {
     }
 
     /// Clears all data from `ParseSlab.exprs` and `ParseSlab.vals`.
+    ///
+    /// User-defined functions are kept.
     #[inline]
     pub fn clear(&mut self) {
-        self.exprs.clear();
-        self.vals.clear();
+        self.exprs.truncate(self.keep_exprs);
+        self.vals.truncate(self.keep_vals);
+    }
+
+    /// Removes all user-defined functions, and then clears all data like `clear()`.
+    pub fn clear_funcs(&mut self) {
+        self.funcs.clear();
+        self.keep_exprs = 0;
+        self.keep_vals = 0;
+        self.clear();
+    }
+
+    /// Returns the user-defined function called `name`, if there is one.
+    ///
+    /// Functions are defined with [`Parser::define()`](../parser/struct.Parser.html#method.define).
+    #[inline]
+    pub fn get_func(&self, name:&str) -> Option<&UserFunc> {
+        self.funcs.get(name)
+    }
+
+    /// Stores a user-defined function, whose body is somewhere in this
+    /// `ParseSlab`, and makes sure that `clear()` keeps it.
+    pub(crate) fn add_func(&mut self, name:String, f:UserFunc) {
+        self.funcs.insert(name, f);
+        self.keep_exprs = self.exprs.len();
+        self.keep_vals = self.vals.len();
+    }
+
+    /// Registers a [`SafeVar`](../evalns/struct.SafeVar.html) under `name`.
+    ///
+    /// [See the `add_safe_var()` documentation above.](#safe-variable-registration-with-add_safe_var)
+    #[inline]
+    pub fn add_safe_var(&mut self, name:String, var:&SafeVar) {
+        self.safe_vars.insert(name, var.clone());
     }
 
     /// [See the `add_unsafe_var()` documentation above.](#unsafe-variable-registration-with-add_unsafe_var)
@@ -335,10 +427,64 @@ impl CompileSlab {
     }
 
     /// Clears all data from `CompileSlab.instrs`.
+    ///
+    /// Slot registrations are kept.
     #[inline]
     pub fn clear(&mut self) {
         self.instrs.clear();
     }
+
+    /// Registers `name` as a variable slot and returns its slot index.
+    ///
+    /// If `name` is already registered, its existing index is returned.
+    ///
+    /// [See the `add_slot()` documentation above.](#variable-slots-with-add_slot)
+    pub fn add_slot(&mut self, name:String) -> usize {
+        match self.slot_index(&name) {
+            Some(i) => i,
+            None => {
+                self.slots.push(name);
+                self.slots.len()-1
+            }
+        }
+    }
+
+    /// Returns the slot index of `name`, if it has been registered.
+    #[inline]
+    pub fn slot_index(&self, name:&str) -> Option<usize> {
+        self.slots.iter().position(|s| s==name)
+    }
+
+    /// Returns the variable name of slot `i`, if it has been registered.
+    #[inline]
+    pub fn slot_name(&self, i:usize) -> Option<&str> {
+        self.slots.get(i).map(|s| s.as_str())
+    }
+
+    /// Sets whether the compiler folds constant operations that produce `NaN`
+    /// from non-`NaN` inputs, like `(-1)^0.5` or `asin(2)`.  The default is `true`.
+    ///
+    /// Disable this when the compiled `Instruction`s will be evaluated with a
+    /// number type where those operations are defined, such as with
+    /// [`eval_complex()`](../complex/fn.eval_complex.html).
+    #[inline]
+    pub fn set_fold_nan(&mut self, fold:bool) {
+        self.fold_nan = fold;
+    }
+
+    /// Sets whether the compiler produces `Instruction`s for
+    /// [integer mode](../number/index.html#integer-mode).  The default is `false`.
+    ///
+    /// In integer mode, constants are folded with the same checked `i64`
+    /// arithmetic that `eval_number()` uses, and operations that would fail
+    /// (like an overflow or a division by zero) are left for the evaluation to
+    /// report.  The operators are applied in the same order as `eval_number()`
+    /// applies them to `i64`s, so `4 * 5 / 2` is `10`.  Evaluate the result
+    /// with [`NumberEvaler`](../number/trait.NumberEvaler.html) and `i64`.
+    #[inline]
+    pub fn set_int_mode(&mut self, int_mode:bool) {
+        self.int_mode = int_mode;
+    }
 }
 
 impl Slab {
@@ -356,21 +502,32 @@ impl Slab {
                 def_expr   :Default::default(),
                 def_val    :Default::default(),
                 char_buf   :String::with_capacity(64),
+                safe_vars  :BTreeMap::new(),
                 #[cfg(feature="unsafe-vars")]
                 unsafe_vars:BTreeMap::new(),
+                funcs      :BTreeMap::new(),
+                locals     :Vec::new(),
+                keep_exprs :0,
+                keep_vals  :0,
             },
             cs:CompileSlab{
                 instrs   :Vec::new(),  // Don't pre-allocate for compilation.
                 def_instr:Default::default(),
+                slots    :Vec::new(),
+                fold_nan :true,
+                int_mode :false,
+                func_args:Vec::new(),
+                locals   :Vec::new(),
             },
         }
     }
 
     /// Clears all data from [`Slab.ps`](struct.ParseSlab.html) and [`Slab.cs`](struct.CompileSlab.html).
+    ///
+    /// User-defined functions are kept.
     #[inline]
     pub fn clear(&mut self) {
-        self.ps.exprs.clear();
-        self.ps.vals.clear();
+        self.ps.clear();
         self.cs.instrs.clear();
     }
 }
@@ -0,0 +1,377 @@
+//! This module explains how the result of an evaluation was computed.
+//!
+//! `eval_traced()` evaluates an `Expression` or an `Instruction` with the
+//! normal `Evaler` implementation, and records the value of every
+//! intermediate step in a tree of `Trace` nodes: variable lookups, function
+//! calls, operators, and the assignments of a program.  Each node is labeled
+//! with the values that were used to compute it, and its children are the
+//! steps that produced those values.  Constants are not recorded, because
+//! their values are already in the label of the step that uses them.
+//!
+//! A `Trace` is displayed as an indented explanation, one step per line.
+//!
+//! The interpreted and the compiled forms of an expression produce different
+//! traces, because the compiler folds constants and rewrites operations
+//! (for example, `a - b` is compiled to `a + -b`).  Safe Variables and
+//! Unsafe Variables are inlined by the compiler, so they are not recorded
+//! in the trace of an `Instruction`.
+//!
+//! # Examples
+//!
+//! ```
+//! use fasteval::{Evaler, Compiler, eval_traced};
+//! use std::collections::BTreeMap;
+//! fn main() -> Result<(), fasteval::Error> {
+//!     let mut slab = fasteval::Slab::new();
+//!     let mut map = BTreeMap::new();
+//!     map.insert("x".to_string(), 1.0);
+//!     map.insert("y".to_string(), 3.0);
+//!
+//!     let expr = fasteval::Parser::new().parse("x + max(y, 2) * 2", &mut slab.ps)?.from(&slab.ps);
+//!     let trace = eval_traced(expr, &slab, &mut map);
+//!     assert_eq!(trace.value, Ok(7.0));
+//!     assert_eq!(trace.to_string(),
+//! "result = 7
+//!   x = 1
+//!   max(3, 2) = 3
+//!     y = 3
+//!   3 * 2 = 6
+//!   1 + 6 = 7
+//! ");
+//!
+//!     // Compiled Instructions can be traced too:
+//!     let instr = expr.compile(&slab.ps, &mut slab.cs);
+//!     let trace = eval_traced(&instr, &slab, &mut map);
+//!     assert_eq!(trace.to_string(),
+//! "result = 7
+//!   1 + 6 = 7
+//!     x = 1
+//!     3 * 2 = 6
+//!       max(3, 2) = 3
+//!         y = 3
+//! ");
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use crate::error::Error;
+use crate::slab::Slab;
+use crate::evaler::Evaler;
+use crate::evalns::EvalNamespace;
+use crate::hooks::{Hooks, HookedEvaler};
+use crate::compiler::{IC, InstructionI, Instruction::{self, IConst, INeg, INot, IInv, IBitNot, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IOR, IAND, IBitOr, IBitXor, IBitAnd, IShl, IShr, IVar, ISlot, ILocal, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc, ICall, IProgram}};
+#[cfg(feature="unsafe-vars")]
+use crate::compiler::Instruction::IUnsafeVar;
+use crate::parser::{PrintFunc, ExpressionOrString::{EExpr, EStr}, UnaryOp::{self, EPos, ENeg, ENot, EBitNot, EParentheses}, BinaryOp::{self, EOR, EAND, ENE, EEQ, EGTE, ELTE, EGT, ELT, EBitOr, EBitXor, EBitAnd, EShr, EShl, EAdd, ESub, EMul, EDiv, EMod, EExp}, StdFunc::{self, EVar, ESafeVar, EFunc, EFuncInt, EFuncCeil, EFuncFloor, EFuncAbs, EFuncSign, EFuncLog, EFuncRound, EFuncMin, EFuncMax, EFuncIf, EProgram, EFuncE, EFuncPi, EFuncSin, EFuncCos, EFuncTan, EFuncASin, EFuncACos, EFuncATan, EFuncSinH, EFuncCosH, EFuncTanH, EFuncASinH, EFuncACosH, EFuncATanH}};
+#[cfg(feature="unsafe-vars")]
+use crate::parser::StdFunc::EUnsafeVar;
+
+use std::fmt;
+
+/// One step of an evaluation, produced by [`eval_traced()`](fn.eval_traced.html).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trace {
+    /// A description of the step, like `x` or `3 * 2`.
+    pub label   :String,
+    /// The result of the step.
+    pub value   :Result<f64,Error>,
+    /// The steps that computed the values that this step used, in evaluation order.
+    pub children:Vec<Trace>,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+impl Trace {
+    fn fmt_indented(&self, f:&mut fmt::Formatter, depth:usize) -> fmt::Result {
+        match &self.value {
+            Ok(val) => writeln!(f, "{:indent$}{} = {}", "", self.label, val, indent=depth*2)?,
+            Err(err) => writeln!(f, "{:indent$}{} = error: {}", "", self.label, err, indent=depth*2)?,
+        }
+        for child in &self.children {
+            child.fmt_indented(f, depth+1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates an `Expression` or an `Instruction`, and records how the result
+/// was computed.
+///
+/// The root of the returned `Trace` is labeled `result`, and its value is the
+/// value that `eval()` would return.  If the evaluation fails, the steps that
+/// were completed before the failure are still recorded, and the step that
+/// failed has the error as its value.
+///
+/// Only parsed `Expression`s (and their parts) and compiled `Instruction`s
+/// can be traced.  The other evaluators (`Bytecode`, `eval_batch()`,
+/// `CompiledExpr`, `eval_complex()`, `eval_interval()` and `eval_grad()`)
+/// don't record their steps, so they are not accepted.
+pub fn eval_traced(evaler:&(impl Evaler + HookedEvaler), slab:&Slab, ns:&mut impl EvalNamespace) -> Trace {
+    let mut tracer = Tracer::new();
+    let value = evaler.eval_hooked(slab, ns, &mut tracer);
+    tracer.finish(value)
+}
+
+// The children and the argument values of a step that is being evaluated:
+#[derive(Default)]
+struct Frame {
+    children:Vec<Trace>,
+    args    :Vec<f64>,
+}
+
+// Records the steps of an evaluation:
+pub(crate) struct Tracer {
+    frames:Vec<Frame>,
+}
+
+impl Tracer {
+    pub(crate) fn new() -> Self {
+        Tracer{ frames:vec![Frame::default()] }
+    }
+
+    // Builds the root of the Trace:
+    pub(crate) fn finish(mut self, value:Result<f64,Error>) -> Trace {
+        let children = self.frames.pop().map(|frame| frame.children).unwrap_or_default();
+        Trace{ label:"result".to_string(), value, children }
+    }
+}
+
+impl Hooks for Tracer {
+    fn tracing(&self) -> bool { true }
+    fn trace_enter(&mut self) {
+        self.frames.push(Frame::default());
+    }
+    fn trace_arg(&mut self, val:f64) {
+        if let Some(frame) = self.frames.last_mut() { frame.args.push(val); }
+    }
+    fn trace_exit(&mut self, label:&dyn Fn(&[Trace],&[f64])->String, val:&Result<f64,Error>) {
+        let frame = self.frames.pop().unwrap_or_default();
+        let node = Trace{ label:label(&frame.children, &frame.args), value:val.clone(), children:frame.children };
+        if let Some(parent) = self.frames.last_mut() { parent.children.push(node); }
+    }
+}
+
+
+
+// ---- Labels ----
+//
+// The labels look like the source code, with the values of the operands
+// substituted.  An operand whose value is unknown (because it was not
+// evaluated, or because its evaluation failed) is shown as `…`.
+
+const UNKNOWN : &str = "…";
+
+fn num(val:Option<f64>) -> String {
+    match val {
+        Some(val) => val.to_string(),
+        None => UNKNOWN.to_string(),
+    }
+}
+
+// Negative operands of unary operators are parenthesized, so that `-(-3)` doesn't look like `--3`:
+fn unary(op:&str, val:Option<f64>) -> String {
+    match val {
+        Some(val) if val.is_sign_negative() => format!("{}({})", op, val),
+        _ => format!("{}{}", op, num(val)),
+    }
+}
+
+fn call(name:&str, args:impl IntoIterator<Item=Option<f64>>) -> String {
+    let args = args.into_iter().map(num).collect::<Vec<_>>();
+    format!("{}({})", name, args.join(", "))
+}
+
+// Only the taken branch of an `if` is evaluated:
+fn call_if(cond:Option<f64>, taken:Option<f64>) -> String {
+    match cond {
+        Some(c) if c!=0.0 => format!("if({}, {}, {})", c, num(taken), UNKNOWN),
+        Some(c) => format!("if({}, {}, {})", c, UNKNOWN, num(taken)),
+        None => format!("if({}, {}, {})", UNKNOWN, UNKNOWN, UNKNOWN),
+    }
+}
+
+pub(crate) fn binaryop_label(op:BinaryOp, left:f64, right:f64) -> String {
+    let sym = match op {
+        EAdd => "+",
+        ESub => "-",
+        EMul => "*",
+        EDiv => "/",
+        EMod => "%",
+        EExp => "^",
+        ELT => "<",
+        ELTE => "<=",
+        EEQ => "==",
+        ENE => "!=",
+        EGTE => ">=",
+        EGT => ">",
+        EOR => "||",
+        EAND => "&&",
+        EBitOr => "|",
+        EBitXor => "xor",
+        EBitAnd => "&",
+        EShl => "<<",
+        EShr => ">>",
+    };
+    format!("{} {} {}", left, sym, right)
+}
+
+pub(crate) fn unaryop_label(u:&UnaryOp, args:&[f64]) -> String {
+    let arg = args.first().copied();
+    match u {
+        ENeg(_) => unary("-", arg),
+        ENot(_) => unary("!", arg),
+        EBitNot(_) => unary("~", arg),
+        EPos(_) | EParentheses(_) => num(arg),  // Not recorded.
+    }
+}
+
+pub(crate) fn stdfunc_label(f:&StdFunc, args:&[f64]) -> String {
+    let arg = |i:usize| args.get(i).copied();
+    let (name, nargs) = match f {
+        #[cfg(feature="unsafe-vars")]
+        EUnsafeVar{name, ..} => return name.clone(),
+        ESafeVar{name, ..} | EVar(name) => return name.clone(),
+        EFunc{name, args} => (name.as_str(), args.len()),
+        EFuncIf{..} => return call_if(arg(0), arg(1)),
+        EProgram(_) => ("program", 0),  // Not recorded.
+
+        EFuncLog{base, ..} => ("log", 1+base.iter().len()),
+        EFuncRound{modulus, ..} => ("round", 1+modulus.iter().len()),
+        EFuncMin{rest, ..} => ("min", 1+rest.len()),
+        EFuncMax{rest, ..} => ("max", 1+rest.len()),
+        EFuncE => ("e", 0),
+        EFuncPi => ("pi", 0),
+
+        EFuncInt(_) => ("int", 1),
+        EFuncCeil(_) => ("ceil", 1),
+        EFuncFloor(_) => ("floor", 1),
+        EFuncAbs(_) => ("abs", 1),
+        EFuncSign(_) => ("sign", 1),
+        EFuncSin(_) => ("sin", 1),
+        EFuncCos(_) => ("cos", 1),
+        EFuncTan(_) => ("tan", 1),
+        EFuncASin(_) => ("asin", 1),
+        EFuncACos(_) => ("acos", 1),
+        EFuncATan(_) => ("atan", 1),
+        EFuncSinH(_) => ("sinh", 1),
+        EFuncCosH(_) => ("cosh", 1),
+        EFuncTanH(_) => ("tanh", 1),
+        EFuncASinH(_) => ("asinh", 1),
+        EFuncACosH(_) => ("acosh", 1),
+        EFuncATanH(_) => ("atanh", 1),
+    };
+    call(name, (0..nargs).map(arg))
+}
+
+pub(crate) fn print_label(pf:&PrintFunc, args:&[f64]) -> String {
+    let mut args = args.iter();
+    let args = pf.0.iter().map(|a| match a {
+        EExpr(_) => num(args.next().copied()),
+        EStr(s) => format!("{:?}", s),
+    }).collect::<Vec<_>>();
+    format!("print({})", args.join(", "))
+}
+
+// The operands of an Instruction are constants, inlined variables, or other
+// Instructions.  Each of the other Instructions that was evaluated is
+// recorded as one child, in evaluation order.
+struct Operands<'a> {
+    slab    :&'a Slab,
+    children:std::slice::Iter<'a,Trace>,
+}
+impl Operands<'_> {
+    fn instr(&mut self, i:InstructionI) -> Option<f64> {
+        match self.slab.cs.get_instr(i) {
+            IConst(c) => Some(*c),
+            ISafeVar{var, ..} => Some(var.get()),
+            #[cfg(feature="unsafe-vars")]
+            IUnsafeVar{ptr, ..} => Some(unsafe { **ptr }),
+            _ => self.children.next().and_then(|child| child.value.clone().ok()),
+        }
+    }
+    fn ic(&mut self, ic:&IC) -> Option<f64> {
+        match ic {
+            IC::C(c) => Some(*c),
+            IC::I(i) => self.instr(*i),
+        }
+    }
+    fn binary(&mut self, left:Option<f64>, sym:&str, right:&IC) -> String {
+        let right = self.ic(right);
+        format!("{} {} {}", num(left), sym, num(right))
+    }
+}
+
+pub(crate) fn instruction_label(instr:&Instruction, slab:&Slab, children:&[Trace]) -> String {
+    let mut o = Operands{ slab, children:children.iter() };
+    match instr {
+        IConst(c) => c.to_string(),
+        ISafeVar{name, ..} | IVar(name) | ISlot{name, ..} => name.clone(),
+        #[cfg(feature="unsafe-vars")]
+        IUnsafeVar{name, ..} => name.clone(),
+        ILocal(_) | IProgram{..} => String::new(),  // Labeled by the caller, or not recorded.
+        IPrintFunc(pf) => print_label(pf, &[]),  // Not recorded.
+
+        INeg(i) => unary("-", o.instr(*i)),
+        INot(i) => unary("!", o.instr(*i)),
+        IInv(i) => format!("1 / {}", num(o.instr(*i))),
+        IBitNot(i) => unary("~", o.instr(*i)),
+
+        IAdd(l,r) => { let l = o.instr(*l); o.binary(l, "+", r) }
+        IMul(l,r) => { let l = o.instr(*l); o.binary(l, "*", r) }
+        IOR(l,r) => { let l = o.instr(*l); o.binary(l, "||", r) }
+        IAND(l,r) => { let l = o.instr(*l); o.binary(l, "&&", r) }
+        IMod{dividend:l, divisor:r} => { let l = o.ic(l); o.binary(l, "%", r) }
+        IExp{base:l, power:r} => { let l = o.ic(l); o.binary(l, "^", r) }
+        ILT(l,r) => { let l = o.ic(l); o.binary(l, "<", r) }
+        ILTE(l,r) => { let l = o.ic(l); o.binary(l, "<=", r) }
+        IEQ(l,r) => { let l = o.ic(l); o.binary(l, "==", r) }
+        INE(l,r) => { let l = o.ic(l); o.binary(l, "!=", r) }
+        IGTE(l,r) => { let l = o.ic(l); o.binary(l, ">=", r) }
+        IGT(l,r) => { let l = o.ic(l); o.binary(l, ">", r) }
+        IBitOr(l,r) => { let l = o.ic(l); o.binary(l, "|", r) }
+        IBitXor(l,r) => { let l = o.ic(l); o.binary(l, "xor", r) }
+        IBitAnd(l,r) => { let l = o.ic(l); o.binary(l, "&", r) }
+        IShl(l,r) => { let l = o.ic(l); o.binary(l, "<<", r) }
+        IShr(l,r) => { let l = o.ic(l); o.binary(l, ">>", r) }
+
+        IFunc{name, args} | ICall{name, args, ..} => {
+            let args = args.iter().map(|ic| o.ic(ic)).collect::<Vec<_>>();
+            call(name, args)
+        }
+        IFuncLog{base, of} => { let base = o.ic(base); call("log", vec![base, o.ic(of)]) }
+        IFuncRound{modulus, of} => { let modulus = o.ic(modulus); call("round", vec![modulus, o.ic(of)]) }
+        IFuncMin(l,r) => { let l = o.instr(*l); call("min", vec![l, o.ic(r)]) }
+        IFuncMax(l,r) => { let l = o.instr(*l); call("max", vec![l, o.ic(r)]) }
+        IFuncIf{cond, then, otherwise} => {
+            let cond = o.instr(*cond);
+            let taken = match cond {
+                Some(c) if c!=0.0 => o.ic(then),
+                Some(_) => o.ic(otherwise),
+                None => None,
+            };
+            call_if(cond, taken)
+        }
+
+        IFuncInt(i) => call("int", vec![o.instr(*i)]),
+        IFuncCeil(i) => call("ceil", vec![o.instr(*i)]),
+        IFuncFloor(i) => call("floor", vec![o.instr(*i)]),
+        IFuncAbs(i) => call("abs", vec![o.instr(*i)]),
+        IFuncSign(i) => call("sign", vec![o.instr(*i)]),
+        IFuncSin(i) => call("sin", vec![o.instr(*i)]),
+        IFuncCos(i) => call("cos", vec![o.instr(*i)]),
+        IFuncTan(i) => call("tan", vec![o.instr(*i)]),
+        IFuncASin(i) => call("asin", vec![o.instr(*i)]),
+        IFuncACos(i) => call("acos", vec![o.instr(*i)]),
+        IFuncATan(i) => call("atan", vec![o.instr(*i)]),
+        IFuncSinH(i) => call("sinh", vec![o.instr(*i)]),
+        IFuncCosH(i) => call("cosh", vec![o.instr(*i)]),
+        IFuncTanH(i) => call("tanh", vec![o.instr(*i)]),
+        IFuncASinH(i) => call("asinh", vec![o.instr(*i)]),
+        IFuncACosH(i) => call("acosh", vec![o.instr(*i)]),
+        IFuncATanH(i) => call("atanh", vec![o.instr(*i)]),
+    }
+}
@@ -0,0 +1,107 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, SafeVar, SlotNamespace, EmptyNamespace, Dual, eval_grad, derive};
+
+use std::collections::BTreeMap;
+
+// Compares the gradient to the symbolic derivatives, at several points.
+fn chk(expr_str:&str) {
+    for &(x,y) in &[(-1.7, 1.5), (-0.3, 0.2), (0.4, -2.0), (0.9, 0.9), (2.5, 3.0)] {
+        let mut slab = Slab::with_capacity(1024);
+        let mut ns = BTreeMap::<String,f64>::new();
+        ns.insert("x".to_string(), x);
+        ns.insert("y".to_string(), y);
+
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        let dx_i = derive(expr_i, "x", &mut slab.ps).unwrap();
+        let dy_i = derive(expr_i, "y", &mut slab.ps).unwrap();
+        let expect = [dx_i.from(&slab.ps).eval(&slab, &mut ns).unwrap(),
+                      dy_i.from(&slab.ps).eval(&slab, &mut ns).unwrap()];
+
+        let instr = expr_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        let dual = eval_grad(&instr, &slab, &["x", "y"], &mut ns).unwrap();
+
+        let val = instr.eval(&slab, &mut ns).unwrap();
+        assert!(dual.val==val || dual.val.is_nan() && val.is_nan(), "{} at ({},{}): value {} != {}", expr_str, x, y, dual.val, val);
+        for (got,expect) in dual.grad.iter().zip(expect.iter()) {
+            assert!(got==expect || got.is_nan() && expect.is_nan() || (got-expect).abs() <= 1e-12 * expect.abs().max(1.0),
+                    "{} at ({},{}): gradient {:?} != {:?}", expr_str, x, y, dual.grad, expect);
+        }
+    }
+}
+
+#[test]
+fn rules() {
+    chk("3");
+    chk("x");
+    chk("-x + y");
+    chk("x*y - y/x");
+    chk("x^3 - 2*x^2*y + x - 1");
+    chk("2^x + y^2");
+    chk("x^y");
+    chk("(x^2+1)^y");
+    chk("x % 0.7 + y % x");
+    chk("(x > y) + !x * 3 + (x == y)");
+    chk("x > 0 && x*y || -y");
+    chk("x > 1 ? x^3 : 2*y");
+    chk("int(x) + ceil(y) + floor(x) + round(0.1, x) + sign(y)");
+    chk("abs(x^3 * y)");
+    chk("log(x^2) + log(2, y^2 + 1)");
+    chk("log(y^2 + 3, x^4 + 1)");
+    chk("min(x, y, 0.5) + max(x*y, y)");
+    chk("sin(x) * cos(2*y) + tan(x/3)");
+    chk("asin(x/3) + acos(y/4) + atan(x*y)");
+    chk("sinh(x) + cosh(y) + tanh(x)");
+    chk("asinh(x) + acosh(y^2 + 2) + atanh(x/3)");
+    chk("e()^x * pi()");
+}
+
+#[test]
+fn non_differentiable_points() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 0.0);
+    ns.insert("y".to_string(), -2.0);
+    let mut grad = |expr_str:&str| -> Dual {
+        let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        eval_grad(&instr, &slab, &["x", "y"], &mut ns).unwrap()
+    };
+
+    assert_eq!(grad("abs(x)"), Dual{ val:0.0, grad:vec![0.0, 0.0] });
+    assert_eq!(grad("floor(x*y + y)"), Dual{ val:-2.0, grad:vec![0.0, 0.0] });
+    assert_eq!(grad("min(x, x*2)"), Dual{ val:0.0, grad:vec![1.0, 0.0] });
+    assert_eq!(grad("max(x*2, x)"), Dual{ val:0.0, grad:vec![2.0, 0.0] });
+    assert_eq!(format!("{:?}", grad("min(y, NaN)")), "Dual { val: NaN, grad: [NaN, NaN] }");
+
+    // Negative bases work with constant powers:
+    assert_eq!(grad("y^3"), Dual{ val:-8.0, grad:vec![0.0, 12.0] });
+    // ...but not with variable powers:
+    assert_eq!(format!("{:?}", grad("y^(x+2)")), "Dual { val: 4.0, grad: [NaN, -4.0] }");
+    // A zero base only breaks the partials of the power:
+    assert_eq!(format!("{:?}", grad("x^(y+4)")), "Dual { val: 0.0, grad: [0.0, NaN] }");
+    assert_eq!(grad("x^2 + y"), Dual{ val:-2.0, grad:vec![0.0, 1.0] });
+}
+
+#[test]
+fn variables() {
+    let mut slab = Slab::new();
+    slab.cs.add_slot("a".to_string());
+    let b = SafeVar::new(3.0);
+    slab.ps.add_safe_var("b".to_string(), &b);
+    let instr = Parser::new().parse("a * b * c + f(2)", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+
+    let mut ns = SlotNamespace::new(&[2.0], |name:&str, args:Vec<f64>| match name {
+        "c" => Some(5.0),
+        "f" => Some(args[0]*10.0),
+        _ => None,
+    });
+    assert_eq!(eval_grad(&instr, &slab, &["c", "b", "a", "z"], &mut ns), Ok(Dual{ val:50.0, grad:vec![6.0, 10.0, 15.0, 0.0] }));
+    assert_eq!(eval_grad(&instr, &slab, &[], &mut ns), Ok(Dual{ val:50.0, grad:vec![] }));
+    assert_eq!(eval_grad(&instr, &slab, &["a"], &mut EmptyNamespace), Err(Error::Undefined("a".to_string())));
+    // Slots that the Namespace doesn't supply are looked up by name:
+    let mut plain = |name:&str, _:Vec<f64>| match name { "a" => Some(2.0), "c" => Some(5.0), "f" => Some(0.0), _ => None };
+    assert_eq!(eval_grad(&instr, &slab, &["a"], &mut plain), Ok(Dual{ val:30.0, grad:vec![15.0] }));
+
+    let instr = Parser::new().parse("f(a) + print(\"b =\", b)", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(eval_grad(&instr, &slab, &["c"], &mut ns), Ok(Dual{ val:23.0, grad:vec![0.0] }));
+    assert_eq!(eval_grad(&instr, &slab, &["a"], &mut ns), Err(Error::NotDifferentiable("custom function `f`".to_string())));
+    assert_eq!(eval_grad(&instr, &slab, &["b"], &mut ns), Err(Error::NotDifferentiable("print()".to_string())));
+}
@@ -0,0 +1,147 @@
+use fasteval::{Parser, Compiler, Error, Slab, EmptyNamespace, NumberEvaler, Interval, eval_interval, eval_grad};
+use std::collections::BTreeMap;
+
+mod common;
+
+fn ns(name:&str, _args:Vec<f64>) -> Option<f64> {
+    match name {
+        "flags" => Some(0b1011 as f64),
+        "x" => Some(2.5),
+        "nan" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+fn eval(expr_str:&str) -> Result<f64,Error> {
+    common::eval_agree(&mut Slab::new(), expr_str, &mut ns)
+}
+
+fn chk(expr_str:&str, expect:f64) {
+    assert_eq!(eval(expr_str), Ok(expect), "{}", expr_str);
+}
+
+#[test]
+fn operators() {
+    chk("12 & 10", 8.0);
+    chk("12 | 10", 14.0);
+    chk("12 xor 10", 6.0);
+    chk("1 << 4", 16.0);
+    chk("256 >> 4", 16.0);
+    chk("~0", -1.0);
+    chk("~5", -6.0);
+    chk("~-1", 0.0);
+    chk("-6 & 7", 2.0);
+    chk("-16 >> 2", -4.0);
+    chk("-17 >> 2", -5.0);  // Arithmetic shift, rounds towards negative infinity.
+    chk("1 << 62", 4611686018427387904.0);
+    chk("~(1 << 3) & 255", 247.0);
+    chk("flags & ~2", 9.0);
+    chk("(flags >> 1) & 1", 1.0);
+    chk("12&&10", 10.0);
+    chk("12||10", 12.0);
+}
+
+#[test]
+fn precedence() {
+    // Tighter than comparisons:
+    chk("flags & 4 == 0", 1.0);
+    chk("flags & 8 == 8", 1.0);
+    chk("1 | 2 == 3", 1.0);
+    chk("1 < 2 | 4", 1.0);
+    // Looser than arithmetic:
+    chk("1 << 2 + 1", 8.0);
+    chk("2 * 3 & 5", 4.0);
+    chk("1 + 2 | 4", 7.0);
+    // & before xor before |:
+    chk("1 | 2 xor 3 & 6", 1.0);
+    chk("1 | 2 xor 3", 1.0);
+    chk("(1 | 2) xor 3 & 6", 1.0);
+    chk("6 & 3 xor 1 | 8", 11.0);
+    // Shifts are evaluated left-to-right:
+    chk("1 << 4 >> 2", 4.0);
+    chk("64 >> 4 << 2", 16.0);
+    chk("1 << 2 >> 3 << 1", 0.0);
+    // Lower than && and ||:
+    chk("0 && 1 | 2", 0.0);
+    chk("1 & 2 || 4", 4.0);
+    // Unary ~ binds like unary -:
+    chk("~1 + 1", -1.0);
+    chk("-~1", 2.0);
+    chk("~~7", 7.0);
+}
+
+#[test]
+fn errors() {
+    assert_eq!(eval("x & 1"), Err(Error::WrongArgs("bitwise operator `&` requires integer operands, but got 2.5".to_string())));
+    assert_eq!(eval("1 | x"), Err(Error::WrongArgs("bitwise operator `|` requires integer operands, but got 2.5".to_string())));
+    assert_eq!(eval("0.5 xor 1"), Err(Error::WrongArgs("bitwise operator `xor` requires integer operands, but got 0.5".to_string())));
+    assert_eq!(eval("~x"), Err(Error::WrongArgs("bitwise operator `~` requires integer operands, but got 2.5".to_string())));
+    assert_eq!(eval("nan >> 1"), Err(Error::WrongArgs("bitwise operator `>>` requires integer operands, but got NaN".to_string())));
+    assert_eq!(eval("1 << 64"), Err(Error::WrongArgs("shift amount of `<<` must be between 0 and 63, but got 64".to_string())));
+    assert_eq!(eval("1 >> -1"), Err(Error::WrongArgs("shift amount of `>>` must be between 0 and 63, but got -1".to_string())));
+    assert_eq!(eval("1 << 63"), Err(Error::Overflow));
+    assert_eq!(eval("3 << 62"), Err(Error::Overflow));
+    assert_eq!(eval("1e19 & 1"), Err(Error::Overflow));
+    assert_eq!(eval("flags & y"), Err(Error::Undefined("y".to_string())));
+    assert_eq!(eval("0 || x & 1").map_err(|e| e.to_string()), Err("bitwise operator `&` requires integer operands, but got 2.5".to_string()));
+}
+
+#[test]
+fn folding() {
+    fn comp(expr_str:&str) -> String {
+        let mut slab = Slab::new();
+        let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        format!("{:?} {:?}", instr, slab.cs)
+    }
+    assert_eq!(comp("(1 << 4) | (1 << 2) | 1"), "IConst(21.0) CompileSlab{ instrs:{} }");
+    assert_eq!(comp("~(1 << 8) & 0"), "IConst(0.0) CompileSlab{ instrs:{} }");
+    assert_eq!(comp("flags & (1 << 3)"), "IBitAnd(I(InstructionI(0)), C(8.0)) CompileSlab{ instrs:{ 0:IVar(\"flags\") } }");
+    assert_eq!(comp("flags >> 1 << 1"), "IShl(I(InstructionI(1)), C(1.0)) CompileSlab{ instrs:{ 0:IVar(\"flags\"), 1:IShr(I(InstructionI(0)), C(1.0)) } }");
+    assert_eq!(comp("~flags"), "IBitNot(InstructionI(0)) CompileSlab{ instrs:{ 0:IVar(\"flags\") } }");
+
+    // Invalid constants are not folded, so that eval() reports the Error:
+    assert_eq!(comp("1.5 & 1"), "IBitAnd(C(1.5), C(1.0)) CompileSlab{ instrs:{} }");
+    assert_eq!(comp("~0.5"), "IBitNot(InstructionI(0)) CompileSlab{ instrs:{ 0:IConst(0.5) } }");
+}
+
+#[test]
+fn other_evaluators() {
+    // Integer mode doesn't round-trip through f64, so all 64 bits are exact:
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("~(1 << 62) xor 9007199254740993", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(!(1i64<<62) ^ 9007199254740993));
+    let expr = Parser::new().parse("12 & 10 | 1 << 4 >> 1", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(8i64 | 8));
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(8.0f32));
+    let expr = Parser::new().parse("1 << 63", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number::<i64>(&slab, &mut EmptyNamespace), Err(Error::Overflow));
+
+    // Batch evaluation:
+    let mut slab = Slab::new();
+    slab.cs.add_slot("a".to_string());
+    let instr = Parser::new().parse("a & 6 | ~a >> 2", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let mut out = [0.0; 4];
+    fasteval::eval_batch(&instr, &slab, &[&[0.0, 1.0, 6.0, -8.0]], &mut out, &mut EmptyNamespace).unwrap();
+    assert_eq!(out, [-1.0, -1.0, -2.0, 1.0]);
+    assert_eq!(fasteval::eval_batch(&instr, &slab, &[&[0.5]], &mut out[..1], &mut EmptyNamespace),
+               Err(Error::WrongArgs("bitwise operator `&` requires integer operands, but got 0.5".to_string())));
+
+    // Intervals:
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Interval::new(0.0, 100.0));
+    map.insert("b".to_string(), Interval::new(-3.5, 5.0));
+    for &(expr_str, lo, hi) in &[("a & 15", 0.0, 15.0), ("a | 1", 1.0, 127.0), ("a xor 200", 0.0, 255.0), ("a << 2", 0.0, 400.0),
+                                 ("a >> 3", 0.0, 12.0), ("b >> 1", -2.0, 2.0), ("~b", -6.0, 2.0), ("12 & 10", 8.0, 8.0)] {
+        let mut slab = Slab::new();
+        let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        let i = eval_interval(&instr, &slab, &mut map).unwrap();
+        assert!(i.lo<=lo && hi<=i.hi && i.lo>=lo-1e-9 && i.hi<=hi+1e-9, "{}: {}", expr_str, i);
+    }
+    assert_eq!(eval_interval(&Parser::new().parse("b & -1", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs), &slab, &mut map), Ok(Interval::entire()));
+
+    // The gradient is zero, like other piecewise-constant functions:
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("x * (flags & 3)", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let dual = eval_grad(&instr, &slab, &["x", "flags"], &mut ns).unwrap();
+    assert_eq!((dual.val, dual.grad), (7.5, vec![3.0, 0.0]));
+}
@@ -0,0 +1,143 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, Budget, EmptyNamespace, SinkNamespace, PrintSink, eval_budgeted, eval_traced, eval_traced_budgeted};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+fn ns(name:&str, args:Vec<f64>) -> Option<f64> {
+    match name {
+        "x" => Some(1.0),
+        "add" => Some(args.iter().sum()),
+        _ => None,
+    }
+}
+
+// The smallest limit that lets both the interpreted and the compiled forms
+// finish, found by lowering the limit until the evaluation fails:
+fn min_limit(expr_str:&str, set:fn(&mut Budget, usize)) -> (usize, usize) {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    let find = |evaler:&dyn Fn(&Budget) -> Result<f64,Error>, expected:Result<f64,Error>| {
+        let mut limit = 0;
+        loop {
+            let mut budget = Budget::new();
+            set(&mut budget, limit);
+            match evaler(&budget) {
+                Err(Error::BudgetExceeded) => limit += 1,
+                val => { assert_eq!(val, expected, "{}", expr_str); return limit; }
+            }
+        }
+    };
+    (find(&|b| eval_budgeted(expr, &slab, &mut ns, b), expr.eval(&slab, &mut ns)),
+     find(&|b| eval_budgeted(&instr, &slab, &mut ns, b), instr.eval(&slab, &mut ns)))
+}
+
+#[test]
+fn unlimited() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("a = add(x, 2); a * sin(x) + nope", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &Budget::new()), Err(Error::Undefined("nope".to_string())));
+    let expr = Parser::new().parse("a = add(x, 2); a * sin(x)", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &Budget::new()), expr.eval(&slab, &mut ns));
+}
+
+#[test]
+fn steps() {
+    let steps = |expr_str| min_limit(expr_str, |b, limit| b.eval_step_limit = limit);
+    assert_eq!(steps("1 + 2"), (1, 1));  // The compiled form is a constant.
+    assert_eq!(steps("x + 2"), (2, 2));  // Expression + x, and IAdd + IVar.
+    assert_eq!(steps("sin(x) * -x"), (6, 5));
+    assert_eq!(steps("x < 1 ? x : 2 * x"), (6, 5));  // Only the taken branch is evaluated.
+    assert_eq!(steps("a = x + 1; b = a * a; b * b"), (10, 9));
+}
+
+#[test]
+fn ns_calls() {
+    let calls = |expr_str| min_limit(expr_str, |b, limit| b.ns_call_limit = limit);
+    assert_eq!(calls("1 + 2"), (0, 0));
+    assert_eq!(calls("x * (x + 1)"), (2, 2));
+    assert_eq!(calls("add(x, x, 3)"), (3, 3));
+    assert_eq!(calls("a = x; a * a"), (1, 1));  // Locals are not looked up in the Namespace.
+
+    // Free variables in the body of a user-defined function are counted:
+    let mut slab = Slab::new();
+    Parser::new().define("f(t) = t * x", &mut slab.ps).unwrap();
+    let expr = Parser::new().parse("f(x) + f(2)", &mut slab.ps).unwrap().from(&slab.ps);
+    let budget = Budget{ ns_call_limit:3, ..Budget::new() };
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Ok(3.0));
+    let budget = Budget{ ns_call_limit:2, ..Budget::new() };
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Err(Error::BudgetExceeded));
+}
+
+#[test]
+fn cancel() {
+    let mut slab = Slab::new();
+    let flag = Arc::new(AtomicBool::new(false));
+    let budget = Budget{ cancel:Some(Arc::clone(&flag)), ..Budget::new() };
+
+    // A custom function that cancels the evaluation, like another thread would:
+    let mut ns = |name:&str, _args:Vec<f64>| -> Option<f64> {
+        match name {
+            "x" => Some(1.0),
+            "stop" => { flag.store(true, Ordering::Relaxed); Some(0.0) }
+            _ => None,
+        }
+    };
+    let expr = Parser::new().parse("stop() + x", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Err(Error::Cancelled));
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &Budget::new()), Ok(1.0));
+    flag.store(false, Ordering::Relaxed);
+
+    // Without Namespace lookups, the flag is checked every 1024 steps:
+    struct StopSink<'a>(&'a AtomicBool);
+    impl PrintSink for StopSink<'_> {
+        fn print(&mut self, _line:&str) { self.0.store(true, Ordering::Relaxed); }
+    }
+    let mut slab = Slab::with_capacity(4096);
+    let mut long = "print(\"stop\")".to_string();
+    for _ in 0..1000 { long.push_str(" + sin(1)"); }
+    let expr = Parser{ expr_len_limit:100000, ..Parser::new() }.parse(&long, &mut slab.ps).unwrap().from(&slab.ps);
+    let mut ns = SinkNamespace::new(EmptyNamespace, StopSink(&flag));
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Err(Error::Cancelled));
+    flag.store(false, Ordering::Relaxed);
+    let expr = Parser::new().parse("print(\"stop\") + sin(1)", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(eval_budgeted(expr, &slab, &mut ns, &budget), Ok(0.8414709848078965));  // Too few steps to notice.
+}
+
+#[test]
+fn deep_nesting() {
+    // Counting the steps must not make the recursion of a long compiled expression use much more stack:
+    let mut slab = Slab::new();
+    let expr_str = vec!["x"; 400].join(" + ");
+    let expr = Parser::new().parse(&expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(400.0));
+    let budget = Budget{ eval_step_limit:799, ..Budget::new() };  // 399 IAdds and 400 IVars.
+    assert_eq!(eval_budgeted(&instr, &slab, &mut ns, &budget), Ok(400.0));
+    let budget = Budget{ eval_step_limit:798, ..Budget::new() };
+    assert_eq!(eval_budgeted(&instr, &slab, &mut ns, &budget), Err(Error::BudgetExceeded));
+}
+
+#[test]
+fn traced() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("x + add(x, 2) * 2", &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+
+    // Within the budget, the trace is the same as the trace of eval_traced():
+    let budget = Budget{ ns_call_limit:3, ..Budget::new() };
+    assert_eq!(eval_traced_budgeted(expr, &slab, &mut ns, &budget), eval_traced(expr, &slab, &mut ns));
+    assert_eq!(eval_traced_budgeted(&instr, &slab, &mut ns, &budget), eval_traced(&instr, &slab, &mut ns));
+
+    // Otherwise, the completed steps are recorded, and the result is the reason for stopping:
+    let budget = Budget{ ns_call_limit:2, ..Budget::new() };
+    let trace = eval_traced_budgeted(expr, &slab, &mut ns, &budget);
+    assert_eq!(trace.value, Err(Error::BudgetExceeded));
+    assert_eq!(trace.to_string(),
+"result = error: evaluation budget exceeded
+  x = 1
+  add(1, 2) = error: evaluation budget exceeded
+    x = 1
+");
+    let budget = Budget{ eval_step_limit:3, ..Budget::new() };
+    assert_eq!(eval_traced_budgeted(&instr, &slab, &mut ns, &budget).value, Err(Error::BudgetExceeded));
+}
@@ -0,0 +1,12 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, EvalNamespace, Bytecode, CompiledExpr};
+
+// Evaluates with the interpreter and the compiler, and checks that they agree.
+pub fn eval_agree(slab:&mut Slab, expr_str:&str, ns:&mut impl EvalNamespace) -> Result<f64,Error> {
+    let expr = Parser::new().parse(expr_str, &mut slab.ps)?.from(&slab.ps);
+    let interpreted = expr.eval(slab, ns);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(slab, ns), interpreted, "{}", expr_str);
+    assert_eq!(Bytecode::new(&instr, slab).eval(slab, ns), interpreted, "{}", expr_str);
+    assert_eq!(CompiledExpr::new(&instr, slab).eval(ns), interpreted, "{}", expr_str);
+    interpreted
+}
@@ -1,9 +1,9 @@
-use fasteval::{Parser, Compiler, Evaler, Error, Slab, EmptyNamespace, CachedCallbackNamespace, ExpressionI, InstructionI, eval_compiled, eval_compiled_ref};
+use fasteval::{Parser, Compiler, CompiledExpr, Evaler, Error, Slab, EmptyNamespace, CachedCallbackNamespace, SlotNamespace, SafeVar, ExpressionI, InstructionI, eval_compiled, eval_compiled_ref};
 use fasteval::parser::{PrintFunc, ExpressionOrString::{EExpr, EStr}};
 #[cfg(feature="eval-builtin")]
 use fasteval::parser::{EvalFunc, KWArg};
 use fasteval::compiler::IC;
-use fasteval::compiler::Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IAND, IOR, IVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
+use fasteval::compiler::Instruction::{self, IConst, INeg, INot, IInv, IAdd, IMul, IMod, IExp, ILT, ILTE, IEQ, INE, IGTE, IGT, IAND, IOR, IVar, ISlot, ISafeVar, IFunc, IFuncInt, IFuncCeil, IFuncFloor, IFuncAbs, IFuncSign, IFuncLog, IFuncRound, IFuncMin, IFuncMax, IFuncIf, IFuncSin, IFuncCos, IFuncTan, IFuncASin, IFuncACos, IFuncATan, IFuncSinH, IFuncCosH, IFuncTanH, IFuncASinH, IFuncACosH, IFuncATanH, IPrintFunc};
 #[cfg(feature="eval-builtin")]
 use fasteval::compiler::Instruction::IEvalFunc;
 
@@ -22,7 +22,7 @@ fn slab_overflow() {
     assert_eq!(format!("{:?}", slab),
 "Slab{ exprs:{ 0:Expression { first: EUnaryOp(EPos(ValueI(1))), pairs: [] }, 1:Expression { first: EConstant(1.0), pairs: [ExprPair(EAdd, EConstant(2.0)), ExprPair(EAdd, EConstant(-3.0)), ExprPair(EAdd, EUnaryOp(EParentheses(ExpressionI(0))))] } }, vals:{ 0:EConstant(4.0), 1:EUnaryOp(EPos(ValueI(0))) }, instrs:{} }");
 
-    assert_eq!(Parser::new().parse("1 + 2 + -3 + ( ++++4 )", &mut slab.ps), Err(Error::SlabOverflow));
+    assert_eq!(Parser::new().parse("1 + 2 + -3 + ( ++++4 )", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::SlabOverflow));
 }
 
 #[test]
@@ -209,6 +209,26 @@ fn double_neg() {
     comp_chk("0.5 - -(-(--((((-(x)) - 1.5)))))", IAdd(InstructionI(3), IC::C(0.5)), "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:INeg(InstructionI(0)), 2:IAdd(InstructionI(1), C(-1.5)), 3:INeg(InstructionI(2)) } }", 3.0);
 }
 
+#[test]
+fn checked_folding() {
+    // Constants are only folded if that doesn't overflow, so the result is the same as eval():
+    for expr_str in &["(x * 1e200) * 1e200", "(x + 1e308) + 1e308", "1e308 + x + 1e308", "x * 1e200 * 1e-200"] {
+        let mut slab = Slab::new();
+        let expr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+        let instr = expr.compile(&slab.ps, &mut slab.cs);
+        let mut ns = |name:&str, _args:Vec<f64>| match name {
+            "x" => Some(if expr_str.contains('+') { -1e308 } else { 1e-300 }),
+            _ => None,
+        };
+        let val = instr.eval(&slab, &mut ns).unwrap();
+        assert_eq!(val, expr.eval(&slab, &mut ns).unwrap(), "{}", expr_str);
+        assert!(val.is_finite(), "{}", expr_str);
+    }
+
+    comp_chk_str("(x * 1e200) * 1e200", "IMul(InstructionI(1), C(1e200))", "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:IMul(InstructionI(0), C(1e200)) } }", std::f64::INFINITY);
+    comp_chk_str("x * 1e200 * 1e-200", "IVar(\"x\")", "CompileSlab{ instrs:{} }", 1.0);
+}
+
 #[test]
 fn all_instrs() {
     // IConst:
@@ -466,6 +486,14 @@ fn all_instrs() {
     comp_chk_str("max(-inf, y7, 4.7)", "IFuncMax(InstructionI(0), C(4.7))", "CompileSlab{ instrs:{ 0:IVar(\"y7\") } }", 4.7);
     comp_chk_str("max(-inf, 4.7)", "IConst(4.7)", "CompileSlab{ instrs:{} }", 4.7);
 
+    // IFuncIf
+    comp_chk("1 ? 2 : 3", IConst(2.0), "CompileSlab{ instrs:{} }", 2.0);
+    comp_chk("0 ? 2 : 3", IConst(3.0), "CompileSlab{ instrs:{} }", 3.0);
+    comp_chk("if(1 < 2, x, y)", IVar("x".to_string()), "CompileSlab{ instrs:{} }", 1.0);
+    comp_chk("if(1 > 2, x, y)", IVar("y".to_string()), "CompileSlab{ instrs:{} }", 2.0);
+    comp_chk("w ? 2 : 3", IFuncIf{cond:InstructionI(0), then:IC::C(2.0), otherwise:IC::C(3.0)}, "CompileSlab{ instrs:{ 0:IVar(\"w\") } }", 3.0);
+    comp_chk("x < y ? z : 4", IFuncIf{cond:InstructionI(2), then:IC::I(InstructionI(3)), otherwise:IC::C(4.0)}, "CompileSlab{ instrs:{ 0:IVar(\"x\"), 1:IVar(\"y\"), 2:ILT(I(InstructionI(0)), I(InstructionI(1))), 3:IVar(\"z\") } }", 3.0);
+
     // IFuncSin
     comp_chk("sin(0)", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
     comp_chk("round(0.000001, sin(pi()))", IConst(0.0), "CompileSlab{ instrs:{} }", 0.0);
@@ -535,6 +563,203 @@ fn custom_func() {
     comp_chk("x(1, 1+1, 1+1+1) + 1", IAdd(InstructionI(0), IC::C(1.0)), "CompileSlab{ instrs:{ 0:IFunc { name: \"x\", args: [C(1.0), C(2.0), C(3.0)] } } }", 2.0);
 }
 
+#[test]
+fn slots() {
+    let mut slab = Slab::new();
+    assert_eq!(slab.cs.add_slot("x".to_string()), 0);
+    assert_eq!(slab.cs.add_slot("y".to_string()), 1);
+    assert_eq!(slab.cs.add_slot("x".to_string()), 0);
+
+    let expr = Parser::new().parse("x + y*z", &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr, IAdd(InstructionI(2), IC::I(InstructionI(3))));
+    assert_eq!(format!("{:?}",slab.cs), "CompileSlab{ instrs:{ 0:ISlot { name: \"y\", slot: 1 }, 1:IVar(\"z\"), 2:ISlot { name: \"x\", slot: 0 }, 3:IMul(InstructionI(0), I(InstructionI(1))) } }");
+    assert_eq!(format!("{:?}",instr.var_names(&slab)), r#"{"x", "y", "z"}"#);
+
+    let mut ns = SlotNamespace::new(&[1.0, 2.0], |name:&str, _:Vec<f64>| if name=="z" { Some(3.0) } else { None });
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(7.0));
+
+    // Missing slot values are looked up by name:
+    let mut ns = SlotNamespace::new(&[1.0], |name:&str, _:Vec<f64>| match name { "y" => Some(4.0), "z" => Some(3.0), _ => None });
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(13.0));
+    let mut ns = SlotNamespace::new(&[1.0], |_:&str, _:Vec<f64>| None);
+    assert_eq!(instr.eval(&slab, &mut ns), Err(Error::Undefined("y".to_string())));
+
+    // Plain Namespaces don't provide any slots, so every variable is looked up by name:
+    let mut ns = |name:&str, _:Vec<f64>| match name { "x" => Some(1.0), "y" => Some(2.0), "z" => Some(3.0), _ => None };
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(7.0));
+    let instr = ISlot{name:"x".to_string(), slot:0};
+    assert_eq!(instr.eval(&slab, &mut EmptyNamespace), Err(Error::Undefined("x".to_string())));
+}
+
+#[test]
+fn compiled_expr() {
+    #[cfg(not(feature="unsafe-vars"))]  // Raw pointers are not Send or Sync.
+    {
+        fn assert_send_sync_clone<T:Send+Sync+Clone>() {}
+        assert_send_sync_clone::<CompiledExpr>();
+    }
+
+    let mut slab = Slab::new();
+    // Leave some garbage in the slab that the CompiledExpr doesn't need:
+    let _ = Parser::new().parse("1 + x*y + z", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let instr = Parser::new().parse("(x + 1) * print(\"x:\", -x) + (y ? 1 : z)", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let compiled = CompiledExpr::new(&instr, &slab);
+
+    assert_eq!(format!("{:?}", compiled),
+"CompiledExpr{ instr:IAdd(InstructionI(3), I(InstructionI(6))), slab:Slab{ exprs:{ 0:Expression { first: EUnaryOp(ENeg(ValueI(0))), pairs: [] } }, vals:{ 0:EStdFunc(EVar(\"x\")) }, instrs:{ 0:IVar(\"x\"), 1:IAdd(InstructionI(0), C(1.0)), 2:IPrintFunc(PrintFunc([EStr(\"x:\"), EExpr(ExpressionI(0))])), 3:IMul(InstructionI(1), I(InstructionI(2))), 4:IVar(\"y\"), 5:IVar(\"z\"), 6:IFuncIf { cond: InstructionI(4), then: C(1.0), otherwise: I(InstructionI(5)) } } } }");
+
+    let mut ns = CachedCallbackNamespace::new(|name,_| {
+        match name {
+            "x" => Some(2.0),
+            "y" => Some(0.0),
+            "z" => Some(3.0),
+            _ => None,
+        }
+    });
+    assert_eq!(compiled.eval(&mut ns), instr.eval(&slab, &mut ns));
+    assert_eq!(compiled.eval(&mut ns), Ok(-3.0));
+    assert_eq!(format!("{:?}", compiled.var_names()), r#"{"x", "y", "z"}"#);
+
+    // It doesn't need the original Slab any more, and can be shared between threads:
+    drop(slab);
+    let cloned = compiled.clone();
+    assert_eq!(format!("{:?}", cloned), format!("{:?}", compiled));
+    #[cfg(not(feature="unsafe-vars"))]
+    {
+        let shared = std::sync::Arc::new(compiled);
+        let handles : Vec<_> = (0..4).map(|i| {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let mut ns = |name:&str, _:Vec<f64>| match name { "x" => Some(i as f64), "y" => Some(1.0), _ => None };
+                shared.eval(&mut ns)
+            })
+        }).collect();
+        let vals : Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(vals, vec![Ok(1.0), Ok(-1.0), Ok(-5.0), Ok(-11.0)]);
+    }
+
+    // Constants don't need any Slab data at all:
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("1 + 2", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let compiled = CompiledExpr::new(&instr, &slab);
+    assert_eq!(format!("{:?}", compiled), "CompiledExpr{ instr:IConst(3.0), slab:Slab{ exprs:{}, vals:{}, instrs:{} } }");
+    assert_eq!(compiled.eval(&mut EmptyNamespace), Ok(3.0));
+}
+
+#[test]
+fn eval_batch() {
+    let x : Vec<f64> = (0..1000).map(|i| i as f64).collect();
+    let y : Vec<f64> = (0..1000).map(|i| (i%7) as f64 - 3.0).collect();
+
+    for expr_str in &["x", "x*y + 1", "-x/y", "x % 3 == 0", "y^2 - x", "min(x, y*100, 500)", "max(y, NaN)", "round(10, x/3) + sin(y)",
+                      "x>500 && y", "x<10 || y", "y ? x : -x", "c * x", "y > 0 ? double(x) : double(y)", "log(2, x) + int(y/2)"] {
+        let mut slab = Slab::new();
+        slab.cs.add_slot("x".to_string());
+        slab.cs.add_slot("y".to_string());
+        let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+
+        let mut calls = 0;
+        let mut out = vec![0.0; 1000];
+        fasteval::eval_batch(&instr, &slab, &[&x, &y], &mut out, &mut |name:&str, args:Vec<f64>| match name {
+            "c" => Some(0.5),
+            "double" => { calls+=1; Some(args[0]*2.0) }
+            _ => None,
+        }).unwrap();
+        let batch_calls = calls;
+
+        calls = 0;
+        for row in 0..1000 {
+            let slots = [x[row], y[row]];
+            let mut ns = SlotNamespace::new(&slots, |name:&str, args:Vec<f64>| match name {
+                "c" => Some(0.5),
+                "double" => { calls+=1; Some(args[0]*2.0) }
+                _ => None,
+            });
+            let expect = instr.eval(&slab, &mut ns).unwrap();
+            assert!(out[row]==expect || out[row].is_nan() && expect.is_nan(), "{} row {}: {} != {}", expr_str, row, out[row], expect);
+        }
+        // Custom functions are only called for the taken branches:
+        assert_eq!(batch_calls, calls, "{}", expr_str);
+    }
+
+    let mut slab = Slab::new();
+    slab.cs.add_slot("x".to_string());
+    slab.cs.add_slot("y".to_string());
+    let compiled = CompiledExpr::new(&Parser::new().parse("x + y", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs), &slab);
+    let mut out = [0.0; 3];
+    compiled.eval_batch(&[&x, &y], &mut out, &mut EmptyNamespace).unwrap();
+    assert_eq!(out, [-3.0, -1.0, 1.0]);
+
+    // Slots without a column are looked up by name:
+    compiled.eval_batch(&[&x], &mut out, &mut |name:&str, _:Vec<f64>| if name=="y" { Some(10.0) } else { None }).unwrap();
+    assert_eq!(out, [10.0, 11.0, 12.0]);
+
+    // Errors:
+    assert_eq!(compiled.eval_batch(&[&x], &mut out, &mut EmptyNamespace), Err(Error::Undefined("y".to_string())));
+    assert_eq!(compiled.eval_batch(&[&x, &y[..2]], &mut out, &mut EmptyNamespace), Err(Error::WrongArgs("column 1 has 2 rows, but 3 are needed".to_string())));
+}
+
+#[test]
+fn bytecode() {
+    let mut ns = |name:&str, args:Vec<f64>| match name {
+        "w" => Some(0.0),
+        "x" => Some(1.0),
+        "y" => Some(2.0),
+        "y7" => Some(2.7),
+        "z" => Some(3.0),
+        "foo" => Some(args.iter().sum()),
+        _ => None,
+    };
+
+    // The bytecode must agree with the tree evaluator:
+    for expr_str in &["x", "-x", "!x", "!w", "x + y - z", "x*y/z", "y^z^x", "-y^2", "z % y", "y7 % -x",
+                      "x<y", "x<=y", "x==y", "x!=y", "x>=y", "x>y", "w || y", "x || y", "w && y", "x && y && z",
+                      "w ? y : z", "x ? y : z", "w ? y : x ? z : 4", "if(y-2, 10, 20)",
+                      "int(-y7)", "ceil(y7)", "floor(y7)", "abs(-y7)", "sign(-y7)", "log(z)", "log(y, 8)", "round(y7)", "round(0.5, y7)",
+                      "min(z, y, x)", "max(x, y7, y)", "min(x, NaN)", "sin(x)+cos(x)+tan(x)", "asin(x)+acos(x)+atan(x)",
+                      "sinh(x)+cosh(x)+tanh(x)", "asinh(x)+acosh(x)+atanh(x/2)", "foo(x, y, z*2) + foo()", "pi()*e()"] {
+        let mut slab = Slab::new();
+        let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+        let bc = fasteval::Bytecode::new(&instr, &slab);
+        let expect = instr.eval(&slab, &mut ns).unwrap();
+        let got = bc.eval(&slab, &mut ns).unwrap();
+        assert!(got==expect || got.is_nan() && expect.is_nan(), "{}: {} != {}", expr_str, got, expect);
+        if !expr_str.contains("foo(x") {  // The tree evaluator doesn't report custom function arguments.
+            assert_eq!(bc.var_names(&slab), instr.var_names(&slab));
+        }
+    }
+
+    // Expressions that need a big stack:
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse(&format!("foo({}x)", "x*y, ".repeat(40)), &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(fasteval::Bytecode::new(&instr, &slab).eval(&slab, &mut ns), Ok(81.0));
+
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("x || y ? foo(z, 1) : -w", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let bc = fasteval::Bytecode::new(&instr, &slab);
+    assert_eq!(format!("{:?}", bc.ops()), r#"[Var("x"), JumpIfTrueOrPop(3), Var("y"), JumpIfFalse(8), Var("z"), Const(1.0), Func { name: "foo", nargs: 2 }, Jump(10), Var("w"), Neg]"#);
+
+    // Only the taken branches are evaluated:
+    let mut looked_up = Vec::new();
+    assert_eq!(bc.eval(&slab, &mut |name:&str, args:Vec<f64>| { looked_up.push(name.to_string()); ns(name, args) }), Ok(4.0));
+    assert_eq!(looked_up, vec!["x", "z", "foo"]);
+
+    // Slots, safe variables, and errors work the same way as with the tree evaluator:
+    let mut slab = Slab::new();
+    slab.cs.add_slot("a".to_string());
+    let b = SafeVar::new(5.0);
+    slab.ps.add_safe_var("b".to_string(), &b);
+    let instr = Parser::new().parse("a * b + c", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let bc = fasteval::Bytecode::new(&instr, &slab);
+    let mut ns = SlotNamespace::new(&[3.0], |name:&str, _| if name=="c" { Some(1.0) } else { None });
+    assert_eq!(bc.eval(&slab, &mut ns), Ok(16.0));
+    b.set(6.0);
+    assert_eq!(bc.eval(&slab, &mut ns), Ok(19.0));
+    assert_eq!(format!("{:?}", bc.var_names(&slab)), r#"{"a", "b", "c"}"#);
+    assert_eq!(bc.eval(&slab, &mut EmptyNamespace), Err(Error::Undefined("a".to_string())));
+}
+
 #[test]
 fn eval_macro() {
     fn wrapped() -> Result<(),Error> {
@@ -550,6 +775,15 @@ fn eval_macro() {
         })().unwrap();
         assert_eq!(eval_compiled!(instr, &slab, &mut ns), 5.0);
 
+        let y = SafeVar::new(2.0);
+        slab.ps.add_safe_var("y".to_string(), &y);
+        let expr = Parser::new().parse("y", &mut slab.ps).unwrap().from(&slab.ps);
+        let instr = expr.compile(&slab.ps, &mut slab.cs);
+        assert_eq!(instr, ISafeVar{name:"y".to_string(), var:y.clone()});
+        assert_eq!(eval_compiled_ref!(&instr, &slab, &mut ns), 2.0);
+        y.set(3.0);
+        assert_eq!(eval_compiled!(instr, &slab, &mut ns), 3.0);
+
         #[cfg(feature="unsafe-vars")]
         {
             let x = 1.0;
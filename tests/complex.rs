@@ -0,0 +1,151 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, EmptyNamespace, Complex, ComplexNamespace, NumberEvaler, eval_complex};
+
+fn eval(expr_str:&str) -> Result<Complex,Error> {
+    let mut slab = Slab::new();
+    slab.cs.set_fold_nan(false);
+    let instr = Parser::new().parse(expr_str, &mut slab.ps)?.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    eval_complex(&instr, &slab, &mut |name:&str, args:Vec<Complex>| match name {
+        "z" => Some(Complex::new(3.0, -4.0)),
+        "w" => Some(Complex::new(-0.5, 2.0)),
+        "f" => Some(Complex::new(args[0].re*2.0, args[0].im*2.0)),
+        _ => None,
+    })
+}
+
+fn chk(expr_str:&str, re:f64, im:f64) {
+    let z = eval(expr_str).unwrap();
+    assert!((z.re-re).abs() < 1e-12 && (z.im-im).abs() < 1e-12, "{}: {} != {}", expr_str, z, Complex::new(re, im));
+}
+
+#[test]
+fn arithmetic() {
+    chk("i", 0.0, 1.0);
+    chk("i * i", -1.0, 0.0);
+    chk("sqrt(-1) * exp(i*pi())", 0.0, -1.0);
+    chk("sqrt(-1) * exp(i*pi)", 0.0, -1.0);
+    chk("e^(i*pi/2)", 0.0, 1.0);
+    chk("z + w", 2.5, -2.0);
+    chk("z - w", 3.5, -6.0);
+    chk("z * w", 6.5, 8.0);
+    chk("z / w", -2.235294117647059, -0.9411764705882352);
+    chk("-z", -3.0, 4.0);
+    chk("1 / i", 0.0, -1.0);
+    chk("f(z) + 1", 7.0, -8.0);
+    chk("re(z) + im(z)*10", -37.0, 0.0);
+    chk("arg(i) * 2", std::f64::consts::PI, 0.0);
+    chk("conj(z)", 3.0, 4.0);
+    chk("abs(z)", 5.0, 0.0);
+    chk("sign(z)", 0.6, -0.8);
+    chk("int(w) + ceil(w) + floor(w)", -1.0, 6.0);
+    chk("round(w)", -1.0, 2.0);  // -0.5 rounds away from zero.
+    chk("round(0.5, z*0.3)", 1.0, -1.0);
+}
+
+#[test]
+fn powers_and_logs() {
+    chk("(-4)^0.5", 0.0, 2.0);
+    chk("(-8)^(1/3)", 1.0, 1.7320508075688772);
+    chk("(-2)^3", -8.0, 0.0);
+    chk("i^2", -1.0, 0.0);
+    chk("i^i", (-std::f64::consts::FRAC_PI_2).exp(), 0.0);
+    chk("0^z", 0.0, 0.0);
+    chk("z^0", 1.0, 0.0);
+    chk("e()^(i*pi()/2)", 0.0, 1.0);
+    chk("log(e(), -1)", 0.0, std::f64::consts::PI);
+    chk("log(-100)", 2.0, 1.3643763538418414);
+    chk("log(2, 8)", 3.0, 0.0);
+    chk("exp(log(e(), z))", 3.0, -4.0);
+    chk("sqrt(z)^2", 3.0, -4.0);
+}
+
+#[test]
+fn trig() {
+    // Inverse functions, both inside and outside of the real domain:
+    for expr_str in &["sin(asin(z))", "cos(acos(z))", "tan(atan(z))", "sinh(asinh(z))", "cosh(acosh(z))", "tanh(atanh(z))"] {
+        chk(expr_str, 3.0, -4.0);
+    }
+    chk("asin(2)", std::f64::consts::FRAC_PI_2, 1.3169578969248166);
+    chk("asin(-2)", -std::f64::consts::FRAC_PI_2, 1.3169578969248166);
+    chk("acos(2)", 0.0, -1.3169578969248166);
+    chk("acos(-2)", std::f64::consts::PI, -1.3169578969248166);
+    chk("acosh(-2)", 1.3169578969248166, std::f64::consts::PI);
+    chk("acosh(0)", 0.0, std::f64::consts::FRAC_PI_2);
+    chk("atanh(2)", 0.5493061443340549, std::f64::consts::FRAC_PI_2);
+    chk("atanh(-2)", -0.5493061443340549, std::f64::consts::FRAC_PI_2);
+    chk("sin(i)", 0.0, 1.1752011936438014);
+    chk("cos(i)", 1.5430806348152437, 0.0);
+    chk("cosh(i*pi())", -1.0, 0.0);
+}
+
+#[test]
+fn logic() {
+    chk("z == conj(conj(z))", 1.0, 0.0);
+    chk("z != conj(z)", 1.0, 0.0);
+    chk("!i", 0.0, 0.0);
+    chk("i && z", 3.0, -4.0);
+    chk("0 || i", 0.0, 1.0);
+    chk("i ? 1 : 2", 1.0, 0.0);
+    chk("re(z) > 1 ? z : w", 3.0, -4.0);
+    chk("min(re(z), im(z), 1)", -4.0, 0.0);
+    chk("7 % 3", 1.0, 0.0);
+
+    assert_eq!(eval("z < w"), Err(Error::WrongArgs("cannot compare complex numbers 3-4i and -0.5+2i".to_string())));
+    assert_eq!(eval("max(z, 1)"), Err(Error::WrongArgs("cannot compare complex numbers 3-4i and 1".to_string())));
+    assert_eq!(eval("z % 2"), Err(Error::WrongArgs("cannot calculate the remainder of complex numbers 3-4i and 2".to_string())));
+    assert_eq!(eval("y + 1"), Err(Error::Undefined("y".to_string())));
+}
+
+#[test]
+fn constants() {
+    // The Namespace takes precedence over the `pi` and `e` constants:
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("e + pi", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(eval_complex(&instr, &slab, &mut |name:&str, _:Vec<Complex>| if name=="e" { Some(Complex::new(0.0, 1.0)) } else { None }),
+               Ok(Complex::new(std::f64::consts::PI, 1.0)));
+    assert_eq!(eval_complex(&instr, &slab, &mut EmptyNamespace), Ok(Complex::from(std::f64::consts::E + std::f64::consts::PI)));
+
+    let expr_ref = Parser::new().parse("2*pi", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr_ref.eval_number(&slab, &mut ComplexNamespace(EmptyNamespace)), Ok(Complex::from(2.0*std::f64::consts::PI)));
+}
+
+// Real inputs give the same results as the f64 evaluator.
+#[test]
+fn real() {
+    for expr_str in &["1 + 2*3 - 4/5 % 6 ^ 2", "2^0.5 + 3^-2", "log(5) + log(2, 10)", "round(0.01, 2/3)", "abs(-3) + sign(-2)",
+                      "sin(1) + cos(2) + tan(3) + asin(0.5) + acos(0.25) + atan(4)",
+                      "sinh(1) + cosh(2) + tanh(3) + asinh(4) + acosh(5) + atanh(0.5)",
+                      "(1 < 2) + (2 <= 2) + (3 > 4) + (4 >= 5) + (1 == 1) + (1 != 1)", "min(3, 1, 2) * max(3, 1, 2)",
+                      "int(2.5) + ceil(2.5) + floor(-2.5)"] {
+        let mut slab = Slab::new();
+        slab.cs.set_fold_nan(false);
+        let expr_ref = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+        let expect = expr_ref.eval(&slab, &mut EmptyNamespace).unwrap();
+        let got = expr_ref.eval_number(&slab, &mut ComplexNamespace(EmptyNamespace)).unwrap();
+        assert_eq!(got, Complex::from(expect), "{}", expr_str);
+
+        let instr = expr_ref.compile(&slab.ps, &mut slab.cs);
+        let expect = instr.eval(&slab, &mut EmptyNamespace).unwrap();
+        assert_eq!(eval_complex(&instr, &slab, &mut EmptyNamespace), Ok(Complex::from(expect)), "{}", expr_str);
+    }
+}
+
+#[test]
+fn folding() {
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("(-4)^0.5", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert!(eval_complex(&instr, &slab, &mut EmptyNamespace).unwrap().re.is_nan());
+
+    // Constant folding is still used for the parts that are real:
+    slab.cs.set_fold_nan(false);
+    let instr = Parser::new().parse("(-4)^0.5 + 4^0.5 + 2*3", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?}", instr), "IAdd(InstructionI(0), C(8.0))");
+    assert_eq!(eval_complex(&instr, &slab, &mut EmptyNamespace), Ok(Complex::new(8.0, 2.0)));
+}
+
+#[test]
+fn display() {
+    assert_eq!(Complex::new(1.5, 0.0).to_string(), "1.5");
+    assert_eq!(Complex::new(0.0, -2.0).to_string(), "-2i");
+    assert_eq!(Complex::new(1.0, 2.0).to_string(), "1+2i");
+    assert_eq!(Complex::new(1.0, -2.0).to_string(), "1-2i");
+}
@@ -0,0 +1,136 @@
+use fasteval::{Parser, Error, Slab, EmptyNamespace, Number, NumberEvaler, Decimal, DecimalContext, Rounding, eval_decimal};
+use fasteval::decimal::MAX_SCALE;
+
+fn eval_ctx(expr_str:&str, ctx:DecimalContext) -> Result<Decimal,Error> {
+    let mut slab = Slab::new();
+    let expr_ref = Parser::new().parse(expr_str, &mut slab.ps)?.from(&slab.ps);
+    eval_decimal(expr_ref, &slab, &mut |name:&str, args:Vec<Decimal>| match name {
+        "price" => Some(Decimal::new(1999, 2)),
+        "qty" => Some(Decimal::from(3)),
+        "twice" => args[0].add(args[0]).ok(),
+        _ => None,
+    }, ctx)
+}
+
+fn eval(expr_str:&str) -> Result<Decimal,Error> { eval_ctx(expr_str, DecimalContext::default()) }
+
+fn chk(expr_str:&str, expect:&str) {
+    assert_eq!(eval(expr_str).map(|d| d.to_string()), Ok(expect.to_string()), "{}", expr_str);
+}
+
+#[test]
+fn exact() {
+    chk("0.1 + 0.2", "0.3");
+    chk("0.1 + 0.2 == 0.3", "1");
+    chk("price * 1.075", "21.48925");
+    chk("price * qty - 0.97", "59.00");
+    chk("1.1 * 3", "3.3");
+    chk("-price", "-19.99");
+    chk("1.5k + 2m", "1500.002");
+    chk("2.5e-3 * 4", "0.0100");
+    chk("1.1 ^ 2", "1.21");
+    chk("2 ^ 10", "1024");
+    chk("2 ^ -2", "0.250000000000000000");
+    chk("7.5 % 2", "1.5");
+    chk("-7.5 % 2", "-1.5");
+    chk("twice(price)", "39.98");
+    chk("int(-2.7) + ceil(2.1) + floor(-2.1) + abs(-1.25) + sign(-3)", "-1.75");
+    chk("min(price, 20, 19.995)", "19.99");
+    chk("max(price, 20, 19.995)", "20");
+    chk("price > 19.989 && price < 19.991", "1");
+    chk("price == 19.990", "1");
+    chk("if(price > 10, 1, 2)", "1");
+
+    // Literals with more digits than an f64 are exact too:
+    chk("12345678901234567.89 - 12345678901234000", "567.89");
+    chk("12345678901234567.89 - 12345678901234567", "0.89");
+    chk("0.123456789012345678 * 1000", "123.456789012345678000");
+}
+
+#[test]
+fn division_and_scale() {
+    chk("10 / 3", "3.333333333333333333");
+    chk("2 / 3", "0.666666666666666667");
+    chk("1 / 4 * 4", "1.000000000000000000");
+    let ctx = DecimalContext::new(2, Rounding::HalfEven);
+    assert_eq!(eval_ctx("price * 1.075", ctx).unwrap().to_string(), "21.49");
+    assert_eq!(eval_ctx("0.125 * 1", ctx).unwrap().to_string(), "0.12");
+    assert_eq!(eval_ctx("0.135 * 1", ctx).unwrap().to_string(), "0.14");
+    assert_eq!(eval_ctx("10 / 4", DecimalContext::new(0, Rounding::HalfEven)).unwrap().to_string(), "2");
+    assert_eq!(eval_ctx("10 / 4", DecimalContext::new(0, Rounding::HalfUp)).unwrap().to_string(), "3");
+
+    // Intermediate values wider than 128 bits:
+    chk("(1/3) * (1/3)", "0.111111111111111111");
+    chk("100000000000000000000 * 0.000000000000000001", "100.000000000000000000");
+}
+
+#[test]
+fn rounding_modes() {
+    for &(mode, expect) in &[(Rounding::Down,     ["2.5", "-2.5", "2.5", "-2.5"]),
+                             (Rounding::Up,       ["2.6", "-2.6", "2.6", "-2.6"]),
+                             (Rounding::Floor,    ["2.5", "-2.6", "2.5", "-2.6"]),
+                             (Rounding::Ceiling,  ["2.6", "-2.5", "2.6", "-2.5"]),
+                             (Rounding::HalfUp,   ["2.6", "-2.6", "2.5", "-2.6"]),
+                             (Rounding::HalfDown, ["2.5", "-2.5", "2.5", "-2.6"]),
+                             (Rounding::HalfEven, ["2.6", "-2.6", "2.5", "-2.6"])] {
+        let ctx = DecimalContext::new(1, mode);
+        let got : Vec<String> = ["2.55 + 0", "-2.55 + 0", "2.54 + 0", "-2.56 + 0"].iter().map(|e| eval_ctx(e, ctx).unwrap().to_string()).collect();
+        assert_eq!(got, expect, "{:?}", mode);
+    }
+    let ctx = DecimalContext::new(1, Rounding::HalfEven);
+    assert_eq!(eval_ctx("2.45 + 0", ctx).unwrap().to_string(), "2.4");
+    assert_eq!(eval_ctx("2.451 + 0", ctx).unwrap().to_string(), "2.5");
+}
+
+#[test]
+fn round() {
+    // round() uses the rounding mode of the context:
+    for &(mode, expect) in &[(Rounding::HalfUp, "21.49"), (Rounding::Down, "21.48"), (Rounding::HalfEven, "21.49")] {
+        assert_eq!(eval_ctx("round(0.01, price * 1.075)", DecimalContext::new(MAX_SCALE, mode)).unwrap().to_string(), expect);
+    }
+    assert_eq!(eval_ctx("round(0.01, 2.345)", DecimalContext::new(MAX_SCALE, Rounding::HalfEven)).unwrap().to_string(), "2.34");
+    assert_eq!(eval_ctx("round(0.01, 2.345)", DecimalContext::new(MAX_SCALE, Rounding::HalfUp)).unwrap().to_string(), "2.35");
+    chk("round(0.05, 1.23)", "1.25");
+    chk("round(2.5)", "2");
+    chk("round(3.5)", "4");
+    chk("round(-0.25, 1)", "1.00");
+    assert_eq!(eval("round(0, 1)"), Err(Error::WrongArgs("round() modulus must not be zero".to_string())));
+}
+
+#[test]
+fn errors() {
+    assert_eq!(eval("1 / (price - 19.99)"), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("1 % 0"), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("0 ^ -1"), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("10 ^ 39"), Err(Error::Overflow));
+    assert_eq!(eval("(0 - 1) ^ 0.5"), Err(Error::WrongArgs("NaN can't be converted to a decimal".to_string())));
+    assert_eq!(eval("y"), Err(Error::Undefined("y".to_string())));
+    assert_eq!(eval_ctx("1", DecimalContext::new(19, Rounding::HalfEven)), Err(Error::WrongArgs("decimal scale 19 is greater than the maximum of 18".to_string())));
+}
+
+#[test]
+fn inexact_functions() {
+    chk("pi()", "3.141592653589793");
+    chk("2 ^ 0.5", "1.4142135623730951");
+    chk("log(2, 10)", "3.321928094887362");
+    assert_eq!(eval_ctx("sin(1)", DecimalContext::new(4, Rounding::HalfEven)).unwrap().to_string(), "0.8415");
+}
+
+#[test]
+fn values() {
+    assert_eq!("1.50".parse::<Decimal>(), Ok(Decimal::new(15, 1)));
+    assert_eq!("-1.2e-3".parse::<Decimal>().unwrap().to_string(), "-0.0012");
+    assert_eq!("12e2".parse::<Decimal>().unwrap().to_string(), "1200");
+    assert_eq!("1.x".parse::<Decimal>(), Err(Error::ParseF64("1.x".to_string())));
+    assert_eq!(Decimal::new(-5, 3).to_string(), "-0.005");
+    assert_eq!(Decimal::new(1500, 3).normalize().to_string(), "1.5");
+    assert_eq!(Decimal::new(-12345, 3).round_to(1, Rounding::HalfUp).to_string(), "-12.3");
+    assert!(Decimal::new(1, 0) > Decimal::new(99, 2));
+    assert_eq!(Decimal::new(1075, 3).to_f64(), 1.075);
+
+    // eval_number() uses the default context outside of eval_decimal():
+    let mut slab = Slab::new();
+    let expr_ref = Parser::new().parse("1 / 8", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr_ref.eval_number(&slab, &mut EmptyNamespace), Ok(Decimal::new(125, 3)));
+    assert_eq!(DecimalContext::current(), DecimalContext::default());
+}
@@ -0,0 +1,114 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, Instruction::IConst, derive};
+
+use std::collections::BTreeMap;
+
+// Compares the symbolic derivative to a numerical one, at several points.
+fn chk(expr_str:&str) {
+    for &x in &[-1.7, -0.3, 0.4, 0.9, 2.5] {
+        let mut slab = Slab::with_capacity(1024);
+        let mut ns = BTreeMap::<String,f64>::new();
+        ns.insert("y".to_string(), 1.5);
+
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        let deriv_i = derive(expr_i, "x", &mut slab.ps).unwrap();
+
+        let mut f = |x:f64| {
+            ns.insert("x".to_string(), x);
+            expr_i.from(&slab.ps).eval(&slab, &mut ns).unwrap()
+        };
+        let h = 1e-6;
+        let expect = (f(x+h) - f(x-h)) / (2.0*h);
+
+        ns.insert("x".to_string(), x);
+        let got = deriv_i.from(&slab.ps).eval(&slab, &mut ns).unwrap();
+        let compiled = deriv_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs).eval(&slab, &mut ns).unwrap();
+
+        if expect.is_nan() {
+            assert!(got.is_nan(), "{} at x={}: expected NaN, got {}", expr_str, x, got);
+        } else {
+            assert!((got-expect).abs() <= 1e-5 * expect.abs().max(1.0), "{} at x={}: expected {}, got {}", expr_str, x, expect, got);
+        }
+        assert!(compiled==got || compiled.is_nan() && got.is_nan() || (compiled-got).abs() <= 1e-12 * got.abs(), "{} at x={}: compiled {} != {}", expr_str, x, compiled, got);
+    }
+}
+
+#[test]
+fn rules() {
+    chk("3");
+    chk("x");
+    chk("y");
+    chk("-x");
+    chk("+x");
+    chk("x + y + 2*x");
+    chk("x - y - 3*x + 1");
+    chk("x * y * x * x");
+    chk("1 / x / y");
+    chk("x / (x + 3)");
+    chk("y / x * x^2");
+    chk("x^3 - 2*x^2 + x - 1");
+    chk("2^x");
+    chk("y^x^2");
+    chk("(x^2+1)^x");
+    chk("x % 0.7");
+    chk("(x*3) % (x+6)");
+    chk("(x > 0) + !x * 3");
+    chk("x > 0 && x^2 || -x");
+    chk("x > 1 ? x^3 : 2*x");
+    chk("if(x < 0, -x, sin(x))");
+
+    chk("int(x) + ceil(x) + floor(x) + round(0.1, x) + sign(x)");
+    chk("abs(x^3)");
+    chk("log(x^2)");
+    chk("log(2, x^2 + 1)");
+    chk("log(x^2 + 3, 7)");
+    chk("log(x^2 + 3, x^4 + 1)");
+    chk("min(x, 1 - x, 0.5)");
+    chk("max(x^2, y, 2*x)");
+    chk("sin(x) * cos(2*x) + tan(x/3)");
+    chk("asin(x/3) + acos(x/4) + atan(x)");
+    chk("sinh(x) + cosh(x) + tanh(x)");
+    chk("asinh(x) + acosh(x^2 + 2) + atanh(x/3)");
+    chk("e()^x * pi()");
+    chk("x * print(\"debug:\", x^2)");
+}
+
+#[test]
+fn folding() {
+    let mut slab = Slab::with_capacity(256);
+
+    let expr_i = Parser::new().parse("3*x + 2*y + 7", &mut slab.ps).unwrap();
+    let deriv_i = derive(expr_i, "x", &mut slab.ps).unwrap();
+    assert_eq!(deriv_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs), IConst(3.0));
+
+    let deriv_i = derive(expr_i, "z", &mut slab.ps).unwrap();
+    assert_eq!(deriv_i.from(&slab.ps).compile(&slab.ps, &mut slab.cs), IConst(0.0));
+
+    // Second derivatives:
+    let expr_i = Parser::new().parse("x^3", &mut slab.ps).unwrap();
+    let d1 = derive(expr_i, "x", &mut slab.ps).unwrap();
+    let d2 = derive(d1, "x", &mut slab.ps).unwrap();
+    let d3 = derive(d2, "x", &mut slab.ps).unwrap();
+    assert_eq!(d3.from(&slab.ps).compile(&slab.ps, &mut slab.cs), IConst(6.0));
+}
+
+#[test]
+fn errors() {
+    let mut slab = Slab::with_capacity(256);
+    let ns = |name:&str, args:Vec<f64>| match name {
+        "f" => Some(args.iter().sum::<f64>()),
+        _ => None,
+    };
+
+    // Custom functions are fine if their arguments don't depend on the variable:
+    let expr_i = Parser::new().parse("f(1, y) * x", &mut slab.ps).unwrap();
+    let deriv_i = derive(expr_i, "x", &mut slab.ps).unwrap();
+    assert_eq!(deriv_i.from(&slab.ps).eval(&slab, &mut |name:&str, args:Vec<f64>| if name=="y" { Some(3.0) } else { ns(name, args) }), Ok(4.0));
+
+    let expr_i = Parser::new().parse("1 + f(2, x)", &mut slab.ps).unwrap();
+    assert_eq!(derive(expr_i, "x", &mut slab.ps), Err(Error::NotDifferentiable("custom function `f`".to_string())));
+    assert_eq!(Error::NotDifferentiable("custom function `f`".to_string()).to_string(), "cannot differentiate custom function `f`");
+
+    let mut slab = Slab::with_capacity(4);
+    let expr_i = Parser::new().parse("x^2", &mut slab.ps).unwrap();
+    assert_eq!(derive(expr_i, "x", &mut slab.ps), Err(Error::SlabOverflow));
+}
@@ -0,0 +1,53 @@
+use fasteval::{Error, Slab, Parser, Evaler, EmptyNamespace};
+
+fn parse_err(expr_str:&str) -> Error {
+    Parser::new().parse(expr_str, &mut Slab::new().ps).unwrap_err()
+}
+
+#[test]
+fn display() {
+    assert_eq!(Error::Undefined("x".to_string()).to_string(), "undefined variable `x`");
+    assert_eq!(Error::ParseF64("1e+".to_string()).to_string(), "invalid number `1e+`");
+    assert_eq!(Error::EofWhileParsing("string".to_string()).to_string(), "unexpected end of input while parsing string");
+    assert_eq!(Error::BudgetExceeded.to_string(), "evaluation budget exceeded");
+
+    assert_eq!(parse_err("log(1,2,3)").to_string(), "function `log` takes 1 or 2 arguments: log(x) or log(base,x) at line 1, column 1");
+    assert_eq!(parse_err("sin()").to_string(), "function `sin` takes 1 argument at line 1, column 1");
+    assert_eq!(parse_err("(1 + 2").to_string(), "unexpected end of input while parsing parentheses at line 1, column 7");
+    assert_eq!(parse_err("(1 + 2]").to_string(), "expected ')' at line 1, column 7");
+    assert_eq!(parse_err("1 + 2 )").to_string(), "unexpected input after the end of the expression: `)` at line 1, column 7");
+    assert_eq!(parse_err(r#"print("a\qb")"#).to_string(), "invalid escape sequence `\\q` in string at line 1, column 9");
+
+    let mut slab = Slab::new();
+    let err = Parser::new().parse("x + 1", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut EmptyNamespace).unwrap_err();
+    assert_eq!(err.to_string(), "undefined variable `x`");
+}
+
+#[test]
+fn render() {
+    assert_eq!(parse_err("1 + * 2").render("1 + * 2"),
+"expected a value at line 1, column 5
+1 + * 2
+    ^");
+
+    let expr_str = "1 +\n\t2 +\n\t* 3";
+    assert_eq!(parse_err(expr_str).render(expr_str),
+"expected a value at line 3, column 2
+\t* 3
+\t^");
+
+    let expr_str = r#"print("µ") + 2 )"#;
+    assert_eq!(parse_err(expr_str).render(expr_str),
+r#"unexpected input after the end of the expression: `)` at line 1, column 16
+print("µ") + 2 )
+               ^"#);
+
+    let expr_str = "1 +\n";
+    assert_eq!(parse_err(expr_str).render(expr_str),
+"unexpected end of input while parsing value at line 2, column 1
+
+^");
+
+    // Errors without a location are rendered as a plain message:
+    assert_eq!(Error::Undefined("x".to_string()).render("x + 1"), "undefined variable `x`");
+}
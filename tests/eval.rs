@@ -1,4 +1,4 @@
-use fasteval::{Evaler, Error, Slab, Cached, EmptyNamespace, CachedCallbackNamespace, Parser};
+use fasteval::{Evaler, Compiler, NumberEvaler, Error, Slab, Cached, EmptyNamespace, CachedCallbackNamespace, Parser, SafeVar};
 use fasteval::bool_to_f64;
 
 use std::mem;
@@ -228,6 +228,43 @@ fn aaa_basics() {
 //     assert_eq!({ ns.push(); let out=TestEvaler{}.eval(&slab, &mut ns); ns.pop(); out }.unwrap(), 1.111);
 // }
 
+#[test]
+fn ternary() {
+    let mut slab = Slab::new();
+    let mut ns = BTreeMap::<String,f64>::new();
+    ns.insert("x".to_string(), 1.0);
+    ns.insert("y".to_string(), 2.0);
+
+    assert_eq!(Parser::new().parse("x < y ? 10 : 20", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(10.0));
+    assert_eq!(Parser::new().parse("x > y ? 10 : 20", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(20.0));
+    assert_eq!(Parser::new().parse("0 ? 1 : 0 ? 2 : 3", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(3.0));
+    assert_eq!(Parser::new().parse("1 ? 0 ? 2 : 3 : 4", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(3.0));
+    assert_eq!(Parser::new().parse("(x ? 2 : 3) * 10", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(20.0));
+    assert_eq!(Parser::new().parse("if(x-1, 2, 3) * 10", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(30.0));
+
+    // Only the taken branch is evaluated:
+    assert_eq!(Parser::new().parse("x ? y : a", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("if(x, y, a)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Ok(2.0));
+    assert_eq!(Parser::new().parse("if(0, y, a)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns), Err(Error::Undefined("a".to_string())));
+
+    // ...but var_names() reports both branches:
+    assert_eq!(format!("{:?}", Parser::new().parse("a ? b : c", &mut slab.ps).unwrap().from(&slab.ps).var_names(&slab)), r#"{"a", "b", "c"}"#);
+}
+
+#[test]
+fn printf() {
+    let mut slab = Slab::new();
+    let mut ns = EmptyNamespace;
+    // print() returns the last value in printf mode too:
+    let expr = Parser::new().parse(r#"print("%5.2f|%-4d|%s|%x%%", 3.14159, 42, "str", 255) + 1"#, &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval(&slab, &mut ns), Ok(256.0));
+    assert_eq!(expr.compile(&slab.ps, &mut slab.cs).eval(&slab, &mut ns), Ok(256.0));
+    assert_eq!(expr.eval_number(&slab, &mut ns), Ok(256.0f32));
+
+    let expr = Parser::new().parse(r#"print("%d", 0.5)"#, &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval(&slab, &mut ns), Err(Error::WrongArgs("print() conversion `%d` requires an integer, but got 0.5".to_string())));
+}
+
 #[test]
 fn corners() {
     let mut slab = Slab::new();
@@ -327,6 +364,65 @@ fn custom_func() {
         "Ok(NaN)");
 }
 
+#[test]
+fn nested_lookup() {
+    // A Namespace callback that evaluates another expression during a lookup:
+    let mut ns = CachedCallbackNamespace::new(|name,args| {
+        match name {
+            "inner" => {
+                let mut slab = Slab::new();
+                let mut ns = CachedCallbackNamespace::new(|name,args| {
+                    match name {
+                        "y" => Some(args.get(0).unwrap_or(&0.0) + 2.0),
+                        _ => None,
+                    }
+                });
+                Parser::new().parse("y(1) + y(2)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns).ok()
+            }
+            "x" => Some(args.get(0).unwrap_or(&0.0) * 10.0),
+            _ => None,
+        }
+    });
+    let mut slab = Slab::new();
+    assert_eq!(
+        Parser::new().parse("x(inner) + x(1)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns),
+        Ok(80.0));
+}
+
+#[test]
+fn safe_var() {
+    let mut slab = Slab::new();
+
+    let sa = SafeVar::new(1.23);
+    let sb = SafeVar::new(4.56);
+    slab.ps.add_safe_var("sa".to_string(), &sa);
+    slab.ps.add_safe_var("sb".to_string(), &sb);
+
+    let mut ns = EmptyNamespace;
+
+    assert_eq!(
+        Parser::new().parse("sa + sb + 5", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns),
+        Ok(10.79));
+
+    sa.set(2.23);
+    sb.set(6.56);
+    let expr = Parser::new().parse("sa + sb() + 5", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval(&slab, &mut ns), Ok(13.79));
+    assert_eq!(format!("{:?}", expr.var_names(&slab)), r#"{"sa", "sb"}"#);
+
+    // Compiled expressions see new values too:
+    let instr = Parser::new().parse("sa * sb", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    sa.set(2.0);
+    sb.set(3.0);
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(6.0));
+    assert_eq!(format!("{:?}", instr.var_names(&slab)), r#"{"sa", "sb"}"#);
+
+    // A SafeVar with arguments is a normal custom function:
+    assert_eq!(
+        Parser::new().parse("sa(1)", &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut ns),
+        Err(Error::Undefined("sa".to_string())));
+}
+
 #[test]
 #[cfg(feature="unsafe-vars")]
 fn unsafe_var() {
@@ -136,3 +136,38 @@ fn custom_vector_funcs() {
     assert_eq!(val, 12.8);
 }
 
+
+#[test]
+fn print_sink() {
+    use fasteval::{Parser, Slab, Evaler, Compiler, NumberEvaler, Bytecode, CompiledExpr, SinkNamespace, StderrSink, NullSink, Complex};
+
+    let cb = |name:&str, _:Vec<f64>| match name { "x" => Some(3.0), _ => None };
+    let mut slab = Slab::new();
+    Parser::new().define("f(t) = t + print(\"in f\")", &mut slab.ps).unwrap();
+    let expr = Parser::new().parse("y = x * 2; print(\"%.1f\", y) + f(y)", &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+
+    let mut lines = Vec::<String>::new();
+    assert_eq!(expr.eval(&slab, &mut SinkNamespace::new(cb, &mut lines)), Ok(12.0));
+    assert_eq!(instr.eval(&slab, &mut SinkNamespace::new(cb, &mut lines)), Ok(12.0));
+    assert_eq!(Bytecode::new(&instr, &slab).eval(&slab, &mut SinkNamespace::new(cb, &mut lines)), Ok(12.0));
+    assert_eq!(CompiledExpr::new(&instr, &slab).eval(&mut SinkNamespace::new(cb, &mut lines)), Ok(12.0));
+    assert_eq!(expr.eval_number(&slab, &mut SinkNamespace::new(|_:&str, _:Vec<i64>| Some(3), &mut lines)), Ok(12i64));
+    assert_eq!(lines, ["6.0", "in f"].iter().cycle().take(10).map(|s| s.to_string()).collect::<Vec<_>>());
+
+    // Batches print once per row, and the other evaluators print too:
+    let mut ns = SinkNamespace::new(cb, Vec::new());
+    let mut out = [0.0; 2];
+    fasteval::eval_batch(&instr, &slab, &[], &mut out, &mut ns).unwrap();
+    assert_eq!(ns.sink.len(), 4);
+    let dual = fasteval::eval_grad(&instr, &slab, &["z"], &mut ns).unwrap();
+    assert_eq!(dual.val, 12.0);
+    assert_eq!(ns.sink.len(), 6);
+    let mut lines = Vec::<String>::new();
+    let val = fasteval::eval_complex(&instr, &slab, &mut SinkNamespace::new(|_:&str, _:Vec<Complex>| Some(Complex::from(3.0)), &mut lines)).unwrap();
+    assert_eq!((val, lines.len()), (Complex::from(12.0), 2));
+
+    // The built-in sinks:
+    assert_eq!(expr.eval(&slab, &mut SinkNamespace::new(cb, NullSink)), Ok(12.0));
+    assert_eq!(expr.eval(&slab, &mut SinkNamespace::new(cb, StderrSink)), Ok(12.0));
+}
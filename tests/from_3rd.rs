@@ -37,7 +37,8 @@ fn chk_ok(expr_str:&str, expect_compile_str:&str, expect_slab_str:&str, expect_e
 fn chk_perr(expr_str:&str, expect_err:Error) {
     let mut slab = Slab::new();
     let res = Parser::new().parse(expr_str, &mut slab.ps);
-    assert_eq!(res, Err(expect_err));
+    assert!(res.as_ref().err().and_then(|err| err.pos()).is_some());
+    assert_eq!(res.map_err(|err| err.kind().clone()), Err(expect_err));
 }
 
 fn chk_eerr(expr_str:&str, expect_err:Error) {
@@ -112,14 +113,14 @@ fn overflow_stack() {
                       expr_depth_limit:31}.parse(
                         from_utf8(&[b'('; 32]).unwrap(),
                         &mut Slab::new().ps
-                      ),
+                      ).map_err(|err| err.kind().clone()),
                Err(Error::TooDeep));
 
     assert_eq!(Parser{expr_len_limit:8,
                       expr_depth_limit:fasteval::parser::DEFAULT_EXPR_DEPTH_LIMIT}.parse(
                         from_utf8(&[b'('; 32]).unwrap(),
                         &mut Slab::new().ps
-                      ),
+                      ).map_err(|err| err.kind().clone()),
                Err(Error::TooLong));
 }
 
@@ -0,0 +1,207 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, EmptyNamespace, NumberEvaler, Bytecode, Interval, eval_interval, eval_grad, eval_batch, derive};
+use std::collections::BTreeMap;
+
+mod common;
+
+fn ns(name:&str, args:Vec<f64>) -> Option<f64> {
+    match name {
+        "x" => Some(10.0),
+        "scale" => Some(2.0),
+        "sum" => Some(args.into_iter().sum()),
+        _ => None,
+    }
+}
+
+fn slab_with(defs:&[&str]) -> Slab {
+    let mut slab = Slab::new();
+    for def in defs {
+        Parser::new().define(def, &mut slab.ps).unwrap();
+    }
+    slab
+}
+
+fn eval(slab:&mut Slab, expr_str:&str) -> Result<f64,Error> {
+    common::eval_agree(slab, expr_str, &mut ns)
+}
+
+#[test]
+fn calls() {
+    let mut slab = slab_with(&["f(x, y) = x^2 + y", "g(a) = f(a, 1) * scale", "two() = 2", "h[t] = t + x"]);
+    assert_eq!(eval(&mut slab, "f(2, 3) * 4"), Ok(28.0));
+    assert_eq!(eval(&mut slab, "f(x, 1)"), Ok(101.0));
+    assert_eq!(eval(&mut slab, "f(f(1, 1), f(0, 0))"), Ok(4.0));
+    assert_eq!(eval(&mut slab, "g(3)"), Ok(20.0));
+    assert_eq!(eval(&mut slab, "two() + two"), Ok(4.0));
+    assert_eq!(eval(&mut slab, "h(1)"), Ok(11.0));  // 'x' is not a parameter of h(), so it comes from the Namespace.
+    assert_eq!(eval(&mut slab, "sum(f(1, 2), 4)"), Ok(7.0));
+
+    // Parameters shadow the Namespace and user-defined functions, but only inside the body:
+    Parser::new().define("k(two, x) = two * x + f(x, 0)", &mut slab.ps).unwrap();
+    assert_eq!(eval(&mut slab, "k(3, 4)"), Ok(28.0));
+
+    // The free variables of a function don't see the parameters of its caller:
+    Parser::new().define("outer(x) = h(0) + x", &mut slab.ps).unwrap();
+    assert_eq!(eval(&mut slab, "outer(1)"), Ok(11.0));
+
+    // Redefinition replaces the old function, even for expressions that have already been parsed:
+    let expr_i = Parser::new().parse("f(2, 3)", &mut slab.ps).unwrap();
+    Parser::new().define("f(x, y) = x - y", &mut slab.ps).unwrap();
+    assert_eq!(expr_i.from(&slab.ps).eval(&slab, &mut ns), Ok(-1.0));
+    assert_eq!(eval(&mut slab, "g(3)"), Ok(4.0));
+}
+
+#[test]
+fn slab() {
+    let mut slab = slab_with(&["sq(x) = x * x"]);
+    assert_eq!(slab.ps.get_func("sq").map(|f| f.params().to_vec()), Some(vec!["x".to_string()]));
+    assert_eq!(slab.ps.get_func("sin"), None);
+
+    // parse() clears the Slab, but keeps the definitions:
+    assert_eq!(eval(&mut slab, "sq(3)"), Ok(9.0));
+    assert_eq!(eval(&mut slab, "sq(4)"), Ok(16.0));
+    slab.clear();
+    assert_eq!(eval(&mut slab, "sq(5)"), Ok(25.0));
+
+    slab.ps.clear_funcs();
+    assert_eq!(slab.ps.get_func("sq"), None);
+    assert_eq!(eval(&mut slab, "sq(5)"), Err(Error::Undefined("sq".to_string())));
+
+    // var_names() reports the free variables of the body, but not the parameters:
+    let mut slab = slab_with(&["f(a, b) = a * b + c"]);
+    let expr = Parser::new().parse("f(y, 2) + z", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.var_names(&slab).into_iter().collect::<Vec<_>>(), vec!["c", "y", "z"]);
+}
+
+#[test]
+fn compile() {
+    let mut slab = slab_with(&["f(x, y) = x^2 + y", "g(a) = f(a, 1) * scale"]);
+    let instr = Parser::new().parse("f(2, 3) * 4", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?}", instr), "IConst(28.0)");
+
+    slab.cs.clear();
+    let instr = Parser::new().parse("g(y)", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(format!("{:?} {:?}", instr, slab.cs),
+               "ICall { name: \"g\", names: [\"a\"], args: [I(InstructionI(0))], body: InstructionI(7) } CompileSlab{ instrs:{ 0:IVar(\"y\"), 1:ILocal(0), 2:ILocal(0), 3:IExp { base: I(InstructionI(2)), power: C(2.0) }, 4:IAdd(InstructionI(3), C(1.0)), 5:ICall { name: \"f\", names: [\"x\", \"y\"], args: [I(InstructionI(1)), C(1.0)], body: InstructionI(4) }, 6:IVar(\"scale\"), 7:IMul(InstructionI(5), I(InstructionI(6))) } }");
+}
+
+#[test]
+fn deep_nesting() {
+    // Each argument is evaluated once, so nested calls don't grow exponentially:
+    let mut slab = slab_with(&["g(a) = a + a + a + a"]);
+    assert_eq!(eval(&mut slab, "g(g(g(g(x))))"), Ok(2560.0));
+
+    let expr_str = format!("{}x{}", "g(".repeat(12), ")".repeat(12));
+    assert_eq!(eval(&mut slab, &expr_str), Ok(167772160.0));
+    let instr = Parser::new().parse(&expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert!(Bytecode::new(&instr, &slab).ops().len() < 200);
+}
+
+#[test]
+fn errors() {
+    let mut slab = slab_with(&["f(x, y) = x + y"]);
+    let err = |slab:&mut Slab, def:&str| Parser::new().define(def, &mut slab.ps).map_err(|e| (e.kind().clone(), e.pos().map(|p| p.offset)));
+
+    assert_eq!(Parser::new().parse("f(1)", &mut slab.ps).map_err(|e| e.kind().clone()), Err(Error::WrongArgs("function `f` takes 2 arguments".to_string())));
+    assert_eq!(Parser::new().parse("1 + f(1, 2, 3)", &mut slab.ps).map_err(|e| e.pos().map(|p| p.offset)), Err(Some(4)));
+
+    assert_eq!(err(&mut slab, "sin(x) = x"), Err((Error::InvalidDefinition("`sin` is a builtin function".to_string()), Some(0))));
+    assert_eq!(err(&mut slab, "print(x) = x"), Err((Error::InvalidDefinition("`print` is a builtin function".to_string()), Some(0))));
+    assert_eq!(err(&mut slab, "g(a, b, a) = a"), Err((Error::InvalidDefinition("parameter `a` is repeated".to_string()), Some(8))));
+    assert_eq!(err(&mut slab, "g(a) = g(a - 1)"), Err((Error::InvalidDefinition("function `g` calls itself".to_string()), Some(0))));
+    assert_eq!(err(&mut slab, "f(a, b) = f(b, a)"), Err((Error::InvalidDefinition("function `f` calls itself".to_string()), Some(0))));
+    Parser::new().define("g(a) = f(a, a)", &mut slab.ps).unwrap();
+    assert_eq!(err(&mut slab, "f(a, b) = g(a) + b"), Err((Error::InvalidDefinition("function `f` calls itself".to_string()), Some(0))));
+
+    assert_eq!(err(&mut slab, "g(a) == a"), Err((Error::Expected("'='".to_string()), Some(5))));
+    assert_eq!(err(&mut slab, "g(a)"), Err((Error::Expected("'='".to_string()), Some(4))));
+    assert_eq!(err(&mut slab, "g = 1"), Err((Error::Expected("'(' or '['".to_string()), Some(2))));
+    assert_eq!(err(&mut slab, "g(a b) = 1"), Err((Error::Expected("',' or ')'".to_string()), Some(4))));
+    assert_eq!(err(&mut slab, "g(1) = 1"), Err((Error::Expected("parameter name".to_string()), Some(2))));
+    assert_eq!(err(&mut slab, "g(a, "), Err((Error::EofWhileParsing("g".to_string()), Some(5))));
+    assert_eq!(err(&mut slab, "g(a) = a +"), Err((Error::EofWhileParsing("value".to_string()), Some(10))));
+    assert_eq!(err(&mut slab, "1 = 1"), Err((Error::Expected("function name".to_string()), Some(0))));
+
+    // 'g' calls 'f', so 'f' must keep its two parameters:
+    assert_eq!(err(&mut slab, "f(a) = a"), Err((Error::InvalidDefinition("function `g` calls `f` with 2 arguments".to_string()), Some(0))));
+    Parser::new().define("f(a, b) = a * b", &mut slab.ps).unwrap();
+
+    // Failed definitions don't change anything:
+    assert_eq!(eval(&mut slab, "f(1, 2) + g(1)"), Ok(3.0));
+    assert_eq!(Parser::new().define("f(a, b) = g(a) + b", &mut slab.ps).unwrap_err().to_string(), "invalid function definition: function `f` calls itself at line 1, column 1");
+}
+
+#[test]
+fn redefined_arity() {
+    // A call that was parsed before its function changed the number of parameters:
+    let mut slab = slab_with(&["f(a, b) = a + b"]);
+    let expr_i = Parser::new().parse("f(x, 1)", &mut slab.ps).unwrap();
+    Parser::new().define("f(a) = a", &mut slab.ps).unwrap();
+    let expr = expr_i.from(&slab.ps);
+    let expect = Err(Error::WrongArgs("function `f` takes 1 argument".to_string()));
+    assert_eq!(expr.eval(&slab, &mut ns), expect);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut ns), expect);
+}
+
+#[test]
+fn other_evaluators() {
+    let mut slab = slab_with(&["f(x, y) = x^2 + y", "sq(t) = t * t", "tri(n) = (n * (n + 1)) / 2"]);
+
+    let expr = Parser::new().parse("tri(100000000) - tri(99999999)", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(100000000i64));
+    let expr = Parser::new().parse("f(3, 1) / 4", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(2i64));
+
+    let instr = Parser::new().parse("sq(a) - 2*a", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Interval::new(0.0, 2.0));
+    assert_eq!(eval_interval(&instr, &slab, &mut map), Ok(Interval::new(-4.0, 4.0)));
+    let dual = eval_grad(&instr, &slab, &["a"], &mut |_:&str, _:Vec<f64>| Some(3.0)).unwrap();
+    assert_eq!((dual.val, dual.grad), (3.0, vec![4.0]));
+
+    slab.cs.add_slot("a".to_string());
+    let instr = Parser::new().parse("sq(a) - 2*a", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let mut out = [0.0; 3];
+    eval_batch(&instr, &slab, &[&[1.0, 2.0, 3.0]], &mut out, &mut EmptyNamespace).unwrap();
+    assert_eq!(out, [-1.0, 0.0, 3.0]);
+}
+
+#[test]
+fn derivatives() {
+    let mut slab = Slab::with_capacity(256);  // The derived functions are kept in the Slab.
+    Parser::new().define("f(x, y) = x^2 * y", &mut slab.ps).unwrap();
+    Parser::new().define("g(t) = f(t, scale) + t * scale", &mut slab.ps).unwrap();
+    let mut vars = |name:&str, _:Vec<f64>| match name { "x" => Some(3.0), "scale" => Some(2.0), _ => None };
+
+    // d/dx f(x, x^3) = 2x * x^3 + x^2 * 3x^2 = 5x^4
+    let expr_i = Parser::new().parse("f(x, x^3)", &mut slab.ps).unwrap();
+    let d = derive(expr_i, "x", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut vars), Ok(405.0));
+    let instr = d.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut vars), Ok(405.0));
+
+    // d/dscale g(x) = x^2 + x, through the free variable 'scale' in the bodies:
+    let expr_i = Parser::new().parse("g(x)", &mut slab.ps).unwrap();
+    let d = derive(expr_i, "scale", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut vars), Ok(12.0));
+
+    // The partial derivatives are user-defined functions too:
+    assert!(slab.ps.get_func("f'x").is_some());
+    assert!(slab.ps.get_func("g'scale").is_some());
+    // ...which are kept when an unrelated function is defined:
+    Parser::new().define("h(a) = a^3", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut vars), Ok(12.0));
+    // ...but forgotten when the function, or a function that it calls, is redefined:
+    Parser::new().define("f(x, y) = x * y", &mut slab.ps).unwrap();
+    assert!(slab.ps.get_func("f'x").is_none());
+    assert!(slab.ps.get_func("g'scale").is_none());
+    let expr_i = Parser::new().parse("f(x, x^3)", &mut slab.ps).unwrap();
+    let d = derive(expr_i, "x", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut vars), Ok(108.0));
+    Parser::new().define("g(t) = t", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut vars), Ok(108.0));
+
+    // The arguments of other custom functions still can't depend on the variable:
+    let expr_i = Parser::new().parse("sum(f(x, 1))", &mut slab.ps).unwrap();
+    assert_eq!(derive(expr_i, "x", &mut slab.ps), Err(Error::NotDifferentiable("custom function `sum`".to_string())));
+}
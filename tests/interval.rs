@@ -0,0 +1,167 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, SafeVar, Interval, IntervalNamespace, eval_interval};
+
+use std::collections::BTreeMap;
+
+// Checks that the interval result encloses the results of normal evaluation
+// at many points within the input intervals.
+fn chk(expr_str:&str, x:(f64,f64), y:(f64,f64)) {
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+
+    let mut ins = BTreeMap::<String,Interval>::new();
+    ins.insert("x".to_string(), Interval::new(x.0, x.1));
+    ins.insert("y".to_string(), Interval::new(y.0, y.1));
+    let bounds = eval_interval(&instr, &slab, &mut ins).unwrap();
+
+    let n = 20;
+    for i in 0..=n {
+        for j in 0..=n {
+            let mut ns = BTreeMap::<String,f64>::new();
+            ns.insert("x".to_string(), x.0 + (x.1-x.0)*f64::from(i)/f64::from(n));
+            ns.insert("y".to_string(), y.0 + (y.1-y.0)*f64::from(j)/f64::from(n));
+            let val = instr.eval(&slab, &mut ns).unwrap();
+            if val.is_nan() { continue; }
+            assert!(bounds.contains(val), "{} with {:?}: {} is not in {}", expr_str, ns, val, bounds);
+        }
+    }
+}
+
+fn eval(expr_str:&str, x:Interval) -> Interval {
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    eval_interval(&instr, &slab, &mut |name:&str, _args:Vec<Interval>| if name=="x" { Some(x) } else { None }).unwrap()
+}
+
+#[test]
+fn enclosure() {
+    let ranges = [(-3.0, -1.5), (-2.0, 0.5), (-0.1, 0.1), (0.0, 1.0), (0.3, 0.7), (1.0, 4.0), (-7.0, 9.0), (2.5, 2.5)];
+    let exprs = [
+        "x + y", "x - y", "x * y", "x / y", "-x * 3 + 1",
+        "x^2", "x^3", "x^-2", "y^-1", "x^y", "2^x", "x^0.5", "(x^2)^y",
+        "x % y", "x % 2", "y % 0.3",
+        "x < y", "x <= y", "x == y", "x != y", "x >= y", "x > y", "!x", "!(x > 0)",
+        "x && y", "x || y", "x > 0 && y", "x > 0 || y", "x > y ? x : y*2",
+        "int(x) + ceil(y) + floor(x*y) + sign(x)", "round(x*y)", "round(0.25, x)", "round(y, x)", "abs(x) + abs(y)",
+        "log(x)", "log(2, y)", "log(y, x)", "min(x, y)", "max(x, y, 0.5)",
+        "sin(x)", "cos(y)", "tan(x)", "sin(x*10)", "cos(x*y*5)", "tan(y*3)",
+        "asin(x/4)", "acos(y/3)", "atan(x*y)", "sinh(x)", "cosh(y)", "tanh(x)", "asinh(x)", "acosh(y)", "atanh(x/3)",
+        "e()^x * pi()",
+    ];
+    for expr_str in exprs.iter() {
+        for &x in ranges.iter() {
+            for &y in ranges.iter() {
+                chk(expr_str, x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn tightness() {
+    assert_eq!(eval("x + 1", Interval::new(1.0, 2.0)), Interval::new(2.0, 3.0));
+    assert_eq!(eval("x * -2", Interval::new(-1.0, 2.0)), Interval::new(-4.0, 2.0));
+    assert_eq!(eval("1 / x", Interval::new(2.0, 4.0)), Interval::new(0.25, 0.5));
+    assert_eq!(eval("1 / x", Interval::new(0.0, 4.0)), Interval::new(0.25, std::f64::INFINITY));
+    assert_eq!(eval("1 / x", Interval::new(-1.0, 4.0)), Interval::entire());
+    assert_eq!(eval("x - x", Interval::new(0.0, 1.0)), Interval::new(-1.0, 1.0));
+
+    // Inexact results are rounded outward:
+    let third = eval("1 / x", Interval::point(3.0));
+    assert!(third.lo < third.hi && third.contains(1.0/3.0));
+    let sum = eval("x + 0.2", Interval::point(0.1));
+    assert!(sum.lo < sum.hi && sum.contains(0.1+0.2));
+
+    // Even powers have a minimum at zero, and odd powers are increasing:
+    let sq = eval("x^2", Interval::new(-2.0, 1.0));
+    assert_eq!(sq.lo, 0.0);
+    assert!(sq.hi >= 4.0 && sq.hi < 4.0001);
+    let cube = eval("x^3", Interval::new(-2.0, 1.0));
+    assert!(cube.contains(-8.0) && cube.contains(1.0) && cube.lo > -8.0001 && cube.hi < 1.0001);
+
+    // Trig functions find their extrema inside the interval:
+    assert_eq!(eval("sin(x)", Interval::new(0.0, 3.0)).hi, 1.0);
+    assert_eq!(eval("cos(x)", Interval::new(3.0, 4.0)).lo, -1.0);
+    assert_eq!(eval("sin(x)", Interval::new(0.0, 100.0)), Interval::new(-1.0, 1.0));
+    assert_eq!(eval("tan(x)", Interval::new(1.0, 2.0)), Interval::entire());
+    let t = eval("tan(x)", Interval::new(-1.0, 1.0));
+    assert!(t.lo < -1.5574 && t.lo > -1.5575 && t.hi > 1.5574 && t.hi < 1.5575);
+
+    let m = eval("x % 3", Interval::new(4.0, 5.0));
+    assert!(m.contains(1.0) && m.contains(2.0) && m.lo > 0.9999 && m.hi < 2.0001);
+    assert_eq!(eval("x % 3", Interval::new(2.0, 5.0)), Interval::new(0.0, 3.0));
+    assert_eq!(eval("x % 3", Interval::new(-5.0, 1.0)), Interval::new(-3.0, 1.0));
+
+    assert_eq!(eval("floor(x) + int(x)", Interval::new(-1.5, 2.5)), Interval::new(-3.0, 4.0));
+    assert_eq!(eval("abs(x)", Interval::new(-3.0, 2.0)), Interval::new(0.0, 3.0));
+    assert_eq!(eval("min(x, 1)", Interval::new(0.0, 2.0)), Interval::new(0.0, 1.0));
+}
+
+#[test]
+fn logic() {
+    let x = Interval::new(1.0, 2.0);
+    assert_eq!(eval("x > 0", x), Interval::point(1.0));
+    assert_eq!(eval("x > 2", x), Interval::point(0.0));
+    assert_eq!(eval("x > 1.5", x), Interval::new(0.0, 1.0));
+    assert_eq!(eval("x == 1.5", x), Interval::new(0.0, 1.0));
+    assert_eq!(eval("x == 5", x), Interval::point(0.0));
+    assert_eq!(eval("x == 5", Interval::point(5.0)), Interval::point(1.0));
+    assert_eq!(eval("!x", x), Interval::point(0.0));
+    assert_eq!(eval("!(x-1)", x), Interval::new(0.0, 1.0));
+
+    // Short-circuits are only taken when the condition is certain:
+    assert_eq!(eval("x > 0 && 7", x), Interval::point(7.0));
+    assert_eq!(eval("x > 1.5 && 7", x), Interval::new(0.0, 7.0));
+    assert_eq!(eval("x > 1.5 || 7", x), Interval::new(0.0, 7.0));
+    assert_eq!(eval("x < 0 || 7", x), Interval::point(7.0));
+    assert_eq!(eval("x > 0 ? 3 : undefined_var", x), Interval::point(3.0));
+    assert_eq!(eval("x > 1.5 ? 3 : -x", x), Interval::new(-2.0, 3.0));
+}
+
+#[test]
+fn domains() {
+    let x = Interval::new(-1.0, 100.0);
+    let l = eval("log(x)", x);
+    assert_eq!(l.lo, std::f64::NEG_INFINITY);
+    assert!(l.hi >= 2.0 && l.hi < 2.0001);
+    assert!(eval("log(x)", Interval::new(-3.0, -1.0)).is_empty());
+    assert!(eval("x^0.5", Interval::new(-3.0, -1.0)).is_empty());
+    assert!(eval("asin(x)", Interval::new(2.0, 3.0)).is_empty());
+    assert!(eval("x % 0", x).is_empty());
+    assert!(eval("(x^0.5) + 1", Interval::new(-3.0, -1.0)).is_empty());
+    assert_eq!(eval("print(\"x\", x)", x), Interval::entire());
+}
+
+#[test]
+fn variables() {
+    struct Ns;
+    impl IntervalNamespace for Ns {
+        fn lookup(&mut self, name:&str, args:Vec<Interval>) -> Option<Interval> {
+            match name {
+                "f" => Some(Interval::new(args[0].lo*10.0, args[0].hi*10.0)),
+                "z" => Some(Interval::point(5.0)),
+                _ => None,
+            }
+        }
+        fn slot(&mut self, i:usize) -> Option<Interval> {
+            if i==0 { Some(Interval::new(1.0, 2.0)) } else { None }
+        }
+    }
+
+    let mut slab = Slab::new();
+    slab.cs.add_slot("a".to_string());
+    slab.cs.add_slot("z".to_string());
+    let b = SafeVar::new(3.0);
+    slab.ps.add_safe_var("b".to_string(), &b);
+    let instr = Parser::new().parse("f(a) + b", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(eval_interval(&instr, &slab, &mut Ns), Ok(Interval::new(13.0, 23.0)));
+
+    // Slot `z` isn't supplied, so it's looked up by name:
+    let instr = Parser::new().parse("a + z", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(eval_interval(&instr, &slab, &mut Ns), Ok(Interval::new(6.0, 7.0)));
+    assert_eq!(eval_interval(&instr, &slab, &mut |_:&str, _:Vec<Interval>| None), Err(Error::Undefined("a".to_string())));
+    let instr = Parser::new().parse("a + c", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(eval_interval(&instr, &slab, &mut Ns), Err(Error::Undefined("c".to_string())));
+
+    assert_eq!(Interval::new(3.0, 1.0), Interval{ lo:1.0, hi:3.0 });
+    assert_eq!(Interval::new(1.0, 3.0).to_string(), "[1, 3]");
+}
@@ -0,0 +1,192 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, EmptyNamespace, Number, NumberEvaler};
+
+use std::cmp::Ordering;
+use std::fmt;
+
+// A decimal fixed-point type with 3 digits after the point.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct Milli(i64);
+
+impl fmt::Display for Milli {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:03}", self.0/1000, (self.0%1000).abs())
+    }
+}
+
+impl Number for Milli {
+    fn from_f64(x:f64) -> Result<Self,Error> { Ok(Milli((x*1000.0).round() as i64)) }
+    fn to_f64(self) -> f64 { self.0 as f64 / 1000.0 }
+    fn from_bool(b:bool) -> Self { Milli(if b { 1000 } else { 0 }) }
+    fn is_zero(self) -> bool { self.0==0 }
+    fn compare(self, other:Self) -> Result<Option<Ordering>,Error> { Ok(self.partial_cmp(&other)) }
+    fn neg(self) -> Result<Self,Error> { Ok(Milli(-self.0)) }
+    fn add(self, other:Self) -> Result<Self,Error> { Ok(Milli(self.0+other.0)) }
+    fn sub(self, other:Self) -> Result<Self,Error> { Ok(Milli(self.0-other.0)) }
+    fn mul(self, other:Self) -> Result<Self,Error> { Ok(Milli(self.0*other.0/1000)) }
+    fn div(self, other:Self) -> Result<Self,Error> {
+        if other.0==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        Ok(Milli(self.0*1000/other.0))
+    }
+    fn rem(self, other:Self) -> Result<Self,Error> {
+        if other.0==0 { return Err(Error::WrongArgs("division by zero".to_string())); }
+        Ok(Milli(self.0%other.0))
+    }
+}
+
+fn eval<N:Number>(expr_str:&str, x:N) -> Result<N,Error> {
+    let mut slab = Slab::new();
+    let expr_ref = Parser::new().parse(expr_str, &mut slab.ps)?.from(&slab.ps);
+    expr_ref.eval_number(&slab, &mut |name:&str, args:Vec<N>| match name {
+        "x" => Some(x),
+        "twice" => args.first().map(|a| a.add(*a).unwrap()),
+        _ => None,
+    })
+}
+
+#[test]
+fn same_as_f64() {
+    for expr_str in &["1 + 2*3 - 4/5 % 6 ^ 2", "2^3^2", "10 - 3 - 2", "100 / 5 / 2", "x^2 - 3*x + 1",
+                      "x < 2", "x <= 2", "x == 2", "x != 2", "x >= 2", "x > 2", "!x", "-x", "+x",
+                      "x > 1 && 3 || 4", "0 && 1", "NaN && 1", "NaN || 1", "!NaN", "NaN != 1",
+                      "x > 2 ? x : -x", "if(x, 1, 2)", "log(x) + log(2, 8) + round(0.01, pi()) + round(x/3)",
+                      "int(-x/3) + ceil(x/3) + floor(-x/3) + abs(-x) + sign(-x)", "min(x, 1, -3) + max(4, x, 0)",
+                      "min(NaN, x)", "max(NaN, x)", "min(x, NaN)", "max(x, NaN)", "e()",
+                      "sin(x) + cos(x) + tan(x) + asin(0.5) + acos(0.5) + atan(x)",
+                      "sinh(x) + cosh(x) + tanh(x) + asinh(x) + acosh(x) + atanh(0.5)",
+                      "twice(x) * 3", "1e308 * 10 / 10", "x * 1e308 * 10 / 10"] {
+        for &x in &[-1.5, 0.0, 2.0, 3.7] {
+            // Evaler::eval() for Expressions is eval_number::<f64>(), so compare with the compiled Instruction:
+            let mut slab = Slab::new();
+            let expr_ref = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+            let instr = expr_ref.compile(&slab.ps, &mut slab.cs);
+            let mut ns = |name:&str, args:Vec<f64>| match name {
+                "x" => Some(x),
+                "twice" => Some(args[0]*2.0),
+                _ => None,
+            };
+            let expect = instr.eval(&slab, &mut ns).unwrap();
+            let got = expr_ref.eval_number(&slab, &mut ns).unwrap();
+            assert_eq!(expr_ref.eval(&slab, &mut ns).map(f64::to_bits), Ok(got.to_bits()));
+            // The compiler may re-order sums, so allow for rounding:
+            assert!(got==expect || (got-expect).abs()<=1e-12*expect.abs() || got.is_nan() && expect.is_nan(), "{} with x={}: {} != {}", expr_str, x, got, expect);
+        }
+    }
+}
+
+#[test]
+fn f32() {
+    assert_eq!(eval("x * 3", 0.1f32), Ok(0.3));
+    assert_eq!(eval("x * 3", 0.1f64), Ok(0.30000000000000004));
+    assert_eq!(eval("twice(x) + pi()", 1.0f32), Ok(2.0 + std::f32::consts::PI));
+    assert_eq!(eval("x == 0.3", 0.1f32+0.2f32), Ok(1.0));
+    assert_eq!(eval("sin(x)", 1.0f32), Ok(1.0f32.sin()));
+}
+
+#[test]
+fn fixed_point() {
+    assert_eq!(eval("x * 3", Milli(100)), Ok(Milli(300)));
+    assert_eq!(eval("10 / 3", Milli(0)), Ok(Milli(3333)));
+    assert_eq!(eval("x / 3 * 3", Milli(1000)), Ok(Milli(999)));
+    assert_eq!(eval("x > 0.5 ? twice(x) : 0", Milli(1500)), Ok(Milli(3000)));
+    assert_eq!(eval("min(x, 2, 0.25)", Milli(1500)), Ok(Milli(250)));
+    assert_eq!(eval("round(0.1, x)", Milli(1234)), Ok(Milli(1200)));
+    assert_eq!(eval("1 / (x - 1)", Milli(1000)), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("y", Milli(0)), Err(Error::Undefined("y".to_string())));
+    assert_eq!(Milli(1234).to_string(), "1.234");
+
+    let mut slab = Slab::new();
+    let expr_ref = Parser::new().parse("2 * 3", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr_ref.eval_number(&slab, &mut EmptyNamespace), Ok(Milli(6000)));
+}
+
+#[test]
+fn integer() {
+    assert_eq!(eval("7 / 2", 0i64), Ok(3));
+    assert_eq!(eval("-7 / 2", 0i64), Ok(-3));
+    assert_eq!(eval("4 * 5 / 2", 0i64), Ok(10));
+    assert_eq!(eval("x * 7 / 2 * 2", 3i64), Ok(20));
+    assert_eq!(eval("7 % 3", 0i64), Ok(1));
+    assert_eq!(eval("-7 % 3", 0i64), Ok(-1));
+    assert_eq!(eval("2 ^ 62", 0i64), Ok(1<<62));
+    assert_eq!(eval("x ^ 0", 5i64), Ok(1));
+    assert_eq!(eval("(0 - 1) ^ 99999999999", 0i64), Ok(-1));
+    assert_eq!(eval("x * 3 - twice(x)", 1_000_000_000_000i64), Ok(1_000_000_000_000));
+    assert_eq!(eval("x > 2 && x <= 4", 3i64), Ok(1));
+    assert_eq!(eval("min(x, 2) + max(x, 9) + abs(-x) + sign(-x)", 5i64), Ok(15));
+    assert_eq!(eval("round(10, x) + round(10, -x) + round(x)", 15i64), Ok(15));
+    assert_eq!(eval("int(x) + ceil(x) + floor(x)", 4i64), Ok(12));
+    assert_eq!(eval("log(2, 1024)", 0i64), Ok(10));
+
+    // Literals that are too large for an f64 are still exact:
+    assert_eq!(eval("9007199254740993 - 9007199254740992", 0i64), Ok(1));
+    assert_eq!(eval("9223372036854775807", 0i64), Ok(i64::MAX));
+    assert_eq!(eval("0x7FFF_FFFF_FFFF_FFFF", 0i64), Ok(i64::MAX));
+    assert_eq!(eval("-0x8000_0000_0000_0000", 0i64), Ok(i64::MIN));
+
+    assert_eq!(eval("x + 1", i64::MAX), Err(Error::Overflow));
+    assert_eq!(eval("x - 1", i64::MIN), Err(Error::Overflow));
+    assert_eq!(eval("x * 2", 1i64<<62), Err(Error::Overflow));
+    assert_eq!(eval("-x", i64::MIN), Err(Error::Overflow));
+    assert_eq!(eval("x / -1", i64::MIN), Err(Error::Overflow));
+    assert_eq!(eval("2 ^ 63", 0i64), Err(Error::Overflow));
+    assert_eq!(eval("9223372036854775808", 0i64), Err(Error::Overflow));
+    assert_eq!(eval("x / 0", 1i64), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("x % 0", 1i64), Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(eval("2 ^ -1", 0i64), Err(Error::WrongArgs("negative exponent -1 in integer mode".to_string())));
+    assert_eq!(eval("1.5", 0i64), Err(Error::WrongArgs("1.5 is not an integer".to_string())));
+    assert_eq!(eval("pi()", 0i64), Err(Error::WrongArgs("3.141592653589793 is not an integer".to_string())));
+    assert_eq!(Error::Overflow.to_string(), "arithmetic overflow");
+}
+
+#[test]
+fn integer_compiled() {
+    // In integer mode, compiled Instructions give the same results as the parsed Expressions:
+    for expr_str in &["7 / 2", "-7 / 2", "4 * 5 / 2", "x * 7 / 2 * 2", "x / 2 * 2 / x", "7 % 3", "-7 % 3",
+                      "2 ^ 62", "x ^ 0", "(0 - 1) ^ 99999999999", "x * 3 - twice(x)", "x > 2 && x <= 4",
+                      "min(x, 2) + max(x, 9) + abs(-x) + sign(-x)", "round(10, x) + round(10, -x) + round(x)",
+                      "int(x) + ceil(x) + floor(x)", "log(2, 1024)", "1 + 2 - 3 * 4 / 5 % 6", "10 - 3 - 2",
+                      "100 / 5 / 2", "x / (7 / 2)", "x - -x", "~x & 6 | 1 << 3", "x < 3 ? 10 / 4 : 1",
+                      "2 ^ 63", "2 ^ 62 * 2 / 4", "4611686018427387904 + 4611686018427387904 - 1", "x * 4611686018427387904",
+                      "-x", "x / 0", "1 / 0", "x % 0", "5 % (x - x)", "2 ^ -1", "1.5", "1.5 * 2", "x + 0.5 - 0.5",
+                      "pi()", "pi() * 0", "1.5 > 1", "sin(1)", "sin(0) + 1", "!0.5", "0.5 || x", "if(0.5, 1, 2)",
+                      "min(0.5, x)", "(0 - 1) ^ 0.5", "1 << 64", "0x100000 * 0x100000 * 0x100000 * 0x100000"] {
+        for &x in &[0i64, 3, -4, 5_000_000_000, i64::MAX, i64::MIN] {
+            let mut slab = Slab::new();
+            slab.cs.set_int_mode(true);
+            let expr_ref = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+            let instr = expr_ref.compile(&slab.ps, &mut slab.cs);
+            let mut ns = |name:&str, args:Vec<i64>| match name {
+                "x" => Some(x),
+                "twice" => args.first().and_then(|a| a.checked_mul(2)),
+                _ => None,
+            };
+            // If several operations fail, the first error may differ, because the parsed Expression
+            // evaluates all of the operands before it applies the operators:
+            match (instr.eval_number(&slab, &mut ns), expr_ref.eval_number(&slab, &mut ns)) {
+                (Err(_), Err(_)) => (),
+                (got, expect) => assert_eq!(got, expect, "{} with x={}", expr_str, x),
+            }
+        }
+    }
+
+    // Constants are folded with checked i64 arithmetic:
+    let compile = |expr_str:&str| {
+        let mut slab = Slab::new();
+        slab.cs.set_int_mode(true);
+        let expr_ref = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+        let instr = expr_ref.compile(&slab.ps, &mut slab.cs);
+        (format!("{:?}", instr), instr.eval_number(&slab, &mut EmptyNamespace))
+    };
+    assert_eq!(compile("7 / 2 * 2"), ("IConst(6.0)".to_string(), Ok(6i64)));
+    assert_eq!(compile("2 ^ 40 / 2 ^ 38 - 10 % 3"), ("IConst(3.0)".to_string(), Ok(3i64)));
+    assert_eq!(compile("2 ^ 63").1, Err(Error::Overflow));
+    assert_eq!(compile("2 ^ 53 + 1").1, Ok((1i64<<53) + 1));  // Not exact in an f64 constant, so it isn't folded.
+    assert_eq!(compile("1 / 0").1, Err(Error::WrongArgs("division by zero".to_string())));
+    assert_eq!(compile("1.5 * 2").1, Err(Error::WrongArgs("1.5 is not an integer".to_string())));
+
+    // Without integer mode, constants are folded with f64 arithmetic:
+    let mut slab = Slab::new();
+    let expr_ref = Parser::new().parse("7 / 2 * 2", &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr_ref.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut EmptyNamespace), Ok(7.0));
+}
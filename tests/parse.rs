@@ -1,4 +1,4 @@
-use fasteval::{Error, Slab, Parser};
+use fasteval::{Error, Pos, Slab, Parser, SafeVar, Evaler, EmptyNamespace};
 
 #[test]
 fn basics() {
@@ -33,6 +33,22 @@ fn basics() {
 
 }
 
+#[test]
+fn ternary() {
+    let mut slab = Slab::new();
+    Parser::new().parse("1 < 2 ? 3 : 4", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}",&slab),
+"Slab{ exprs:{ 0:Expression { first: EConstant(1.0), pairs: [ExprPair(ELT, EConstant(2.0))] }, 1:Expression { first: EConstant(3.0), pairs: [] }, 2:Expression { first: EConstant(4.0), pairs: [] }, 3:Expression { first: EStdFunc(EFuncIf { cond: ExpressionI(0), then: ExpressionI(1), otherwise: ExpressionI(2) }), pairs: [] } }, vals:{}, instrs:{} }");
+
+    Parser::new().parse("if(1, 2, 3)", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}",&slab),
+"Slab{ exprs:{ 0:Expression { first: EConstant(1.0), pairs: [] }, 1:Expression { first: EConstant(2.0), pairs: [] }, 2:Expression { first: EConstant(3.0), pairs: [] }, 3:Expression { first: EStdFunc(EFuncIf { cond: ExpressionI(0), then: ExpressionI(1), otherwise: ExpressionI(2) }), pairs: [] } }, vals:{}, instrs:{} }");
+
+    Parser::new().parse("x || y ? 1 : z ? 2 : 3", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}",&slab),
+"Slab{ exprs:{ 0:Expression { first: EStdFunc(EVar(\"x\")), pairs: [ExprPair(EOR, EStdFunc(EVar(\"y\")))] }, 1:Expression { first: EConstant(1.0), pairs: [] }, 2:Expression { first: EStdFunc(EVar(\"z\")), pairs: [] }, 3:Expression { first: EConstant(2.0), pairs: [] }, 4:Expression { first: EConstant(3.0), pairs: [] }, 5:Expression { first: EStdFunc(EFuncIf { cond: ExpressionI(2), then: ExpressionI(3), otherwise: ExpressionI(4) }), pairs: [] }, 6:Expression { first: EStdFunc(EFuncIf { cond: ExpressionI(0), then: ExpressionI(1), otherwise: ExpressionI(5) }), pairs: [] } }, vals:{}, instrs:{} }");
+}
+
 #[test]
 fn consts() {
     let mut slab = Slab::new();
@@ -49,9 +65,9 @@ fn consts() {
     assert_eq!(format!("{:?}",&slab),
 "Slab{ exprs:{ 0:Expression { first: EConstant(12.0), pairs: [] } }, vals:{}, instrs:{} }");
 
-    assert_eq!(Parser::new().parse(".", &mut slab.ps), Err(Error::ParseF64(".".to_string())));
+    assert_eq!(Parser::new().parse(".", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::ParseF64(".".to_string())));
 
-    assert_eq!(Parser::new().parse("12..34", &mut slab.ps), Err(Error::ParseF64("12..34".to_string())));
+    assert_eq!(Parser::new().parse("12..34", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::ParseF64("12..34".to_string())));
 
     Parser::new().parse("12.34k", &mut slab.ps).unwrap();
     assert_eq!(format!("{:?}",&slab),
@@ -155,10 +171,169 @@ fn consts() {
 
 
 
-    assert_eq!(Parser::new().parse("-infK", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
-    assert_eq!(Parser::new().parse("NaNK", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
-    assert_eq!(Parser::new().parse("12.34e56K", &mut slab.ps), Err(Error::UnparsedTokensRemaining("K".to_string())));
+    assert_eq!(Parser::new().parse("-infK", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::UnparsedTokensRemaining("K".to_string())));
+    assert_eq!(Parser::new().parse("NaNK", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::UnparsedTokensRemaining("K".to_string())));
+    assert_eq!(Parser::new().parse("12.34e56K", &mut slab.ps).map_err(|err| err.kind().clone()), Err(Error::UnparsedTokensRemaining("K".to_string())));
+
+}
+
+#[test]
+fn radix_consts() {
+    fn first(s:&str) -> Result<String,Error> {
+        let mut slab = Slab::new();
+        let expr = Parser::new().parse(s, &mut slab.ps).map_err(|err| err.kind().clone())?.from(&slab.ps);
+        let dbg = format!("{:?}", expr);
+        Ok(dbg[dbg.find("first: ").unwrap()+7 .. dbg.find(", pairs").unwrap()].to_string())
+    }
+
+    assert_eq!(first("0x1F"), Ok("EConstant(31.0)".to_string()));
+    assert_eq!(first("0XdeadBEEF"), Ok("EConstant(3735928559.0)".to_string()));
+    assert_eq!(first("0o17"), Ok("EConstant(15.0)".to_string()));
+    assert_eq!(first("0b1010"), Ok("EConstant(10.0)".to_string()));
+    assert_eq!(first("0B1"), Ok("EConstant(1.0)".to_string()));
+    assert_eq!(first("-0x10"), Ok("EConstant(-16.0)".to_string()));
+    assert_eq!(first("+0b11"), Ok("EConstant(3.0)".to_string()));
+    assert_eq!(first("0x1E5"), Ok("EConstant(485.0)".to_string()));  // 'E' is a digit, not an exponent.
+    assert_eq!(first("0xFFFF_0000"), Ok("EConstant(4294901760.0)".to_string()));
+    assert_eq!(first("0b1010_0101"), Ok("EConstant(165.0)".to_string()));
+    assert_eq!(first("0x20000000000000"), Ok("EConstant(9007199254740992.0)".to_string()));
+    assert_eq!(first("0x20000000000001"), Ok("EExactConstant(9007199254740992.0, \"9007199254740993\")".to_string()));
+    assert_eq!(first("-0xFFFFFFFFFFFFFFFF"), Ok("EExactConstant(-1.8446744073709552e19, \"-18446744073709551615\")".to_string()));
+
+    assert_eq!(first("1_000_000"), Ok("EConstant(1000000.0)".to_string()));
+    assert_eq!(first("-1_000.000_5"), Ok("EConstant(-1000.0005)".to_string()));
+    assert_eq!(first("1e1_0"), Ok("EConstant(10000000000.0)".to_string()));
+    assert_eq!(first("1_500k"), Ok("EConstant(1500000.0)".to_string()));
+    assert_eq!(first("2_5m"), Ok("EConstant(0.025)".to_string()));
+    assert_eq!(first("1_234_567_890_123_456_789"), Ok("EExactConstant(1.2345678901234568e18, \"1234567890123456789\")".to_string()));
+
+    // SI suffixes don't apply, because some of them are digits:
+    assert_eq!(first("0x10k"), Err(Error::ParseF64("0x10k".to_string())));
+    assert_eq!(first("0x1G"), Err(Error::ParseF64("0x1G".to_string())));
+    assert_eq!(first("0b102"), Err(Error::ParseF64("0b102".to_string())));
+    assert_eq!(first("0o19 + 1"), Err(Error::ParseF64("0o19".to_string())));
+    assert_eq!(first("0x1.8"), Err(Error::ParseF64("0x1.8".to_string())));
+    assert_eq!(first("0x1_0000_0000_0000_0000"), Err(Error::ParseF64("0x1_0000_0000_0000_0000".to_string())));
+
+    // Separators must be between two digits:
+    assert_eq!(first("1__000"), Err(Error::UnparsedTokensRemaining("__000".to_string())));
+    assert_eq!(first("1_"), Err(Error::UnparsedTokensRemaining("_".to_string())));
+    assert_eq!(first("1._5"), Err(Error::UnparsedTokensRemaining("_5".to_string())));
+    assert_eq!(first("0x_FF"), Err(Error::UnparsedTokensRemaining("x_FF".to_string())));
+    assert_eq!(first("0xFF_"), Err(Error::ParseF64("0xFF_".to_string())));
+    assert_eq!(first("_1"), Ok("EStdFunc(EVar(\"_1\"))".to_string()));
+
+    // A prefix that isn't followed by a digit is a '0' followed by something else:
+    assert_eq!(first("0xor 1"), Ok("EConstant(0.0)".to_string()));
+    assert_eq!(first("0b"), Err(Error::UnparsedTokensRemaining("b".to_string())));
+    #[cfg(feature="alpha-keywords")]
+    assert_eq!(first("0or 1"), Ok("EConstant(0.0)".to_string()));
+
+    assert_eq!(fasteval::ez_eval("0xFF & 0b1010 | 0o7 << 4", &mut fasteval::EmptyNamespace), Ok(122.0));
+    #[cfg(feature="alpha-keywords")]
+    assert_eq!(fasteval::ez_eval("0xor 1", &mut fasteval::EmptyNamespace), Ok(1.0));
+}
+
+#[test]
+fn comments() {
+    fn eval(expr_str:&str) -> f64 {
+        let mut slab = Slab::new();
+        Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).eval(&slab, &mut EmptyNamespace).unwrap()
+    }
+    assert_eq!(eval("1 + 2  # three"), 3.0);
+    assert_eq!(eval("1 + 2  // three"), 3.0);
+    assert_eq!(eval("1 /* one */ + /* two */ 2"), 3.0);
+    assert_eq!(eval("/* a */ 6 //* b */ 2"), 6.0);
+    assert_eq!(eval("6 / /* b */ 2"), 3.0);
+    assert_eq!(eval("1 +  # one\n  2 // two\n  * 3 /* three */"), 7.0);
+    assert_eq!(eval("max(1, # first\n    5 /* second */,\n    2)  # last"), 5.0);
+    assert_eq!(eval("print(\"# not a comment\", /* 1, */ 2)"), 2.0);
+    assert_eq!(eval("#\n#\n1"), 1.0);
+
+    // A newline after a line comment still ends a Statement, but a newline inside of a block comment doesn't:
+    assert_eq!(eval("a = 1  # one\n/* two */ a + 1  // two"), 2.0);
+    assert_eq!(eval("a = 1  /* one\n */ + 1\na"), 2.0);
+    assert_eq!(eval("a = 1 /* one */\n/* two */ a + 1"), 2.0);
+    let err = Parser::new().parse("a = 1 /* one\n */ a + 1", &mut Slab::new().ps).unwrap_err();
+    assert_eq!(err.kind(), &Error::UnparsedTokensRemaining("a + 1".to_string()));
+
+    // Comments count towards the length limit:
+    let err = Parser{expr_len_limit:8, expr_depth_limit:32}.parse("1 # a long comment", &mut Slab::new().ps).unwrap_err();
+    assert_eq!(err.kind(), &Error::TooLong);
+}
+
+#[test]
+fn strings() {
+    fn strs(expr_str:&str) -> String {
+        let mut slab = Slab::new();
+        let expr_i = Parser::new().parse(expr_str, &mut slab.ps).unwrap();
+        format!("{:?}", slab.ps.get_expr(expr_i))
+    }
+    assert_eq!(strs(r#"print("a", 'b', "")"#), r#"Expression { first: EPrintFunc(PrintFunc([EStr("a"), EStr("b"), EStr("")])), pairs: [] }"#);
+    assert_eq!(strs(r#"print("say \"hi\"", 'it\'s', "'", '"')"#), r#"Expression { first: EPrintFunc(PrintFunc([EStr("say \"hi\""), EStr("it's"), EStr("'"), EStr("\"")])), pairs: [] }"#);
+    assert_eq!(strs(r#"print("a\\b\n\t\r\0")"#), r#"Expression { first: EPrintFunc(PrintFunc([EStr("a\\b\n\t\r\0")])), pairs: [] }"#);
+    assert_eq!(strs(r#"print("\u{263A} \u{1F600}\u{41}", "µ#//")"#), r#"Expression { first: EPrintFunc(PrintFunc([EStr("☺ 😀A"), EStr("µ#//")])), pairs: [] }"#);
+    assert_eq!(strs(r#"print("x =", x, "\\n")"#), r#"Expression { first: EPrintFunc(PrintFunc([EStr("x ="), EExpr(ExpressionI(0)), EStr("\\n")])), pairs: [] }"#);
+}
 
+#[test]
+fn error_pos() {
+    fn chk(expr_str:&str, expect_err:Error, offset:usize, line:usize, col:usize) {
+        let mut slab = Slab::new();
+        let err = Parser::new().parse(expr_str, &mut slab.ps).unwrap_err();
+        assert_eq!(err.kind(), &expect_err);
+        assert_eq!(err.pos(), Some(Pos{offset, line, col}));
+    }
+
+    chk("1 + ", Error::EofWhileParsing("value".to_string()), 4, 1, 5);
+    chk("1 + * 2", Error::InvalidValue, 4, 1, 5);
+    chk("1 + 2 3", Error::UnparsedTokensRemaining("3".to_string()), 6, 1, 7);
+    chk("1 + 12..34", Error::ParseF64("12..34".to_string()), 4, 1, 5);
+    chk("(1 + 2]", Error::Expected("')'".to_string()), 6, 1, 7);
+    chk("[1 + 2", Error::EofWhileParsing("square brackets".to_string()), 6, 1, 7);
+    chk("min(1 2)", Error::Expected("',' or ';'".to_string()), 6, 1, 7);
+    chk("1 + log(1,2,3)", Error::WrongArgs("function `log` takes 1 or 2 arguments: log(x) or log(base,x)".to_string()), 4, 1, 5);
+    chk("abs(log())", Error::WrongArgs("function `log` takes 1 or 2 arguments: log(x) or log(base,x)".to_string()), 4, 1, 5);
+    chk(r#"print("abc, 1)"#, Error::EofWhileParsing("string".to_string()), 6, 1, 7);
+    chk("1 +\n  2 +\n  * 3", Error::InvalidValue, 12, 3, 3);
+    chk(r#"print("µ", * 3)"#, Error::InvalidValue, 12, 1, 12);
+    chk("x ? 1 2", Error::Expected("':'".to_string()), 6, 1, 7);
+    chk("x ? 1", Error::EofWhileParsing("ternary".to_string()), 5, 1, 6);
+    chk("1 + /* 2", Error::EofWhileParsing("comment".to_string()), 4, 1, 5);
+    chk("1 + 2 /* 3 * /", Error::EofWhileParsing("comment".to_string()), 6, 1, 7);
+    chk(r#"print('abc)"#, Error::EofWhileParsing("string".to_string()), 6, 1, 7);
+    chk(r#"print("abc\")"#, Error::EofWhileParsing("string".to_string()), 6, 1, 7);
+    chk(r#"print("abc\"#, Error::EofWhileParsing("string".to_string()), 6, 1, 7);
+    chk(r#"print("ab\qc")"#, Error::InvalidEscape(r"\q".to_string()), 9, 1, 10);
+    chk(r#"print("ab\µ")"#, Error::InvalidEscape(r"\µ".to_string()), 9, 1, 10);
+    chk(r#"print("\u")"#, Error::InvalidEscape(r"\u".to_string()), 7, 1, 8);
+    chk(r#"print("\u{}")"#, Error::InvalidEscape(r"\u{".to_string()), 7, 1, 8);
+    chk(r#"print("\u{263A")"#, Error::InvalidEscape(r"\u{263A".to_string()), 7, 1, 8);
+    chk(r#"print("\u{1234567}")"#, Error::InvalidEscape(r"\u{1234567".to_string()), 7, 1, 8);
+    chk(r#"print("\u{110000}")"#, Error::InvalidEscape(r"\u{110000}".to_string()), 7, 1, 8);
+    chk(r#"print("\u{D800}")"#, Error::InvalidEscape(r"\u{D800}".to_string()), 7, 1, 8);
+    chk(r#"print("%d %d", 1)"#, Error::WrongArgs("print() format string expects 2 arguments, but got 1".to_string()), 6, 1, 7);
+    chk(r#"print( "%d", x, y)"#, Error::WrongArgs("print() format string expects 1 argument, but got 2".to_string()), 7, 1, 8);
+    chk(r#"print("%s %d", 1, "2")"#, Error::WrongArgs("print() conversion `%d` requires a number, but got a string".to_string()), 6, 1, 7);
+    chk(r#"print("100%")"#, Error::WrongArgs("incomplete conversion `%` in print() format string".to_string()), 6, 1, 7);
+    chk(r#"print("%y", 1)"#, Error::WrongArgs("invalid conversion `%y` in print() format string".to_string()), 6, 1, 7);
+    chk("if(1, 2)", Error::WrongArgs("function `if` takes 3 arguments: if(cond, then, otherwise)".to_string()), 0, 1, 1);
+
+    let err = Parser{expr_len_limit:4, expr_depth_limit:32}.parse("1+2+3", &mut Slab::new().ps).unwrap_err();
+    assert_eq!(err.kind(), &Error::TooLong);
+    assert_eq!(err.pos(), Some(Pos{offset:4, line:1, col:5}));
+}
+
+#[test]
+fn safe_var() {
+    let mut slab = Slab::new();
+
+    let sa = SafeVar::new(1.23);
+    slab.ps.add_safe_var("sa".to_string(), &sa);
+
+    Parser::new().parse("sa + sb + sa()", &mut slab.ps).unwrap();
+    assert_eq!(format!("{:?}",&slab),
+"Slab{ exprs:{ 0:Expression { first: EStdFunc(ESafeVar { name: \"sa\", var: SafeVar(1.23) }), pairs: [ExprPair(EAdd, EStdFunc(EVar(\"sb\"))), ExprPair(EAdd, EStdFunc(ESafeVar { name: \"sa\", var: SafeVar(1.23) }))] } }, vals:{}, instrs:{} }");
 }
 
 #[test]
@@ -0,0 +1,136 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, EmptyNamespace, NumberEvaler, Interval, SafeVar, eval_interval, eval_grad, eval_batch, derive};
+use std::collections::BTreeMap;
+
+mod common;
+
+fn ns(name:&str, _args:Vec<f64>) -> Option<f64> {
+    match name {
+        "x" => Some(10.0),
+        "y" => Some(3.0),
+        _ => None,
+    }
+}
+
+fn eval_with(slab:&mut Slab, expr_str:&str) -> Result<f64,Error> {
+    common::eval_agree(slab, expr_str, &mut ns)
+}
+
+fn eval(expr_str:&str) -> Result<f64,Error> {
+    eval_with(&mut Slab::new(), expr_str)
+}
+
+fn comp(expr_str:&str) -> String {
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    format!("{:?}", instr)
+}
+
+#[test]
+fn statements() {
+    assert_eq!(eval("a = 2; b = a * 3; a + b"), Ok(8.0));
+    assert_eq!(eval("a = 2\nb = a * 3\na + b"), Ok(8.0));
+    assert_eq!(eval("a = 2 ;;\n\n b = a * 3;\n a + b"), Ok(8.0));
+    assert_eq!(eval("a = 2; b = a * 3"), Ok(6.0));  // The value of the last assignment.
+    assert_eq!(eval("1; 2; 3"), Ok(3.0));
+    assert_eq!(eval("2 + 3"), Ok(5.0));
+
+    // A newline doesn't end an Expression that can continue:
+    assert_eq!(eval("a = 2 +\n 3\na"), Ok(5.0));
+    assert_eq!(eval("(1\n+ 2) * 2"), Ok(6.0));
+    // ...but a name that starts with a keyword operator starts a new statement:
+    assert_eq!(eval("a = 1\nxorigin = 2\na + xorigin"), Ok(3.0));
+    assert_eq!(eval("a = 1\norange = 2\nandroid = 3\na + orange + android"), Ok(6.0));
+    #[cfg(feature="alpha-keywords")]
+    assert_eq!(eval("a = 6\nxor 3"), Ok(5.0));
+
+    // Reassignment, and variables from the Namespace:
+    assert_eq!(eval("n = x; n = n + 1; n = n * y; n"), Ok(33.0));
+    assert_eq!(eval("x = x + 1; x * 2"), Ok(22.0));
+    assert_eq!(eval("y = x; x = y + x; x + y"), Ok(30.0));
+    assert_eq!(eval("a = x < 5 ? 1 : 2; a * 3"), Ok(6.0));
+    assert_eq!(eval("a = 1; a == 1"), Ok(1.0));
+    assert_eq!(eval("x == 10"), Ok(1.0));
+
+    // Locals shadow Safe Variables too:
+    let mut slab = Slab::new();
+    slab.ps.add_safe_var("s".to_string(), &SafeVar::new(4.0));
+    assert_eq!(eval_with(&mut slab, "t = s; s = 1; s + t"), Ok(5.0));
+
+    // print() sees the locals:
+    assert_eq!(eval("a = 7; print(\"a =\", a); a"), Ok(7.0));
+}
+
+#[test]
+fn functions() {
+    let mut slab = Slab::new();
+    Parser::new().define("f(t) = t + x", &mut slab.ps).unwrap();
+    // The body of a function only sees its parameters and the Namespace:
+    assert_eq!(eval_with(&mut slab, "x = 1; f(x)"), Ok(11.0));
+    assert_eq!(eval_with(&mut slab, "t = 5; f(2) + t"), Ok(17.0));
+    // The arguments are evaluated in the program's scope:
+    assert_eq!(eval_with(&mut slab, "a = y; b = f(a * 2) + a; f(b)"), Ok(29.0));
+}
+
+#[test]
+fn var_names() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("a = x + 1; b = a * y; a = a + b + z; a", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.var_names(&slab).into_iter().collect::<Vec<_>>(), vec!["x", "y", "z"]);
+    // A use before the assignment is a free variable:
+    let expr = Parser::new().parse("a = a + 1; a", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.var_names(&slab).into_iter().collect::<Vec<_>>(), vec!["a"]);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.var_names(&slab).into_iter().collect::<Vec<_>>(), vec!["a"]);
+}
+
+#[test]
+fn compile() {
+    assert_eq!(comp("a = 2; b = a * 3; a + b"), "IConst(8.0)");
+    assert_eq!(comp("a = 2\n1 + 1"), "IConst(2.0)");
+    assert_eq!(comp("a = x; a * 2"), "IProgram { names: [\"a\"], stmts: [(Some(0), I(InstructionI(0))), (None, I(InstructionI(2)))] }");
+}
+
+#[test]
+fn errors() {
+    let err = |expr_str:&str| Parser::new().parse(expr_str, &mut Slab::new().ps).map_err(|e| (e.kind().clone(), e.pos().map(|p| p.offset)));
+    assert_eq!(err("a = 1 b = 2"), Err((Error::UnparsedTokensRemaining("b = 2".to_string()), Some(6))));
+    assert_eq!(err("a = ; 1").map_err(|e| e.0), Err(Error::InvalidValue));
+    assert_eq!(eval("a = 1; b"), Err(Error::Undefined("b".to_string())));
+    assert!(eval("b + (b = 1)").is_err());
+}
+
+#[test]
+fn other_evaluators() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("n = 9007199254740993; n = n * 2\nn - 1", &mut slab.ps).unwrap().from(&slab.ps);
+    assert_eq!(expr.eval_number(&slab, &mut EmptyNamespace), Ok(18014398509481985i64));
+
+    // Batch evaluation:
+    let mut slab = Slab::new();
+    slab.cs.add_slot("a".to_string());
+    let instr = Parser::new().parse("b = a * 2; c = b + a; c * b", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let mut out = [0.0; 3];
+    eval_batch(&instr, &slab, &[&[1.0, 2.0, 3.0]], &mut out, &mut EmptyNamespace).unwrap();
+    assert_eq!(out, [6.0, 24.0, 54.0]);
+    Parser::new().define("f(t) = t * t", &mut slab.ps).unwrap();
+    let instr = Parser::new().parse("b = a + 1; f(b) - b", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    eval_batch(&instr, &slab, &[&[1.0, 2.0, 3.0]], &mut out, &mut EmptyNamespace).unwrap();
+    assert_eq!(out, [2.0, 6.0, 12.0]);
+
+    // Intervals and gradients:
+    let mut slab = Slab::new();
+    let instr = Parser::new().parse("b = a * a; b = b - a; b", &mut slab.ps).unwrap().from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Interval::new(1.0, 2.0));
+    assert_eq!(eval_interval(&instr, &slab, &mut map), Ok(Interval::new(-1.0, 3.0)));
+    let dual = eval_grad(&instr, &slab, &["a"], &mut |_:&str, _:Vec<f64>| Some(3.0)).unwrap();
+    assert_eq!((dual.val, dual.grad), (6.0, vec![5.0]));
+
+    // Symbolic derivatives:
+    let mut slab = Slab::new();
+    let expr_i = Parser::new().parse("b = x * x; c = 5; b = b * c + y; b * x", &mut slab.ps).unwrap();
+    let d = derive(expr_i, "x", &mut slab.ps).unwrap();
+    assert_eq!(d.from(&slab.ps).eval(&slab, &mut ns), Ok(1503.0));  // d/dx (5x^3 + xy) = 15x^2 + y
+    let instr = d.from(&slab.ps).compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(1503.0));
+}
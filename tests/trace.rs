@@ -0,0 +1,120 @@
+use fasteval::{Parser, Compiler, Evaler, Error, Slab, Trace, eval_traced};
+
+fn ns(name:&str, args:Vec<f64>) -> Option<f64> {
+    match name {
+        "x" => Some(1.0),
+        "y" => Some(3.0),
+        "add" => Some(args.iter().sum()),
+        _ => None,
+    }
+}
+
+// Traces the interpreted and the compiled forms, and checks that they agree with eval():
+fn trace(slab:&mut Slab, expr_str:&str) -> (String, String) {
+    let expr = Parser::new().parse(expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+    let interpreted = eval_traced(expr, slab, &mut ns);
+    assert_eq!(interpreted.value, expr.eval(slab, &mut ns), "{}", expr_str);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    let compiled = eval_traced(&instr, slab, &mut ns);
+    assert_eq!(compiled.value, instr.eval(slab, &mut ns), "{}", expr_str);
+    (interpreted.to_string(), compiled.to_string())
+}
+
+#[test]
+fn tree() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("add(x, 2) * -y", &mut slab.ps).unwrap().from(&slab.ps);
+    let leaf = |label:&str, val:f64| Trace{ label:label.to_string(), value:Ok(val), children:vec![] };
+    assert_eq!(eval_traced(expr, &slab, &mut ns), Trace{
+        label:"result".to_string(),
+        value:Ok(-9.0),
+        children:vec![
+            Trace{ label:"add(1, 2)".to_string(), value:Ok(3.0), children:vec![leaf("x", 1.0)] },
+            Trace{ label:"-3".to_string(), value:Ok(-3.0), children:vec![leaf("y", 3.0)] },
+            leaf("3 * -3", -9.0),
+        ],
+    });
+}
+
+#[test]
+fn display() {
+    let mut slab = Slab::new();
+    assert_eq!(trace(&mut slab, "x < 2 ? y^2 : nope"), (
+"result = 9
+  if(1, 9, …) = 9
+    x = 1
+    1 < 2 = 1
+    y = 3
+    3 ^ 2 = 9
+".to_string(),
+"result = 9
+  if(1, 9, …) = 9
+    1 < 2 = 1
+      x = 1
+    3 ^ 2 = 9
+      y = 3
+".to_string()));
+
+    // Constants are not recorded:
+    assert_eq!(trace(&mut slab, "log(100) + 1"), (
+"result = 3
+  log(100) = 2
+  2 + 1 = 3
+".to_string(),
+"result = 3
+".to_string()));
+}
+
+#[test]
+fn programs_and_functions() {
+    let mut slab = Slab::new();
+    Parser::new().define("f(t) = t * y", &mut slab.ps).unwrap();
+    assert_eq!(trace(&mut slab, "a = f(x + 1); a - 1"), (
+"result = 5
+  set a = 6
+    f(2) = 6
+      x = 1
+      1 + 1 = 2
+      t = 2
+      y = 3
+      2 * 3 = 6
+  a = 6
+  6 - 1 = 5
+".to_string(),
+"result = 5
+  set a = 6
+    f(2) = 6
+      1 + 1 = 2
+        x = 1
+      2 * 3 = 6
+        t = 2
+        y = 3
+  6 + -1 = 5
+    a = 6
+".to_string()));
+}
+
+#[test]
+fn errors() {
+    let mut slab = Slab::new();
+    let expr = Parser::new().parse("x + sin(nope)", &mut slab.ps).unwrap().from(&slab.ps);
+    let t = eval_traced(expr, &slab, &mut ns);
+    assert_eq!(t.value, Err(Error::Undefined("nope".to_string())));
+    assert_eq!(t.to_string(),
+"result = error: undefined variable `nope`
+  x = 1
+  sin(…) = error: undefined variable `nope`
+    nope = error: undefined variable `nope`
+");
+}
+
+#[test]
+fn deep_nesting() {
+    // Tracing must not make the recursion of a long compiled expression use much more stack:
+    let mut slab = Slab::new();
+    let expr_str = vec!["x"; 300].join(" + ");
+    let expr = Parser::new().parse(&expr_str, &mut slab.ps).unwrap().from(&slab.ps);
+    let instr = expr.compile(&slab.ps, &mut slab.cs);
+    assert_eq!(instr.eval(&slab, &mut ns), Ok(300.0));
+    assert_eq!(eval_traced(&instr, &slab, &mut ns).value, Ok(300.0));
+}